@@ -0,0 +1,94 @@
+/*
+ * Host stand-in for a target with the UART monitor, to try the serial backend
+ * without hardware. It opens a pseudo terminal and prints its path.
+ *
+ *   cc -g -no-pie -o host_sim host_sim.c vmr_uart.c -lm
+ *   ./host_sim
+ *
+ * Load host_sim as the ELF, select "UART" and enter the printed /dev/pts/N as
+ * the port. -no-pie keeps the globals below 4 GiB, where the 32-bit addresses
+ * of the protocol can reach them.
+ */
+#define _DEFAULT_SOURCE
+#define _XOPEN_SOURCE 600
+#include <fcntl.h>
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <termios.h>
+#include <time.h>
+#include <unistd.h>
+
+#include "vmr_uart.h"
+
+volatile float sine_wave;
+volatile float amplitude = 1.0f;
+volatile int counter;
+volatile unsigned char led_on;
+
+static int pty;
+
+/* .data and .bss of this program, from the GNU linker */
+extern char __data_start, _end;
+
+/* The host would crash on a wrong address, unlike most MCUs. */
+int vmr_uart_address_ok(uint32_t address, size_t len, int write)
+{
+    (void)write;
+    return address >= (uintptr_t)&__data_start && address + len <= (uintptr_t)&_end;
+}
+
+static void pty_write(const uint8_t *data, size_t len)
+{
+    while (len > 0u) {
+        ssize_t written = write(pty, data, len);
+        if (written <= 0) {
+            return;
+        }
+        data += written;
+        len -= (size_t)written;
+    }
+}
+
+static uint32_t millis(void)
+{
+    struct timespec now;
+    clock_gettime(CLOCK_MONOTONIC, &now);
+    return (uint32_t)(now.tv_sec * 1000u + now.tv_nsec / 1000000u);
+}
+
+int main(void)
+{
+    pty = posix_openpt(O_RDWR | O_NOCTTY);
+    if (pty < 0 || grantpt(pty) != 0 || unlockpt(pty) != 0) {
+        perror("pty");
+        return 1;
+    }
+    struct termios raw;
+    tcgetattr(pty, &raw);
+    cfmakeraw(&raw);
+    tcsetattr(pty, TCSANOW, &raw);
+    fcntl(pty, F_SETFL, O_NONBLOCK);
+
+    printf("port : %s\n", ptsname(pty));
+    printf("sine_wave %p, amplitude %p, counter %p, led_on %p\n",
+           (void *)&sine_wave, (void *)&amplitude, (void *)&counter, (void *)&led_on);
+    fflush(stdout);
+
+    vmr_uart_init(pty_write, millis);
+    for (;;) {
+        uint8_t buff[64];
+        ssize_t count = read(pty, buff, sizeof(buff));
+        for (ssize_t i = 0; i < count; i++) {
+            vmr_uart_receive(buff[i]);
+            /* フレームが揃ったら次のバイトの前に処理する */
+            vmr_uart_poll();
+        }
+        vmr_uart_poll();
+
+        sine_wave = amplitude * sinf((float)millis() * 0.002f);
+        counter++;
+        led_on = sine_wave > 0.0f;
+        usleep(1000);
+    }
+}
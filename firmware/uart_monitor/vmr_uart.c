@@ -0,0 +1,260 @@
+#include "vmr_uart.h"
+
+#include <string.h>
+
+#define VMR_SOF 0xA5u
+#define VMR_CMD_PING 0x01u
+#define VMR_CMD_READ 0x02u
+#define VMR_CMD_WRITE 0x03u
+#define VMR_CMD_SUBSCRIBE 0x04u
+#define VMR_REPLY 0x80u
+#define VMR_STREAM 0x85u
+#define VMR_ERROR 0xFFu
+
+#define VMR_ERROR_UNKNOWN_COMMAND 1u
+#define VMR_ERROR_BAD_LENGTH 2u
+#define VMR_ERROR_ADDRESS 3u
+#define VMR_ERROR_TOO_MANY 4u
+
+#define VMR_PROTOCOL_VERSION 1u
+#define VMR_HEADER_LEN 5u
+#define VMR_CRC_LEN 2u
+#define VMR_FRAME_MAX (VMR_HEADER_LEN + VMR_UART_MAX_PAYLOAD + VMR_CRC_LEN)
+
+/* The receive buffer is written by vmr_uart_receive() (maybe from an interrupt)
+ * until a whole frame is there, then handed to vmr_uart_poll(). */
+static volatile uint8_t rx_buffer[VMR_FRAME_MAX];
+static volatile size_t rx_len;
+static volatile int rx_ready;
+
+static uint8_t tx_buffer[VMR_FRAME_MAX];
+
+static vmr_uart_write_fn uart_write;
+static vmr_uart_millis_fn uart_millis;
+
+static struct {
+    uint32_t address;
+    uint8_t len;
+} entries[VMR_UART_MAX_ENTRIES];
+static size_t entry_count;
+static uint16_t stream_period;
+static uint32_t stream_last;
+
+/* ------------------------------------------------------------------------ */
+static uint16_t crc16(const volatile uint8_t *data, size_t len)
+{
+    uint16_t crc = 0xFFFFu;
+    for (size_t i = 0; i < len; i++) {
+        crc ^= (uint16_t)data[i] << 8;
+        for (int bit = 0; bit < 8; bit++) {
+            crc = (crc & 0x8000u) ? (uint16_t)((crc << 1) ^ 0x1021u) : (uint16_t)(crc << 1);
+        }
+    }
+    return crc;
+}
+
+static uint32_t get_u32(const volatile uint8_t *p)
+{
+    return (uint32_t)p[0] | ((uint32_t)p[1] << 8) | ((uint32_t)p[2] << 16) | ((uint32_t)p[3] << 24);
+}
+
+static void put_u32(uint8_t *p, uint32_t value)
+{
+    p[0] = (uint8_t)value;
+    p[1] = (uint8_t)(value >> 8);
+    p[2] = (uint8_t)(value >> 16);
+    p[3] = (uint8_t)(value >> 24);
+}
+
+/* The payload is already in tx_buffer[VMR_HEADER_LEN..]. */
+static void send_frame(uint8_t cmd, uint8_t seq, size_t len)
+{
+    tx_buffer[0] = VMR_SOF;
+    tx_buffer[1] = cmd;
+    tx_buffer[2] = seq;
+    tx_buffer[3] = (uint8_t)len;
+    tx_buffer[4] = (uint8_t)(len >> 8);
+    uint16_t crc = crc16(&tx_buffer[1], VMR_HEADER_LEN - 1u + len);
+    tx_buffer[VMR_HEADER_LEN + len] = (uint8_t)crc;
+    tx_buffer[VMR_HEADER_LEN + len + 1u] = (uint8_t)(crc >> 8);
+    uart_write(tx_buffer, VMR_HEADER_LEN + len + VMR_CRC_LEN);
+}
+
+static void send_error(uint8_t seq, uint8_t code)
+{
+    tx_buffer[VMR_HEADER_LEN] = code;
+    send_frame(VMR_ERROR, seq, 1u);
+}
+
+__attribute__((weak)) int vmr_uart_address_ok(uint32_t address, size_t len, int write)
+{
+    (void)address;
+    (void)len;
+    (void)write;
+    return 1;
+}
+
+static void *pointer(uint32_t address)
+{
+    return (void *)(uintptr_t)address;
+}
+
+/* ------------------------------------------------------------------------ */
+static void handle_frame(uint8_t cmd, uint8_t seq, const volatile uint8_t *payload, size_t len)
+{
+    uint8_t *reply = &tx_buffer[VMR_HEADER_LEN];
+
+    switch (cmd) {
+    case VMR_CMD_PING:
+        reply[0] = VMR_PROTOCOL_VERSION;
+        reply[1] = (uint8_t)VMR_UART_MAX_PAYLOAD;
+        reply[2] = (uint8_t)(VMR_UART_MAX_PAYLOAD >> 8);
+        send_frame(cmd | VMR_REPLY, seq, 3u);
+        break;
+
+    case VMR_CMD_READ: {
+        if (len != 6u) {
+            send_error(seq, VMR_ERROR_BAD_LENGTH);
+            break;
+        }
+        uint32_t address = get_u32(payload);
+        size_t read_len = (size_t)payload[4] | ((size_t)payload[5] << 8);
+        if (read_len + 4u > VMR_UART_MAX_PAYLOAD) {
+            send_error(seq, VMR_ERROR_BAD_LENGTH);
+            break;
+        }
+        if (!vmr_uart_address_ok(address, read_len, 0)) {
+            send_error(seq, VMR_ERROR_ADDRESS);
+            break;
+        }
+        put_u32(reply, address);
+        memcpy(&reply[4], pointer(address), read_len);
+        send_frame(cmd | VMR_REPLY, seq, 4u + read_len);
+        break;
+    }
+
+    case VMR_CMD_WRITE: {
+        if (len < 4u) {
+            send_error(seq, VMR_ERROR_BAD_LENGTH);
+            break;
+        }
+        uint32_t address = get_u32(payload);
+        size_t write_len = len - 4u;
+        if (!vmr_uart_address_ok(address, write_len, 1)) {
+            send_error(seq, VMR_ERROR_ADDRESS);
+            break;
+        }
+        uint8_t *dest = pointer(address);
+        for (size_t i = 0; i < write_len; i++) {
+            dest[i] = payload[4u + i];
+        }
+        send_frame(cmd | VMR_REPLY, seq, 0u);
+        break;
+    }
+
+    case VMR_CMD_SUBSCRIBE: {
+        if (len < 3u || len != 3u + (size_t)payload[2] * 5u) {
+            send_error(seq, VMR_ERROR_BAD_LENGTH);
+            break;
+        }
+        size_t count = payload[2];
+        size_t sample_len = 4u;
+        if (count > VMR_UART_MAX_ENTRIES) {
+            send_error(seq, VMR_ERROR_TOO_MANY);
+            break;
+        }
+        for (size_t i = 0; i < count; i++) {
+            const volatile uint8_t *entry = &payload[3u + i * 5u];
+            sample_len += entry[4];
+            if (!vmr_uart_address_ok(get_u32(entry), entry[4], 0)) {
+                send_error(seq, VMR_ERROR_ADDRESS);
+                return;
+            }
+        }
+        if (sample_len > VMR_UART_MAX_PAYLOAD) {
+            send_error(seq, VMR_ERROR_BAD_LENGTH);
+            break;
+        }
+        for (size_t i = 0; i < count; i++) {
+            entries[i].address = get_u32(&payload[3u + i * 5u]);
+            entries[i].len = payload[3u + i * 5u + 4u];
+        }
+        entry_count = count;
+        stream_period = (uint16_t)(payload[0] | (payload[1] << 8));
+        stream_last = uart_millis();
+        send_frame(cmd | VMR_REPLY, seq, 0u);
+        break;
+    }
+
+    default:
+        send_error(seq, VMR_ERROR_UNKNOWN_COMMAND);
+        break;
+    }
+}
+
+static void send_stream(void)
+{
+    uint8_t *sample = &tx_buffer[VMR_HEADER_LEN];
+    size_t len = 4u;
+
+    put_u32(sample, uart_millis());
+    for (size_t i = 0; i < entry_count; i++) {
+        memcpy(&sample[len], pointer(entries[i].address), entries[i].len);
+        len += entries[i].len;
+    }
+    send_frame(VMR_STREAM, 0u, len);
+}
+
+/* ------------------------------------------------------------------------ */
+void vmr_uart_init(vmr_uart_write_fn write, vmr_uart_millis_fn millis)
+{
+    uart_write = write;
+    uart_millis = millis;
+    rx_len = 0;
+    rx_ready = 0;
+    entry_count = 0;
+    stream_period = 0;
+}
+
+void vmr_uart_receive(uint8_t byte)
+{
+    /* the previous frame is not handled yet */
+    if (rx_ready) {
+        return;
+    }
+    if (rx_len == 0u && byte != VMR_SOF) {
+        return;
+    }
+    rx_buffer[rx_len++] = byte;
+
+    if (rx_len >= VMR_HEADER_LEN) {
+        size_t len = (size_t)rx_buffer[3] | ((size_t)rx_buffer[4] << 8);
+        if (len > VMR_UART_MAX_PAYLOAD) {
+            rx_len = 0;
+        } else if (rx_len == VMR_HEADER_LEN + len + VMR_CRC_LEN) {
+            rx_ready = 1;
+        }
+    }
+}
+
+void vmr_uart_poll(void)
+{
+    if (rx_ready) {
+        size_t len = (size_t)rx_buffer[3] | ((size_t)rx_buffer[4] << 8);
+        uint16_t crc = (uint16_t)(rx_buffer[VMR_HEADER_LEN + len] | (rx_buffer[VMR_HEADER_LEN + len + 1u] << 8));
+        if (crc == crc16(&rx_buffer[1], VMR_HEADER_LEN - 1u + len)) {
+            handle_frame(rx_buffer[1], rx_buffer[2], &rx_buffer[VMR_HEADER_LEN], len);
+        }
+        rx_len = 0;
+        rx_ready = 0;
+    }
+
+    if (stream_period != 0u && entry_count != 0u && (uint32_t)(uart_millis() - stream_last) >= stream_period) {
+        stream_last += stream_period;
+        /* 送信が周期に追いつかないときは溜めずに今から数え直す */
+        if ((uint32_t)(uart_millis() - stream_last) >= stream_period) {
+            stream_last = uart_millis();
+        }
+        send_stream();
+    }
+}
@@ -0,0 +1,52 @@
+/*
+ * VisuModiRam UART monitor : target side of the framed serial protocol.
+ *
+ * The frame format and the commands are described in
+ * src/debugging_tools/serial_monitor.rs.
+ *
+ * Usage
+ *   vmr_uart_init(uart_write, millis);   at start up
+ *   vmr_uart_receive(byte);              for every received byte (RX interrupt is fine)
+ *   vmr_uart_poll();                     from the main loop
+ *
+ * vmr_uart_poll() answers the requests and sends the subscribed values, so
+ * uart_write() is called only from the main loop.
+ */
+#ifndef VMR_UART_H
+#define VMR_UART_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Longest payload of a frame; the host reads and writes in chunks of this size. */
+#ifndef VMR_UART_MAX_PAYLOAD
+#define VMR_UART_MAX_PAYLOAD 128
+#endif
+
+/* Entries of one subscription. */
+#ifndef VMR_UART_MAX_ENTRIES
+#define VMR_UART_MAX_ENTRIES 16
+#endif
+
+typedef void (*vmr_uart_write_fn)(const uint8_t *data, size_t len);
+typedef uint32_t (*vmr_uart_millis_fn)(void);
+
+void vmr_uart_init(vmr_uart_write_fn write, vmr_uart_millis_fn millis);
+void vmr_uart_receive(uint8_t byte);
+void vmr_uart_poll(void);
+
+/*
+ * Called before every access. Override it (it is weak) to refuse addresses
+ * outside RAM or peripherals that must not be read.
+ */
+int vmr_uart_address_ok(uint32_t address, size_t len, int write);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* VMR_UART_H */
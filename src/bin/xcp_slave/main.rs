@@ -0,0 +1,127 @@
+//! Software XCP on Ethernet slave, a stand-in for an ECU to try the XCP backend.
+//!
+//!   xcp_slave [--tcp] [--port 5555] [--ram 0x20000000:0x20000] [--elf FILE]
+//!             [--sine SYMBOL] [--counter SYMBOL]
+//!
+//! It serves a block of simulated RAM. With `--elf`, `.data` of the image is
+//! copied in, so the variables of a firmware start with their initial values,
+//! and `--sine` / `--counter` animate a float / 32-bit integer found by name.
+//! Event channel 0 "1ms" samples the DAQ lists every millisecond with a 32-bit
+//! timestamp in µs.
+
+#[path = "../../debugging_tools/elf_symbols.rs"]
+#[allow(dead_code)]
+mod elf_symbols;
+#[allow(dead_code)]
+mod slave;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use elf_symbols::ElfSymbols;
+use slave::{Slave, Transport};
+
+// ----------------------------------------------------------------------------
+/// Simulated signals written into the RAM every tick.
+struct Animation {
+    sine: Option<u32>,
+    counter: Option<u32>,
+}
+
+impl Animation {
+    fn update(&self, slave: &mut Slave) {
+        let time = slave.started.elapsed().as_secs_f32();
+        if let Some(bytes) = self.sine.and_then(|address| slave.memory(address, 4)) {
+            bytes.copy_from_slice(&(time * std::f32::consts::TAU * 0.5).sin().to_le_bytes());
+        }
+        if let Some(bytes) = self.counter.and_then(|address| slave.memory(address, 4)) {
+            let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            bytes.copy_from_slice(&value.wrapping_add(1).to_le_bytes());
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage : xcp_slave [--tcp] [--port 5555] [--ram 0x20000000:0x20000] [--elf FILE] [--sine SYMBOL] [--counter SYMBOL]"
+    );
+    std::process::exit(2);
+}
+
+fn main() -> std::io::Result<()> {
+    let mut tcp = false;
+    let mut port = 5555u16;
+    let mut ram = (0x2000_0000u32, 0x2_0000usize);
+    let mut elf_path = None;
+    let mut sine = None;
+    let mut counter = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--tcp" => tcp = true,
+            "--port" => port = value().parse().unwrap_or_else(|_| usage()),
+            "--ram" => {
+                let text = value();
+                let (base, size) = text.split_once(':').unwrap_or_else(|| usage());
+                ram = (
+                    parse_number(base).unwrap_or_else(|| usage()),
+                    parse_number(size).unwrap_or_else(|| usage()) as usize,
+                );
+            }
+            "--elf" => elf_path = Some(PathBuf::from(value())),
+            "--sine" => sine = Some(value()),
+            "--counter" => counter = Some(value()),
+            _ => usage(),
+        }
+    }
+
+    let mut slave = Slave::new(ram.0, ram.1);
+
+    // シンボルの解決と初期値のコピー
+    let symbols = elf_path.as_ref().and_then(ElfSymbols::load);
+    if let Some((address, data)) = elf_path
+        .as_ref()
+        .and_then(|path| elf_symbols::load_section(path, ".data"))
+    {
+        match slave.memory(address as u32, data.len()) {
+            Some(bytes) => bytes.copy_from_slice(&data),
+            None => eprintln!(".data at 0x{address:08X} is outside the RAM"),
+        }
+    }
+    let find = |name: Option<String>| -> Option<u32> {
+        let name = name?;
+        let symbol = symbols.as_ref().and_then(|symbols| symbols.find(&name));
+        if symbol.is_none() {
+            eprintln!("symbol {name} is not found, give the ELF with --elf");
+        }
+        symbol.map(|symbol| symbol.value as u32)
+    };
+    let animation = Animation {
+        sine: find(sine),
+        counter: find(counter),
+    };
+
+    let mut transport = Transport::bind(tcp, SocketAddr::from(([0, 0, 0, 0], port)))?;
+    println!(
+        "XCP slave on {} port {port}, RAM 0x{:08X}..0x{:08X}",
+        if tcp { "TCP" } else { "UDP" },
+        ram.0,
+        ram.0 as usize + ram.1
+    );
+
+    slave::serve(
+        &mut slave,
+        &mut transport,
+        |slave| animation.update(slave),
+        || false,
+    )
+}
@@ -0,0 +1,466 @@
+//! Protocol side of the XCP slave: the simulated RAM, the DAQ lists and the
+//! XCP on Ethernet transport. Shared with the tests of the XCP master.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+const MAX_CTO: usize = 64;
+const MAX_DTO: usize = 256;
+const EVENT_CYCLE_MS: u8 = 1;
+const EVENT_NAME: &str = "1ms";
+const MAX_DAQ_LISTS: usize = 4;
+
+// エラーコード
+const ERR_CMD_SYNTAX: u8 = 0x21;
+const ERR_CMD_UNKNOWN: u8 = 0x20;
+const ERR_OUT_OF_RANGE: u8 = 0x22;
+const ERR_SEQUENCE: u8 = 0x29;
+const ERR_MEMORY_OVERFLOW: u8 = 0x30;
+// ----------------------------------------------------------------------------
+#[derive(Default)]
+struct DaqList {
+    odts: Vec<Vec<(u32, u8)>>,
+    timestamp: bool,
+    prescaler: u8,
+    selected: bool,
+    running: bool,
+    first_pid: u8,
+}
+
+/// Simulated RAM and DAQ processor.
+pub struct Slave {
+    ram_base: u32,
+    ram: Vec<u8>,
+    connected: bool,
+    mta: u32,
+    daq_lists: Vec<DaqList>,
+    // SET_DAQ_PTRの位置 (DAQ, ODT, エントリ)
+    daq_ptr: Option<(usize, usize, usize)>,
+    pub started: Instant,
+    ticks: u32,
+}
+
+impl Slave {
+    pub fn new(ram_base: u32, ram_size: usize) -> Self {
+        Self {
+            ram_base,
+            ram: vec![0; ram_size],
+            connected: false,
+            mta: 0,
+            daq_lists: Vec::new(),
+            daq_ptr: None,
+            started: Instant::now(),
+            ticks: 0,
+        }
+    }
+
+    pub fn memory(&mut self, address: u32, len: usize) -> Option<&mut [u8]> {
+        let offset = address.checked_sub(self.ram_base)? as usize;
+        self.ram.get_mut(offset..offset.checked_add(len)?)
+    }
+
+    fn read_u32(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn daq_list(&mut self, packet: &[u8]) -> Result<&mut DaqList, u8> {
+        let number = u16::from_le_bytes([packet[2], packet[3]]) as usize;
+        self.daq_lists.get_mut(number).ok_or(ERR_OUT_OF_RANGE)
+    }
+
+    /// Answers one command packet with a response or an error packet.
+    pub fn command(&mut self, packet: &[u8]) -> Vec<u8> {
+        match self.try_command(packet) {
+            Ok(mut reply) => {
+                reply.insert(0, 0xFF);
+                reply
+            }
+            Err(code) => vec![0xFE, code],
+        }
+    }
+
+    fn try_command(&mut self, packet: &[u8]) -> Result<Vec<u8>, u8> {
+        let need = |len: usize| {
+            if packet.len() < len {
+                Err(ERR_CMD_SYNTAX)
+            } else {
+                Ok(())
+            }
+        };
+        let cmd = *packet.first().ok_or(ERR_CMD_SYNTAX)?;
+        if cmd != 0xFF && !self.connected {
+            // 未接続の間はCONNECT以外に応答しない決まりだが、分かりやすくエラーを返す
+            return Err(ERR_SEQUENCE);
+        }
+
+        match cmd {
+            // CONNECT : DAQ対応, Intel, バイト単位
+            0xFF => {
+                self.connected = true;
+                let dto = (MAX_DTO as u16).to_le_bytes();
+                Ok(vec![0x04, 0x00, MAX_CTO as u8, dto[0], dto[1], 1, 1])
+            }
+            // DISCONNECT
+            0xFE => {
+                self.connected = false;
+                self.stop_all();
+                Ok(vec![])
+            }
+            // GET_STATUS
+            0xFD => {
+                let running = self.daq_lists.iter().any(|list| list.running);
+                Ok(vec![if running { 0x40 } else { 0 }, 0, 0, 0, 0])
+            }
+            // SET_MTA
+            0xF6 => {
+                need(8)?;
+                self.mta = Self::read_u32(&packet[4..8]);
+                Ok(vec![])
+            }
+            // UPLOAD
+            0xF5 => {
+                need(2)?;
+                let len = packet[1] as usize;
+                if len > MAX_CTO - 1 {
+                    return Err(ERR_OUT_OF_RANGE);
+                }
+                let address = self.mta;
+                let data = self.memory(address, len).ok_or(ERR_OUT_OF_RANGE)?.to_vec();
+                self.mta += len as u32;
+                Ok(data)
+            }
+            // SHORT_UPLOAD
+            0xF4 => {
+                need(8)?;
+                let len = packet[1] as usize;
+                if len > MAX_CTO - 1 {
+                    return Err(ERR_OUT_OF_RANGE);
+                }
+                let address = Self::read_u32(&packet[4..8]);
+                let data = self.memory(address, len).ok_or(ERR_OUT_OF_RANGE)?.to_vec();
+                self.mta = address + len as u32;
+                Ok(data)
+            }
+            // DOWNLOAD
+            0xF0 => {
+                need(2)?;
+                let len = packet[1] as usize;
+                let data = packet.get(2..2 + len).ok_or(ERR_CMD_SYNTAX)?;
+                let address = self.mta;
+                self.memory(address, len)
+                    .ok_or(ERR_OUT_OF_RANGE)?
+                    .copy_from_slice(data);
+                self.mta += len as u32;
+                Ok(vec![])
+            }
+            // SET_DAQ_PTR
+            0xE2 => {
+                need(6)?;
+                let daq = u16::from_le_bytes([packet[2], packet[3]]) as usize;
+                let (odt, entry) = (packet[4] as usize, packet[5] as usize);
+                let list = self.daq_lists.get(daq).ok_or(ERR_OUT_OF_RANGE)?;
+                if !list
+                    .odts
+                    .get(odt)
+                    .is_some_and(|entries| entry < entries.len())
+                {
+                    return Err(ERR_OUT_OF_RANGE);
+                }
+                self.daq_ptr = Some((daq, odt, entry));
+                Ok(vec![])
+            }
+            // WRITE_DAQ
+            0xE1 => {
+                need(8)?;
+                let (daq, odt, entry) = self.daq_ptr.ok_or(ERR_SEQUENCE)?;
+                let size = packet[2];
+                let address = Self::read_u32(&packet[4..8]);
+                if self.memory(address, size as usize).is_none() {
+                    return Err(ERR_OUT_OF_RANGE);
+                }
+                let entries = &mut self.daq_lists[daq].odts[odt];
+                *entries.get_mut(entry).ok_or(ERR_OUT_OF_RANGE)? = (address, size);
+                self.daq_ptr = Some((daq, odt, entry + 1));
+                Ok(vec![])
+            }
+            // SET_DAQ_LIST_MODE
+            0xE0 => {
+                need(8)?;
+                let event = u16::from_le_bytes([packet[4], packet[5]]);
+                if event != 0 {
+                    return Err(ERR_OUT_OF_RANGE);
+                }
+                let list = self.daq_list(packet)?;
+                list.timestamp = packet[1] & 0x10 != 0;
+                list.prescaler = packet[6].max(1);
+                Ok(vec![])
+            }
+            // START_STOP_DAQ_LIST
+            0xDE => {
+                need(4)?;
+                let mode = packet[1];
+                let number = u16::from_le_bytes([packet[2], packet[3]]) as usize;
+                let first_pid = self.daq_lists[..number.min(self.daq_lists.len())]
+                    .iter()
+                    .map(|list| list.odts.len())
+                    .sum::<usize>() as u8;
+                let list = self.daq_list(packet)?;
+                list.first_pid = first_pid;
+                match mode {
+                    0 => list.running = false,
+                    1 => list.running = true,
+                    2 => list.selected = true,
+                    _ => return Err(ERR_OUT_OF_RANGE),
+                }
+                Ok(vec![first_pid])
+            }
+            // START_STOP_SYNCH
+            0xDD => {
+                need(2)?;
+                match packet[1] {
+                    0 => self.stop_all(),
+                    mode @ (1 | 2) => {
+                        for list in self.daq_lists.iter_mut().filter(|list| list.selected) {
+                            list.running = mode == 1;
+                            list.selected = false;
+                        }
+                    }
+                    _ => return Err(ERR_OUT_OF_RANGE),
+                }
+                Ok(vec![])
+            }
+            // GET_DAQ_PROCESSOR_INFO : 動的構成, タイムスタンプ対応, 絶対ODT番号
+            0xDA => {
+                let max_daq = (MAX_DAQ_LISTS as u16).to_le_bytes();
+                Ok(vec![0x11, max_daq[0], max_daq[1], 1, 0, 0, 0x00])
+            }
+            // GET_DAQ_RESOLUTION_INFO : 4バイト, 1µs
+            0xD9 => Ok(vec![1, 0xFF, 1, 0xFF, 0x04 | (3 << 4), 1, 0]),
+            // GET_DAQ_EVENT_INFO : 周期的, 1ms
+            0xD7 => {
+                need(4)?;
+                if u16::from_le_bytes([packet[2], packet[3]]) != 0 {
+                    return Err(ERR_OUT_OF_RANGE);
+                }
+                Ok(vec![
+                    0x04,
+                    0xFF,
+                    EVENT_NAME.len() as u8,
+                    EVENT_CYCLE_MS,
+                    6,
+                    0,
+                ])
+            }
+            // FREE_DAQ
+            0xD6 => {
+                self.daq_lists.clear();
+                self.daq_ptr = None;
+                Ok(vec![])
+            }
+            // ALLOC_DAQ
+            0xD5 => {
+                need(4)?;
+                let count = u16::from_le_bytes([packet[2], packet[3]]) as usize;
+                if !self.daq_lists.is_empty() {
+                    return Err(ERR_SEQUENCE);
+                }
+                if count > MAX_DAQ_LISTS {
+                    return Err(ERR_MEMORY_OVERFLOW);
+                }
+                self.daq_lists = (0..count).map(|_| DaqList::default()).collect();
+                Ok(vec![])
+            }
+            // ALLOC_ODT
+            0xD4 => {
+                need(5)?;
+                let count = packet[4] as usize;
+                let list = self.daq_list(packet)?;
+                list.odts = vec![Vec::new(); count];
+                Ok(vec![])
+            }
+            // ALLOC_ODT_ENTRY
+            0xD3 => {
+                need(6)?;
+                let (odt, count) = (packet[4] as usize, packet[5] as usize);
+                let list = self.daq_list(packet)?;
+                *list.odts.get_mut(odt).ok_or(ERR_OUT_OF_RANGE)? = vec![(0, 0); count];
+                Ok(vec![])
+            }
+            _ => Err(ERR_CMD_UNKNOWN),
+        }
+    }
+
+    fn stop_all(&mut self) {
+        for list in &mut self.daq_lists {
+            list.running = false;
+            list.selected = false;
+        }
+    }
+
+    /// Samples the running DAQ lists on one tick of event channel 0.
+    pub fn event(&mut self) -> Vec<Vec<u8>> {
+        self.ticks = self.ticks.wrapping_add(1);
+        let timestamp = (self.started.elapsed().as_micros() as u32).to_le_bytes();
+        let mut packets = Vec::new();
+        for list in &self.daq_lists {
+            if !list.running || self.ticks % list.prescaler.max(1) as u32 != 0 {
+                continue;
+            }
+            for (odt, entries) in list.odts.iter().enumerate() {
+                let mut packet = vec![list.first_pid + odt as u8];
+                if odt == 0 && list.timestamp {
+                    packet.extend_from_slice(&timestamp);
+                }
+                for (address, size) in entries {
+                    let offset = (address - self.ram_base) as usize;
+                    packet.extend_from_slice(&self.ram[offset..offset + *size as usize]);
+                }
+                packets.push(packet);
+            }
+        }
+        packets
+    }
+}
+// ----------------------------------------------------------------------------
+enum Server {
+    Tcp(TcpListener, Option<TcpStream>),
+    Udp(UdpSocket, Option<SocketAddr>),
+}
+
+/// XCP on Ethernet server side, one master at a time.
+pub struct Transport {
+    server: Server,
+    received: Vec<u8>,
+    ctr: u16,
+}
+
+impl Transport {
+    pub fn bind(tcp: bool, address: SocketAddr) -> std::io::Result<Self> {
+        let server = if tcp {
+            let listener = TcpListener::bind(address)?;
+            listener.set_nonblocking(true)?;
+            Server::Tcp(listener, None)
+        } else {
+            let socket = UdpSocket::bind(address)?;
+            socket.set_read_timeout(Some(Duration::from_millis(1)))?;
+            Server::Udp(socket, None)
+        };
+        Ok(Self {
+            server,
+            received: Vec::new(),
+            ctr: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        match &self.server {
+            Server::Tcp(listener, _) => listener.local_addr(),
+            Server::Udp(socket, _) => socket.local_addr(),
+        }
+    }
+
+    fn receive(&mut self) -> std::io::Result<()> {
+        let mut buff = [0u8; 65536];
+        match &mut self.server {
+            Server::Tcp(listener, client) => {
+                if client.is_none() {
+                    if let Ok((stream, peer)) = listener.accept() {
+                        println!("master connected from {peer}");
+                        stream.set_read_timeout(Some(Duration::from_millis(1)))?;
+                        let _res = stream.set_nodelay(true);
+                        *client = Some(stream);
+                        self.received.clear();
+                    } else {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    return Ok(());
+                }
+                let stream = client.as_mut().unwrap();
+                match stream.read(&mut buff) {
+                    Ok(0) => {
+                        println!("master disconnected");
+                        *client = None;
+                    }
+                    Ok(count) => self.received.extend_from_slice(&buff[..count]),
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                    Err(_) => *client = None,
+                }
+            }
+            Server::Udp(socket, peer) => match socket.recv_from(&mut buff) {
+                Ok((count, from)) => {
+                    if *peer != Some(from) {
+                        println!("master connected from {from}");
+                        *peer = Some(from);
+                        self.received.clear();
+                    }
+                    self.received.extend_from_slice(&buff[..count]);
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e),
+            },
+        }
+        Ok(())
+    }
+
+    fn take_packet(&mut self) -> Option<Vec<u8>> {
+        let len = u16::from_le_bytes([*self.received.first()?, *self.received.get(1)?]) as usize;
+        if self.received.len() < 4 + len {
+            return None;
+        }
+        Some(self.received.drain(..4 + len).skip(4).collect())
+    }
+
+    fn send(&mut self, packets: &[Vec<u8>]) {
+        if packets.is_empty() {
+            return;
+        }
+        let mut frame = Vec::new();
+        for packet in packets {
+            frame.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+            frame.extend_from_slice(&self.ctr.to_le_bytes());
+            frame.extend_from_slice(packet);
+            self.ctr = self.ctr.wrapping_add(1);
+        }
+        match &mut self.server {
+            Server::Tcp(_, client) => {
+                if let Some(stream) = client {
+                    if stream.write_all(&frame).is_err() {
+                        *client = None;
+                    }
+                }
+            }
+            Server::Udp(socket, Some(peer)) => {
+                let _res = socket.send_to(&frame, *peer);
+            }
+            Server::Udp(_, None) => {}
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+/// Answers the master and samples the DAQ lists every millisecond until `stop`
+/// returns true. `tick` runs before every event, e.g. to animate the RAM.
+pub fn serve(
+    slave: &mut Slave,
+    transport: &mut Transport,
+    mut tick: impl FnMut(&mut Slave),
+    stop: impl Fn() -> bool,
+) -> std::io::Result<()> {
+    let mut next_tick = Instant::now();
+    while !stop() {
+        transport.receive()?;
+        while let Some(packet) = transport.take_packet() {
+            let reply = slave.command(&packet);
+            transport.send(&[reply]);
+        }
+
+        // 1msごとのイベント
+        while Instant::now() >= next_tick {
+            next_tick += Duration::from_millis(EVENT_CYCLE_MS as u64);
+            tick(slave);
+            let packets = slave.event();
+            transport.send(&packets);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,655 @@
+use std::collections::BTreeMap;
+
+use super::elf_parser::VariableInfo;
+use super::literal_parser::ScalarType;
+
+// ----------------------------------------------------------------------------
+/// Linear scaling of a watch entry, `physical = raw * factor + offset`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Conversion {
+    pub factor: f64,
+    pub offset: f64,
+    pub unit: String,
+    /// Exported as a CHARACTERISTIC (calibration value) instead of a MEASUREMENT.
+    pub calibration: bool,
+}
+
+impl Default for Conversion {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            offset: 0.0,
+            unit: String::new(),
+            calibration: false,
+        }
+    }
+}
+
+impl Conversion {
+    pub fn is_identity(&self) -> bool {
+        self.factor == 1.0 && self.offset == 0.0
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.factor == 0.0 || !self.factor.is_finite() || !self.offset.is_finite() {
+            return Err("the factor must be a number other than 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// Physical value of a decoded raw value; text that is not a number is kept.
+    pub fn physical(&self, raw: &str) -> String {
+        if self.is_identity() {
+            return raw.to_string();
+        }
+        match raw.trim().parse::<f64>() {
+            Ok(value) => format!("{}", value * self.factor + self.offset),
+            Err(_) => raw.to_string(),
+        }
+    }
+
+    /// Raw literal to write for a physical value typed by the user.
+    pub fn raw(&self, physical: &str, c_type: &str, size: usize) -> Result<String, String> {
+        if self.is_identity() {
+            return Ok(physical.to_string());
+        }
+        self.check()?;
+        let value = physical
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("{} is not a number", physical.trim()))?;
+        let raw = (value - self.offset) / self.factor;
+        match ScalarType::from_c_type(c_type, size) {
+            Some(ScalarType::F32) | Some(ScalarType::F64) => Ok(format!("{raw:?}")),
+            // 整数型は最も近い値に丸める (範囲外はparse_literalがエラーにする)
+            _ => Ok(format!("{}", raw.round() as i128)),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+// ASAP2のデータ型とCの型
+const DATATYPES: [(ScalarType, &str, &str); 11] = [
+    (ScalarType::Bool, "UBYTE", "unsigned char"),
+    (ScalarType::I8, "SBYTE", "signed char"),
+    (ScalarType::U8, "UBYTE", "unsigned char"),
+    (ScalarType::I16, "SWORD", "short int"),
+    (ScalarType::U16, "UWORD", "short unsigned int"),
+    (ScalarType::I32, "SLONG", "int"),
+    (ScalarType::U32, "ULONG", "unsigned int"),
+    (ScalarType::I64, "A_INT64", "long long int"),
+    (ScalarType::U64, "A_UINT64", "long long unsigned int"),
+    (ScalarType::F32, "FLOAT32_IEEE", "float"),
+    (ScalarType::F64, "FLOAT64_IEEE", "double"),
+];
+
+fn datatype_name(scalar: ScalarType) -> &'static str {
+    DATATYPES
+        .iter()
+        .find(|(s, _, _)| *s == scalar)
+        .map_or("UBYTE", |(_, name, _)| name)
+}
+
+fn datatype_c_type(name: &str) -> Option<(&'static str, usize)> {
+    let (scalar, _, c_type) = DATATYPES.iter().skip(1).find(|(_, n, _)| *n == name)?;
+    Some((c_type, scalar.size()))
+}
+
+// 生値の範囲 (浮動小数点数はASAP2の慣例でfloatの範囲)
+fn raw_limits(scalar: ScalarType) -> (f64, f64) {
+    match scalar {
+        ScalarType::Bool => (0.0, 1.0),
+        ScalarType::I8 => (i8::MIN as f64, i8::MAX as f64),
+        ScalarType::U8 => (0.0, u8::MAX as f64),
+        ScalarType::I16 => (i16::MIN as f64, i16::MAX as f64),
+        ScalarType::U16 => (0.0, u16::MAX as f64),
+        ScalarType::I32 => (i32::MIN as f64, i32::MAX as f64),
+        ScalarType::U32 => (0.0, u32::MAX as f64),
+        ScalarType::I64 => (i64::MIN as f64, i64::MAX as f64),
+        ScalarType::U64 => (0.0, u64::MAX as f64),
+        ScalarType::F32 | ScalarType::F64 => (f32::MIN as f64, f32::MAX as f64),
+    }
+}
+
+/// Symbol name usable as an ASAP2 identifier (`a.b[2]` is fine, `::` is not).
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "_.[]".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes an A2L file with a MEASUREMENT, or a CHARACTERISTIC for calibration
+/// values, for every scalar symbol and a COMPU_METHOD for every scaled one.
+/// Returns the text and the names of the symbols that were left out.
+pub fn export_a2l(
+    project: &str,
+    symbols: &[VariableInfo],
+    conversions: &BTreeMap<String, Conversion>,
+) -> (String, Vec<String>) {
+    let project = match identifier(project) {
+        name if name.is_empty() => "VisuModiRam".to_string(),
+        name => name,
+    };
+    let mut skipped = Vec::new();
+    let mut layouts = Vec::new();
+    let mut methods = String::new();
+    let mut objects = String::new();
+
+    for symbol in symbols {
+        let Some(scalar) = ScalarType::from_c_type(&symbol.types, symbol.size) else {
+            skipped.push(symbol.name.clone());
+            continue;
+        };
+        let name = identifier(&symbol.name);
+        let datatype = datatype_name(scalar);
+        let default = Conversion::default();
+        let conversion = conversions.get(&symbol.name).unwrap_or(&default);
+
+        let method = if conversion.is_identity() && conversion.unit.is_empty() {
+            "NO_COMPU_METHOD".to_string()
+        } else {
+            let method = format!("CM_{name}");
+            let (kind, coeffs) = if conversion.is_identity() {
+                ("IDENTICAL", String::new())
+            } else {
+                (
+                    "LINEAR",
+                    format!(
+                        "\n      COEFFS_LINEAR {:?} {:?}",
+                        conversion.factor, conversion.offset
+                    ),
+                )
+            };
+            methods += &format!(
+                "    /begin COMPU_METHOD {method} \"\"\n      {kind} \"%12.6\" {}{coeffs}\n    /end COMPU_METHOD\n",
+                quoted(&conversion.unit)
+            );
+            method
+        };
+
+        let (low, high) = raw_limits(scalar);
+        let (low, high) = {
+            let a = low * conversion.factor + conversion.offset;
+            let b = high * conversion.factor + conversion.offset;
+            (a.min(b), a.max(b))
+        };
+        let description = quoted(&symbol.types);
+
+        if conversion.calibration {
+            if !layouts.contains(&datatype) {
+                layouts.push(datatype);
+            }
+            objects += &format!(
+                "    /begin CHARACTERISTIC {name} {description}\n      VALUE 0x{:08X} RL_{datatype} 0 {method} {low:?} {high:?}\n    /end CHARACTERISTIC\n",
+                symbol.address
+            );
+        } else {
+            objects += &format!(
+                "    /begin MEASUREMENT {name} {description}\n      {datatype} {method} 1 0 {low:?} {high:?}\n      ECU_ADDRESS 0x{:08X}\n    /end MEASUREMENT\n",
+                symbol.address
+            );
+        }
+    }
+
+    let mut text = String::new();
+    text += "ASAP2_VERSION 1 71\n";
+    text += &format!("/begin PROJECT {project} \"\"\n");
+    text += &format!("  /begin MODULE {project} \"generated by VisuModiRam\"\n");
+    text += "    /begin MOD_COMMON \"\"\n      BYTE_ORDER MSB_LAST\n      ALIGNMENT_BYTE 1\n    /end MOD_COMMON\n";
+    for datatype in layouts {
+        text += &format!(
+            "    /begin RECORD_LAYOUT RL_{datatype}\n      FNC_VALUES 1 {datatype} COLUMN_DIR DIRECT\n    /end RECORD_LAYOUT\n"
+        );
+    }
+    text += &methods;
+    text += &objects;
+    text += "  /end MODULE\n";
+    text += "/end PROJECT\n";
+    (text, skipped)
+}
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+}
+
+impl Token {
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Word(word) | Self::Text(word) => word,
+        }
+    }
+}
+
+// コメント (/* */ と //) を除いて単語と文字列に分ける
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut last = ' ';
+            loop {
+                match chars.next() {
+                    Some('/') if last == '*' => break,
+                    Some(c) => last = c,
+                    None => return Err("unterminated comment".to_string()),
+                }
+            }
+            continue;
+        }
+        if c == '/' && chars.peek() == Some(&'/') {
+            for c in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+            continue;
+        }
+        if c == '"' {
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('\\') => text.extend(chars.next()),
+                    // ""は文字列中の"
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        text.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => text.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(Token::Text(text));
+            continue;
+        }
+        let mut word = c.to_string();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '"' {
+                break;
+            }
+            word.push(c);
+            chars.next();
+        }
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|value| value as f64),
+        None => text.parse().ok(),
+    }
+}
+
+/// `/begin KIND ... /end KIND` with its tokens; nested blocks stay in `body`.
+struct Block<'a> {
+    kind: &'a str,
+    body: &'a [Token],
+}
+
+impl<'a> Block<'a> {
+    // 入れ子のブロックを除いた、キーワードの次の値
+    fn keyword(&self, keyword: &str, index: usize) -> Option<&'a str> {
+        let mut depth = 0;
+        for (position, token) in self.body.iter().enumerate() {
+            match token {
+                Token::Word(word) if word == "/begin" => depth += 1,
+                Token::Word(word) if word == "/end" => depth -= 1,
+                Token::Word(word) if depth == 0 && word == keyword => {
+                    return self.body.get(position + 1 + index).map(Token::as_str);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn field(&self, index: usize) -> Option<&'a str> {
+        self.body.get(index).map(Token::as_str)
+    }
+}
+
+fn blocks(tokens: &[Token]) -> Result<Vec<Block<'_>>, String> {
+    let mut blocks = Vec::new();
+    let mut stack: Vec<(&str, usize)> = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        match &tokens[index] {
+            Token::Word(word) if word == "/begin" => {
+                let kind = tokens
+                    .get(index + 1)
+                    .map(Token::as_str)
+                    .ok_or("/begin at the end of the file")?;
+                stack.push((kind, index + 2));
+                index += 2;
+            }
+            Token::Word(word) if word == "/end" => {
+                let kind = tokens.get(index + 1).map(Token::as_str).unwrap_or("");
+                let (begin, start) = stack.pop().ok_or(format!("/end {kind} without /begin"))?;
+                if begin != kind {
+                    return Err(format!("/begin {begin} is closed by /end {kind}"));
+                }
+                blocks.push(Block {
+                    kind: begin,
+                    body: &tokens[start..index],
+                });
+                index += 2;
+            }
+            _ => index += 1,
+        }
+    }
+    if let Some((kind, _)) = stack.last() {
+        return Err(format!("/begin {kind} is not closed"));
+    }
+    Ok(blocks)
+}
+
+/// Watch entries read from an A2L file.
+#[derive(Default, Clone, Debug)]
+pub struct A2lImport {
+    pub entries: Vec<(VariableInfo, Conversion)>,
+    /// Objects that were skipped or only partly understood.
+    pub warnings: Vec<String>,
+}
+
+/// Reads the scalar MEASUREMENTs and VALUE CHARACTERISTICs of an A2L file with their
+/// COMPU_METHODs. Linear methods (IDENTICAL, LINEAR, linear RAT_FUNC) are converted,
+/// the others are imported without scaling.
+pub fn import_a2l(text: &str) -> Result<A2lImport, String> {
+    let tokens = tokenize(text)?;
+    let blocks = blocks(&tokens)?;
+    let mut import = A2lImport::default();
+
+    let mut methods: BTreeMap<&str, Result<Conversion, String>> = BTreeMap::new();
+    let mut layouts: BTreeMap<&str, &str> = BTreeMap::new();
+    for block in &blocks {
+        match block.kind {
+            "COMPU_METHOD" => {
+                let (Some(name), Some(kind)) = (block.field(0), block.field(2)) else {
+                    continue;
+                };
+                let unit = block.field(4).unwrap_or("").to_string();
+                let coeffs = |keyword: &str, count: usize| -> Option<Vec<f64>> {
+                    (0..count)
+                        .map(|i| block.keyword(keyword, i).and_then(parse_number))
+                        .collect()
+                };
+                let conversion = match kind {
+                    "IDENTICAL" => Ok((1.0, 0.0)),
+                    "LINEAR" => coeffs("COEFFS_LINEAR", 2)
+                        .map(|c| (c[0], c[1]))
+                        .ok_or_else(|| "COEFFS_LINEAR is missing".to_string()),
+                    // 生値 = (a x^2 + b x + c) / (d x^2 + e x + f), xは物理値
+                    "RAT_FUNC" => match coeffs("COEFFS", 6) {
+                        Some(c) if c[0] == 0.0 && c[3] == 0.0 && c[4] == 0.0 && c[1] != 0.0 => {
+                            Ok((c[5] / c[1], -c[2] / c[1]))
+                        }
+                        Some(_) => Err("RAT_FUNC is not linear".to_string()),
+                        None => Err("COEFFS is missing".to_string()),
+                    },
+                    kind => Err(format!("{kind} is not supported")),
+                };
+                methods.insert(
+                    name,
+                    conversion.map(|(factor, offset)| Conversion {
+                        factor,
+                        offset,
+                        unit,
+                        calibration: false,
+                    }),
+                );
+            }
+            "RECORD_LAYOUT" => {
+                if let (Some(name), Some(datatype)) =
+                    (block.field(0), block.keyword("FNC_VALUES", 1))
+                {
+                    layouts.insert(name, datatype);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for block in &blocks {
+        let (name, datatype, method, address, calibration) = match block.kind {
+            "MEASUREMENT" => {
+                if block.keyword("ARRAY_SIZE", 0).is_some()
+                    || block.keyword("MATRIX_DIM", 0).is_some()
+                {
+                    import.warnings.push(format!(
+                        "{}: arrays are not supported",
+                        block.field(0).unwrap_or("")
+                    ));
+                    continue;
+                }
+                (
+                    block.field(0),
+                    block.field(2),
+                    block.field(3),
+                    block.keyword("ECU_ADDRESS", 0),
+                    false,
+                )
+            }
+            "CHARACTERISTIC" => {
+                if block.field(2) != Some("VALUE") {
+                    import.warnings.push(format!(
+                        "{}: {} characteristics are not supported",
+                        block.field(0).unwrap_or(""),
+                        block.field(2).unwrap_or("")
+                    ));
+                    continue;
+                }
+                (
+                    block.field(0),
+                    block
+                        .field(4)
+                        .and_then(|layout| layouts.get(layout).copied()),
+                    block.field(6),
+                    block.field(3),
+                    true,
+                )
+            }
+            _ => continue,
+        };
+        let Some(name) = name else {
+            continue;
+        };
+        let Some((c_type, size)) = datatype.and_then(datatype_c_type) else {
+            import.warnings.push(format!(
+                "{name}: unknown data type {}",
+                datatype.unwrap_or("")
+            ));
+            continue;
+        };
+        let Some(address) = address.and_then(parse_number) else {
+            import.warnings.push(format!("{name}: no address"));
+            continue;
+        };
+
+        let mut conversion = match method {
+            None | Some("NO_COMPU_METHOD") => Conversion::default(),
+            Some(method) => match methods.get(method) {
+                Some(Ok(conversion)) => conversion.clone(),
+                Some(Err(e)) => {
+                    import
+                        .warnings
+                        .push(format!("{name}: {method} {e}, imported unscaled"));
+                    Conversion::default()
+                }
+                None => {
+                    import
+                        .warnings
+                        .push(format!("{name}: {method} is not found, imported unscaled"));
+                    Conversion::default()
+                }
+            },
+        };
+        if let Some(unit) = block.keyword("PHYS_UNIT", 0) {
+            conversion.unit = unit.to_string();
+        }
+        conversion.calibration = calibration;
+
+        import.entries.push((
+            VariableInfo {
+                name: name.to_string(),
+                types: c_type.to_string(),
+                address: address as u64,
+                size,
+            },
+            conversion,
+        ));
+    }
+    Ok(import)
+}
+
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, types: &str, address: u64, size: usize) -> VariableInfo {
+        VariableInfo {
+            name: name.to_string(),
+            types: types.to_string(),
+            address,
+            size,
+        }
+    }
+
+    fn scaled(factor: f64, offset: f64, unit: &str, calibration: bool) -> Conversion {
+        Conversion {
+            factor,
+            offset,
+            unit: unit.to_string(),
+            calibration,
+        }
+    }
+
+    #[test]
+    fn exported_file_imports_back() {
+        let symbols = vec![
+            variable("speed", "volatile short unsigned int", 0x2000_0010, 2),
+            variable("gain", "float", 0x2000_0020, 4),
+            variable("temp", "signed char", 0x2000_0030, 1),
+            variable("state.mode", "unsigned char", 0x2000_0031, 1),
+            variable("total", "long long int", 0x2000_0038, 8),
+            variable("buf", "struct foo", 0x2000_0040, 8),
+        ];
+        let mut conversions = BTreeMap::new();
+        conversions.insert("speed".to_string(), scaled(0.1, -40.0, "km/h", false));
+        conversions.insert("gain".to_string(), scaled(1.0, 0.0, "\"x\"", true));
+        conversions.insert("temp".to_string(), scaled(0.5, 0.0, "degC", true));
+
+        let (text, skipped) = export_a2l("demo", &symbols, &conversions);
+        assert_eq!(skipped, vec!["buf".to_string()]);
+        assert!(text.contains("/begin MEASUREMENT speed"));
+        assert!(text.contains("/begin CHARACTERISTIC gain"));
+        assert!(text.contains("COEFFS_LINEAR 0.1 -40.0"));
+
+        let import = import_a2l(&text).unwrap();
+        assert!(import.warnings.is_empty(), "{:?}", import.warnings);
+        let expected = vec![
+            (
+                variable("speed", "short unsigned int", 0x2000_0010, 2),
+                scaled(0.1, -40.0, "km/h", false),
+            ),
+            (
+                variable("gain", "float", 0x2000_0020, 4),
+                scaled(1.0, 0.0, "\"x\"", true),
+            ),
+            (
+                variable("temp", "signed char", 0x2000_0030, 1),
+                scaled(0.5, 0.0, "degC", true),
+            ),
+            (
+                variable("state.mode", "unsigned char", 0x2000_0031, 1),
+                Conversion::default(),
+            ),
+            (
+                variable("total", "long long int", 0x2000_0038, 8),
+                Conversion::default(),
+            ),
+        ];
+        assert_eq!(import.entries, expected);
+    }
+
+    #[test]
+    fn imports_linear_rat_func_and_warns_about_the_rest() {
+        let text = r#"/* header */ ASAP2_VERSION 1 61
+            /begin PROJECT P "" /begin MODULE M ""
+            /begin COMPU_METHOD CM_RAT "rat" RAT_FUNC "%6.2" "V" COEFFS 0 100 -50 0 0 1 /end COMPU_METHOD
+            /begin COMPU_METHOD CM_TAB "" TAB_VERB "%4.0" "" COMPU_TAB_REF T /end COMPU_METHOD
+            /begin MEASUREMENT volt "Voltage" UWORD CM_RAT 1 100 0 100 // comment
+              ECU_ADDRESS 0x20001000
+              /begin IF_DATA XCP /begin DAQ_EVENT FIXED_EVENT_LIST EVENT 0x0 /end DAQ_EVENT /end IF_DATA
+            /end MEASUREMENT
+            /begin MEASUREMENT st "" UBYTE CM_TAB 1 100 0 3 ECU_ADDRESS 0x20001002 /end MEASUREMENT
+            /begin MEASUREMENT arr "" UBYTE NO_COMPU_METHOD 1 100 0 3 ECU_ADDRESS 0x20001003 MATRIX_DIM 4 /end MEASUREMENT
+            /begin CHARACTERISTIC map "" MAP 0x20002000 RL 0 NO_COMPU_METHOD 0 1 /end CHARACTERISTIC
+            /end MODULE /end PROJECT"#;
+        let import = import_a2l(text).unwrap();
+        assert_eq!(
+            import.entries,
+            vec![
+                (
+                    variable("volt", "short unsigned int", 0x2000_1000, 2),
+                    scaled(0.01, 0.5, "V", false),
+                ),
+                (
+                    variable("st", "unsigned char", 0x2000_1002, 1),
+                    Conversion::default(),
+                ),
+            ]
+        );
+        assert_eq!(
+            import.warnings,
+            vec![
+                "st: CM_TAB TAB_VERB is not supported, imported unscaled",
+                "arr: arrays are not supported",
+                "map: MAP characteristics are not supported",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_blocks() {
+        assert_eq!(
+            import_a2l("/begin PROJECT x /end MODULE").err().unwrap(),
+            "/begin PROJECT is closed by /end MODULE"
+        );
+        assert!(import_a2l("/begin PROJECT x").is_err());
+    }
+
+    #[test]
+    fn converts_between_raw_and_physical() {
+        let speed = scaled(0.1, -40.0, "km/h", false);
+        assert_eq!(speed.physical("500"), "10");
+        assert_eq!(speed.physical("?"), "?");
+        assert_eq!(speed.raw("10", "short unsigned int", 2).unwrap(), "500");
+        assert_eq!(speed.raw("10.04", "short unsigned int", 2).unwrap(), "500");
+        assert!(speed.raw("fast", "short unsigned int", 2).is_err());
+        assert_eq!(
+            scaled(0.5, 0.0, "", false).raw("3.3", "float", 4).unwrap(),
+            "6.6"
+        );
+        assert!(scaled(0.0, 0.0, "", false).raw("1", "int", 4).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use probe_rs::{Core, MemoryInterface, RegisterId};
+
+use super::cfi_unwinder::{
+    ArmRegisters, CfiTable, UnwoundFrame, VfpRegisters, ARM_REGISTER_COUNT, VFP_REGISTER_COUNT,
+};
+use super::dwarf_locals::{DwarfLocals, FrameVariable};
+use super::symbol_resolver::{SourceInfo, SymbolResolver};
+
+// DCRSRのREGSELと同じ番号
+const REGISTER_PSP: RegisterId = RegisterId(0b1_0010);
+const REGISTER_S0: u16 = 0b100_0000;
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    pub pc: u64,
+    pub sp: u64,
+    pub location: Option<SourceInfo>,
+    /// This frame was interrupted by an exception.
+    pub exception: bool,
+    pub variables: Vec<FrameVariable>,
+}
+
+impl StackFrame {
+    pub fn function(&self) -> String {
+        match &self.location {
+            Some(location) => location.function.clone(),
+            None => format!("0x{:08X}", self.pc),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+pub struct CallStack {}
+
+impl CallStack {
+    /// Unwinds the halted core with `.debug_frame` and evaluates the locals of
+    /// every frame. Without CFI only the innermost frame is returned.
+    pub fn capture(
+        core: &mut Core,
+        cfi: Option<&CfiTable>,
+        resolver: Option<&SymbolResolver>,
+        locals: Option<&DwarfLocals>,
+    ) -> Result<Vec<StackFrame>, probe_rs::Error> {
+        if !core.core_halted()? {
+            return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                "core is running, halt it to inspect the call stack"
+            )));
+        }
+
+        let (registers, psp) = read_registers(core);
+        let (Some(pc), Some(sp)) = (registers[15], registers[13]) else {
+            return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                "can not read PC and SP"
+            )));
+        };
+        let vfp = read_vfp_registers(core);
+        let unwound: Vec<UnwoundFrame> = match cfi {
+            Some(cfi) => cfi.unwind(registers, vfp, psp, |address| {
+                core.read_word_32(address).ok()
+            }),
+            None => vec![UnwoundFrame {
+                pc: pc as u64,
+                sp: sp as u64,
+                registers,
+                vfp,
+                cfa: None,
+                exception: false,
+            }],
+        };
+
+        let mut frames = Vec::new();
+        for (depth, frame) in unwound.iter().enumerate() {
+            let precise = depth == 0 || frame.exception;
+            // 呼び出し元は呼び出し命令の行を表示する
+            let lookup = if precise {
+                frame.pc
+            } else {
+                frame.pc.saturating_sub(1)
+            };
+            let variables = match locals {
+                Some(locals) => locals.variables(frame, precise, |address, len| {
+                    let mut buff = vec![0u8; len];
+                    core.read_8(address, &mut buff).ok().map(|_| buff)
+                }),
+                None => Vec::new(),
+            };
+            frames.push(StackFrame {
+                pc: frame.pc,
+                sp: frame.sp,
+                location: resolver.and_then(|resolver| resolver.resolve(lookup)),
+                exception: frame.exception,
+                variables,
+            });
+        }
+        Ok(frames)
+    }
+}
+
+/// r0..r15 of the halted core, and PSP for unwinding thread mode exception frames.
+pub(super) fn read_registers(core: &mut Core) -> (ArmRegisters, Option<u32>) {
+    let mut registers = [None; ARM_REGISTER_COUNT];
+    for (i, register) in registers.iter_mut().enumerate() {
+        let value: Result<u32, _> = core.read_core_reg(RegisterId(i as u16));
+        *register = value.ok();
+    }
+    let psp: Option<u32> = core.read_core_reg(REGISTER_PSP).ok();
+    (registers, psp)
+}
+
+/// s0..s31 of the halted core; all None on cores without an FPU.
+fn read_vfp_registers(core: &mut Core) -> VfpRegisters {
+    let mut registers = [None; VFP_REGISTER_COUNT];
+    for (i, register) in registers.iter_mut().enumerate() {
+        let value: Result<u32, _> = core.read_core_reg(RegisterId(REGISTER_S0 + i as u16));
+        *register = value.ok();
+    }
+    registers
+}
@@ -0,0 +1,449 @@
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EndianArcSlice, FrameDescriptionEntry, LittleEndian,
+    RegisterRule, UnwindContext, UnwindSection,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::elf_symbols::load_section;
+
+type CfiReader = EndianArcSlice<LittleEndian>;
+
+pub const ARM_REGISTER_COUNT: usize = 16;
+pub const VFP_REGISTER_COUNT: usize = 32;
+// DWARFのARMレジスタ番号 64..95 は s0..s31
+const DWARF_S0: u16 = 64;
+// s0-s15 は呼び出し規約で保存されない
+const VFP_CALLER_SAVED: usize = 16;
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+const UNWIND_MAX_DEPTH: usize = 32;
+// EXC_RETURN : 例外から戻るときにLRへ入る特殊な値
+const EXC_RETURN_MASK: u32 = 0xFFFF_FF00;
+const EXC_RETURN_PSP: u32 = 1 << 2;
+const EXC_RETURN_NO_FPU: u32 = 1 << 4;
+const XPSR_STACK_ALIGN: u32 = 1 << 9;
+// r0-r3, r12, lr, pc, xPSR
+const BASIC_FRAME_SIZE: u32 = 0x20;
+// 上記 + s0-s15, FPSCR, 予約の1ワード
+const EXTENDED_FRAME_SIZE: u32 = 0x68;
+
+pub type ArmRegisters = [Option<u32>; ARM_REGISTER_COUNT];
+pub type VfpRegisters = [Option<u32>; VFP_REGISTER_COUNT];
+
+pub fn is_exc_return(value: u32) -> bool {
+    value & EXC_RETURN_MASK == EXC_RETURN_MASK
+}
+
+/// Bytes the core pushed on exception entry: the basic or, when EXC_RETURN bit 4
+/// is clear, the FP extended frame, plus the alignment word flagged in xPSR bit 9.
+pub fn exception_frame_size(exc_return: u32, stacked_xpsr: u32) -> u32 {
+    let size = if exc_return & EXC_RETURN_NO_FPU != 0 {
+        BASIC_FRAME_SIZE
+    } else {
+        EXTENDED_FRAME_SIZE
+    };
+    if stacked_xpsr & XPSR_STACK_ALIGN != 0 {
+        size + 4
+    } else {
+        size
+    }
+}
+// ----------------------------------------------------------------------------
+/// One frame of an unwound call stack; `registers` are the values in that frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnwoundFrame {
+    pub pc: u64,
+    pub sp: u64,
+    pub registers: ArmRegisters,
+    /// s0..s31; None without an FPU or where the value is not recoverable.
+    pub vfp: VfpRegisters,
+    /// Canonical frame address (the caller's SP at the call); None for the outermost frame.
+    pub cfa: Option<u64>,
+    /// This frame was interrupted by an exception; its registers come from the stacked frame.
+    pub exception: bool,
+}
+
+impl UnwoundFrame {
+    /// Value of the DWARF register `number` (r0..r15, s0..s31) in this frame.
+    pub fn register(&self, number: u16) -> Option<u32> {
+        dwarf_register(&self.registers, &self.vfp, number)
+    }
+}
+
+fn dwarf_register(registers: &ArmRegisters, vfp: &VfpRegisters, number: u16) -> Option<u32> {
+    match number.checked_sub(DWARF_S0) {
+        Some(index) => vfp.get(index as usize).copied().flatten(),
+        None => registers.get(number as usize).copied().flatten(),
+    }
+}
+// ----------------------------------------------------------------------------
+/// Call frame information of `.debug_frame`, for unwinding ARM (Cortex-M) stacks.
+pub struct CfiTable {
+    debug_frame: DebugFrame<CfiReader>,
+    bases: BaseAddresses,
+    /// Sorted by start address.
+    fdes: Vec<FrameDescriptionEntry<CfiReader>>,
+}
+
+impl CfiTable {
+    pub fn load(elf_path: &PathBuf) -> Option<Self> {
+        let (_, data) = load_section(elf_path, ".debug_frame")?;
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut debug_frame = DebugFrame::from(CfiReader::new(Arc::from(data), LittleEndian));
+        // バージョン4未満のCIEはアドレスサイズを持たない
+        debug_frame.set_address_size(4);
+        let bases = BaseAddresses::default();
+
+        let mut fdes = Vec::new();
+        let mut entries = debug_frame.entries(&bases);
+        while let Ok(Some(entry)) = entries.next() {
+            // 解釈できないCIEを参照するFDEは飛ばす
+            if let gimli::CieOrFde::Fde(partial) = entry {
+                if let Ok(fde) = partial.parse(DebugFrame::cie_from_offset) {
+                    fdes.push(fde);
+                }
+            }
+        }
+        fdes.sort_by_key(|fde| fde.initial_address());
+        Some(Self {
+            debug_frame,
+            bases,
+            fdes,
+        })
+    }
+
+    fn find_fde(&self, pc: u64) -> Option<&FrameDescriptionEntry<CfiReader>> {
+        let index = self.fdes.partition_point(|fde| fde.initial_address() <= pc);
+        let fde = self.fdes.get(index.checked_sub(1)?)?;
+        fde.contains(pc).then_some(fde)
+    }
+
+    /// Registers of the caller of the frame at `pc`, the CFA and the return address
+    /// register. `None` without CFI or with rules that can not be evaluated here.
+    fn step(
+        &self,
+        context: &mut UnwindContext<CfiReader>,
+        pc: u64,
+        regs: &ArmRegisters,
+        vfp: &VfpRegisters,
+        read_word: &mut impl FnMut(u64) -> Option<u32>,
+    ) -> Option<(ArmRegisters, VfpRegisters, u32, usize)> {
+        let fde = self.find_fde(pc)?;
+        let row = fde
+            .unwind_info_for_address(&self.debug_frame, &self.bases, context, pc)
+            .ok()?;
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                let base = regs.get(register.0 as usize).copied().flatten()?;
+                (base as i64 + offset) as u32
+            }
+            // DWARF式のCFAは扱わない
+            CfaRule::Expression(_) => return None,
+        };
+
+        let mut next = *regs;
+        let mut next_vfp = *vfp;
+        for (register, rule) in row.registers() {
+            let number = register.0;
+            let slot = match number.checked_sub(DWARF_S0) {
+                Some(index) => next_vfp.get_mut(index as usize),
+                None => next.get_mut(number as usize),
+            };
+            let Some(slot) = slot else {
+                continue;
+            };
+            *slot = match rule {
+                RegisterRule::SameValue => dwarf_register(regs, vfp, number),
+                RegisterRule::Offset(offset) => read_word((cfa as i64 + offset) as u32 as u64),
+                RegisterRule::ValOffset(offset) => Some((cfa as i64 + offset) as u32),
+                RegisterRule::Register(from) => dwarf_register(regs, vfp, from.0),
+                RegisterRule::Constant(value) => Some(*value as u32),
+                // DWARF式等は復元できない
+                _ => None,
+            };
+        }
+        Some((
+            next,
+            next_vfp,
+            cfa,
+            fde.cie().return_address_register().0 as usize,
+        ))
+    }
+
+    // ------------------------------------------------------------------------
+    /// Unwinds from `registers` (r0..r15 of the halted core) and `vfp` (s0..s31).
+    /// Exception frames are followed through EXC_RETURN, using `psp` for thread
+    /// mode stacks.
+    pub fn unwind(
+        &self,
+        registers: ArmRegisters,
+        vfp: VfpRegisters,
+        psp: Option<u32>,
+        mut read_word: impl FnMut(u64) -> Option<u32>,
+    ) -> Vec<UnwoundFrame> {
+        let mut frames: Vec<UnwoundFrame> = Vec::new();
+        let mut regs = registers;
+        let mut vfp = vfp;
+        let mut exception = false;
+        let mut context = UnwindContext::new();
+
+        for depth in 0..UNWIND_MAX_DEPTH {
+            let (Some(pc), Some(sp)) = (regs[PC], regs[SP]) else {
+                break;
+            };
+            frames.push(UnwoundFrame {
+                pc: pc as u64,
+                sp: sp as u64,
+                registers: regs,
+                vfp,
+                cfa: None,
+                exception,
+            });
+
+            // 戻りアドレスは呼び出し命令の次なので、1つ前で探す
+            let precise = depth == 0 || exception;
+            let lookup = if precise { pc } else { pc.saturating_sub(1) };
+
+            let (mut next, mut next_vfp, cfa, return_register) =
+                match self.step(&mut context, lookup as u64, &regs, &vfp, &mut read_word) {
+                    Some(step) => step,
+                    // CFIの無い関数 (アセンブラのハンドラ等) はスタックを使わないリーフとみなす
+                    None if precise => (regs, vfp, sp, LR),
+                    None => break,
+                };
+            if let Some(frame) = frames.last_mut() {
+                frame.cfa = Some(cfa as u64);
+            }
+            // 呼び出し規約で保存されないレジスタは分からない
+            for register in [0, 1, 2, 3, 12] {
+                next[register] = None;
+            }
+            next_vfp[..VFP_CALLER_SAVED].fill(None);
+            next[SP] = Some(cfa);
+
+            let Some(return_address) = next.get(return_register).copied().flatten() else {
+                break;
+            };
+            exception = is_exc_return(return_address);
+            if exception {
+                let frame_sp = if return_address & EXC_RETURN_PSP != 0 {
+                    match psp {
+                        Some(psp) => psp,
+                        None => break,
+                    }
+                } else {
+                    cfa
+                };
+                let mut stacked = [0u32; 8];
+                for (i, value) in stacked.iter_mut().enumerate() {
+                    match read_word(frame_sp as u64 + i as u64 * 4) {
+                        Some(word) => *value = word,
+                        None => return frames,
+                    }
+                }
+                let [r0, r1, r2, r3, r12, lr, pc, xpsr] = stacked;
+                // r4-r11 はハンドラ側のCFIで復元済みの値をそのまま使う
+                next[0] = Some(r0);
+                next[1] = Some(r1);
+                next[2] = Some(r2);
+                next[3] = Some(r3);
+                next[12] = Some(r12);
+                next[LR] = Some(lr);
+                next[PC] = Some(pc & !1);
+                next[SP] = Some(frame_sp + exception_frame_size(return_address, xpsr));
+                // 拡張フレームには s0-s15 も積まれている
+                if return_address & EXC_RETURN_NO_FPU == 0 {
+                    for (i, value) in next_vfp[..VFP_CALLER_SAVED].iter_mut().enumerate() {
+                        *value =
+                            read_word(frame_sp as u64 + BASIC_FRAME_SIZE as u64 + i as u64 * 4);
+                    }
+                }
+            } else {
+                next[PC] = Some(return_address & !1);
+                // 進まなければ終わり (リセットハンドラ等)
+                if next[PC] == Some(pc) && cfa <= sp {
+                    break;
+                }
+            }
+            if next[PC] == Some(0) {
+                break;
+            }
+            regs = next;
+            vfp = next_vfp;
+        }
+        frames
+    }
+}
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FUNCTION: u64 = 0x0800_0100;
+    const NO_VFP: VfpRegisters = [None; VFP_REGISTER_COUNT];
+
+    // push {r7, lr} と vpush {s16} を持つ関数1つ分の .debug_frame
+    // (code_align 2, data_align -4, 2バイト目以降は CFA = sp + 12,
+    //  lr = [CFA - 4], r7 = [CFA - 8], s16 = [CFA - 12])
+    fn debug_frame() -> Vec<u8> {
+        let mut data = vec![0x0C, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 1, 0, 2, 0x7C, 14];
+        data.extend([0x0C, 13, 0]);
+        data.extend([0x18, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend((FUNCTION as u32).to_le_bytes());
+        data.extend(0x20u32.to_le_bytes());
+        data.extend([0x41, 0x0E, 0x0C, 0x8E, 0x01, 0x87, 0x02, 0x05, 80, 0x03]);
+        data.extend([0x00, 0x00]);
+        data
+    }
+
+    fn registers(pc: u32, sp: u32) -> ArmRegisters {
+        let mut registers = [Some(0); ARM_REGISTER_COUNT];
+        registers[PC] = Some(pc);
+        registers[SP] = Some(sp);
+        registers
+    }
+
+    fn memory(words: &[(u32, u32)]) -> impl FnMut(u64) -> Option<u32> + '_ {
+        |address| {
+            words
+                .iter()
+                .find(|(at, _)| *at as u64 == address)
+                .map(|(_, word)| *word)
+        }
+    }
+
+    // 例外に割り込まれた関数の積まれたフレーム
+    fn stacked_frame(words: &mut Vec<(u32, u32)>, at: u32, xpsr: u32) {
+        let stacked = [1, 2, 3, 4, 12, 0, 0x0800_0301, xpsr];
+        for (i, word) in stacked.iter().enumerate() {
+            words.push((at + i as u32 * 4, *word));
+        }
+    }
+
+    #[test]
+    fn unwinds_to_the_caller() {
+        let cfi = CfiTable::parse(&debug_frame()).unwrap();
+        let words = [
+            (0x2000_0FF4, 0x4120_0000),
+            (0x2000_0FF8, 0x1234),
+            (0x2000_0FFC, 0x0800_0201),
+        ];
+        let mut vfp = NO_VFP;
+        vfp[0] = Some(0x3F80_0000);
+        let frames = cfi.unwind(
+            registers(0x0800_0110, 0x2000_0FF4),
+            vfp,
+            None,
+            memory(&words),
+        );
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].cfa, Some(0x2000_1000));
+        assert_eq!(frames[0].register(64), Some(0x3F80_0000));
+        assert_eq!(frames[1].pc, 0x0800_0200);
+        assert_eq!(frames[1].sp, 0x2000_1000);
+        assert_eq!(frames[1].registers[7], Some(0x1234));
+        assert_eq!(frames[1].registers[0], None);
+        assert_eq!(frames[1].register(80), Some(0x4120_0000));
+        assert_eq!(frames[1].register(64), None);
+        assert!(!frames[1].exception);
+    }
+
+    #[test]
+    fn unwinds_through_an_exception_frame() {
+        let cfi = CfiTable::parse(&debug_frame()).unwrap();
+        // MSPへの戻り、FPU無し、xPSR bit 9 で4バイトの詰め物あり
+        let mut words = vec![
+            (0x2000_0FF4, 0),
+            (0x2000_0FF8, 0),
+            (0x2000_0FFC, 0xFFFF_FFF9),
+        ];
+        stacked_frame(&mut words, 0x2000_1000, 0x0100_0200);
+        let frames = cfi.unwind(
+            registers(0x0800_0104, 0x2000_0FF4),
+            NO_VFP,
+            None,
+            memory(&words),
+        );
+
+        assert_eq!(frames.len(), 2);
+        assert!(frames[1].exception);
+        assert_eq!(frames[1].pc, 0x0800_0300);
+        assert_eq!(frames[1].sp, 0x2000_1024);
+        assert_eq!(frames[1].registers[3], Some(4));
+    }
+
+    #[test]
+    fn callee_saved_registers_survive_an_exception_frame() {
+        let cfi = CfiTable::parse(&debug_frame()).unwrap();
+        let mut words = vec![
+            (0x2000_0FF4, 0),
+            (0x2000_0FF8, 0x7777),
+            (0x2000_0FFC, 0xFFFF_FFF9),
+        ];
+        stacked_frame(&mut words, 0x2000_1000, 0x0100_0000);
+        let mut regs = registers(0x0800_0104, 0x2000_0FF4);
+        for register in 4..=11 {
+            regs[register] = Some(0x400 + register as u32);
+        }
+        let frames = cfi.unwind(regs, NO_VFP, None, memory(&words));
+
+        assert_eq!(frames.len(), 2);
+        assert!(frames[1].exception);
+        for register in [4, 5, 6, 8, 9, 10, 11] {
+            assert_eq!(frames[1].registers[register], Some(0x400 + register as u32));
+        }
+        // r7 はハンドラが積んだ値
+        assert_eq!(frames[1].registers[7], Some(0x7777));
+        assert_eq!(frames[1].registers[12], Some(12));
+        assert_eq!(frames[1].registers[LR], Some(0));
+    }
+
+    #[test]
+    fn unwinds_through_an_extended_frame() {
+        let cfi = CfiTable::parse(&debug_frame()).unwrap();
+        // PSPへの戻り、FPUのレジスタも積まれている
+        let mut words = vec![
+            (0x2000_0FF4, 0),
+            (0x2000_0FF8, 0),
+            (0x2000_0FFC, 0xFFFF_FFED),
+        ];
+        stacked_frame(&mut words, 0x2000_2000, 0x0100_0000);
+        for i in 0..16 {
+            words.push((0x2000_2020 + i * 4, 0x100 + i));
+        }
+        let frames = cfi.unwind(
+            registers(0x0800_0104, 0x2000_0FF4),
+            NO_VFP,
+            Some(0x2000_2000),
+            memory(&words),
+        );
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].sp, 0x2000_2068);
+        assert_eq!(frames[1].register(64), Some(0x100));
+        assert_eq!(frames[1].register(79), Some(0x10F));
+        assert_eq!(frames[1].register(80), Some(0));
+    }
+
+    #[test]
+    fn no_frame_outside_the_cfi() {
+        let cfi = CfiTable::parse(&debug_frame()).unwrap();
+        assert!(cfi.find_fde(FUNCTION - 2).is_none());
+        assert!(cfi.find_fde(FUNCTION + 0x20).is_none());
+        assert!(cfi.find_fde(FUNCTION + 0x1E).is_some());
+    }
+
+    #[test]
+    fn exception_frame_sizes() {
+        assert_eq!(exception_frame_size(0xFFFF_FFFD, 0x0100_0000), 0x20);
+        assert_eq!(exception_frame_size(0xFFFF_FFFD, 0x0100_0200), 0x24);
+        assert_eq!(exception_frame_size(0xFFFF_FFED, 0x0100_0000), 0x68);
+        assert_eq!(exception_frame_size(0xFFFF_FFE9, 0x0100_0200), 0x6C);
+    }
+}
@@ -0,0 +1,385 @@
+use object::elf::{self, FileHeader32, FileHeader64};
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::Endianness;
+use std::path::{Path, PathBuf};
+
+use super::cfi_unwinder::{ArmRegisters, ARM_REGISTER_COUNT};
+use super::flash_options::FlashFormat;
+use super::run_control::{CoreState, TargetStatus};
+use super::target_backend::{backend_error, TargetBackend};
+
+// Intel HEXのレコード種別
+const RECORD_DATA: u8 = 0x00;
+const RECORD_EOF: u8 = 0x01;
+const RECORD_SEGMENT_ADDRESS: u8 = 0x02;
+const RECORD_LINEAR_ADDRESS: u8 = 0x04;
+
+// 32bit ARMのelf_prstatus中のpr_reg (r0..r15, cpsr, orig_r0) の位置
+const PRSTATUS_REG_OFFSET: usize = 72;
+const PRSTATUS_CPSR: usize = 16;
+// ----------------------------------------------------------------------------
+/// One file of a RAM dump.
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DumpFile {
+    pub path: String,
+    /// ELF core file, Intel HEX or raw binary.
+    pub format: FlashFormat,
+    /// Start address of a raw binary; the other formats carry their addresses.
+    pub address: u64,
+}
+
+impl DumpFile {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_string_lossy().to_string(),
+            format: FlashFormat::from_path(path),
+            address: 0x2000_0000,
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        Path::new(&self.path)
+            .file_name()
+            .map_or(self.path.clone(), |name| name.to_string_lossy().to_string())
+    }
+}
+// ----------------------------------------------------------------------------
+/// Memory image of a target saved in the field, browsed with the matching ELF.
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CoreDumpSetting {
+    pub files: Vec<DumpFile>,
+}
+
+impl CoreDumpSetting {
+    pub fn check(&self) -> Result<(), String> {
+        if self.files.is_empty() {
+            return Err("add the dump files".to_string());
+        }
+        Ok(())
+    }
+}
+// ----------------------------------------------------------------------------
+/// Registers saved with the memory (ELF core files only).
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SavedRegisters {
+    /// r0..r15
+    pub registers: ArmRegisters,
+    pub psp: Option<u32>,
+    pub xpsr: Option<u32>,
+}
+
+/// Serves reads from the loaded dump; nothing is ever written.
+pub struct CoreDump {
+    name: String,
+    /// (start address, contents), sorted by address.
+    regions: Vec<(u64, Vec<u8>)>,
+    saved_registers: Option<SavedRegisters>,
+}
+
+impl CoreDump {
+    pub fn load(setting: &CoreDumpSetting) -> Result<Self, probe_rs::Error> {
+        setting.check().map_err(|e| backend_error(&e))?;
+
+        let mut regions: Vec<(u64, Vec<u8>)> = Vec::new();
+        let mut saved_registers = None;
+        for file in &setting.files {
+            let data = std::fs::read(PathBuf::from(&file.path))
+                .map_err(|e| backend_error(&format!("can not read {} : {e}", file.path)))?;
+            let invalid = |what: &str| backend_error(&format!("{} : {what}", file.file_name()));
+            match file.format {
+                FlashFormat::Bin => regions.push((file.address, data)),
+                FlashFormat::Hex => {
+                    let text = String::from_utf8(data).map_err(|_| invalid("not a text file"))?;
+                    regions.extend(parse_intel_hex(&text).map_err(|e| invalid(&e))?);
+                }
+                FlashFormat::Elf => {
+                    let (loaded, registers) =
+                        parse_elf_core(&data).ok_or_else(|| invalid("not an ELF core file"))?;
+                    regions.extend(loaded);
+                    saved_registers = saved_registers.or(registers);
+                }
+            }
+        }
+        regions.retain(|(_, data)| !data.is_empty());
+        regions.sort_by_key(|(address, _)| *address);
+
+        let name = match setting.files.as_slice() {
+            [file] => file.file_name(),
+            files => format!("{} files", files.len()),
+        };
+        Ok(Self {
+            name,
+            regions,
+            saved_registers,
+        })
+    }
+
+    /// Bytes at `address`, also across adjacent regions (e.g. HEX records).
+    fn read(&self, address: u64, data: &mut [u8]) -> bool {
+        let mut done = 0;
+        while done < data.len() {
+            let current = address + done as u64;
+            let Some((start, bytes)) =
+                self.regions.iter().rev().find(|(start, bytes)| {
+                    *start <= current && current < *start + bytes.len() as u64
+                })
+            else {
+                return false;
+            };
+            let offset = (current - start) as usize;
+            let len = (bytes.len() - offset).min(data.len() - done);
+            data[done..done + len].copy_from_slice(&bytes[offset..offset + len]);
+            done += len;
+        }
+        true
+    }
+}
+
+impl TargetBackend for CoreDump {
+    fn describe(&self) -> String {
+        format!("core dump {}", self.name)
+    }
+
+    fn poll_status(&mut self) -> Result<TargetStatus, probe_rs::Error> {
+        Ok(TargetStatus {
+            state: CoreState::Halted,
+            halt_reason: None,
+            pc: self
+                .saved_registers
+                .as_ref()
+                .and_then(|saved| saved.registers[15])
+                .map(|pc| pc as u64),
+            location: None,
+        })
+    }
+
+    fn read_memory(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+        if self.read(address, data) {
+            Ok(())
+        } else {
+            Err(backend_error(&format!(
+                "0x{address:08X} ({} bytes) is not in the dump",
+                data.len()
+            )))
+        }
+    }
+
+    fn write_memory(&mut self, _address: u64, _data: &[u8]) -> Result<(), probe_rs::Error> {
+        Err(backend_error("a core dump is read-only"))
+    }
+
+    fn saved_registers(&self) -> Option<SavedRegisters> {
+        self.saved_registers.clone()
+    }
+}
+// ----------------------------------------------------------------------------
+/// Data records of an Intel HEX file as (address, bytes).
+fn parse_intel_hex(text: &str) -> Result<Vec<(u64, Vec<u8>)>, String> {
+    let mut regions: Vec<(u64, Vec<u8>)> = Vec::new();
+    let mut base: u64 = 0;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |what: &str| format!("line {} : {what}", number + 1);
+        let record = line
+            .strip_prefix(':')
+            .filter(|hex| hex.len() % 2 == 0)
+            .and_then(|hex| {
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                    .collect::<Option<Vec<u8>>>()
+            })
+            .ok_or_else(|| error("not a HEX record"))?;
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(error("wrong record length"));
+        }
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("checksum error"));
+        }
+
+        let offset = u16::from_be_bytes([record[1], record[2]]) as u64;
+        let payload = &record[4..record.len() - 1];
+        match record[3] {
+            RECORD_DATA => {
+                let address = base + offset;
+                // 連続するレコードは1つの領域にまとめる
+                match regions.last_mut() {
+                    Some((start, bytes)) if *start + bytes.len() as u64 == address => {
+                        bytes.extend_from_slice(payload)
+                    }
+                    _ => regions.push((address, payload.to_vec())),
+                }
+            }
+            RECORD_EOF => break,
+            RECORD_SEGMENT_ADDRESS if payload.len() == 2 => {
+                base = (u16::from_be_bytes([payload[0], payload[1]]) as u64) << 4;
+            }
+            RECORD_LINEAR_ADDRESS if payload.len() == 2 => {
+                base = (u16::from_be_bytes([payload[0], payload[1]]) as u64) << 16;
+            }
+            // 開始アドレスのレコードは使わない
+            _ => {}
+        }
+    }
+    Ok(regions)
+}
+
+/// Loaded segments (address, bytes) and the registers of the first thread.
+type ElfCore = (Vec<(u64, Vec<u8>)>, Option<SavedRegisters>);
+
+/// Contents of an ELF core file.
+fn parse_elf_core(data: &[u8]) -> Option<ElfCore> {
+    match object::FileKind::parse(data).ok()? {
+        object::FileKind::Elf32 => parse_core::<FileHeader32<Endianness>>(data),
+        object::FileKind::Elf64 => parse_core::<FileHeader64<Endianness>>(data),
+        _ => None,
+    }
+}
+
+fn parse_core<Elf: FileHeader<Endian = Endianness>>(data: &[u8]) -> Option<ElfCore> {
+    let header = Elf::parse(data).ok()?;
+    let endian = header.endian().ok()?;
+    if header.e_type(endian) != elf::ET_CORE {
+        return None;
+    }
+    // レジスタの配置はCortex-M (32bit ARM) のものしか解釈しない
+    let arm32 = !header.is_type_64() && endian == Endianness::Little;
+
+    let mut regions = Vec::new();
+    let mut registers = None;
+    for segment in header.program_headers(endian, data).ok()? {
+        match segment.p_type(endian) {
+            elf::PT_LOAD => {
+                let Ok(bytes) = segment.data(endian, data) else {
+                    continue;
+                };
+                regions.push((segment.p_vaddr(endian).into(), bytes.to_vec()));
+            }
+            elf::PT_NOTE if arm32 && registers.is_none() => {
+                let Ok(Some(mut notes)) = segment.notes(endian, data) else {
+                    continue;
+                };
+                while let Ok(Some(note)) = notes.next() {
+                    if note.n_type(endian) == elf::NT_PRSTATUS {
+                        registers = Some(prstatus_registers(note.desc()));
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some((regions, registers))
+}
+
+fn prstatus_registers(desc: &[u8]) -> SavedRegisters {
+    let reg = |index: usize| {
+        let offset = PRSTATUS_REG_OFFSET + index * 4;
+        desc.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let mut registers = [None; ARM_REGISTER_COUNT];
+    for (i, register) in registers.iter_mut().enumerate() {
+        *register = reg(i);
+    }
+    SavedRegisters {
+        registers,
+        psp: None,
+        xpsr: reg(PRSTATUS_CPSR),
+    }
+}
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRSTATUS_SIZE: usize = 148;
+
+    // NT_PRSTATUS 1つとRAM 8バイトを持つ32bit ARMのELFコア
+    fn elf_core(e_type: u16) -> Vec<u8> {
+        let note_offset = 52 + 2 * 32;
+        let note_size = 12 + 8 + PRSTATUS_SIZE;
+        let load_offset = note_offset + note_size;
+
+        let mut data = vec![0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend(e_type.to_le_bytes());
+        data.extend(40u16.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(52u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        for half in [52u16, 32, 2, 40, 0, 0] {
+            data.extend(half.to_le_bytes());
+        }
+
+        let program_header = |kind: u32, offset: usize, address: u32, size: usize| {
+            [
+                kind,
+                offset as u32,
+                address,
+                address,
+                size as u32,
+                size as u32,
+                4,
+                4,
+            ]
+        };
+        for word in program_header(elf::PT_NOTE, note_offset, 0, note_size)
+            .into_iter()
+            .chain(program_header(elf::PT_LOAD, load_offset, 0x2000_0000, 8))
+        {
+            data.extend(word.to_le_bytes());
+        }
+
+        data.extend(5u32.to_le_bytes());
+        data.extend((PRSTATUS_SIZE as u32).to_le_bytes());
+        data.extend(elf::NT_PRSTATUS.to_le_bytes());
+        data.extend(b"CORE\0\0\0\0");
+        let mut prstatus = vec![0u8; PRSTATUS_SIZE];
+        for i in 0..ARM_REGISTER_COUNT {
+            let offset = PRSTATUS_REG_OFFSET + i * 4;
+            prstatus[offset..offset + 4].copy_from_slice(&(i as u32 * 0x11).to_le_bytes());
+        }
+        let cpsr = PRSTATUS_REG_OFFSET + PRSTATUS_CPSR * 4;
+        prstatus[cpsr..cpsr + 4].copy_from_slice(&0x0100_0003u32.to_le_bytes());
+        data.extend(prstatus);
+
+        data.extend([1, 2, 3, 4, 5, 6, 7, 8]);
+        data
+    }
+
+    #[test]
+    fn reads_segments_and_registers() {
+        let (regions, registers) = parse_elf_core(&elf_core(elf::ET_CORE)).unwrap();
+        assert_eq!(regions, vec![(0x2000_0000, vec![1, 2, 3, 4, 5, 6, 7, 8])]);
+
+        let registers = registers.unwrap();
+        assert_eq!(registers.registers[0], Some(0));
+        assert_eq!(registers.registers[13], Some(13 * 0x11));
+        assert_eq!(registers.registers[15], Some(15 * 0x11));
+        assert_eq!(registers.xpsr, Some(0x0100_0003));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse_elf_core(&elf_core(elf::ET_EXEC)).is_none());
+        assert!(parse_elf_core(b"not an ELF").is_none());
+    }
+
+    #[test]
+    fn reads_intel_hex() {
+        let text = ":020000042000DA\n:0400000001020304F2\n:0400040005060708DE\n:00000001FF\n";
+        let regions = parse_intel_hex(text).unwrap();
+        assert_eq!(regions, vec![(0x2000_0000, vec![1, 2, 3, 4, 5, 6, 7, 8])]);
+        assert!(parse_intel_hex(":0400000001020304F3\n").is_err());
+    }
+}
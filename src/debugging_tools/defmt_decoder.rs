@@ -0,0 +1,390 @@
+use defmt_decoder::{DecodeError, Encoding, Frame, Locations, Table};
+use defmt_parser::{DisplayHint, Fragment, Parameter, ParserMode, Type};
+use object::{Object, ObjectSection, ObjectSymbol};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_uppercase())
+    }
+}
+
+impl LogLevel {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "trace" => Some(Self::Trace),
+            "debug" => Some(Self::Debug),
+            "info" => Some(Self::Info),
+            "warn" => Some(Self::Warn),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct DefmtMeta {
+    pub level: Option<LogLevel>,
+    pub module: String,
+    pub file: String,
+    pub line: Option<u64>,
+    /// Format string of the message, e.g. `ADC1 value={=u16}`.
+    pub format: String,
+    /// Value of every parameter of `format`, `None` for those that are not numbers.
+    pub values: Vec<Option<f64>>,
+}
+
+/// A numeric argument of a defmt message that is stored as a plot channel.
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct DefmtPlotChannel {
+    pub name: String,
+    /// Format string of the messages to take the value from.
+    pub template: String,
+    /// Position of the parameter in the format string.
+    pub arg: usize,
+}
+// ----------------------------------------------------------------------------
+/// Decodes the defmt stream of an RTT up-channel with the `.defmt` table of the loaded ELF.
+pub struct DefmtDecoder {
+    table: Table,
+    locations: Option<Locations>,
+    // テーブルの番号 -> フォーマット文字列
+    formats: BTreeMap<u64, String>,
+    // まだフレームになっていない受信データ
+    buffer: Vec<u8>,
+}
+
+impl DefmtDecoder {
+    pub fn load(elf_path: &PathBuf) -> Option<Self> {
+        let elf = std::fs::read(elf_path).ok()?;
+        let table = Table::parse(&elf).ok()??;
+        let locations = table.get_locations(&elf).ok();
+
+        Some(Self {
+            table,
+            locations,
+            formats: format_strings(&elf),
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<(String, DefmtMeta)> {
+        let mut messages = Vec::new();
+        self.buffer.extend_from_slice(bytes);
+
+        let consumed = match self.table.encoding() {
+            Encoding::Raw => {
+                let mut consumed = 0;
+                loop {
+                    match self.table.decode(&self.buffer[consumed..]) {
+                        Ok((frame, len)) => {
+                            messages.push(self.message(&frame));
+                            consumed += len;
+                        }
+                        Err(DecodeError::UnexpectedEof) => break,
+                        Err(DecodeError::Malformed) => {
+                            #[cfg(debug_assertions)]
+                            println!("defmt: malformed frame");
+                            // rawには区切りが無いので同期できない。受信済みのデータは捨てる
+                            consumed = self.buffer.len();
+                            break;
+                        }
+                    }
+                }
+                consumed
+            }
+            _ => {
+                // 区切りの0まで揃ったフレームだけを、呼び出しごとに作るデコーダに渡す
+                let Some(end) = self.buffer.iter().rposition(|&byte| byte == 0) else {
+                    return messages;
+                };
+                let mut decoder = self.table.new_stream_decoder();
+                decoder.received(&self.buffer[..=end]);
+                loop {
+                    match decoder.decode() {
+                        Ok(frame) => messages.push(self.message(&frame)),
+                        Err(DecodeError::UnexpectedEof) => break,
+                        Err(DecodeError::Malformed) => {
+                            #[cfg(debug_assertions)]
+                            println!("defmt: malformed frame");
+                            // 壊れたフレームは読み捨てて次のフレームから再開する
+                            continue;
+                        }
+                    }
+                }
+                end + 1
+            }
+        };
+        self.buffer.drain(..consumed);
+        messages
+    }
+
+    fn message(&self, frame: &Frame) -> (String, DefmtMeta) {
+        let location = self
+            .locations
+            .as_ref()
+            .and_then(|locations| locations.get(&frame.index()));
+        let text = frame.display_message().to_string();
+        let (format, values) = match self.formats.get(&frame.index()) {
+            Some(format) => (
+                format.clone(),
+                message_values(format, &text).unwrap_or_default(),
+            ),
+            None => Default::default(),
+        };
+        let meta = DefmtMeta {
+            level: frame
+                .level()
+                .and_then(|level| LogLevel::parse(&format!("{:?}", level))),
+            module: location.map(|l| l.module.clone()).unwrap_or_default(),
+            file: location
+                .map(|l| l.file.display().to_string())
+                .unwrap_or_default(),
+            line: location.map(|l| l.line),
+            format,
+            values,
+        };
+        (text, meta)
+    }
+}
+
+// .defmtセクションのシンボル名はJSONで、dataがフォーマット文字列、アドレスがテーブルの番号
+fn format_strings(elf: &[u8]) -> BTreeMap<u64, String> {
+    let mut formats = BTreeMap::new();
+    let Ok(file) = object::File::parse(elf) else {
+        return formats;
+    };
+    let Some(section) = file.section_by_name(".defmt") else {
+        return formats;
+    };
+    for symbol in file.symbols() {
+        if symbol.section_index() != Some(section.index()) {
+            continue;
+        }
+        let Ok(name) = symbol.name() else {
+            continue;
+        };
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(name) else {
+            continue;
+        };
+        if let Some(data) = json.get("data").and_then(|data| data.as_str()) {
+            formats.insert(symbol.address(), data.to_string());
+        }
+    }
+    formats
+}
+// ----------------------------------------------------------------------------
+fn is_integer(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::U8
+            | Type::U16
+            | Type::U32
+            | Type::U64
+            | Type::U128
+            | Type::Usize
+            | Type::I8
+            | Type::I16
+            | Type::I32
+            | Type::I64
+            | Type::I128
+            | Type::Isize
+            | Type::BitField(_)
+    )
+}
+
+fn is_number(parameter: &Parameter) -> bool {
+    match parameter.ty {
+        Type::Bool | Type::F32 | Type::F64 => true,
+        ref ty if is_integer(ty) => matches!(
+            parameter.hint,
+            None | Some(DisplayHint::NoHint { .. })
+                | Some(DisplayHint::Hexadecimal { .. })
+                | Some(DisplayHint::Binary { .. })
+                | Some(DisplayHint::Microseconds)
+                | Some(DisplayHint::Debug)
+        ),
+        _ => false,
+    }
+}
+
+// defmt_decoderが表示した1つの引数を数値に戻す
+fn parse_parameter(text: &str, parameter: &Parameter) -> Option<f64> {
+    match parameter.ty {
+        Type::Bool => match text {
+            "true" => Some(1.0),
+            "false" => Some(0.0),
+            _ => None,
+        },
+        Type::F32 | Type::F64 => text.parse().ok(),
+        _ => match parameter.hint {
+            Some(DisplayHint::Hexadecimal { .. }) => {
+                let digits = text.strip_prefix("0x").or(text.strip_prefix("0X"));
+                u128::from_str_radix(digits.unwrap_or(text), 16)
+                    .ok()
+                    .map(|value| value as f64)
+            }
+            Some(DisplayHint::Binary { .. }) => {
+                u128::from_str_radix(text.strip_prefix("0b").unwrap_or(text), 2)
+                    .ok()
+                    .map(|value| value as f64)
+            }
+            // 秒.マイクロ秒
+            Some(DisplayHint::Microseconds) => text
+                .split_once('.')
+                .filter(|(_, micros)| micros.len() == 6)
+                .and_then(|_| text.parse().ok()),
+            _ => text.parse::<i128>().ok().map(|value| value as f64),
+        },
+    }
+}
+
+/// Values of the parameters of a defmt format string, read back from the message
+/// that defmt_decoder rendered from it.
+///
+/// defmt_decoder 0.3 does not expose the decoded arguments of a `Frame`, so the
+/// message is split at the literal text of the format string and every parameter is
+/// parsed with its type and display hint. Digits in the literal text are never taken
+/// as values. Parameters that are not numbers (`{=str}`, `{=?}` ...) are `None`.
+/// Returns `None` if the message does not match the format string, or two parameters
+/// follow each other without text between them.
+///
+/// `("ADC1 value={=u16:x}", "ADC1 value=1f")` -> `[Some(31.0)]`
+pub fn message_values(format: &str, message: &str) -> Option<Vec<Option<f64>>> {
+    let fragments = defmt_parser::parse(format, ParserMode::ForwardsCompatible).ok()?;
+    let mut rest = message;
+    let mut values = Vec::new();
+    for (index, fragment) in fragments.iter().enumerate() {
+        let parameter = match fragment {
+            Fragment::Literal(literal) => {
+                rest = rest.strip_prefix(literal.as_ref())?;
+                continue;
+            }
+            Fragment::Parameter(parameter) => parameter,
+        };
+        let number = is_number(parameter);
+        let end = match fragments.get(index + 1) {
+            None => rest.len(),
+            // 続く文字列が数字で始まっても、数値として読める位置で区切る
+            Some(Fragment::Literal(literal)) => rest
+                .match_indices(literal.as_ref())
+                .map(|(end, _)| end)
+                .find(|end| !number || parse_parameter(&rest[..*end], parameter).is_some())?,
+            Some(Fragment::Parameter(_)) => return None,
+        };
+        let value = if number {
+            Some(parse_parameter(&rest[..end], parameter)?)
+        } else {
+            None
+        };
+        values.push(value);
+        rest = &rest[end..];
+    }
+    Some(values)
+}
+
+/// Label of a parameter for its plot channel: the last word before it in the format
+/// string, e.g. `speed` for the parameter 0 of `"speed = {=u16} rpm"`.
+pub fn parameter_label(format: &str, arg: usize) -> Option<String> {
+    let fragments = defmt_parser::parse(format, ParserMode::ForwardsCompatible).ok()?;
+    let mut before = "";
+    let mut count = 0;
+    for fragment in &fragments {
+        match fragment {
+            Fragment::Literal(literal) => before = literal.as_ref(),
+            Fragment::Parameter(_) if count == arg => {
+                let word = before
+                    .trim_matches(|c: char| !c.is_alphanumeric() && c != '_')
+                    .rsplit(|c: char| !c.is_alphanumeric() && c != '_')
+                    .next()?;
+                return (!word.is_empty()).then(|| word.to_string());
+            }
+            Fragment::Parameter(_) => {
+                count += 1;
+                before = "";
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{message_values, parameter_label};
+
+    #[test]
+    fn digits_of_the_format_string_are_not_values() {
+        assert_eq!(
+            message_values("ADC1 value={=u16}", "ADC1 value=123"),
+            Some(vec![Some(123.0)])
+        );
+        assert_eq!(
+            message_values("ch{=u8}: {=i32} mV", "ch2: -1500 mV"),
+            Some(vec![Some(2.0), Some(-1500.0)])
+        );
+        assert_eq!(message_values("idle", "idle"), Some(vec![]));
+    }
+
+    #[test]
+    fn values_follow_the_display_hint() {
+        assert_eq!(
+            message_values(
+                "raw={=u8:x} mask={=u16:#b} ok={=bool} t={=u32:us} v={=f32} w={=u8:#04X}",
+                "raw=ff mask=0b101 ok=true t=1.500000 v=-0.5 w=0x1F"
+            ),
+            Some(vec![
+                Some(255.0),
+                Some(5.0),
+                Some(1.0),
+                Some(1.5),
+                Some(-0.5),
+                Some(31.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn text_parameters_have_no_value() {
+        assert_eq!(
+            message_values("{=str} took {=u8}0 ms", "motor: on took 50 ms"),
+            Some(vec![None, Some(5.0)])
+        );
+        assert_eq!(
+            message_values("state {}, n={=usize}", "state Idle { a: 1 }, n=3"),
+            Some(vec![None, Some(3.0)])
+        );
+    }
+
+    #[test]
+    fn mismatched_messages_have_no_values() {
+        assert_eq!(message_values("a={=u8}", "b=1"), None);
+        assert_eq!(message_values("a={=u8}", "a=x"), None);
+        assert_eq!(message_values("{=u8}{=u8}", "12"), None);
+    }
+
+    #[test]
+    fn labels_are_the_word_before_the_parameter() {
+        assert_eq!(
+            parameter_label("speed = {=u16} rpm, i = {=f32}", 0).as_deref(),
+            Some("speed")
+        );
+        assert_eq!(
+            parameter_label("speed = {=u16} rpm, i = {=f32}", 1).as_deref(),
+            Some("i")
+        );
+        assert_eq!(parameter_label("{=u8}{=u8}", 1), None);
+    }
+}
@@ -0,0 +1,626 @@
+use gimli::{
+    AttributeValue, EndianArcSlice, EvaluationResult, LittleEndian, Location, Reader, Value,
+};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::cfi_unwinder::UnwoundFrame;
+use super::elf_symbols::find_section;
+use super::memory_interface::MCUMemory;
+
+type DwarfReader = EndianArcSlice<LittleEndian>;
+type Unit = gimli::Unit<DwarfReader>;
+type Entry<'abbrev, 'unit> = gimli::DebuggingInformationEntry<'abbrev, 'unit, DwarfReader>;
+
+// 型をたどる深さの上限 (壊れたDWARFでの無限ループ対策)
+const TYPE_MAX_DEPTH: usize = 16;
+// 構造体や配列はこのバイト数までhexで表示する
+const RAW_VALUE_MAX_LEN: usize = 32;
+// ----------------------------------------------------------------------------
+/// A local variable or parameter evaluated in one stack frame.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct FrameVariable {
+    pub name: String,
+    pub types: String,
+    pub parameter: bool,
+    /// Where the value lives in this frame: a register, an address, "constant" or "optimized out".
+    pub location: String,
+    pub value: String,
+    pub error: Option<String>,
+}
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug)]
+enum ValueKind {
+    // MCUMemory::decode_typeに渡すC言語の型名
+    Scalar(String),
+    Pointer,
+    Raw,
+}
+
+#[derive(Clone, Debug)]
+struct TypeInfo {
+    name: String,
+    size: Option<u64>,
+    kind: ValueKind,
+}
+
+impl TypeInfo {
+    fn unknown() -> Self {
+        Self {
+            name: "?".to_string(),
+            size: None,
+            kind: ValueKind::Raw,
+        }
+    }
+}
+
+struct LocalVariable {
+    name: String,
+    parameter: bool,
+    types: TypeInfo,
+    // 変数が見えるレキシカルブロックの範囲 (空なら関数全体)
+    scope: Vec<Range<u64>>,
+    location: Option<AttributeValue<DwarfReader>>,
+    const_value: Option<i64>,
+}
+
+struct Function {
+    unit: usize,
+    ranges: Vec<Range<u64>>,
+    frame_base: Option<AttributeValue<DwarfReader>>,
+    variables: Vec<LocalVariable>,
+}
+// ----------------------------------------------------------------------------
+/// Locals and parameters of the functions in the ELF's DWARF info.
+///
+/// `ELFParser` only lists variables with a static address; this evaluates the
+/// location expressions of function scope variables against an unwound frame.
+pub struct DwarfLocals {
+    dwarf: gimli::Dwarf<DwarfReader>,
+    units: Vec<Unit>,
+    functions: Vec<Function>,
+}
+
+impl DwarfLocals {
+    pub fn load(elf_path: &PathBuf) -> Option<Self> {
+        let data = std::fs::read(elf_path).ok()?;
+        let dwarf = gimli::Dwarf::load(|id| -> Result<DwarfReader, gimli::Error> {
+            let section = find_section(&data, id.name()).map_or(&[][..], |(_, bytes)| bytes);
+            Ok(DwarfReader::new(Arc::from(section), LittleEndian))
+        })
+        .ok()?;
+
+        let mut units = Vec::new();
+        let mut headers = dwarf.units();
+        while let Ok(Some(header)) = headers.next() {
+            if let Ok(unit) = dwarf.unit(header) {
+                units.push(unit);
+            }
+        }
+
+        let mut locals = Self {
+            dwarf,
+            units,
+            functions: Vec::new(),
+        };
+        let mut functions = Vec::new();
+        for index in 0..locals.units.len() {
+            // 読めないユニットは飛ばす
+            if let Err(_e) = locals.scan_unit(index, &mut functions) {
+                #[cfg(debug_assertions)]
+                println!("DWARF unit {} skipped: {}", index, _e);
+            }
+        }
+        if functions.is_empty() {
+            return None;
+        }
+        locals.functions = functions;
+        Some(locals)
+    }
+
+    /// Evaluates the variables in scope at the PC of `frame`. `precise` is false
+    /// for caller frames, whose PC is a return address.
+    pub fn variables(
+        &self,
+        frame: &UnwoundFrame,
+        precise: bool,
+        mut read_memory: impl FnMut(u64, usize) -> Option<Vec<u8>>,
+    ) -> Vec<FrameVariable> {
+        // 戻りアドレスは呼び出し命令の次なので、1つ前で探す
+        let pc = if precise {
+            frame.pc
+        } else {
+            frame.pc.saturating_sub(1)
+        };
+        let Some(function) = self
+            .functions
+            .iter()
+            .find(|function| function.ranges.iter().any(|range| range.contains(&pc)))
+        else {
+            return Vec::new();
+        };
+        let unit = &self.units[function.unit];
+
+        let frame_base = function
+            .frame_base
+            .as_ref()
+            .and_then(|attr| self.expression(unit, attr, pc).ok().flatten())
+            .and_then(|expression| {
+                let pieces = self
+                    .evaluate(unit, expression, frame, None, &mut read_memory)
+                    .ok()?;
+                match pieces.first()?.location {
+                    Location::Register { register } => {
+                        frame.register(register.0).map(|value| value as u64)
+                    }
+                    Location::Address { address } => Some(address),
+                    _ => None,
+                }
+            });
+
+        let mut variables = Vec::new();
+        for variable in &function.variables {
+            if !variable.scope.is_empty() && !variable.scope.iter().any(|r| r.contains(&pc)) {
+                continue;
+            }
+            let mut result = FrameVariable {
+                name: variable.name.clone(),
+                types: variable.types.name.clone(),
+                parameter: variable.parameter,
+                ..Default::default()
+            };
+
+            let expression = match &variable.location {
+                Some(attr) => self.expression(unit, attr, pc),
+                None => Ok(None),
+            };
+            match expression {
+                Ok(Some(expression)) => {
+                    let value = self
+                        .evaluate(unit, expression, frame, frame_base, &mut read_memory)
+                        .and_then(|pieces| {
+                            read_pieces(&pieces, &variable.types, frame, &mut read_memory)
+                        });
+                    match value {
+                        Ok((location, bytes)) => {
+                            result.location = location;
+                            if !bytes.is_empty() {
+                                result.value = format_value(&variable.types, &bytes);
+                            }
+                        }
+                        Err(e) => result.error = Some(e),
+                    }
+                }
+                Ok(None) => match variable.const_value {
+                    Some(value) => {
+                        result.location = "constant".to_string();
+                        result.value = value.to_string();
+                    }
+                    None => result.location = "optimized out".to_string(),
+                },
+                Err(e) => result.error = Some(e),
+            }
+            variables.push(result);
+        }
+        variables
+    }
+
+    // ------------------------------------------------------------------------
+    fn scan_unit(&self, index: usize, functions: &mut Vec<Function>) -> gimli::Result<()> {
+        let unit = &self.units[index];
+        let mut tree = unit.entries_tree(None)?;
+        let root = tree.root()?;
+        self.scan_functions(index, root, functions)
+    }
+
+    fn scan_functions(
+        &self,
+        index: usize,
+        node: gimli::EntriesTreeNode<'_, '_, '_, DwarfReader>,
+        functions: &mut Vec<Function>,
+    ) -> gimli::Result<()> {
+        let unit = &self.units[index];
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            if child.entry().tag() != gimli::DW_TAG_subprogram {
+                // namespace等の中も探す
+                self.scan_functions(index, child, functions)?;
+                continue;
+            }
+            let ranges = self.ranges(unit, child.entry())?;
+            // 宣言だけのものやインライン展開専用のものはコードを持たない
+            if ranges.is_empty() {
+                continue;
+            }
+            let frame_base = child.entry().attr_value(gimli::DW_AT_frame_base)?;
+            let mut variables = Vec::new();
+            self.scan_variables(unit, child, &[], &mut variables)?;
+            functions.push(Function {
+                unit: index,
+                ranges,
+                frame_base,
+                variables,
+            });
+        }
+        Ok(())
+    }
+
+    fn scan_variables(
+        &self,
+        unit: &Unit,
+        node: gimli::EntriesTreeNode<'_, '_, '_, DwarfReader>,
+        scope: &[Range<u64>],
+        variables: &mut Vec<LocalVariable>,
+    ) -> gimli::Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            match child.entry().tag() {
+                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                    if let Some(variable) = self.variable(unit, child.entry(), scope)? {
+                        variables.push(variable);
+                    }
+                }
+                gimli::DW_TAG_lexical_block => {
+                    let ranges = self.ranges(unit, child.entry())?;
+                    let inner = if ranges.is_empty() {
+                        scope.to_vec()
+                    } else {
+                        ranges
+                    };
+                    self.scan_variables(unit, child, &inner, variables)?;
+                }
+                // インライン展開された関数の中の変数は扱わない
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn variable(
+        &self,
+        unit: &Unit,
+        entry: &Entry,
+        scope: &[Range<u64>],
+    ) -> gimli::Result<Option<LocalVariable>> {
+        // 関数の実体は名前と型をabstract originに持つことがある
+        let origin = match entry.attr_value(gimli::DW_AT_abstract_origin)? {
+            Some(AttributeValue::UnitRef(offset)) => Some(unit.entry(offset)?),
+            _ => None,
+        };
+        let attr = |name| -> gimli::Result<Option<AttributeValue<DwarfReader>>> {
+            match (entry.attr_value(name)?, &origin) {
+                (Some(value), _) => Ok(Some(value)),
+                (None, Some(origin)) => origin.attr_value(name),
+                (None, None) => Ok(None),
+            }
+        };
+
+        let Some(name) = attr(gimli::DW_AT_name)? else {
+            return Ok(None);
+        };
+        let name = self
+            .dwarf
+            .attr_string(unit, name)?
+            .to_string_lossy()?
+            .into_owned();
+        let types = match attr(gimli::DW_AT_type)? {
+            Some(AttributeValue::UnitRef(offset)) => self.type_info(unit, offset, 0),
+            _ => TypeInfo::unknown(),
+        };
+        let const_value = entry
+            .attr_value(gimli::DW_AT_const_value)?
+            .and_then(|value| {
+                value
+                    .sdata_value()
+                    .or_else(|| value.udata_value().map(|v| v as i64))
+            });
+
+        Ok(Some(LocalVariable {
+            name,
+            parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+            types,
+            scope: scope.to_vec(),
+            location: entry.attr_value(gimli::DW_AT_location)?,
+            const_value,
+        }))
+    }
+
+    fn ranges(&self, unit: &Unit, entry: &Entry) -> gimli::Result<Vec<Range<u64>>> {
+        let mut ranges = Vec::new();
+        let mut iter = self.dwarf.die_ranges(unit, entry)?;
+        while let Some(range) = iter.next()? {
+            // リンク時に捨てられた関数はアドレス0になる
+            if range.begin != 0 && range.begin < range.end {
+                ranges.push(range.begin..range.end);
+            }
+        }
+        Ok(ranges)
+    }
+
+    fn type_info(&self, unit: &Unit, offset: gimli::UnitOffset, depth: usize) -> TypeInfo {
+        if depth > TYPE_MAX_DEPTH {
+            return TypeInfo::unknown();
+        }
+        let Ok(entry) = unit.entry(offset) else {
+            return TypeInfo::unknown();
+        };
+        let name = entry
+            .attr_value(gimli::DW_AT_name)
+            .ok()
+            .flatten()
+            .and_then(|value| self.dwarf.attr_string(unit, value).ok())
+            .and_then(|name| name.to_string_lossy().ok().map(|name| name.into_owned()));
+        let size = entry
+            .attr_value(gimli::DW_AT_byte_size)
+            .ok()
+            .flatten()
+            .and_then(|value| value.udata_value());
+        let inner = match entry.attr_value(gimli::DW_AT_type) {
+            Ok(Some(AttributeValue::UnitRef(inner))) => {
+                Some(self.type_info(unit, inner, depth + 1))
+            }
+            _ => None,
+        };
+
+        match entry.tag() {
+            gimli::DW_TAG_base_type => {
+                let name = name.unwrap_or_default();
+                TypeInfo {
+                    kind: ValueKind::Scalar(name.clone()),
+                    name,
+                    size,
+                }
+            }
+            gimli::DW_TAG_pointer_type => TypeInfo {
+                name: format!("{} *", inner.map_or("void".to_string(), |inner| inner.name)),
+                size: size.or(Some(unit.encoding().address_size as u64)),
+                kind: ValueKind::Pointer,
+            },
+            gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type => {
+                let qualifier = if entry.tag() == gimli::DW_TAG_const_type {
+                    "const"
+                } else {
+                    "volatile"
+                };
+                match inner {
+                    Some(inner) => TypeInfo {
+                        name: format!("{} {}", qualifier, inner.name),
+                        ..inner
+                    },
+                    None => TypeInfo {
+                        name: format!("{} void", qualifier),
+                        ..TypeInfo::unknown()
+                    },
+                }
+            }
+            gimli::DW_TAG_typedef => match inner {
+                Some(inner) => TypeInfo {
+                    name: name.unwrap_or_else(|| inner.name.clone()),
+                    ..inner
+                },
+                None => TypeInfo::unknown(),
+            },
+            gimli::DW_TAG_enumeration_type => TypeInfo {
+                name: format!("enum {}", name.unwrap_or_default()),
+                kind: ValueKind::Scalar(
+                    match size {
+                        Some(1) => "unsigned char",
+                        Some(2) => "short unsigned int",
+                        _ => "unsigned int",
+                    }
+                    .to_string(),
+                ),
+                size,
+            },
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type | gimli::DW_TAG_class_type => {
+                let keyword = match entry.tag() {
+                    gimli::DW_TAG_union_type => "union",
+                    gimli::DW_TAG_class_type => "class",
+                    _ => "struct",
+                };
+                TypeInfo {
+                    name: format!("{} {}", keyword, name.unwrap_or_default()),
+                    size,
+                    kind: ValueKind::Raw,
+                }
+            }
+            gimli::DW_TAG_array_type => {
+                let inner = inner.unwrap_or_else(TypeInfo::unknown);
+                let len = Self::array_len(unit, offset);
+                TypeInfo {
+                    name: format!(
+                        "{} [{}]",
+                        inner.name,
+                        len.map_or(String::new(), |len| len.to_string())
+                    ),
+                    size: size.or_else(|| Some(inner.size? * len?)),
+                    kind: ValueKind::Raw,
+                }
+            }
+            _ => TypeInfo {
+                name: name.unwrap_or_else(|| "?".to_string()),
+                size,
+                kind: ValueKind::Raw,
+            },
+        }
+    }
+
+    // 多次元配列は全要素数を返す
+    fn array_len(unit: &Unit, offset: gimli::UnitOffset) -> Option<u64> {
+        let mut tree = unit.entries_tree(Some(offset)).ok()?;
+        let root = tree.root().ok()?;
+        let mut children = root.children();
+        let mut len = None;
+        while let Ok(Some(child)) = children.next() {
+            let entry = child.entry();
+            if entry.tag() != gimli::DW_TAG_subrange_type {
+                continue;
+            }
+            let count = match entry.attr_value(gimli::DW_AT_count).ok().flatten() {
+                Some(count) => count.udata_value()?,
+                None => {
+                    entry
+                        .attr_value(gimli::DW_AT_upper_bound)
+                        .ok()
+                        .flatten()?
+                        .udata_value()?
+                        + 1
+                }
+            };
+            len = Some(len.unwrap_or(1) * count);
+        }
+        len
+    }
+
+    // 位置リストの場合はpcに合う式を選ぶ。Noneはそのpcでは値が無い
+    fn expression(
+        &self,
+        unit: &Unit,
+        attr: &AttributeValue<DwarfReader>,
+        pc: u64,
+    ) -> Result<Option<gimli::Expression<DwarfReader>>, String> {
+        if let AttributeValue::Exprloc(expression) = attr {
+            return Ok(Some(expression.clone()));
+        }
+        let Some(mut locations) = self
+            .dwarf
+            .attr_locations(unit, attr.clone())
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(None);
+        };
+        while let Some(entry) = locations.next().map_err(|e| e.to_string())? {
+            if entry.range.begin <= pc && pc < entry.range.end {
+                return Ok(Some(entry.data));
+            }
+        }
+        Ok(None)
+    }
+
+    fn evaluate(
+        &self,
+        unit: &Unit,
+        expression: gimli::Expression<DwarfReader>,
+        frame: &UnwoundFrame,
+        frame_base: Option<u64>,
+        read_memory: &mut impl FnMut(u64, usize) -> Option<Vec<u8>>,
+    ) -> Result<Vec<gimli::Piece<DwarfReader>>, String> {
+        let mut evaluation = expression.evaluation(unit.encoding());
+        let mut result = evaluation.evaluate();
+        loop {
+            result = match result.map_err(|e| e.to_string())? {
+                EvaluationResult::Complete => break,
+                EvaluationResult::RequiresMemory { address, size, .. } => {
+                    let bytes = read_memory(address, size as usize)
+                        .ok_or_else(|| format!("can not read 0x{:08X}", address))?;
+                    evaluation.resume_with_memory(Value::Generic(le_u64(&bytes)))
+                }
+                EvaluationResult::RequiresRegister { register, .. } => {
+                    let value = frame.register(register.0).ok_or_else(|| {
+                        format!("{} is not known in this frame", register_name(register.0))
+                    })?;
+                    evaluation.resume_with_register(Value::Generic(value as u64))
+                }
+                EvaluationResult::RequiresFrameBase => {
+                    evaluation.resume_with_frame_base(frame_base.ok_or("frame base is not known")?)
+                }
+                EvaluationResult::RequiresCallFrameCfa => {
+                    evaluation.resume_with_call_frame_cfa(frame.cfa.ok_or("CFA is not known")?)
+                }
+                EvaluationResult::RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address)
+                }
+                other => return Err(format!("unsupported DWARF expression ({:?})", other)),
+            };
+        }
+        Ok(evaluation.result())
+    }
+}
+// ----------------------------------------------------------------------------
+// 評価結果の各ピースから値のバイト列を集める
+fn read_pieces(
+    pieces: &[gimli::Piece<DwarfReader>],
+    types: &TypeInfo,
+    frame: &UnwoundFrame,
+    read_memory: &mut impl FnMut(u64, usize) -> Option<Vec<u8>>,
+) -> Result<(String, Vec<u8>), String> {
+    let mut places = Vec::new();
+    let mut bytes = Vec::new();
+    for piece in pieces {
+        let len = piece
+            .size_in_bits
+            .map(|bits| ((bits + 7) / 8) as usize)
+            .or(types.size.map(|size| size as usize))
+            .unwrap_or(4);
+        match &piece.location {
+            Location::Empty => return Ok(("optimized out".to_string(), Vec::new())),
+            Location::Register { register } => {
+                let value = frame.register(register.0).ok_or_else(|| {
+                    format!("{} is not known in this frame", register_name(register.0))
+                })?;
+                places.push(register_name(register.0));
+                bytes.extend(value.to_le_bytes().iter().take(len));
+            }
+            Location::Address { address } => {
+                places.push(format!("0x{:08X}", address));
+                let len = len.min(RAW_VALUE_MAX_LEN);
+                let data = read_memory(*address, len)
+                    .ok_or_else(|| format!("can not read 0x{:08X}", address))?;
+                bytes.extend(data);
+            }
+            Location::Value { value } => {
+                places.push("value".to_string());
+                let value = value.to_u64(u64::MAX).map_err(|e| e.to_string())?;
+                bytes.extend(value.to_le_bytes().iter().take(len));
+            }
+            Location::Bytes { value } => {
+                places.push("value".to_string());
+                bytes.extend(value.to_slice().map_err(|e| e.to_string())?.iter());
+            }
+            Location::ImplicitPointer { .. } => {
+                return Err("implicit pointer is not supported".to_string());
+            }
+        }
+    }
+    Ok((places.join(" + "), bytes))
+}
+
+fn format_value(types: &TypeInfo, bytes: &[u8]) -> String {
+    match &types.kind {
+        ValueKind::Scalar(c_type) => {
+            let text = MCUMemory::decode_type(c_type, bytes);
+            if !text.is_empty() {
+                return text;
+            }
+        }
+        ValueKind::Pointer => return format!("0x{:08X}", le_u64(bytes)),
+        ValueKind::Raw => {}
+    }
+    let mut text = bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if types.size.map_or(false, |size| size as usize > bytes.len()) {
+        text.push_str(" ...");
+    }
+    text
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    let mut buff = [0u8; 8];
+    let len = bytes.len().min(8);
+    buff[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(buff)
+}
+
+fn register_name(number: u16) -> String {
+    match number {
+        13 => "sp".to_string(),
+        14 => "lr".to_string(),
+        15 => "pc".to_string(),
+        n @ 64..=95 => format!("s{}", n - 64),
+        n => format!("r{}", n),
+    }
+}
@@ -450,6 +450,22 @@ pub fn get_base_type(type_offset: TypeOffset, hash: &FileHash) -> Option<String>
 
 // ----------------------------------------------------------------------
 
+// グローバル変数のアドレスをELFから探す (例: _SEGGER_RTT)
+pub fn search_symbol_address(elf_file_path: &PathBuf, symbol_name: &str) -> Option<u64> {
+    ddbug_parser::File::parse(elf_file_path.to_str()?.to_string())
+        .ok()
+        .and_then(|ctx| {
+            ctx.file().units().iter().find_map(|unit| {
+                unit.variables()
+                    .iter()
+                    .filter(|var| var.name() == Some(symbol_name))
+                    .find_map(|var| var.address().filter(|address| *address != 0))
+            })
+        })
+}
+
+// ----------------------------------------------------------------------
+
 pub fn search_target_mcu_name(elf_file_path: &PathBuf) -> Option<String> {
     let project_name = elf_file_path.file_stem()?.to_str()?.to_string();
     let mut project_dir = elf_file_path.parent();
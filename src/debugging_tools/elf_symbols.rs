@@ -0,0 +1,77 @@
+use object::{Object, ObjectKind, ObjectSection, ObjectSegment, ObjectSymbol};
+use std::path::PathBuf;
+
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub value: u64,
+    pub size: u64,
+}
+
+/// The ELF symbol table (`.symtab`).
+///
+/// The DWARF info only knows variables, so linker symbols such as `_estack`
+/// or `_Min_Stack_Size` (absolute, no type) are read from here.
+pub struct ElfSymbols {
+    symbols: Vec<ElfSymbol>,
+}
+
+impl ElfSymbols {
+    pub fn load(elf_path: &PathBuf) -> Option<Self> {
+        let data = std::fs::read(elf_path).ok()?;
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let file = object::File::parse(data).ok()?;
+        let symbols = file
+            .symbols()
+            .filter_map(|symbol| {
+                let name = symbol.name().ok().filter(|name| !name.is_empty())?;
+                Some(ElfSymbol {
+                    name: name.to_string(),
+                    value: symbol.address(),
+                    size: symbol.size(),
+                })
+            })
+            .collect();
+        Some(Self { symbols })
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ElfSymbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    pub fn symbols(&self) -> &Vec<ElfSymbol> {
+        &self.symbols
+    }
+}
+
+/// Contents of the section `name` (e.g. `.debug_frame`) and its load address.
+pub fn load_section(elf_path: &PathBuf, name: &str) -> Option<(u64, Vec<u8>)> {
+    let data = std::fs::read(elf_path).ok()?;
+    find_section(&data, name).map(|(address, bytes)| (address, bytes.to_vec()))
+}
+
+/// Like `load_section`, on an ELF image already read into memory.
+pub fn find_section<'a>(data: &'a [u8], name: &str) -> Option<(u64, &'a [u8])> {
+    let file = object::File::parse(data).ok()?;
+    let section = file.section_by_name(name)?;
+    Some((section.address(), section.data().ok()?))
+}
+
+/// Whether the image is position independent (`ET_DYN`) and the link-time address
+/// of its file offset 0, i.e. where the first `PT_LOAD` segment would be mapped
+/// without relocation. The load bias of a running process is the mapped address
+/// of offset 0 minus this value.
+pub fn link_base(data: &[u8]) -> Option<(bool, u64)> {
+    let file = object::File::parse(data).ok()?;
+    // ELFのsegments()はPT_LOADだけを返す
+    let segment = file.segments().next()?;
+    let (offset, _) = segment.file_range();
+    Some((
+        file.kind() == ObjectKind::Dynamic,
+        segment.address().wrapping_sub(offset),
+    ))
+}
@@ -0,0 +1,417 @@
+use probe_rs::{Core, MemoryInterface, RegisterId};
+use std::time::Duration;
+
+use super::call_stack::read_registers;
+use super::cfi_unwinder::{
+    exception_frame_size, is_exc_return, ArmRegisters, CfiTable, UnwoundFrame, VFP_REGISTER_COUNT,
+};
+use super::run_control::{CoreState, TargetStatus};
+use super::symbol_resolver::{SourceInfo, SymbolResolver};
+
+// System Control Block
+const ICSR: u64 = 0xE000_ED04;
+const ICSR_VECTACTIVE: u32 = 0x1FF;
+const CFSR: u64 = 0xE000_ED28;
+const HFSR: u64 = 0xE000_ED2C;
+const MMFAR: u64 = 0xE000_ED34;
+const BFAR: u64 = 0xE000_ED38;
+
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_BFARVALID: u32 = 1 << 15;
+
+// DCRSRのREGSELと同じ番号
+const REGISTER_XPSR: RegisterId = RegisterId(0b1_0000);
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+
+const CFSR_CAUSES: [(u32, &str); 18] = [
+    (0, "MemManage: instruction access violation (IACCVIOL)"),
+    (1, "MemManage: data access violation (DACCVIOL)"),
+    (
+        3,
+        "MemManage: fault on unstacking for a return from exception (MUNSTKERR)",
+    ),
+    (
+        4,
+        "MemManage: fault on stacking for exception entry (MSTKERR)",
+    ),
+    (
+        5,
+        "MemManage: fault during lazy FP state preservation (MLSPERR)",
+    ),
+    (8, "BusFault: instruction bus error (IBUSERR)"),
+    (9, "BusFault: precise data bus error (PRECISERR)"),
+    (
+        10,
+        "BusFault: imprecise data bus error, PC is not exact (IMPRECISERR)",
+    ),
+    (
+        11,
+        "BusFault: fault on unstacking for a return from exception (UNSTKERR)",
+    ),
+    (
+        12,
+        "BusFault: fault on stacking for exception entry (STKERR)",
+    ),
+    (
+        13,
+        "BusFault: fault during lazy FP state preservation (LSPERR)",
+    ),
+    (16, "UsageFault: undefined instruction (UNDEFINSTR)"),
+    (
+        17,
+        "UsageFault: invalid state, e.g. Thumb bit cleared (INVSTATE)",
+    ),
+    (18, "UsageFault: invalid PC load by EXC_RETURN (INVPC)"),
+    (19, "UsageFault: no coprocessor, is the FPU enabled? (NOCP)"),
+    (20, "UsageFault: stack overflow (STKOF)"),
+    (24, "UsageFault: unaligned access (UNALIGNED)"),
+    (25, "UsageFault: divide by zero (DIVBYZERO)"),
+];
+
+const HFSR_CAUSES: [(u32, &str); 3] = [
+    (1, "HardFault: bus fault on vector table read (VECTTBL)"),
+    (
+        30,
+        "HardFault: escalated from a configurable fault (FORCED)",
+    ),
+    (31, "HardFault: debug event (DEBUGEVT)"),
+];
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FaultKind {
+    HardFault,
+    MemManage,
+    BusFault,
+    UsageFault,
+    Lockup,
+}
+
+impl std::fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl FaultKind {
+    /// The firmware can not return from it; MemManage / BusFault / UsageFault
+    /// handlers may recover, so a running core is not halted for them.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::HardFault | Self::Lockup)
+    }
+
+    fn from_exception(number: u32) -> Option<Self> {
+        match number {
+            3 => Some(Self::HardFault),
+            4 => Some(Self::MemManage),
+            5 => Some(Self::BusFault),
+            6 => Some(Self::UsageFault),
+            _ => None,
+        }
+    }
+
+    fn from_handler_name(function: &str) -> Option<Self> {
+        match function {
+            "HardFault_Handler" => Some(Self::HardFault),
+            "MemManage_Handler" => Some(Self::MemManage),
+            "BusFault_Handler" => Some(Self::BusFault),
+            "UsageFault_Handler" => Some(Self::UsageFault),
+            _ => None,
+        }
+    }
+}
+
+/// Registers pushed by the core on exception entry.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct ExceptionFrame {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    /// SP of the interrupted context (after the frame is popped).
+    pub sp: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallFrame {
+    pub pc: u64,
+    pub location: Option<SourceInfo>,
+    /// The frame was interrupted by an exception (the stacked context).
+    pub exception: bool,
+}
+
+impl std::fmt::Display for CallFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:08X}", self.pc)?;
+        if let Some(location) = &self.location {
+            write!(f, " in {}", location)?;
+        }
+        if self.exception {
+            write!(f, " <exception>")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultReport {
+    pub time_ms: i64,
+    pub kind: FaultKind,
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: Option<u32>,
+    pub bfar: Option<u32>,
+    pub causes: Vec<String>,
+    pub frame: Option<ExceptionFrame>,
+    /// Source location of the faulting (stacked) PC.
+    pub location: Option<SourceInfo>,
+    pub call_stack: Vec<CallFrame>,
+}
+
+impl std::fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} at {:.3}s", self.kind, self.time_ms as f64 / 1000.0)?;
+        match (&self.frame, &self.location) {
+            (Some(frame), Some(location)) => writeln!(f, "PC 0x{:08X} in {}", frame.pc, location)?,
+            (Some(frame), None) => writeln!(f, "PC 0x{:08X}", frame.pc)?,
+            _ => writeln!(f, "no exception frame found")?,
+        }
+        writeln!(f, "CFSR 0x{:08X}  HFSR 0x{:08X}", self.cfsr, self.hfsr)?;
+        if let Some(address) = self.mmfar {
+            writeln!(f, "MMFAR 0x{:08X}", address)?;
+        }
+        if let Some(address) = self.bfar {
+            writeln!(f, "BFAR 0x{:08X}", address)?;
+        }
+        for cause in &self.causes {
+            writeln!(f, "  - {}", cause)?;
+        }
+        if let Some(frame) = &self.frame {
+            writeln!(f, "stacked frame:")?;
+            writeln!(
+                f,
+                "  R0 0x{:08X}  R1 0x{:08X}  R2 0x{:08X}  R3 0x{:08X}",
+                frame.r0, frame.r1, frame.r2, frame.r3
+            )?;
+            writeln!(
+                f,
+                "  R12 0x{:08X}  LR 0x{:08X}  PC 0x{:08X}  SP 0x{:08X}",
+                frame.r12, frame.lr, frame.pc, frame.sp
+            )?;
+        }
+        writeln!(f, "call stack:")?;
+        for (i, frame) in self.call_stack.iter().enumerate() {
+            writeln!(f, "  #{} {}", i, frame)?;
+        }
+        Ok(())
+    }
+}
+// ----------------------------------------------------------------------------
+pub struct FaultAnalyzer {}
+
+impl FaultAnalyzer {
+    /// Fault handler active (also while spinning in it) or lockup.
+    pub fn detect(
+        core: &mut Core,
+        status: &TargetStatus,
+    ) -> Result<Option<FaultKind>, probe_rs::Error> {
+        if status.state == CoreState::LockedUp {
+            return Ok(Some(FaultKind::Lockup));
+        }
+        let handler = status
+            .location
+            .as_ref()
+            .and_then(|location| FaultKind::from_handler_name(&location.function));
+        if handler.is_some() {
+            return Ok(handler);
+        }
+        // ICSRは動作中でも読めるので、ハンドラの無限ループも分かる
+        let icsr = core.read_word_32(ICSR)?;
+        Ok(FaultKind::from_exception(icsr & ICSR_VECTACTIVE))
+    }
+
+    /// Halts the core and builds the report; the core stays halted.
+    pub fn analyze(
+        core: &mut Core,
+        kind: FaultKind,
+        resolver: Option<&SymbolResolver>,
+        cfi: Option<&CfiTable>,
+        time_ms: i64,
+    ) -> Result<FaultReport, probe_rs::Error> {
+        if !core.core_halted()? {
+            core.halt(HALT_TIMEOUT)?;
+        }
+
+        let cfsr = core.read_word_32(CFSR)?;
+        let hfsr = core.read_word_32(HFSR)?;
+        let mmfar = core.read_word_32(MMFAR)?;
+        let bfar = core.read_word_32(BFAR)?;
+
+        // ロックアップ時はIPSRから本来の例外を調べる
+        let xpsr: u32 = core.read_core_reg(REGISTER_XPSR)?;
+        let kind = FaultKind::from_exception(xpsr & ICSR_VECTACTIVE).unwrap_or(kind);
+
+        let (registers, psp) = read_registers(core);
+        Ok(Self::report(
+            kind,
+            [cfsr, hfsr, mmfar, bfar],
+            registers,
+            psp,
+            |address| core.read_word_32(address).ok(),
+            resolver,
+            cfi,
+            time_ms,
+        ))
+    }
+
+    /// Post-mortem analysis of a memory image (core dump) and the registers saved
+    /// with it. None when the image shows no active fault.
+    pub fn analyze_image(
+        registers: ArmRegisters,
+        psp: Option<u32>,
+        xpsr: Option<u32>,
+        mut read_word: impl FnMut(u64) -> Option<u32>,
+        resolver: Option<&SymbolResolver>,
+        cfi: Option<&CfiTable>,
+        time_ms: i64,
+    ) -> Option<FaultReport> {
+        // xPSRが保存されていなければダンプ中のICSR、それも無ければPCの関数名で判断する
+        let kind = xpsr
+            .and_then(|xpsr| FaultKind::from_exception(xpsr & ICSR_VECTACTIVE))
+            .or_else(|| {
+                read_word(ICSR).and_then(|icsr| FaultKind::from_exception(icsr & ICSR_VECTACTIVE))
+            })
+            .or_else(|| {
+                let pc = registers[15]?;
+                let location = resolver?.resolve(pc as u64)?;
+                FaultKind::from_handler_name(&location.function)
+            })?;
+        let fault_registers =
+            [CFSR, HFSR, MMFAR, BFAR].map(|address| read_word(address).unwrap_or(0));
+        Some(Self::report(
+            kind,
+            fault_registers,
+            registers,
+            psp,
+            read_word,
+            resolver,
+            cfi,
+            time_ms,
+        ))
+    }
+
+    /// `fault_registers` are CFSR, HFSR, MMFAR and BFAR.
+    #[allow(clippy::too_many_arguments)]
+    fn report(
+        kind: FaultKind,
+        fault_registers: [u32; 4],
+        registers: ArmRegisters,
+        psp: Option<u32>,
+        mut read_word: impl FnMut(u64) -> Option<u32>,
+        resolver: Option<&SymbolResolver>,
+        cfi: Option<&CfiTable>,
+        time_ms: i64,
+    ) -> FaultReport {
+        let [cfsr, hfsr, mmfar, bfar] = fault_registers;
+
+        let unwound: Vec<UnwoundFrame> = match cfi {
+            // 浮動小数点レジスタは報告に使わない
+            Some(cfi) => cfi.unwind(registers, [None; VFP_REGISTER_COUNT], psp, &mut read_word),
+            None => Vec::new(),
+        };
+        let frame = match unwound.iter().find(|frame| frame.exception) {
+            Some(frame) => Some(ExceptionFrame {
+                r0: frame.registers[0].unwrap_or_default(),
+                r1: frame.registers[1].unwrap_or_default(),
+                r2: frame.registers[2].unwrap_or_default(),
+                r3: frame.registers[3].unwrap_or_default(),
+                r12: frame.registers[12].unwrap_or_default(),
+                lr: frame.registers[14].unwrap_or_default(),
+                pc: frame.pc as u32,
+                sp: frame.sp as u32,
+            }),
+            // CFIが無い場合はハンドラの入口で止まっているとみなしてSPから読む
+            None => Self::read_frame(read_word, registers[14], registers[13], psp),
+        };
+
+        let call_stack = if unwound.is_empty() {
+            frame
+                .iter()
+                .map(|frame| CallFrame {
+                    pc: frame.pc as u64,
+                    location: resolver.and_then(|resolver| resolver.resolve(frame.pc as u64)),
+                    exception: true,
+                })
+                .collect()
+        } else {
+            unwound
+                .iter()
+                .map(|frame| CallFrame {
+                    pc: frame.pc,
+                    location: resolver.and_then(|resolver| resolver.resolve(frame.pc)),
+                    exception: frame.exception,
+                })
+                .collect()
+        };
+
+        let mut causes: Vec<String> = CFSR_CAUSES
+            .iter()
+            .filter(|(bit, _)| cfsr & (1 << bit) != 0)
+            .map(|(_, text)| text.to_string())
+            .collect();
+        causes.extend(
+            HFSR_CAUSES
+                .iter()
+                .filter(|(bit, _)| hfsr & (1 << bit) != 0)
+                .map(|(_, text)| text.to_string()),
+        );
+        if kind == FaultKind::Lockup {
+            causes.push("core locked up (fault inside a fault handler or NMI)".to_string());
+        }
+
+        FaultReport {
+            time_ms,
+            kind,
+            cfsr,
+            hfsr,
+            mmfar: (cfsr & CFSR_MMARVALID != 0).then_some(mmfar),
+            bfar: (cfsr & CFSR_BFARVALID != 0).then_some(bfar),
+            causes,
+            location: frame
+                .and_then(|frame| resolver.and_then(|resolver| resolver.resolve(frame.pc as u64))),
+            frame,
+            call_stack,
+        }
+    }
+
+    fn read_frame(
+        mut read_word: impl FnMut(u64) -> Option<u32>,
+        lr: Option<u32>,
+        msp: Option<u32>,
+        psp: Option<u32>,
+    ) -> Option<ExceptionFrame> {
+        let exc_return = lr.filter(|lr| is_exc_return(*lr))?;
+        let sp = if exc_return & (1 << 2) != 0 {
+            psp?
+        } else {
+            msp?
+        };
+        let mut words = [0u32; 8];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = read_word(sp as u64 + i as u64 * 4)?;
+        }
+        let [r0, r1, r2, r3, r12, lr, pc, xpsr] = words;
+        Some(ExceptionFrame {
+            r0,
+            r1,
+            r2,
+            r3,
+            r12,
+            lr,
+            pc,
+            sp: sp + exception_frame_size(exc_return, xpsr),
+        })
+    }
+}
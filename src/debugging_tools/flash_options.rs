@@ -0,0 +1,200 @@
+use probe_rs::flashing::{BinOptions, Format};
+use std::path::{Path, PathBuf};
+
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum FlashFormat {
+    Elf,
+    Hex,
+    Bin,
+}
+
+impl std::fmt::Display for FlashFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for FlashFormat {
+    fn default() -> Self {
+        Self::Elf
+    }
+}
+
+impl FlashFormat {
+    /// Guesses the format from the file extension; ELF when unknown.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_str() {
+            "hex" | "ihex" => Self::Hex,
+            "bin" => Self::Bin,
+            _ => Self::Elf,
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum EraseMode {
+    /// Erase only the sectors touched by the image.
+    Sector,
+    Chip,
+}
+
+impl std::fmt::Display for EraseMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for EraseMode {
+    fn default() -> Self {
+        Self::Sector
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum AfterFlash {
+    /// Reset and let the program run.
+    Run,
+    /// Reset and stop at the first instruction of `main` (ELF only).
+    HaltAtMain,
+    /// Leave the core as the flash loader left it.
+    NoReset,
+}
+
+impl std::fmt::Display for AfterFlash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for AfterFlash {
+    fn default() -> Self {
+        Self::Run
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FlashSetting {
+    /// Image to program; the loaded ELF when empty.
+    pub image_path: String,
+    pub format: FlashFormat,
+    /// Load address of a raw BIN image.
+    pub base_address: u64,
+    pub verify: bool,
+    pub erase: EraseMode,
+    pub after: AfterFlash,
+}
+
+impl Default for FlashSetting {
+    fn default() -> Self {
+        Self {
+            image_path: String::new(),
+            format: FlashFormat::Elf,
+            base_address: 0x0800_0000,
+            verify: true,
+            erase: EraseMode::Sector,
+            after: AfterFlash::Run,
+        }
+    }
+}
+
+impl FlashSetting {
+    /// The image to program, falling back to `elf_path`.
+    pub fn image(&self, elf_path: &str) -> PathBuf {
+        if self.image_path.is_empty() {
+            PathBuf::from(elf_path)
+        } else {
+            PathBuf::from(format!("{}", shellexpand::tilde(&self.image_path)))
+        }
+    }
+
+    /// Format of `image`; an empty `image_path` always means the ELF.
+    pub fn image_format(&self) -> FlashFormat {
+        if self.image_path.is_empty() {
+            FlashFormat::Elf
+        } else {
+            self.format
+        }
+    }
+
+    /// Rejects option combinations the selected image can not do.
+    pub fn check(&self) -> Result<(), String> {
+        if self.after == AfterFlash::HaltAtMain && self.image_format() != FlashFormat::Elf {
+            return Err("halt at main needs an ELF image to find `main`".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn probe_rs_format(&self) -> Format {
+        match self.image_format() {
+            FlashFormat::Elf => Format::Elf,
+            FlashFormat::Hex => Format::Hex,
+            FlashFormat::Bin => Format::Bin(BinOptions {
+                base_address: Some(self.base_address),
+                skip: 0,
+            }),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+/// Outcome of one flash run.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct FlashReport {
+    pub image: String,
+    pub format: FlashFormat,
+    pub erase: EraseMode,
+    pub after: AfterFlash,
+    pub erased_bytes: u64,
+    pub written_bytes: u64,
+    pub erase_ms: u64,
+    pub program_ms: u64,
+    /// None when verification was not requested.
+    pub verify_ms: Option<u64>,
+    pub total_ms: u64,
+    /// Messages of the flash algorithm (e.g. RTT output of the loader).
+    pub diagnostics: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl FlashReport {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl std::fmt::Display for FlashReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "image    : {} ({})", self.image, self.format)?;
+        writeln!(
+            f,
+            "erase    : {} erase, {} bytes in {} ms",
+            self.erase, self.erased_bytes, self.erase_ms
+        )?;
+        writeln!(
+            f,
+            "program  : {} bytes in {} ms",
+            self.written_bytes, self.program_ms
+        )?;
+        match self.verify_ms {
+            Some(ms) => writeln!(f, "verify   : {} ms", ms)?,
+            None => writeln!(f, "verify   : skipped")?,
+        }
+        writeln!(f, "after    : {}", self.after)?;
+        writeln!(f, "total    : {} ms", self.total_ms)?;
+        for message in &self.diagnostics {
+            writeln!(f, "message  : {}", message)?;
+        }
+        match &self.error {
+            Some(e) => write!(f, "result   : failed, {}", e),
+            None => write!(f, "result   : ok"),
+        }
+    }
+}
@@ -0,0 +1,972 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use super::run_control::{CoreState, HaltCause, RunCommand, TargetStatus};
+use super::target_backend::{backend_error, TargetBackend};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(2000);
+const REPLY_TIMEOUT: Duration = Duration::from_millis(2000);
+const HALT_TIMEOUT: Duration = Duration::from_millis(1000);
+// qSupportedでPacketSizeが返らないときのパケット長
+const DEFAULT_PACKET_SIZE: usize = 400;
+// 'm'/'M'の応答や引数に使うヘッダ分 ("m20000000,100" など)
+const PACKET_OVERHEAD: usize = 32;
+const SEND_RETRY: usize = 3;
+// ARMのPCはr15
+const REGISTER_PC: u32 = 15;
+const INTERRUPT: u8 = 0x03;
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GdbSetting {
+    /// host:port of the server, e.g. OpenOCD "localhost:3333" or `qemu -s` "localhost:1234".
+    pub address: String,
+    /// Use non-stop mode when the server supports it, to read memory while the target runs.
+    pub non_stop: bool,
+    /// Without non-stop, interrupt the target for every polling cycle and continue it after.
+    pub halt_to_read: bool,
+    /// Continue the target when the server reports it halted at connection.
+    pub resume_on_connect: bool,
+    /// Monitor command that resets and halts the target, e.g. OpenOCD "reset halt"
+    /// or QEMU "system_reset". Empty when the server can not reset.
+    pub reset_command: String,
+}
+
+impl Default for GdbSetting {
+    fn default() -> Self {
+        Self {
+            address: "localhost:3333".to_string(),
+            non_stop: true,
+            halt_to_read: true,
+            resume_on_connect: true,
+            reset_command: "reset halt".to_string(),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+enum Packet {
+    /// `$...#cs`, the reply to a command or an all-stop stop reply.
+    Reply(Vec<u8>),
+    /// `%...#cs`, asynchronous notification of non-stop mode.
+    Notification(Vec<u8>),
+}
+
+/// GDB remote serial protocol client.
+pub struct GdbRemote {
+    stream: TcpStream,
+    address: String,
+    ack: bool,
+    packet_size: usize,
+    non_stop: bool,
+    halt_to_read: bool,
+    reset_command: String,
+    running: bool,
+    halt_reason: Option<HaltCause>,
+    // non-stopでStop通知を受けて、まだvStoppedで読み切っていない
+    stop_pending: bool,
+    // 'X'が使えるか (None = まだ試していない)
+    binary_write: Option<bool>,
+    // 読み出しのために止めたので、終わったら再開する
+    resume_after_access: bool,
+    received: Vec<u8>,
+}
+
+impl GdbRemote {
+    pub fn connect(setting: &GdbSetting) -> Result<Self, probe_rs::Error> {
+        let socket = setting
+            .address
+            .to_socket_addrs()
+            .map_err(|e| backend_error(&format!("{}: {}", setting.address, e)))?
+            .next()
+            .ok_or_else(|| backend_error(&format!("{}: no address", setting.address)))?;
+        let stream = TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT)
+            .map_err(|e| backend_error(&format!("connect {}: {}", setting.address, e)))?;
+        let _res = stream.set_nodelay(true);
+
+        let mut gdb = Self {
+            stream,
+            address: setting.address.clone(),
+            ack: true,
+            packet_size: DEFAULT_PACKET_SIZE,
+            non_stop: false,
+            halt_to_read: setting.halt_to_read,
+            reset_command: setting.reset_command.trim().to_string(),
+            running: false,
+            halt_reason: None,
+            stop_pending: false,
+            binary_write: None,
+            resume_after_access: false,
+            received: Vec::new(),
+        };
+        gdb.write_raw(b"+")?;
+
+        let supported = gdb.request(b"qSupported:swbreak+;hwbreak+;vContSupported+")?;
+        let supported = String::from_utf8_lossy(&supported).to_string();
+        for feature in supported.split(';') {
+            if let Some(size) = feature.strip_prefix("PacketSize=") {
+                if let Ok(size) = usize::from_str_radix(size, 16) {
+                    gdb.packet_size = size.max(PACKET_OVERHEAD * 2);
+                }
+            }
+        }
+        if supported.contains("QStartNoAckMode+") && gdb.request(b"QStartNoAckMode")? == b"OK" {
+            gdb.ack = false;
+        }
+        if setting.non_stop
+            && supported.contains("QNonStop+")
+            && gdb.request(b"QNonStop:1")? == b"OK"
+        {
+            gdb.non_stop = true;
+        }
+
+        // 接続時の状態 (all-stopでは止まっている)
+        let reply = gdb.request(b"?")?;
+        if reply == b"OK" {
+            gdb.running = true;
+        } else {
+            gdb.stop_reply(&reply)?;
+            if gdb.non_stop {
+                gdb.stop_pending = true;
+                gdb.drain_stops()?;
+            }
+        }
+        #[cfg(debug_assertions)]
+        println!(
+            "gdb {} : packet {} ack {} non-stop {} running {}",
+            gdb.address, gdb.packet_size, gdb.ack, gdb.non_stop, gdb.running
+        );
+
+        if setting.resume_on_connect && !gdb.running {
+            gdb.resume()?;
+        }
+        Ok(gdb)
+    }
+
+    // ------------------------------------------------------------------------
+    fn write_raw(&mut self, bytes: &[u8]) -> Result<(), probe_rs::Error> {
+        self.stream.write_all(bytes).map_err(|e| self.io_error(e))
+    }
+
+    fn io_error(&self, e: std::io::Error) -> probe_rs::Error {
+        backend_error(&format!("GDB server {}: {}", self.address, e))
+    }
+
+    // 受信済みのバイト列に追加する。タイムアウトならfalse
+    fn fill(&mut self, deadline: Instant) -> Result<bool, probe_rs::Error> {
+        let timeout = deadline
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1));
+        self.stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|e| self.io_error(e))?;
+        let mut buff = [0u8; 4096];
+        match self.stream.read(&mut buff) {
+            Ok(0) => Err(backend_error(&format!(
+                "GDB server {} closed the connection",
+                self.address
+            ))),
+            Ok(count) => {
+                self.received.extend_from_slice(&buff[..count]);
+                Ok(true)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                Ok(false)
+            }
+            Err(e) => Err(self.io_error(e)),
+        }
+    }
+
+    fn send_packet(&mut self, payload: &[u8]) -> Result<(), probe_rs::Error> {
+        let checksum = payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        let mut frame = Vec::with_capacity(payload.len() + 4);
+        frame.push(b'$');
+        frame.extend_from_slice(payload);
+        frame.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+
+        for _ in 0..SEND_RETRY {
+            self.write_raw(&frame)?;
+            if !self.ack || self.wait_ack()? {
+                return Ok(());
+            }
+        }
+        Err(backend_error(&format!(
+            "GDB server {} rejected a packet",
+            self.address
+        )))
+    }
+
+    // '+'ならtrue、'-'(再送要求)ならfalse
+    fn wait_ack(&mut self) -> Result<bool, probe_rs::Error> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            while let Some(&byte) = self.received.first() {
+                match byte {
+                    b'+' => {
+                        self.received.remove(0);
+                        return Ok(true);
+                    }
+                    b'-' => {
+                        self.received.remove(0);
+                        return Ok(false);
+                    }
+                    // 応答が先に来た (ackが落ちた) ときはそのまま受け付ける
+                    b'$' | b'%' => return Ok(true),
+                    _ => {
+                        self.received.remove(0);
+                    }
+                }
+            }
+            if !self.fill(deadline)? && Instant::now() >= deadline {
+                return Err(backend_error(&format!(
+                    "GDB server {} did not acknowledge",
+                    self.address
+                )));
+            }
+        }
+    }
+
+    // 受信済みのバイト列から1パケット取り出す
+    fn take_packet(&mut self) -> Result<Option<Packet>, probe_rs::Error> {
+        loop {
+            let Some(start) = self.received.iter().position(|b| *b == b'$' || *b == b'%') else {
+                self.received.clear();
+                return Ok(None);
+            };
+            let Some(end) = self.received[start..].iter().position(|b| *b == b'#') else {
+                self.received.drain(..start);
+                return Ok(None);
+            };
+            let end = start + end;
+            if self.received.len() < end + 3 {
+                self.received.drain(..start);
+                return Ok(None);
+            }
+
+            let frame: Vec<u8> = self.received.drain(..end + 3).skip(start).collect();
+            let notification = frame[0] == b'%';
+            let body = &frame[1..frame.len() - 3];
+            let checksum = std::str::from_utf8(&frame[frame.len() - 2..])
+                .ok()
+                .and_then(|text| u8::from_str_radix(text, 16).ok());
+            let valid = checksum == Some(body.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+
+            // 通知にはackを返さない
+            if self.ack && !notification {
+                self.write_raw(if valid { b"+" } else { b"-" })?;
+            }
+            if !valid {
+                continue;
+            }
+            let payload = unescape(body);
+            return Ok(Some(if notification {
+                Packet::Notification(payload)
+            } else {
+                Packet::Reply(payload)
+            }));
+        }
+    }
+
+    fn receive(&mut self, deadline: Instant) -> Result<Option<Packet>, probe_rs::Error> {
+        loop {
+            if let Some(packet) = self.take_packet()? {
+                return Ok(Some(packet));
+            }
+            if !self.fill(deadline)? && Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+    }
+
+    // 応答パケットを待つ (途中の通知は処理しておく)
+    fn receive_reply(&mut self, timeout: Duration) -> Result<Vec<u8>, probe_rs::Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.receive(deadline)? {
+                Some(Packet::Reply(reply)) => return Ok(reply),
+                Some(Packet::Notification(notification)) => self.notification(&notification)?,
+                None => {
+                    return Err(backend_error(&format!(
+                        "GDB server {} did not reply",
+                        self.address
+                    )))
+                }
+            }
+        }
+    }
+
+    fn request(&mut self, payload: &[u8]) -> Result<Vec<u8>, probe_rs::Error> {
+        self.send_packet(payload)?;
+        self.receive_reply(REPLY_TIMEOUT)
+    }
+
+    // ------------------------------------------------------------------------
+    fn notification(&mut self, notification: &[u8]) -> Result<(), probe_rs::Error> {
+        if let Some(reply) = notification.strip_prefix(b"Stop:") {
+            self.stop_reply(reply)?;
+            self.stop_pending = true;
+        }
+        Ok(())
+    }
+
+    // non-stopでは残りの停止をvStoppedで読み切る
+    fn drain_stops(&mut self) -> Result<(), probe_rs::Error> {
+        while self.stop_pending {
+            self.stop_pending = false;
+            let reply = self.request(b"vStopped")?;
+            if reply != b"OK" {
+                self.stop_reply(&reply)?;
+                self.stop_pending = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_reply(&mut self, reply: &[u8]) -> Result<(), probe_rs::Error> {
+        match reply.first() {
+            Some(b'T') | Some(b'S') => {
+                let text = String::from_utf8_lossy(reply);
+                let signal = u8::from_str_radix(text.get(1..3).unwrap_or(""), 16).unwrap_or(0);
+                self.running = false;
+                self.halt_reason = Some(
+                    if text.contains("watch:")
+                        || text.contains("rwatch:")
+                        || text.contains("awatch:")
+                    {
+                        HaltCause::Watchpoint
+                    } else if text.contains("swbreak") || text.contains("hwbreak") {
+                        HaltCause::Breakpoint
+                    } else {
+                        match signal {
+                            // 0 (non-stopのvCont;t) / SIGINT : 割り込み要求
+                            0 | 2 => HaltCause::Request,
+                            // SIGTRAP : ブレークポイントかステップ
+                            5 => HaltCause::Breakpoint,
+                            _ => HaltCause::Exception,
+                        }
+                    },
+                );
+                Ok(())
+            }
+            Some(b'W') | Some(b'X') => Err(backend_error(&format!(
+                "GDB server {}: target exited ({})",
+                self.address,
+                String::from_utf8_lossy(reply)
+            ))),
+            _ => Err(backend_error(&format!(
+                "GDB server {}: unexpected stop reply \"{}\"",
+                self.address,
+                String::from_utf8_lossy(reply)
+            ))),
+        }
+    }
+
+    fn interrupt(&mut self) -> Result<(), probe_rs::Error> {
+        if !self.running {
+            return Ok(());
+        }
+        if self.non_stop {
+            let reply = self.request(b"vCont;t")?;
+            if reply != b"OK" {
+                return Err(self.reply_error("vCont;t", &reply));
+            }
+            let deadline = Instant::now() + HALT_TIMEOUT;
+            while self.running {
+                match self.receive(deadline)? {
+                    Some(Packet::Notification(notification)) => self.notification(&notification)?,
+                    Some(Packet::Reply(_)) => {}
+                    None => break,
+                }
+            }
+            self.drain_stops()?;
+        } else {
+            self.write_raw(&[INTERRUPT])?;
+            let reply = self.receive_reply(HALT_TIMEOUT)?;
+            self.stop_reply(&reply)?;
+        }
+        if self.running {
+            return Err(backend_error(&format!(
+                "GDB server {} did not halt the target",
+                self.address
+            )));
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), probe_rs::Error> {
+        if self.running {
+            return Ok(());
+        }
+        if self.non_stop {
+            let reply = self.request(b"vCont;c")?;
+            if reply != b"OK" {
+                return Err(self.reply_error("vCont;c", &reply));
+            }
+        } else {
+            // all-stopでは止まるまで応答が無い
+            self.send_packet(b"c")?;
+        }
+        self.running = true;
+        self.halt_reason = None;
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<(), probe_rs::Error> {
+        self.interrupt()?;
+        if self.non_stop {
+            let reply = self.request(b"vCont;s")?;
+            if reply != b"OK" {
+                return Err(self.reply_error("vCont;s", &reply));
+            }
+            self.running = true;
+            let deadline = Instant::now() + HALT_TIMEOUT;
+            while self.running {
+                match self.receive(deadline)? {
+                    Some(Packet::Notification(notification)) => self.notification(&notification)?,
+                    Some(Packet::Reply(_)) => {}
+                    None => break,
+                }
+            }
+            self.drain_stops()
+        } else {
+            self.send_packet(b"s")?;
+            let reply = self.receive_reply(HALT_TIMEOUT)?;
+            self.stop_reply(&reply)
+        }
+    }
+
+    // "monitor reset halt" など、サーバー固有のコマンド (未対応なら空の応答が返る)
+    fn monitor(&mut self, command: &str) -> Result<(), probe_rs::Error> {
+        let mut payload = b"qRcmd,".to_vec();
+        payload.extend_from_slice(to_hex(command.as_bytes()).as_bytes());
+        self.send_packet(&payload)?;
+        loop {
+            let reply = self.receive_reply(REPLY_TIMEOUT)?;
+            // 'O'で始まるのはコンソール出力
+            if reply.first() == Some(&b'O') && reply != b"OK" {
+                #[cfg(debug_assertions)]
+                println!(
+                    "gdb monitor: {}",
+                    String::from_utf8_lossy(&from_hex(&reply[1..]).unwrap_or_default())
+                );
+                continue;
+            }
+            if reply == b"OK" {
+                return Ok(());
+            }
+            return Err(self.reply_error(&format!("monitor {}", command), &reply));
+        }
+    }
+
+    fn reply_error(&self, command: &str, reply: &[u8]) -> probe_rs::Error {
+        if reply.is_empty() {
+            backend_error(&format!(
+                "GDB server {} does not support \"{}\"",
+                self.address, command
+            ))
+        } else {
+            backend_error(&format!(
+                "GDB server {}: \"{}\" failed ({})",
+                self.address,
+                command,
+                String::from_utf8_lossy(reply)
+            ))
+        }
+    }
+
+    // 1パケットで扱えるメモリのバイト数 (16進で2倍になる)
+    fn chunk_size(&self) -> usize {
+        (self.packet_size.saturating_sub(PACKET_OVERHEAD) / 2).max(1)
+    }
+
+    fn read_pc(&mut self) -> Option<u64> {
+        let reply = self
+            .request(format!("p{:x}", REGISTER_PC).as_bytes())
+            .ok()?;
+        let bytes = from_hex(&reply)?;
+        let bytes: [u8; 4] = bytes.get(..4)?.try_into().ok()?;
+        Some(u32::from_le_bytes(bytes) as u64)
+    }
+}
+// ----------------------------------------------------------------------------
+impl TargetBackend for GdbRemote {
+    fn describe(&self) -> String {
+        format!("GDB server {}", self.address)
+    }
+
+    fn poll_status(&mut self) -> Result<TargetStatus, probe_rs::Error> {
+        // 動作中に届いた停止の知らせを拾う
+        if self.running {
+            match self.receive(Instant::now())? {
+                Some(Packet::Reply(reply)) => self.stop_reply(&reply)?,
+                Some(Packet::Notification(notification)) => self.notification(&notification)?,
+                None => {}
+            }
+        }
+        self.drain_stops()?;
+
+        if self.running {
+            return Ok(TargetStatus {
+                state: CoreState::Running,
+                ..Default::default()
+            });
+        }
+        Ok(TargetStatus {
+            state: CoreState::Halted,
+            halt_reason: self.halt_reason,
+            pc: self.read_pc(),
+            location: None,
+        })
+    }
+
+    fn begin_access(&mut self) -> Result<bool, probe_rs::Error> {
+        if !self.running || self.non_stop {
+            return Ok(true);
+        }
+        if !self.halt_to_read {
+            return Ok(false);
+        }
+        let reason = self.halt_reason;
+        self.interrupt()?;
+        self.halt_reason = reason;
+        self.resume_after_access = true;
+        Ok(true)
+    }
+
+    fn end_access(&mut self) -> Result<(), probe_rs::Error> {
+        if std::mem::take(&mut self.resume_after_access) {
+            self.resume()?;
+        }
+        Ok(())
+    }
+
+    fn read_memory(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+        let chunk_size = self.chunk_size();
+        let mut offset = 0;
+        while offset < data.len() {
+            let len = chunk_size.min(data.len() - offset);
+            let command = format!("m{:x},{:x}", address + offset as u64, len);
+            let reply = self.request(command.as_bytes())?;
+            if reply.first() == Some(&b'E') || reply.is_empty() {
+                return Err(self.reply_error(&command, &reply));
+            }
+            let bytes = from_hex(&reply).ok_or_else(|| self.reply_error(&command, &reply))?;
+            // サーバーは要求より短く返してもよい
+            if bytes.is_empty() || bytes.len() > len {
+                return Err(self.reply_error(&command, &reply));
+            }
+            data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+            offset += bytes.len();
+        }
+        Ok(())
+    }
+
+    fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<(), probe_rs::Error> {
+        let chunk_size = self.chunk_size();
+        for (i, chunk) in data.chunks(chunk_size).enumerate() {
+            let address = address + (i * chunk_size) as u64;
+
+            if self.binary_write != Some(false) {
+                let mut payload = format!("X{:x},{:x}:", address, chunk.len()).into_bytes();
+                payload.extend_from_slice(&escape(chunk));
+                let reply = self.request(&payload)?;
+                if reply == b"OK" {
+                    self.binary_write = Some(true);
+                    continue;
+                }
+                if !reply.is_empty() {
+                    return Err(self.reply_error(&format!("X{:x}", address), &reply));
+                }
+                // 'X'が無いサーバーは'M'で書く
+                self.binary_write = Some(false);
+            }
+
+            let command = format!("M{:x},{:x}:{}", address, chunk.len(), to_hex(chunk));
+            let reply = self.request(command.as_bytes())?;
+            if reply != b"OK" {
+                return Err(self.reply_error(&format!("M{:x}", address), &reply));
+            }
+        }
+        Ok(())
+    }
+
+    fn run_command(&mut self, command: RunCommand) -> Result<(), probe_rs::Error> {
+        self.resume_after_access = false;
+        match command {
+            RunCommand::Halt => self.interrupt(),
+            RunCommand::Run => self.resume(),
+            RunCommand::Step => self.step(),
+            RunCommand::Reset | RunCommand::ResetAndHalt => {
+                if self.reset_command.is_empty() {
+                    return Err(backend_error(&format!(
+                        "GDB server {}: no reset command is set",
+                        self.address
+                    )));
+                }
+                self.interrupt()?;
+                let reset_command = self.reset_command.clone();
+                self.monitor(&reset_command)?;
+                self.halt_reason = Some(HaltCause::Request);
+                if command == RunCommand::Reset {
+                    self.resume()?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for GdbRemote {
+    fn drop(&mut self) {
+        // 接続を切ってもターゲットは動かしたままにする
+        // (all-stopで動作中のときはサーバーが受け付けないので送らない)
+        if !self.running || self.non_stop {
+            let _res = self.send_packet(b"D");
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+// '}'のエスケープとランレングス('*')を戻す
+fn unescape(body: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            b'}' if i + 1 < body.len() => {
+                payload.push(body[i + 1] ^ 0x20);
+                i += 2;
+            }
+            b'*' if i + 1 < body.len() => {
+                if let Some(&last) = payload.last() {
+                    let count = body[i + 1].saturating_sub(29);
+                    payload.extend(std::iter::repeat(last).take(count as usize));
+                }
+                i += 2;
+            }
+            byte => {
+                payload.push(byte);
+                i += 1;
+            }
+        }
+    }
+    payload
+}
+
+// 'X'のバイナリデータ
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            escaped.push(b'}');
+            escaped.push(byte ^ 0x20);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    escaped
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &[u8]) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            // 'xx'は読めなかったレジスタ
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    const RAM_BASE: u64 = 0x2000_0000;
+    const RAM_SIZE: usize = 1024;
+    const STUB_PC: u32 = 0x0800_0123;
+
+    fn frame(start: u8, body: &[u8]) -> Vec<u8> {
+        let checksum = body.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        let mut frame = vec![start];
+        frame.extend_from_slice(body);
+        frame.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+        frame
+    }
+
+    // ソケットの相手側を返す (クライアントは接続手順を踏まずに作る)
+    fn client() -> (GdbRemote, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (peer, _) = listener.accept().unwrap();
+        peer.set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let gdb = GdbRemote {
+            stream,
+            address: "test".to_string(),
+            ack: true,
+            packet_size: DEFAULT_PACKET_SIZE,
+            non_stop: false,
+            halt_to_read: true,
+            reset_command: String::new(),
+            running: false,
+            halt_reason: None,
+            stop_pending: false,
+            binary_write: None,
+            resume_after_access: false,
+            received: Vec::new(),
+        };
+        (gdb, peer)
+    }
+
+    fn read_acks(peer: &mut TcpStream) -> Vec<u8> {
+        let mut buff = [0u8; 16];
+        match peer.read(&mut buff) {
+            Ok(count) => buff[..count].to_vec(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn unescape_run_length_and_escapes() {
+        // ' ' (32) は直前の文字を3回繰り返す
+        assert_eq!(unescape(b"0* 1"), b"00001");
+        assert_eq!(unescape(b"}\x03}\x04}\x5d}\x0a"), b"#$}*");
+        // 先頭の'*'は繰り返すものが無い
+        assert_eq!(unescape(b"* a"), b"a");
+        assert_eq!(unescape(b"ab}"), b"ab}");
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let data = [0x23, 0x24, 0x7D, 0x2A, 0x41, 0x00, 0xFF];
+        assert_eq!(
+            escape(&data),
+            [0x7D, 0x03, 0x7D, 0x04, 0x7D, 0x5D, 0x7D, 0x0A, 0x41, 0x00, 0xFF]
+        );
+        assert_eq!(unescape(&escape(&data)), data);
+        assert_eq!(from_hex(to_hex(&data).as_bytes()).unwrap(), data);
+        assert_eq!(from_hex(b"abc"), None);
+        assert_eq!(from_hex(b"xx"), None);
+    }
+
+    #[test]
+    fn take_packet_checks_the_checksum() {
+        let (mut gdb, mut peer) = client();
+
+        let mut corrupted = frame(b'$', b"E01");
+        let len = corrupted.len();
+        corrupted[len - 1] = b'0';
+        gdb.received = corrupted;
+        gdb.received.extend(frame(b'$', b"OK"));
+        gdb.received.extend(b"$O");
+        match gdb.take_packet().unwrap() {
+            Some(Packet::Reply(reply)) => assert_eq!(reply, b"OK"),
+            _ => panic!("no reply"),
+        }
+        // 壊れたパケットには再送要求、正しいパケットには'+'
+        assert_eq!(read_acks(&mut peer), b"-+");
+        // 途中までのパケットは次の受信まで残す
+        assert!(gdb.take_packet().unwrap().is_none());
+        assert_eq!(gdb.received, b"$O");
+    }
+
+    #[test]
+    fn take_packet_does_not_acknowledge_notifications() {
+        let (mut gdb, mut peer) = client();
+
+        gdb.received = b"+".to_vec();
+        gdb.received.extend(frame(b'%', b"Stop:T05thread:1;"));
+        match gdb.take_packet().unwrap() {
+            Some(Packet::Notification(notification)) => {
+                assert_eq!(notification, b"Stop:T05thread:1;")
+            }
+            _ => panic!("no notification"),
+        }
+        assert!(read_acks(&mut peer).is_empty());
+
+        // ack無しモードでは返さない
+        gdb.ack = false;
+        gdb.received = frame(b'$', b"OK");
+        assert!(gdb.take_packet().unwrap().is_some());
+        assert!(read_acks(&mut peer).is_empty());
+    }
+
+    #[test]
+    fn stop_reply_halt_causes() {
+        let (mut gdb, _peer) = client();
+        for (reply, cause) in [
+            (&b"T05swbreak:;thread:1;"[..], HaltCause::Breakpoint),
+            (b"T05watch:20000000;", HaltCause::Watchpoint),
+            (b"T05awatch:20000000;", HaltCause::Watchpoint),
+            (b"S05", HaltCause::Breakpoint),
+            (b"S02", HaltCause::Request),
+            (b"T00thread:1;", HaltCause::Request),
+            (b"S0b", HaltCause::Exception),
+        ] {
+            gdb.running = true;
+            gdb.stop_reply(reply).unwrap();
+            assert!(!gdb.running);
+            assert_eq!(gdb.halt_reason, Some(cause), "{:?}", reply);
+        }
+        assert!(gdb.stop_reply(b"W00").is_err());
+        assert!(gdb.stop_reply(b"").is_err());
+    }
+
+    // ------------------------------------------------------------------------
+    // 'm'/'M'/'c'/割り込み/qRcmd だけを扱う all-stop のサーバー ('X'は未対応)
+    fn rsp_stub(listener: TcpListener) -> std::thread::JoinHandle<Vec<String>> {
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut memory: Vec<u8> = (0..RAM_SIZE).map(|i| i as u8).collect();
+            let mut running = false;
+            let mut commands = Vec::new();
+            let mut received: Vec<u8> = Vec::new();
+            let mut buff = [0u8; 4096];
+            loop {
+                let count = match stream.read(&mut buff) {
+                    Ok(0) | Err(_) => return commands,
+                    Ok(count) => count,
+                };
+                received.extend_from_slice(&buff[..count]);
+                loop {
+                    match received.first() {
+                        None => break,
+                        Some(&INTERRUPT) => {
+                            received.remove(0);
+                            if running {
+                                running = false;
+                                stream.write_all(&frame(b'$', b"S02")).unwrap();
+                            }
+                            continue;
+                        }
+                        Some(b'$') => {}
+                        Some(_) => {
+                            received.remove(0);
+                            continue;
+                        }
+                    }
+                    let Some(end) = received.iter().position(|b| *b == b'#') else {
+                        break;
+                    };
+                    if received.len() < end + 3 {
+                        break;
+                    }
+                    let packet: Vec<u8> = received.drain(..end + 3).collect();
+                    let command = unescape(&packet[1..end]);
+                    stream.write_all(b"+").unwrap();
+
+                    let text = String::from_utf8_lossy(&command).to_string();
+                    let range = |args: &str| {
+                        let (address, len) = args.split_once(',').unwrap();
+                        let address = u64::from_str_radix(address, 16).unwrap();
+                        let len = usize::from_str_radix(len, 16).unwrap();
+                        let start = (address - RAM_BASE) as usize;
+                        start..start + len
+                    };
+                    let reply: Option<Vec<u8>> = match command[0] {
+                        b'q' if text.starts_with("qSupported") => Some(b"PacketSize=100".to_vec()),
+                        b'q' if text.starts_with("qRcmd,") => {
+                            let monitor = from_hex(&command[6..]).unwrap();
+                            Some(if monitor == b"system_reset" {
+                                b"OK".to_vec()
+                            } else {
+                                Vec::new()
+                            })
+                        }
+                        b'?' => Some(b"S05".to_vec()),
+                        b'p' => Some(to_hex(&STUB_PC.to_le_bytes()).into_bytes()),
+                        b'm' => Some(to_hex(&memory[range(&text[1..])]).into_bytes()),
+                        b'M' => {
+                            let (args, data) = text[1..].split_once(':').unwrap();
+                            let range = range(args);
+                            memory[range].copy_from_slice(&from_hex(data.as_bytes()).unwrap());
+                            Some(b"OK".to_vec())
+                        }
+                        b'c' => {
+                            running = true;
+                            None
+                        }
+                        b'D' => Some(b"OK".to_vec()),
+                        _ => Some(Vec::new()),
+                    };
+                    commands.push(text.chars().take(1).collect());
+                    if let Some(reply) = reply {
+                        stream.write_all(&frame(b'$', &reply)).unwrap();
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn all_stop_session_with_a_stub() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let setting = GdbSetting {
+            address: listener.local_addr().unwrap().to_string(),
+            resume_on_connect: false,
+            reset_command: "system_reset".to_string(),
+            ..Default::default()
+        };
+        let stub = rsp_stub(listener);
+        let mut gdb = GdbRemote::connect(&setting).unwrap();
+        assert!(gdb.ack);
+        assert!(!gdb.non_stop);
+        assert_eq!(gdb.packet_size, 0x100);
+
+        let status = gdb.poll_status().unwrap();
+        assert_eq!(status.state, CoreState::Halted);
+        assert_eq!(status.halt_reason, Some(HaltCause::Breakpoint));
+        assert_eq!(status.pc, Some(STUB_PC as u64));
+
+        // 1パケットに収まらない長さは分けて読む
+        let mut data = vec![0u8; 300];
+        gdb.read_memory(RAM_BASE + 4, &mut data).unwrap();
+        assert!(data.iter().enumerate().all(|(i, b)| *b == (i + 4) as u8));
+
+        // 'X'が空の応答なら'M'で書き、以後は'M'だけを使う
+        let written: Vec<u8> = (0..200).map(|i| 0xFF - i as u8).collect();
+        gdb.write_memory(RAM_BASE + 0x100, &written).unwrap();
+        assert_eq!(gdb.binary_write, Some(false));
+        gdb.write_memory(RAM_BASE, &[0x23, 0x24]).unwrap();
+        let mut data = vec![0u8; 200];
+        gdb.read_memory(RAM_BASE + 0x100, &mut data).unwrap();
+        assert_eq!(data, written);
+
+        gdb.run_command(RunCommand::Run).unwrap();
+        assert_eq!(gdb.poll_status().unwrap().state, CoreState::Running);
+
+        // 動作中は割り込んで読み、終わったら再開する
+        assert!(gdb.begin_access().unwrap());
+        let mut data = [0u8; 2];
+        gdb.read_memory(RAM_BASE, &mut data).unwrap();
+        assert_eq!(data, [0x23, 0x24]);
+        gdb.end_access().unwrap();
+        assert_eq!(gdb.poll_status().unwrap().state, CoreState::Running);
+
+        gdb.run_command(RunCommand::Halt).unwrap();
+        let status = gdb.poll_status().unwrap();
+        assert_eq!(status.state, CoreState::Halted);
+        assert_eq!(status.halt_reason, Some(HaltCause::Request));
+
+        gdb.run_command(RunCommand::ResetAndHalt).unwrap();
+        assert_eq!(gdb.poll_status().unwrap().state, CoreState::Halted);
+        // サーバーが知らないモニタコマンドは未対応として返す
+        gdb.reset_command = "reset halt".to_string();
+        let error = gdb.run_command(RunCommand::Reset).unwrap_err();
+        assert!(error.to_string().contains("does not support"), "{}", error);
+
+        drop(gdb);
+        let commands = stub.join().unwrap();
+        assert_eq!(commands.first().map(String::as_str), Some("q"));
+        assert_eq!(commands.iter().filter(|c| *c == "X").count(), 1);
+        assert_eq!(commands.last().map(String::as_str), Some("D"));
+    }
+}
@@ -0,0 +1,360 @@
+#[cfg(target_os = "linux")]
+pub use process::LinuxProcess;
+
+// ----------------------------------------------------------------------------
+/// Host process of a software-in-the-loop build, accessed through `/proc/<pid>/mem`.
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LinuxProcessSetting {
+    /// Process to attach to; 0 looks it up by `process_name`.
+    pub pid: u32,
+    /// Executable name as in `ps -o comm`, used when `pid` is 0. A restarted
+    /// simulation is found again on reconnect.
+    pub process_name: String,
+}
+
+impl LinuxProcessSetting {
+    pub fn check(&self) -> Result<(), String> {
+        if self.pid == 0 && self.process_name.trim().is_empty() {
+            return Err("set a PID or a process name".to_string());
+        }
+        Ok(())
+    }
+}
+// ----------------------------------------------------------------------------
+#[cfg(target_os = "linux")]
+mod process {
+    use std::fs::File;
+    use std::os::unix::fs::FileExt;
+    use std::path::{Path, PathBuf};
+
+    use super::super::elf_symbols::link_base;
+    use super::super::run_control::{CoreState, HaltCause, RunCommand, TargetStatus};
+    use super::super::target_backend::{backend_error, TargetBackend};
+    use super::LinuxProcessSetting;
+
+    // /proc/<pid>/commに入る実行ファイル名の最大長
+    const COMM_LEN: usize = 15;
+    // ----------------------------------------------------------------------------
+    /// Reads and writes the globals of a running process.
+    ///
+    /// The addresses of the watch list come from the ELF. For a position independent
+    /// executable (or a shared library holding the model) they are moved by the load
+    /// bias found in `/proc/<pid>/maps`.
+    pub struct LinuxProcess {
+        pid: u32,
+        name: String,
+        mem: File,
+        load_bias: u64,
+    }
+
+    impl LinuxProcess {
+        pub fn attach(
+            setting: &LinuxProcessSetting,
+            elf_path: &str,
+        ) -> Result<Self, probe_rs::Error> {
+            setting.check().map_err(|e| backend_error(&e))?;
+            let pid = if setting.pid != 0 {
+                setting.pid
+            } else {
+                find_process(setting.process_name.trim())?
+            };
+            let name = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                .map_err(|e| backend_error(&format!("no process {pid} : {e}")))?
+                .trim_end()
+                .to_string();
+
+            let mem = File::options()
+                .read(true)
+                .write(true)
+                .open(format!("/proc/{pid}/mem"))
+                .map_err(|e| {
+                    backend_error(&format!(
+                        "can not open /proc/{pid}/mem : {e} \
+                         (run as the same user, with kernel.yama.ptrace_scope = 0)"
+                    ))
+                })?;
+            let load_bias = load_bias(pid, Path::new(elf_path))?;
+
+            #[cfg(debug_assertions)]
+            println!("attached to {name} ({pid}), load bias {load_bias:#x}");
+
+            Ok(Self {
+                pid,
+                name,
+                mem,
+                load_bias,
+            })
+        }
+
+        fn signal(&self, signal: &str) -> Result<(), probe_rs::Error> {
+            let status = std::process::Command::new("kill")
+                .args(["-s", signal, &self.pid.to_string()])
+                .status()
+                .map_err(|e| backend_error(&format!("kill : {e}")))?;
+            if !status.success() {
+                return Err(backend_error(&format!(
+                    "kill -s {signal} {} failed",
+                    self.pid
+                )));
+            }
+            Ok(())
+        }
+    }
+
+    impl TargetBackend for LinuxProcess {
+        fn describe(&self) -> String {
+            format!("process {} ({})", self.name, self.pid)
+        }
+
+        fn poll_status(&mut self) -> Result<TargetStatus, probe_rs::Error> {
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", self.pid))
+                .map_err(|_| backend_error(&format!("process {} exited", self.pid)))?;
+
+            let (state, halt_reason) = match stat_state(&stat) {
+                'Z' | 'X' => return Err(backend_error(&format!("process {} exited", self.pid))),
+                'T' => (CoreState::Halted, Some(HaltCause::Request)),
+                // デバッガ(gdbなど)に止められている
+                't' => (CoreState::Halted, Some(HaltCause::External)),
+                _ => (CoreState::Running, None),
+            };
+            Ok(TargetStatus {
+                state,
+                halt_reason,
+                pc: None,
+                location: None,
+            })
+        }
+
+        fn read_memory(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+            let address = address.wrapping_add(self.load_bias);
+            self.mem
+                .read_exact_at(data, address)
+                .map_err(|e| backend_error(&format!("read {address:#x} : {e}")))
+        }
+
+        fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<(), probe_rs::Error> {
+            let address = address.wrapping_add(self.load_bias);
+            self.mem
+                .write_all_at(data, address)
+                .map_err(|e| backend_error(&format!("write {address:#x} : {e}")))
+        }
+
+        fn run_command(&mut self, command: RunCommand) -> Result<(), probe_rs::Error> {
+            match command {
+                RunCommand::Halt => self.signal("STOP"),
+                RunCommand::Run => self.signal("CONT"),
+                _ => Err(backend_error(&format!(
+                    "{} can not {}",
+                    self.describe(),
+                    command.to_string().to_lowercase()
+                ))),
+            }
+        }
+    }
+
+    /// State letter of `/proc/<pid>/stat`, "pid (comm) state ...".
+    fn stat_state(stat: &str) -> char {
+        // commには空白や')'が入りうるので最後の')'で区切る
+        stat.rsplit_once(')')
+            .and_then(|(_, rest)| rest.trim_start().chars().next())
+            .unwrap_or('?')
+    }
+
+    /// PID of the process named `name`; the largest one when several are running.
+    fn find_process(name: &str) -> Result<u32, probe_rs::Error> {
+        let comm: String = name.chars().take(COMM_LEN).collect();
+        let entries = std::fs::read_dir("/proc").map_err(|e| backend_error(&e.to_string()))?;
+
+        entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter(|pid| {
+                std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                    .map(|found| found.trim_end() == comm)
+                    .unwrap_or(false)
+            })
+            // 終了済み(ゾンビ)のプロセスはexeを持たない
+            .filter(|pid| std::fs::read_link(format!("/proc/{pid}/exe")).is_ok())
+            .max()
+            .ok_or_else(|| backend_error(&format!("no process named {name}")))
+    }
+
+    /// Distance between the addresses in the ELF and in the process.
+    fn load_bias(pid: u32, elf_path: &Path) -> Result<u64, probe_rs::Error> {
+        let data = std::fs::read(elf_path)
+            .map_err(|e| backend_error(&format!("can not read {} : {e}", elf_path.display())))?;
+        let (relocatable, base) = link_base(&data)
+            .ok_or_else(|| backend_error(&format!("{} is not an ELF", elf_path.display())))?;
+        if !relocatable {
+            return Ok(0);
+        }
+
+        let maps = std::fs::read_to_string(format!("/proc/{pid}/maps"))
+            .map_err(|e| backend_error(&format!("can not read /proc/{pid}/maps : {e}")))?;
+        // ELFが共有ライブラリならその写像を、見つからなければ実行ファイル自身の写像を使う
+        // (監視用にコピーしたELFを指定している場合など)
+        let mut images: Vec<PathBuf> = Vec::new();
+        if let Ok(path) = elf_path.canonicalize() {
+            images.push(path);
+        }
+        if let Ok(path) = std::fs::read_link(format!("/proc/{pid}/exe")) {
+            images.push(PathBuf::from(
+                path.to_string_lossy().trim_end_matches(" (deleted)"),
+            ));
+        }
+
+        images
+            .iter()
+            .find_map(|image| mapped_start(&maps, image))
+            .map(|start| start.wrapping_sub(base))
+            .ok_or_else(|| {
+                backend_error(&format!(
+                    "{} is not mapped in process {pid}",
+                    elf_path.display()
+                ))
+            })
+    }
+
+    /// Start address of the mapping of file offset 0 of `image`.
+    ///
+    /// A line of maps is "start-end perms offset dev inode path".
+    fn mapped_start(maps: &str, image: &Path) -> Option<u64> {
+        maps.lines().find_map(|line| {
+            let mut fields = line.splitn(6, ' ');
+            let range = fields.next()?;
+            let offset = fields.nth(1)?;
+            let path = fields.nth(2)?.trim_start();
+            let path = path.strip_suffix(" (deleted)").unwrap_or(path);
+
+            if u64::from_str_radix(offset, 16).ok()? != 0 || Path::new(path) != image {
+                return None;
+            }
+            u64::from_str_radix(range.split_once('-')?.0, 16).ok()
+        })
+    }
+
+    // ------------------------------------------------------------------------
+    #[cfg(test)]
+    mod tests {
+        use super::super::super::elf_symbols::ElfSymbols;
+        use super::*;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::time::{Duration, Instant};
+
+        #[no_mangle]
+        static VMR_LINUX_PROCESS_TEST_VALUE: AtomicU32 = AtomicU32::new(0x1234_5678);
+
+        fn temp_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("vmr_{}_{}", std::process::id(), name))
+        }
+
+        #[test]
+        fn mapped_start_of_this_executable() {
+            let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+            let exe = std::env::current_exe().unwrap().canonicalize().unwrap();
+            let line = maps
+                .lines()
+                .find(|line| line.ends_with(exe.to_str().unwrap()) && line.contains(" 00000000 "))
+                .unwrap();
+            let start = u64::from_str_radix(line.split_once('-').unwrap().0, 16).unwrap();
+            assert_eq!(mapped_start(&maps, &exe), Some(start));
+            assert_eq!(mapped_start(&maps, Path::new("/no/such/file")), None);
+        }
+
+        #[test]
+        fn mapped_start_with_padding_and_deleted_files() {
+            let maps = "\
+55d0c0a00000-55d0c0a02000 r--p 00000000 fd:01 1835043                    /opt/sim/model\n\
+55d0c0a02000-55d0c0a05000 r-xp 00002000 fd:01 1835043                    /opt/sim/model\n\
+7f1200000000-7f1200021000 rw-p 00000000 00:00 0 \n\
+7f1200400000-7f1200410000 r--p 00000000 fd:01 1835099                    /opt/sim/libplant.so (deleted)\n\
+7ffd1e3c0000-7ffd1e3e1000 rw-p 00000000 00:00 0                          [stack]\n";
+            assert_eq!(
+                mapped_start(maps, Path::new("/opt/sim/model")),
+                Some(0x55d0_c0a0_0000)
+            );
+            assert_eq!(
+                mapped_start(maps, Path::new("/opt/sim/libplant.so")),
+                Some(0x7f12_0040_0000)
+            );
+            assert_eq!(mapped_start(maps, Path::new("/opt/sim")), None);
+        }
+
+        #[test]
+        fn stat_state_after_a_comm_with_parentheses() {
+            assert_eq!(stat_state("4242 (sim) R 1 4242 4242 0 -1"), 'R');
+            assert_eq!(stat_state("4242 (a) T (b)) S 1 4242 4242 0 -1"), 'S');
+            assert_eq!(stat_state("4242 (sim"), '?');
+        }
+
+        #[test]
+        fn halt_and_run_a_process_named_with_a_parenthesis() {
+            // commに')'と空白を含むプロセス (sleepのコピー)
+            let dir = temp_path("comm");
+            std::fs::create_dir_all(&dir).unwrap();
+            let exe = dir.join("x) T (y");
+            std::fs::copy("/bin/sleep", &exe).unwrap();
+            let mut child = std::process::Command::new(&exe).arg("30").spawn().unwrap();
+
+            let setting = LinuxProcessSetting {
+                pid: child.id(),
+                process_name: String::new(),
+            };
+            let res = (|| {
+                let mut process = LinuxProcess::attach(&setting, exe.to_str().unwrap())?;
+                let running = process.poll_status()?;
+                process.run_command(RunCommand::Halt)?;
+                // シグナルが届くまで待つ
+                let deadline = Instant::now() + Duration::from_secs(2);
+                let mut halted = process.poll_status()?;
+                while halted.state != CoreState::Halted && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(10));
+                    halted = process.poll_status()?;
+                }
+                process.run_command(RunCommand::Run)?;
+                std::thread::sleep(Duration::from_millis(50));
+                let resumed = process.poll_status()?;
+                Ok::<_, probe_rs::Error>((process.describe(), running, halted, resumed))
+            })();
+            let _res = child.kill();
+            let _res = child.wait();
+            std::fs::remove_dir_all(&dir).unwrap();
+
+            let (describe, running, halted, resumed) = res.unwrap();
+            assert_eq!(describe, format!("process x) T (y ({})", setting.pid));
+            assert_eq!(running.state, CoreState::Running);
+            assert_eq!(halted.state, CoreState::Halted);
+            assert_eq!(halted.halt_reason, Some(HaltCause::Request));
+            assert_eq!(resumed.state, CoreState::Running);
+        }
+
+        #[test]
+        fn read_and_write_a_static_of_this_process() {
+            let exe = std::env::current_exe().unwrap();
+            let symbol = ElfSymbols::load(&exe)
+                .and_then(|symbols| symbols.find("VMR_LINUX_PROCESS_TEST_VALUE").cloned())
+                .unwrap();
+            let setting = LinuxProcessSetting {
+                pid: std::process::id(),
+                process_name: String::new(),
+            };
+            let mut process = LinuxProcess::attach(&setting, exe.to_str().unwrap()).unwrap();
+
+            // ELFのアドレスにロードバイアスを足した場所が実際の変数
+            let mut bytes = [0u8; 4];
+            process.read_memory(symbol.value, &mut bytes).unwrap();
+            assert_eq!(u32::from_le_bytes(bytes), 0x1234_5678);
+
+            process
+                .write_memory(symbol.value, &0xCAFE_F00Du32.to_le_bytes())
+                .unwrap();
+            assert_eq!(
+                VMR_LINUX_PROCESS_TEST_VALUE.load(Ordering::SeqCst),
+                0xCAFE_F00D
+            );
+            process.read_memory(symbol.value, &mut bytes).unwrap();
+            assert_eq!(u32::from_le_bytes(bytes), 0xCAFE_F00D);
+        }
+    }
+}
@@ -0,0 +1,436 @@
+// ----------------------------------------------------------------------------
+/// Scalar C types that can be written to the target.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ScalarType {
+    Bool,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+}
+
+impl std::fmt::Display for ScalarType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+impl ScalarType {
+    /// `types` of `VariableInfo` (e.g. "volatile short unsigned int") to a scalar type.
+    /// `size` is the byte size from DWARF; it decides the width of `long`, which is
+    /// 4 bytes on 32-bit MCUs but 8 on LP64 hosts. `long double` is only supported
+    /// where it is the same as `double`.
+    pub fn from_c_type(c_type: &str, size: usize) -> Option<Self> {
+        let c_type = c_type.strip_prefix("volatile ").unwrap_or(c_type);
+        let c_type = c_type.strip_suffix(" [").unwrap_or(c_type);
+        match c_type {
+            "bool" | "_Bool" => Some(Self::Bool),
+            "char" | "signed char" => Some(Self::I8),
+            "unsigned char" => Some(Self::U8),
+            "short int" => Some(Self::I16),
+            "short unsigned int" => Some(Self::U16),
+            "int" => Some(Self::I32),
+            "unsigned int" => Some(Self::U32),
+            "long int" if size == 8 => Some(Self::I64),
+            "long int" => Some(Self::I32),
+            "long unsigned int" if size == 8 => Some(Self::U64),
+            "long unsigned int" => Some(Self::U32),
+            "long long int" => Some(Self::I64),
+            "long long unsigned int" => Some(Self::U64),
+            "float" => Some(Self::F32),
+            "double" => Some(Self::F64),
+            // x86_64では80bitの拡張精度 (16バイト) なので扱わない
+            "long double" if size == 8 => Some(Self::F64),
+            _ => None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Bool | Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::I64 | Self::U64 | Self::F64 => 8,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        *self == Self::F32 || *self == Self::F64
+    }
+
+    fn range(&self) -> (i128, i128) {
+        match self {
+            Self::Bool => (0, 1),
+            Self::I8 => (i8::MIN as i128, i8::MAX as i128),
+            Self::U8 => (0, u8::MAX as i128),
+            Self::I16 => (i16::MIN as i128, i16::MAX as i128),
+            Self::U16 => (0, u16::MAX as i128),
+            Self::I32 => (i32::MIN as i128, i32::MAX as i128),
+            Self::U32 => (0, u32::MAX as i128),
+            Self::I64 => (i64::MIN as i128, i64::MAX as i128),
+            Self::U64 => (0, u64::MAX as i128),
+            Self::F32 | Self::F64 => (i128::MIN, i128::MAX),
+        }
+    }
+
+    // 2の補数として受け付ける範囲 (0x / 0o / 0b 付きのリテラル用)
+    fn bit_pattern_range(&self) -> (i128, i128) {
+        match self {
+            Self::Bool => (0, 1),
+            _ => {
+                let bits = self.size() as u32 * 8;
+                (-(1i128 << (bits - 1)), (1i128 << bits) - 1)
+            }
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum LiteralError {
+    Empty,
+    Invalid(String),
+    Overflow {
+        text: String,
+        target: ScalarType,
+        min: i128,
+        max: i128,
+    },
+}
+
+impl std::fmt::Display for LiteralError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty value"),
+            Self::Invalid(text) => write!(f, "invalid literal \"{}\"", text),
+            Self::Overflow {
+                text,
+                target,
+                min,
+                max,
+            } => write!(
+                f,
+                "\"{}\" overflows {} (range {} ..= {})",
+                text, target, min, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LiteralError {}
+
+impl LiteralError {
+    fn overflow(text: &str, target: ScalarType) -> Self {
+        let (min, max) = target.range();
+        Self::Overflow {
+            text: text.trim().to_string(),
+            target,
+            min,
+            max,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Literal {
+    Int { value: i128, bit_pattern: bool },
+    Float(f64),
+}
+// ----------------------------------------------------------------------------
+/// Parses a user literal and encodes it little-endian for `target`.
+///
+/// Accepts decimal, `0x` / `0o` / `0b` prefixes, `_` separators, C suffixes
+/// (`u`, `l`, `f`), char literals (`'A'`, `'\n'`, `'\x41'`), `true` / `false`
+/// and floats. Floats written to integers are rounded; prefixed literals are
+/// bit patterns, so `0xFF` into an `i8` is `-1`.
+pub fn parse_literal(text: &str, target: ScalarType) -> Result<Vec<u8>, LiteralError> {
+    let literal = parse(text, target)?;
+
+    if target.is_float() {
+        let value = match literal {
+            Literal::Int { value, .. } => value as f64,
+            Literal::Float(value) => value,
+        };
+        return match target {
+            ScalarType::F32 => {
+                if value.is_finite() && value.abs() > f32::MAX as f64 {
+                    return Err(LiteralError::Overflow {
+                        text: text.trim().to_string(),
+                        target,
+                        min: f32::MIN as i128,
+                        max: f32::MAX as i128,
+                    });
+                }
+                Ok((value as f32).to_le_bytes().to_vec())
+            }
+            _ => Ok(value.to_le_bytes().to_vec()),
+        };
+    }
+
+    let (value, (min, max)) = match literal {
+        Literal::Int {
+            value,
+            bit_pattern: true,
+        } => (value, target.bit_pattern_range()),
+        Literal::Int { value, .. } => (value, target.range()),
+        Literal::Float(value) => {
+            if !value.is_finite() {
+                return Err(LiteralError::Invalid(text.trim().to_string()));
+            }
+            let rounded = value.round();
+            if rounded < i128::MIN as f64 || rounded > i128::MAX as f64 {
+                (i128::MAX, target.range())
+            } else {
+                (rounded as i128, target.range())
+            }
+        }
+    };
+    if value < min || value > max {
+        return Err(LiteralError::overflow(text, target));
+    }
+
+    // 負の値は2の補数のまま下位バイトを使う
+    Ok(value.to_le_bytes()[..target.size()].to_vec())
+}
+
+/// Parses and checks `text` for `target` without encoding, e.g. for input validation.
+pub fn check_literal(text: &str, target: ScalarType) -> Result<(), LiteralError> {
+    parse_literal(text, target).map(|_| ())
+}
+
+fn parse(text: &str, target: ScalarType) -> Result<Literal, LiteralError> {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return Err(LiteralError::Empty);
+    }
+    let invalid = || LiteralError::Invalid(trimmed.to_string());
+
+    match trimmed.to_lowercase().as_str() {
+        "true" => {
+            return Ok(Literal::Int {
+                value: 1,
+                bit_pattern: false,
+            })
+        }
+        "false" => {
+            return Ok(Literal::Int {
+                value: 0,
+                bit_pattern: false,
+            })
+        }
+        _ => {}
+    }
+
+    if trimmed.starts_with('\'') {
+        return parse_char(trimmed)
+            .map(|value| Literal::Int {
+                value,
+                bit_pattern: false,
+            })
+            .ok_or_else(invalid);
+    }
+
+    let body: String = trimmed.chars().filter(|c| *c != '_').collect();
+    let (negative, body) = match body.strip_prefix('-') {
+        Some(rest) => (true, rest.to_string()),
+        None => (false, body.strip_prefix('+').unwrap_or(&body).to_string()),
+    };
+    // 符号は1つだけ ("--5" や "-+5" は受け付けない)
+    if body.is_empty() || body.starts_with(['+', '-']) {
+        return Err(invalid());
+    }
+
+    let lower = body.to_lowercase();
+    let radix = if lower.starts_with("0x") {
+        Some(16)
+    } else if lower.starts_with("0o") {
+        Some(8)
+    } else if lower.starts_with("0b") {
+        Some(2)
+    } else {
+        None
+    };
+
+    if let Some(radix) = radix {
+        let digits = lower[2..].trim_end_matches(['u', 'l']);
+        if digits.starts_with('+') {
+            return Err(invalid());
+        }
+        let value = u128::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+            std::num::IntErrorKind::PosOverflow => LiteralError::overflow(trimmed, target),
+            _ => invalid(),
+        })?;
+        // i128に収まらない値はどの型でも範囲外
+        let value = i128::try_from(value).map_err(|_| LiteralError::overflow(trimmed, target))?;
+        return Ok(Literal::Int {
+            value: if negative { -value } else { value },
+            bit_pattern: true,
+        });
+    }
+
+    let digits = lower.trim_end_matches(['u', 'l']);
+    if let Ok(value) = digits.parse::<i128>() {
+        return Ok(Literal::Int {
+            value: if negative { -value } else { value },
+            bit_pattern: false,
+        });
+    }
+
+    let digits = lower.strip_suffix('f').unwrap_or(&lower);
+    let value = digits.parse::<f64>().map_err(|_| invalid())?;
+    Ok(Literal::Float(if negative { -value } else { value }))
+}
+
+// 'A', '\n', '\x41', '\0'
+fn parse_char(text: &str) -> Option<i128> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+    let value = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n' as i128,
+            'r' => '\r' as i128,
+            't' => '\t' as i128,
+            '0' => 0,
+            '\\' => '\\' as i128,
+            '\'' => '\'' as i128,
+            '"' => '"' as i128,
+            'x' => {
+                let hex: String = chars.by_ref().collect();
+                return i128::from_str_radix(&hex, 16).ok();
+            }
+            _ => return None,
+        },
+        c => c as i128,
+    };
+    if chars.next().is_some() {
+        return None;
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ScalarType::*;
+
+    #[test]
+    fn accepted_literals() {
+        let cases: &[(&str, ScalarType, &[u8])] = &[
+            // 10進数と範囲の境界
+            ("0", U8, &[0x00]),
+            ("255", U8, &[0xFF]),
+            ("+5", I8, &[0x05]),
+            ("127", I8, &[0x7F]),
+            ("-128", I8, &[0x80]),
+            ("65535", U16, &[0xFF, 0xFF]),
+            ("-32768", I16, &[0x00, 0x80]),
+            ("4_294_967_295", U32, &[0xFF; 4]),
+            ("-2147483648", I32, &[0x00, 0x00, 0x00, 0x80]),
+            ("18446744073709551615", U64, &[0xFF; 8]),
+            ("-9223372036854775808", I64, &[0, 0, 0, 0, 0, 0, 0, 0x80]),
+            // 接頭辞付きはビットパターン
+            ("0xFF", I8, &[0xFF]),
+            ("0XaB", U8, &[0xAB]),
+            ("-0x80", I8, &[0x80]),
+            ("0o17", U8, &[0x0F]),
+            ("0b1010_0101", U8, &[0xA5]),
+            ("0xFFFF_FFFF", I32, &[0xFF; 4]),
+            ("0xFFFFFFFFFFFFFFFF", U64, &[0xFF; 8]),
+            ("0xFFFFFFFFFFFFFFFF", I64, &[0xFF; 8]),
+            // Cの接尾辞
+            ("10u", U32, &[0x0A, 0, 0, 0]),
+            ("10UL", U32, &[0x0A, 0, 0, 0]),
+            ("0x10ul", U16, &[0x10, 0x00]),
+            // 文字とbool
+            ("'A'", U8, &[0x41]),
+            ("'\\n'", I8, &[0x0A]),
+            ("'\\x7f'", U8, &[0x7F]),
+            ("true", Bool, &[0x01]),
+            ("FALSE", Bool, &[0x00]),
+            // 整数型への小数は丸める
+            ("1.5", I32, &[0x02, 0, 0, 0]),
+            ("-2.5", I16, &[0xFD, 0xFF]),
+            // 浮動小数点型
+            ("0x10", F32, &[0x00, 0x00, 0x80, 0x41]),
+            ("1.5f", F32, &[0x00, 0x00, 0xC0, 0x3F]),
+            ("-2", F64, &[0, 0, 0, 0, 0, 0, 0, 0xC0]),
+        ];
+        for (text, target, bytes) in cases {
+            assert_eq!(
+                parse_literal(text, *target).as_deref(),
+                Ok(*bytes),
+                "{text} as {target}"
+            );
+        }
+    }
+
+    #[test]
+    fn overflowing_literals() {
+        let cases: &[(&str, ScalarType)] = &[
+            ("256", U8),
+            ("-1", U8),
+            ("128", I8),
+            ("-129", I8),
+            ("0x100", I8),
+            ("-0x81", I8),
+            ("4294967296", U32),
+            ("18446744073709551616", U64),
+            ("2", Bool),
+            ("1e40", I64),
+            ("1e39", F32),
+            ("-1e39", F32),
+            // i128に収まらないビットパターンは-1に化けない
+            ("0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF", I8),
+            ("0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF", U64),
+            ("0x1_0000_0000_0000_0000_0000_0000_0000_0000", U64),
+            ("-0x8000_0000_0000_0000_0000_0000_0000_0001", I64),
+        ];
+        for (text, target) in cases {
+            assert!(
+                matches!(
+                    parse_literal(text, *target),
+                    Err(LiteralError::Overflow { .. })
+                ),
+                "{text} as {target}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_literals() {
+        assert_eq!(parse_literal("  ", I8), Err(LiteralError::Empty));
+        for text in [
+            "-", "--5", "-+5", "+-5", "0x+5", "0x", "0x1G", "0b102", "0o8", "12z", "'AB'", "'\\q'",
+            "nan",
+        ] {
+            assert!(
+                matches!(parse_literal(text, I32), Err(LiteralError::Invalid(_))),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn c_type_names() {
+        assert_eq!(
+            ScalarType::from_c_type("volatile short unsigned int", 2),
+            Some(U16)
+        );
+        assert_eq!(ScalarType::from_c_type("long int", 4), Some(I32));
+        assert_eq!(ScalarType::from_c_type("struct foo", 8), None);
+    }
+
+    #[test]
+    fn long_follows_the_dwarf_size() {
+        assert_eq!(ScalarType::from_c_type("long int", 8), Some(I64));
+        assert_eq!(ScalarType::from_c_type("long unsigned int", 8), Some(U64));
+        assert_eq!(ScalarType::from_c_type("long unsigned int", 4), Some(U32));
+        assert_eq!(ScalarType::from_c_type("long double", 8), Some(F64));
+        assert_eq!(ScalarType::from_c_type("long double", 16), None);
+    }
+}
@@ -0,0 +1,396 @@
+use std::path::Path;
+
+use super::session_export::{Recording, TimeBase};
+
+// ----------------------------------------------------------------------------
+/// ASAM MDF 4.10 file assembled block by block; links are absolute file offsets.
+struct MdfFile {
+    buf: Vec<u8>,
+}
+
+impl MdfFile {
+    fn new() -> Self {
+        // IDブロック (64 byte)
+        let mut buf = Vec::new();
+        buf.extend(b"MDF     4.10    VisuModi");
+        buf.extend([0u8; 4]);
+        buf.extend(410u16.to_le_bytes());
+        buf.extend([0u8; 30]);
+        buf.extend([0u8; 4]);
+        Self { buf }
+    }
+
+    fn position(&self) -> u64 {
+        self.buf.len() as u64
+    }
+
+    /// Appends a block and returns its offset. `links` are patched later with `link`.
+    fn block(&mut self, id: &[u8; 4], links: usize, data: &[u8]) -> u64 {
+        let offset = self.position();
+        let padded = data.len().div_ceil(8) * 8;
+        let length = 24 + 8 * links + padded;
+        self.buf.extend(id);
+        self.buf.extend([0u8; 4]);
+        self.buf.extend((length as u64).to_le_bytes());
+        self.buf.extend((links as u64).to_le_bytes());
+        self.buf.extend(vec![0u8; 8 * links]);
+        self.buf.extend(data);
+        self.buf.extend(vec![0u8; padded - data.len()]);
+        offset
+    }
+
+    fn link(&mut self, block: u64, index: usize, target: u64) {
+        let at = block as usize + 24 + 8 * index;
+        self.buf[at..at + 8].copy_from_slice(&target.to_le_bytes());
+    }
+
+    // 0終端のテキスト (TX) かXML (MD)
+    fn text(&mut self, id: &[u8; 4], text: &str) -> u64 {
+        let mut data = text.as_bytes().to_vec();
+        data.push(0);
+        self.block(id, 0, &data)
+    }
+}
+
+struct ChannelDef<'a> {
+    name: &'a str,
+    unit: &'a str,
+    master: bool,
+    byte_offset: u32,
+    // physical = raw * factor + offset
+    linear: Option<(f64, f64)>,
+}
+
+fn channel_block(mdf: &mut MdfFile, channel: &ChannelDef) -> u64 {
+    let mut data = Vec::new();
+    data.push(if channel.master { 2 } else { 0 }); // cn_type
+    data.push(u8::from(channel.master)); // cn_sync_type (時間)
+    data.push(4); // cn_data_type (IEEE 754 LE)
+    data.push(0); // cn_bit_offset
+    data.extend(channel.byte_offset.to_le_bytes());
+    data.extend(64u32.to_le_bytes()); // cn_bit_count
+    data.extend(0u32.to_le_bytes()); // cn_flags
+    data.extend(0u32.to_le_bytes()); // cn_inval_bit_pos
+    data.push(0xFF); // cn_precision
+    data.push(0);
+    data.extend(0u16.to_le_bytes()); // cn_attachment_count
+    data.extend([0u8; 6 * 8]); // 値と表示の範囲 (無効)
+    let block = mdf.block(b"##CN", 8, &data);
+
+    let name = mdf.text(b"##TX", channel.name);
+    mdf.link(block, 2, name);
+    if !channel.unit.is_empty() {
+        let unit = mdf.text(b"##TX", channel.unit);
+        mdf.link(block, 6, unit);
+    }
+    if let Some((factor, offset)) = channel.linear {
+        let mut data = Vec::new();
+        data.push(1); // cc_type (linear)
+        data.push(0xFF); // cc_precision
+        data.extend(0u16.to_le_bytes()); // cc_flags
+        data.extend(0u16.to_le_bytes()); // cc_ref_count
+        data.extend(2u16.to_le_bytes()); // cc_val_count
+        data.extend(0f64.to_le_bytes());
+        data.extend(0f64.to_le_bytes());
+        data.extend(offset.to_le_bytes());
+        data.extend(factor.to_le_bytes());
+        let conversion = mdf.block(b"##CC", 4, &data);
+        if !channel.unit.is_empty() {
+            let unit = mdf.text(b"##TX", channel.unit);
+            mdf.link(conversion, 1, unit);
+        }
+        mdf.link(block, 4, conversion);
+    }
+    block
+}
+
+/// Writes every channel as its own data group of (time, value) records. Scaled
+/// channels are stored as raw values with their linear conversion.
+pub(super) fn write_mdf4(recording: &Recording, path: &Path) -> std::io::Result<()> {
+    let mut mdf = MdfFile::new();
+
+    // 記録の開始時刻 (実時間が分からなければ0)
+    let origin_ms = match recording.time_base {
+        TimeBase::Relative => recording.wall_clock_ms(0),
+        TimeBase::WallClock => recording
+            .first_time_ms()
+            .map_or(0, |time_ms| recording.wall_clock_ms(time_ms)),
+    };
+    let mut data = Vec::new();
+    data.extend((origin_ms * 1_000_000).to_le_bytes()); // hd_start_time_ns (UTC)
+    data.extend(0i16.to_le_bytes());
+    data.extend(0i16.to_le_bytes());
+    data.extend([0u8; 4]); // time flags, time class, flags
+    data.extend(0f64.to_le_bytes());
+    data.extend(0f64.to_le_bytes());
+    let header = mdf.block(b"##HD", 6, &data);
+
+    let mut data = Vec::new();
+    data.extend((origin_ms * 1_000_000).to_le_bytes());
+    data.extend([0u8; 8]);
+    let history = mdf.block(b"##FH", 2, &data);
+    mdf.link(header, 1, history);
+    let comment = mdf.text(
+        b"##MD",
+        "<FHcomment><TX>exported recording</TX><tool_id>VisuModiRam</tool_id>\
+         <tool_vendor>VisuModiRam</tool_vendor><tool_version>0.2</tool_version></FHcomment>",
+    );
+    mdf.link(history, 1, comment);
+
+    let mut previous_group: Option<u64> = None;
+    for channel in &recording.channels {
+        let conversion = &channel.conversion;
+        let linear = (!conversion.is_identity() && conversion.check().is_ok())
+            .then_some((conversion.factor, conversion.offset));
+
+        let group = mdf.block(b"##DG", 4, &[0u8; 8]);
+        match previous_group {
+            Some(previous) => mdf.link(previous, 0, group),
+            None => mdf.link(header, 0, group),
+        }
+        previous_group = Some(group);
+
+        let mut data = Vec::new();
+        data.extend(0u64.to_le_bytes()); // cg_record_id
+        data.extend((channel.samples.len() as u64).to_le_bytes()); // cg_cycle_count
+        data.extend(0u16.to_le_bytes()); // cg_flags
+        data.extend(0u16.to_le_bytes()); // cg_path_separator
+        data.extend([0u8; 4]);
+        data.extend(16u32.to_le_bytes()); // cg_data_bytes
+        data.extend(0u32.to_le_bytes()); // cg_inval_bytes
+        let channel_group = mdf.block(b"##CG", 6, &data);
+        mdf.link(group, 1, channel_group);
+        let acquisition = mdf.text(b"##TX", &channel.name);
+        mdf.link(channel_group, 2, acquisition);
+
+        let time = channel_block(
+            &mut mdf,
+            &ChannelDef {
+                name: "time",
+                unit: "s",
+                master: true,
+                byte_offset: 0,
+                linear: None,
+            },
+        );
+        let value = channel_block(
+            &mut mdf,
+            &ChannelDef {
+                name: &channel.name,
+                unit: &conversion.unit,
+                master: false,
+                byte_offset: 8,
+                linear,
+            },
+        );
+        mdf.link(channel_group, 1, time);
+        mdf.link(time, 0, value);
+
+        let mut records = Vec::with_capacity(channel.samples.len() * 16);
+        for (time_ms, physical) in &channel.samples {
+            let seconds = match recording.time_base {
+                TimeBase::Relative => *time_ms as f64 / 1000.0,
+                TimeBase::WallClock => {
+                    (recording.wall_clock_ms(*time_ms) as f64 - origin_ms as f64) / 1000.0
+                }
+            };
+            let raw = match linear {
+                Some((factor, offset)) => (physical - offset) / factor,
+                None => *physical,
+            };
+            records.extend(seconds.to_le_bytes());
+            records.extend(raw.to_le_bytes());
+        }
+        let data_block = mdf.block(b"##DT", 0, &records);
+        mdf.link(group, 2, data_block);
+    }
+
+    std::fs::write(path, mdf.buf)
+}
+
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::super::a2l::Conversion;
+    use super::super::session_export::{ExportChannel, RecordedSession};
+    use super::*;
+
+    /// Block of a written file: (id, links, data).
+    struct Block<'a> {
+        id: &'a [u8],
+        links: Vec<u64>,
+        data: &'a [u8],
+    }
+
+    fn block(file: &[u8], offset: u64) -> Block<'_> {
+        let offset = offset as usize;
+        assert_eq!(offset % 8, 0);
+        let u64_at = |at: usize| u64::from_le_bytes(file[at..at + 8].try_into().unwrap());
+        let length = u64_at(offset + 8) as usize;
+        let count = u64_at(offset + 16) as usize;
+        assert_eq!(length % 8, 0);
+        assert!(offset + length <= file.len());
+        Block {
+            id: &file[offset..offset + 4],
+            links: (0..count).map(|i| u64_at(offset + 24 + 8 * i)).collect(),
+            data: &file[offset + 24 + 8 * count..offset + length],
+        }
+    }
+
+    fn text(file: &[u8], offset: u64) -> &str {
+        let block = block(file, offset);
+        assert!(block.id == b"##TX" || block.id == b"##MD");
+        let end = block.data.iter().position(|b| *b == 0).unwrap();
+        std::str::from_utf8(&block.data[..end]).unwrap()
+    }
+
+    fn f64_at(data: &[u8], at: usize) -> f64 {
+        f64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+    }
+
+    fn write(time_base: TimeBase, name: &str) -> Vec<u8> {
+        let speed = ExportChannel {
+            name: "speed".to_string(),
+            conversion: Conversion {
+                factor: 0.5,
+                offset: -3.0,
+                unit: "km/h".to_string(),
+                calibration: false,
+            },
+            samples: vec![(1000, 1.0), (1100, 2.5)],
+        };
+        let count = ExportChannel {
+            name: "count".to_string(),
+            conversion: Conversion::default(),
+            samples: vec![(1200, 9.0)],
+        };
+        let session = RecordedSession {
+            channels: vec![],
+            clock: vec![(0, 1_700_000_000_000)],
+        };
+        let recording = Recording::new(vec![speed, count], time_base, session);
+
+        let path = std::env::temp_dir().join(format!("vmr_{}_{}", std::process::id(), name));
+        write_mdf4(&recording, &path).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        file
+    }
+
+    /// (acquisition name, cycle count, channels (name, unit, conversion), records).
+    type Group = (
+        String,
+        u64,
+        Vec<(String, String, Option<(f64, f64)>)>,
+        Vec<(f64, f64)>,
+    );
+
+    fn groups(file: &[u8]) -> Vec<Group> {
+        let header = block(file, 64);
+        assert_eq!(header.id, b"##HD");
+        let mut groups = Vec::new();
+        let mut next = header.links[0];
+        while next != 0 {
+            let group = block(file, next);
+            assert_eq!(group.id, b"##DG");
+            let channel_group = block(file, group.links[1]);
+            assert_eq!(channel_group.id, b"##CG");
+            let cycles = u64::from_le_bytes(channel_group.data[8..16].try_into().unwrap());
+            assert_eq!(channel_group.data[24..28], 16u32.to_le_bytes());
+
+            let mut channels = Vec::new();
+            let mut channel = channel_group.links[1];
+            while channel != 0 {
+                let cn = block(file, channel);
+                assert_eq!(cn.id, b"##CN");
+                let unit = match cn.links[6] {
+                    0 => String::new(),
+                    unit => text(file, unit).to_string(),
+                };
+                let conversion = (cn.links[4] != 0).then(|| {
+                    let cc = block(file, cn.links[4]);
+                    assert_eq!(cc.id, b"##CC");
+                    assert_eq!(cc.data[0], 1);
+                    (f64_at(cc.data, 24), f64_at(cc.data, 32))
+                });
+                channels.push((text(file, cn.links[2]).to_string(), unit, conversion));
+                channel = cn.links[0];
+            }
+
+            let data = block(file, group.links[2]);
+            assert_eq!(data.id, b"##DT");
+            let records = (0..cycles as usize)
+                .map(|i| (f64_at(data.data, 16 * i), f64_at(data.data, 16 * i + 8)))
+                .collect();
+            groups.push((
+                text(file, channel_group.links[2]).to_string(),
+                cycles,
+                channels,
+                records,
+            ));
+            next = group.links[0];
+        }
+        groups
+    }
+
+    #[test]
+    fn writes_the_identification_and_header() {
+        let file = write(TimeBase::Relative, "header.mf4");
+        assert_eq!(&file[..24], b"MDF     4.10    VisuModi");
+        assert_eq!(file[28..30], 410u16.to_le_bytes());
+
+        let header = block(&file, 64);
+        assert_eq!(header.id, b"##HD");
+        assert_eq!(header.links.len(), 6);
+        // 相対時刻でも開始時刻はログタイマの0
+        assert_eq!(
+            header.data[..8],
+            (1_700_000_000_000u64 * 1_000_000).to_le_bytes()
+        );
+        let history = block(&file, header.links[1]);
+        assert_eq!(history.id, b"##FH");
+        assert!(text(&file, history.links[1]).contains("<tool_id>VisuModiRam</tool_id>"));
+    }
+
+    #[test]
+    fn writes_a_data_group_per_channel() {
+        let file = write(TimeBase::Relative, "relative.mf4");
+        let time = ("time".to_string(), "s".to_string(), None);
+        assert_eq!(
+            groups(&file),
+            vec![
+                (
+                    "speed".to_string(),
+                    2,
+                    vec![
+                        time.clone(),
+                        ("speed".to_string(), "km/h".to_string(), Some((-3.0, 0.5))),
+                    ],
+                    // 生値 = (物理値 - オフセット) / 係数
+                    vec![(1.0, 8.0), (1.1, 11.0)],
+                ),
+                (
+                    "count".to_string(),
+                    1,
+                    vec![time, ("count".to_string(), String::new(), None)],
+                    vec![(1.2, 9.0)],
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_wall_clock_time_from_the_first_sample() {
+        let file = write(TimeBase::WallClock, "wall.mf4");
+        let header = block(&file, 64);
+        assert_eq!(
+            header.data[..8],
+            (1_700_000_001_000u64 * 1_000_000).to_le_bytes()
+        );
+        let records: Vec<Vec<(f64, f64)>> = groups(&file)
+            .into_iter()
+            .map(|(_, _, _, records)| records)
+            .collect();
+        assert_eq!(records, [vec![(0.0, 8.0), (0.1, 11.0)], vec![(0.2, 9.0)]]);
+    }
+}
@@ -1,186 +1,88 @@
 use super::elf_parser::VariableInfo;
+use super::literal_parser::{parse_literal, ScalarType};
 use probe_rs::{Core, MemoryInterface};
 
 pub struct MCUMemory {}
 
 impl MCUMemory {
+    /// Writes `value_str` and returns the bytes that were written.
     pub fn write(
         core: &mut Core,
         symbol: &VariableInfo,
         value_str: &str,
-    ) -> Result<(), probe_rs::Error> {
-        let c_type = symbol.types.as_str();
-        let c_type = c_type.strip_prefix("volatile ").unwrap_or(&c_type);
-        let c_type = c_type.strip_suffix(" [").unwrap_or(c_type);
-        match c_type {
-            "signed char" | "char" => match value_str.parse::<i8>() {
-                Ok(val) => core.write_word_8(symbol.address, val as u8),
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for signed char"
-                ))),
-            },
-            "unsigned char" | "bool" | "_Bool" => match value_str.parse::<u8>() {
-                Ok(val) => core.write_word_8(symbol.address, val),
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for unsigned char"
-                ))),
-            },
-            "short int" => match value_str.parse::<i16>() {
-                Ok(val) => {
-                    let block = val.to_le_bytes();
-                    core.write_8(symbol.address, &block).map_err(|e| e.into())
-                }
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for short"
-                ))),
-            },
-            "short unsigned int" => match value_str.parse::<u16>() {
-                Ok(val) => {
-                    let block = val.to_le_bytes();
-                    core.write_8(symbol.address, &block).map_err(|e| e.into())
-                }
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for unsigned short"
-                ))),
-            },
-            "int" | "long int" => match value_str.parse::<i32>() {
-                Ok(val) => core.write_word_32(symbol.address, val as u32),
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for int/long"
-                ))),
-            },
-            "unsigned int" | "long unsigned int" => match value_str.parse::<u32>() {
-                Ok(val) => core.write_word_32(symbol.address, val),
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for unsigned int/long"
-                ))),
-            },
-            "long long int" => match value_str.parse::<i64>() {
-                Ok(val) => {
-                    let block = val.to_le_bytes();
-                    let block_u32 = [
-                        u32::from_le_bytes([block[0], block[1], block[2], block[3]]),
-                        u32::from_le_bytes([block[4], block[5], block[6], block[7]]),
-                    ];
-                    core.write_32(symbol.address, &block_u32)
-                        .map_err(|e| e.into())
-                }
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for long long"
-                ))),
-            },
-            "long long unsigned int" => match value_str.parse::<u64>() {
-                Ok(val) => {
-                    let block = val.to_le_bytes();
-                    let block_u32 = [
-                        u32::from_le_bytes([block[0], block[1], block[2], block[3]]),
-                        u32::from_le_bytes([block[4], block[5], block[6], block[7]]),
-                    ];
-                    core.write_32(symbol.address, &block_u32)
-                        .map_err(|e| e.into())
-                }
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for unsigned long long"
-                ))),
-            },
-            "float" => match value_str.parse::<f32>() {
-                Ok(val) => core.write_word_32(symbol.address, val.to_bits()),
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for float"
-                ))),
-            },
-            "double" | "long double" => match value_str.parse::<f64>() {
-                Ok(val) => core.write_word_64(symbol.address, val.to_bits()),
-                Err(_) => Err(probe_rs::Error::Other(anyhow::anyhow!(
-                    "Parse error for double/long double"
-                ))),
-            },
-            _ => Err(probe_rs::Error::Other(anyhow::anyhow!("Unsupported type"))),
+    ) -> Result<Vec<u8>, probe_rs::Error> {
+        let block = Self::encode(symbol, value_str)?;
+        match block.len() {
+            1 => core.write_word_8(symbol.address, block[0])?,
+            4 => core.write_word_32(
+                symbol.address,
+                u32::from_le_bytes([block[0], block[1], block[2], block[3]]),
+            )?,
+            _ => core.write_8(symbol.address, &block)?,
         }
+        Ok(block)
     }
 
-    pub fn read(core: &mut Core, symbol: &VariableInfo) -> String {
-        let c_type = symbol.types.as_str();
-        let c_type = c_type.strip_prefix("volatile ").unwrap_or(&c_type);
-        let c_type = c_type.strip_suffix(" [").unwrap_or(c_type);
-        let val_str = {
-            match c_type {
-                "char" | "signed char" => {
-                    let val_bits = core
-                        .read_word_8(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{}", val_bits as i8)
-                }
-                "unsigned char" | "bool" | "_Bool" => {
-                    let val_bits = core
-                        .read_word_8(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{}", val_bits)
-                }
-                "short int" => {
-                    let mut buff = [0u8; 2];
-                    core.read_8(symbol.address, &mut buff)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    let val_bits = u16::from_le_bytes(buff);
-                    format!("{}", val_bits as i16)
-                }
-                "short unsigned int" => {
-                    let mut buff = [0u8; 2];
-                    core.read_8(symbol.address, &mut buff)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    let val_bits = u16::from_le_bytes(buff);
-                    format!("{}", val_bits)
-                }
-                "int" | "long int" => {
-                    let val_bits = core
-                        .read_word_32(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{}", val_bits as i32)
-                }
-                "unsigned int" | "long unsigned int" => {
-                    let val_bits = core
-                        .read_word_32(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{}", val_bits)
-                }
-                "long long int" => {
-                    let val_bits = core
-                        .read_word_64(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{}", val_bits as i64)
-                }
-                "long long unsigned int" => {
-                    let val_bits = core
-                        .read_word_64(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{}", val_bits)
-                }
-                "float" => {
-                    let val_bits = core
-                        .read_word_32(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{:?}", f32::from_bits(val_bits))
-                }
-                "double" | "long double" => {
-                    // long double cast to double
-                    let val_bits = core
-                        .read_word_64(symbol.address)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-                        .unwrap();
-                    format!("{:?}", f64::from_bits(val_bits))
-                }
-                _ => format!(""),
-            }
+    /// Little-endian bytes of `value_str` for the type of `symbol` (see `parse_literal`).
+    pub fn encode(symbol: &VariableInfo, value_str: &str) -> Result<Vec<u8>, probe_rs::Error> {
+        let scalar = ScalarType::from_c_type(&symbol.types, symbol.size)
+            .ok_or_else(|| probe_rs::Error::Other(anyhow::anyhow!("Unsupported type")))?;
+        parse_literal(value_str, scalar).map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e)))
+    }
+
+    pub fn read_bytes(
+        core: &mut Core,
+        symbol: &VariableInfo,
+        len: usize,
+    ) -> Result<Vec<u8>, probe_rs::Error> {
+        let mut buff = vec![0u8; len];
+        core.read_8(symbol.address, &mut buff)?;
+        Ok(buff)
+    }
+
+    /// Reads and formats a scalar symbol; empty for types that can not be decoded.
+    pub fn read(core: &mut Core, symbol: &VariableInfo) -> Result<String, probe_rs::Error> {
+        let Some(scalar) = ScalarType::from_c_type(&symbol.types, symbol.size) else {
+            return Ok(String::new());
         };
-        val_str
+        let bytes = Self::read_bytes(core, symbol, scalar.size())?;
+        Ok(Self::decode(symbol, &bytes))
+    }
+
+    /// Formats little-endian raw bytes (e.g. from a trace packet) like `read` does.
+    pub fn decode(symbol: &VariableInfo, bytes: &[u8]) -> String {
+        match ScalarType::from_c_type(&symbol.types, symbol.size) {
+            Some(scalar) => Self::decode_scalar(scalar, bytes),
+            None => String::new(),
+        }
+    }
+
+    /// `decode` for a bare C type name, e.g. a local variable from the DWARF info;
+    /// `bytes` is the whole variable.
+    pub fn decode_type(c_type: &str, bytes: &[u8]) -> String {
+        match ScalarType::from_c_type(c_type, bytes.len()) {
+            Some(scalar) => Self::decode_scalar(scalar, bytes),
+            None => String::new(),
+        }
+    }
+
+    fn decode_scalar(scalar: ScalarType, bytes: &[u8]) -> String {
+        let mut buff = [0u8; 8];
+        let len = bytes.len().min(8);
+        buff[..len].copy_from_slice(&bytes[..len]);
+        let val_bits = u64::from_le_bytes(buff);
+
+        match scalar {
+            ScalarType::I8 => format!("{}", val_bits as i8),
+            ScalarType::U8 | ScalarType::Bool => format!("{}", val_bits as u8),
+            ScalarType::I16 => format!("{}", val_bits as i16),
+            ScalarType::U16 => format!("{}", val_bits as u16),
+            ScalarType::I32 => format!("{}", val_bits as i32),
+            ScalarType::U32 => format!("{}", val_bits as u32),
+            ScalarType::I64 => format!("{}", val_bits as i64),
+            ScalarType::U64 => format!("{}", val_bits),
+            ScalarType::F32 => format!("{:?}", f32::from_bits(val_bits as u32)),
+            ScalarType::F64 => format!("{:?}", f64::from_bits(val_bits)),
+        }
     }
 }
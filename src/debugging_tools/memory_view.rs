@@ -0,0 +1,80 @@
+use probe_rs::{Core, MemoryInterface};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+pub const MEMORY_VIEW_MAX_LEN: usize = 4096;
+// 要求が途切れた領域は読まない (ウィジェットを閉じた場合など)
+const MEMORY_VIEW_TIMEOUT: Duration = Duration::from_secs(2);
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct MemoryBlock {
+    pub address: u64,
+    pub data: Vec<u8>,
+    pub time_ms: i64,
+    pub error: Option<String>,
+}
+
+struct MemoryRequest {
+    address: u64,
+    len: usize,
+    requested_at: Instant,
+}
+
+/// Raw memory ranges that widgets ask the watch thread to sample every cycle.
+#[derive(Default)]
+pub struct MemoryViews {
+    requests: BTreeMap<String, MemoryRequest>,
+    blocks: BTreeMap<String, MemoryBlock>,
+}
+
+impl MemoryViews {
+    pub fn request(&mut self, key: &str, address: u64, len: usize) {
+        self.requests.insert(
+            key.to_string(),
+            MemoryRequest {
+                address,
+                len: len.min(MEMORY_VIEW_MAX_LEN),
+                requested_at: Instant::now(),
+            },
+        );
+    }
+
+    pub fn block(&self, key: &str) -> Option<MemoryBlock> {
+        self.blocks.get(key).cloned()
+    }
+
+    /// (key, address, len) of the live requests; stale ones are dropped.
+    pub fn take_requests(&mut self) -> Vec<(String, u64, usize)> {
+        self.requests
+            .retain(|_, request| request.requested_at.elapsed() < MEMORY_VIEW_TIMEOUT);
+        let requests = &self.requests;
+        self.blocks.retain(|key, _| requests.contains_key(key));
+
+        self.requests
+            .iter()
+            .map(|(key, request)| (key.clone(), request.address, request.len))
+            .collect()
+    }
+
+    pub fn store(&mut self, key: String, block: MemoryBlock) {
+        self.blocks.insert(key, block);
+    }
+
+    pub fn read(core: &mut Core, address: u64, len: usize, time_ms: i64) -> MemoryBlock {
+        let mut data = vec![0u8; len];
+        match core.read_8(address, &mut data) {
+            Ok(_) => MemoryBlock {
+                address,
+                data,
+                time_ms,
+                error: None,
+            },
+            Err(e) => MemoryBlock {
+                address,
+                data: Vec::new(),
+                time_ms,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
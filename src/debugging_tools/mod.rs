@@ -1,8 +1,71 @@
+mod a2l;
+mod call_stack;
+mod cfi_unwinder;
+mod core_dump;
+mod defmt_decoder;
+mod dwarf_locals;
 mod elf_parser;
+mod elf_symbols;
+mod fault_analyzer;
+mod flash_options;
+mod gdb_remote;
+mod linux_process;
+mod literal_parser;
+mod mdf_writer;
 mod memory_interface;
+mod memory_view;
+mod parquet_writer;
+mod probe_config;
 mod probe_interface;
+mod project_import;
+mod ram_snapshot;
+mod rtt_channel;
+mod run_control;
+mod semihosting;
+mod serial_monitor;
+mod session_event;
+mod session_export;
+mod session_health;
+mod stack_monitor;
+mod swo_trace;
+mod symbol_map;
+mod symbol_resolver;
+mod target_backend;
+mod watchpoint;
+mod write_history;
+mod xcp_master;
 
+pub use a2l::{export_a2l, import_a2l, A2lImport, Conversion};
+pub use call_stack::StackFrame;
+pub use core_dump::{CoreDumpSetting, DumpFile};
+pub use defmt_decoder::{parameter_label, DefmtMeta, DefmtPlotChannel, LogLevel};
+pub use dwarf_locals::FrameVariable;
 pub use elf_parser::*;
-pub use probe_interface::{FlashProgressState, ProbeInterface, WatchSetting};
+pub use fault_analyzer::{CallFrame, ExceptionFrame, FaultKind, FaultReport};
+pub use flash_options::{AfterFlash, EraseMode, FlashFormat, FlashReport, FlashSetting};
+pub use gdb_remote::GdbSetting;
+pub use linux_process::LinuxProcessSetting;
+pub use literal_parser::{check_literal, parse_literal, LiteralError, ScalarType};
+pub use memory_interface::MCUMemory;
+pub use memory_view::{MemoryBlock, MEMORY_VIEW_MAX_LEN};
+pub use probe_config::{ProbeConfig, ProbeProtocol};
+pub use probe_interface::{FlashProgressState, ProbeInterface, SessionOptions, WatchSetting};
+pub use project_import::{import_project, ImportedPanel, ImportedProject, PanelKind};
+pub use ram_snapshot::{RamSnapshot, SnapshotDiff, ValueChange};
+pub use rtt_channel::{RttChannelInfo, RttLine, RttStatus};
+pub use run_control::{CoreState, HaltCause, RunCommand, TargetStatus};
+pub use semihosting::HANDLE_STDERR;
+pub use serial_monitor::SerialSetting;
+pub use session_event::{SessionEvent, SessionEventKind};
+pub use session_export::{export_command, ExportFormat, ExportSetting, TimeBase};
+pub use session_health::{ConnectionState, ReconnectPolicy, SessionHealth};
+pub use stack_monitor::{StackSetting, StackUsage, STACK_PAINT_PATTERN};
+pub use swo_trace::{ItmValueFormat, SwoSetting, DWT_DATA_TRACE_MAX};
+pub use symbol_map::SymbolMap;
+pub use symbol_resolver::{SourceInfo, SymbolResolver};
+pub use target_backend::{BackendKind, BackendSetting};
+pub use watchpoint::{WatchpointHit, WatchpointKind, WatchpointSetting};
+pub use write_history::{WriteRecord, WriteStatus};
+pub use xcp_master::{XcpSetting, XcpTransport};
 
 pub use elf_parser::search_target_mcu_name;
@@ -0,0 +1,535 @@
+use std::path::Path;
+
+use super::session_export::{Recording, TimeBase};
+
+// ----------------------------------------------------------------------------
+// Thrift compact protocolの型番号
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+// parquet.thrift の列挙値
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+const TIMESTAMP_MILLIS: i32 = 9;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const UNCOMPRESSED: i32 = 0;
+const DATA_PAGE: i32 = 0;
+
+/// Minimal Thrift compact protocol encoder for the Parquet footer and page headers.
+#[derive(Default)]
+struct Thrift {
+    buf: Vec<u8>,
+    last_field: Vec<i16>,
+}
+
+impl Thrift {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last_field.last_mut().expect("field outside a struct");
+        let delta = id - std::mem::replace(last, id);
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | kind);
+        } else {
+            self.buf.push(kind);
+            self.varint(((id << 1) ^ (id >> 15)) as u16 as u64);
+        }
+    }
+
+    fn begin(&mut self) {
+        self.last_field.push(0);
+    }
+
+    fn end(&mut self) {
+        self.buf.push(0);
+        self.last_field.pop();
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, T_I32);
+        self.varint(((value << 1) ^ (value >> 31)) as u32 as u64);
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, T_I64);
+        self.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn string(&mut self, id: i16, text: &str) {
+        self.field(id, T_BINARY);
+        self.raw_string(text);
+    }
+
+    fn raw_string(&mut self, text: &str) {
+        self.varint(text.len() as u64);
+        self.buf.extend_from_slice(text.as_bytes());
+    }
+
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, T_LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | kind);
+        } else {
+            self.buf.push(0xF0 | kind);
+            self.varint(len as u64);
+        }
+    }
+
+    fn struct_field(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.begin();
+    }
+}
+// ----------------------------------------------------------------------------
+struct Column {
+    name: String,
+    physical_type: i32,
+    optional: bool,
+    converted_type: Option<i32>,
+    num_values: usize,
+    /// Definition levels and PLAIN values of the only data page.
+    page: Vec<u8>,
+}
+
+// 定義レベル (ビット幅1) をRLE/ビットパックのハイブリッドで符号化する
+fn definition_levels(present: &[bool]) -> Vec<u8> {
+    let mut levels = Thrift::default();
+    levels.varint((present.len() as u64).div_ceil(8) << 1 | 1);
+    for group in present.chunks(8) {
+        let byte = group
+            .iter()
+            .enumerate()
+            .fold(0u8, |byte, (bit, set)| byte | (u8::from(*set) << bit));
+        levels.buf.push(byte);
+    }
+    let mut page = (levels.buf.len() as u32).to_le_bytes().to_vec();
+    page.extend(levels.buf);
+    page
+}
+
+/// Writes the wide layout (a time column and one nullable DOUBLE column per channel)
+/// as a single row group Parquet file. Units are kept in the key/value metadata.
+pub(super) fn write_parquet(recording: &Recording, path: &Path) -> std::io::Result<()> {
+    let rows = recording.rows();
+    let mut columns = Vec::new();
+
+    let mut page = Vec::new();
+    for (time_ms, _) in &rows {
+        match recording.time_base {
+            TimeBase::Relative => page.extend((*time_ms as f64 / 1000.0).to_le_bytes()),
+            TimeBase::WallClock => {
+                page.extend((recording.wall_clock_ms(*time_ms) as i64).to_le_bytes())
+            }
+        }
+    }
+    columns.push(match recording.time_base {
+        TimeBase::Relative => Column {
+            name: "time_s".to_string(),
+            physical_type: TYPE_DOUBLE,
+            optional: false,
+            converted_type: None,
+            num_values: rows.len(),
+            page,
+        },
+        TimeBase::WallClock => Column {
+            name: "time_utc".to_string(),
+            physical_type: TYPE_INT64,
+            optional: false,
+            converted_type: Some(TIMESTAMP_MILLIS),
+            num_values: rows.len(),
+            page,
+        },
+    });
+
+    for (index, channel) in recording.channels.iter().enumerate() {
+        let present: Vec<bool> = rows
+            .iter()
+            .map(|(_, values)| values[index].is_some())
+            .collect();
+        let mut page = definition_levels(&present);
+        for (_, values) in &rows {
+            if let Some(value) = values[index] {
+                page.extend(value.to_le_bytes());
+            }
+        }
+        columns.push(Column {
+            name: channel.name.clone(),
+            physical_type: TYPE_DOUBLE,
+            optional: true,
+            converted_type: None,
+            num_values: rows.len(),
+            page,
+        });
+    }
+
+    let mut file = b"PAR1".to_vec();
+    // (データページの位置, ヘッダ込みの大きさ)
+    let mut chunks = Vec::new();
+    for column in &columns {
+        let mut header = Thrift::default();
+        header.begin();
+        header.i32(1, DATA_PAGE);
+        header.i32(2, column.page.len() as i32);
+        header.i32(3, column.page.len() as i32);
+        header.struct_field(5);
+        header.i32(1, column.num_values as i32);
+        header.i32(2, ENCODING_PLAIN);
+        header.i32(3, ENCODING_RLE);
+        header.i32(4, ENCODING_RLE);
+        header.end();
+        header.end();
+
+        let offset = file.len();
+        file.extend(&header.buf);
+        file.extend(&column.page);
+        chunks.push((offset, header.buf.len() + column.page.len()));
+    }
+
+    let mut meta = Thrift::default();
+    meta.begin();
+    meta.i32(1, 1);
+    // スキーマ: ルートと各列
+    meta.list(2, T_STRUCT, columns.len() + 1);
+    meta.begin();
+    meta.string(4, "schema");
+    meta.i32(5, columns.len() as i32);
+    meta.end();
+    for column in &columns {
+        meta.begin();
+        meta.i32(1, column.physical_type);
+        meta.i32(3, if column.optional { OPTIONAL } else { REQUIRED });
+        meta.string(4, &column.name);
+        if let Some(converted_type) = column.converted_type {
+            meta.i32(6, converted_type);
+        }
+        meta.end();
+    }
+    meta.i64(3, rows.len() as i64);
+
+    let total_size: usize = chunks.iter().map(|(_, size)| size).sum();
+    meta.list(4, T_STRUCT, 1);
+    meta.begin();
+    meta.list(1, T_STRUCT, columns.len());
+    for (column, (offset, size)) in columns.iter().zip(&chunks) {
+        meta.begin();
+        meta.i64(2, *offset as i64);
+        meta.struct_field(3);
+        meta.i32(1, column.physical_type);
+        meta.list(2, T_I32, 2);
+        meta.varint(((ENCODING_PLAIN << 1) ^ (ENCODING_PLAIN >> 31)) as u64);
+        meta.varint(((ENCODING_RLE << 1) ^ (ENCODING_RLE >> 31)) as u64);
+        meta.list(3, T_BINARY, 1);
+        meta.raw_string(&column.name);
+        meta.i32(4, UNCOMPRESSED);
+        meta.i64(5, column.num_values as i64);
+        meta.i64(6, *size as i64);
+        meta.i64(7, *size as i64);
+        meta.i64(9, *offset as i64);
+        meta.end();
+        meta.end();
+    }
+    meta.i64(2, total_size as i64);
+    meta.i64(3, rows.len() as i64);
+    meta.end();
+
+    let units: Vec<_> = recording
+        .channels
+        .iter()
+        .filter(|channel| !channel.conversion.unit.is_empty())
+        .collect();
+    if !units.is_empty() {
+        meta.list(5, T_STRUCT, units.len());
+        for channel in units {
+            meta.begin();
+            meta.string(1, &format!("unit:{}", channel.name));
+            meta.string(2, &channel.conversion.unit);
+            meta.end();
+        }
+    }
+    meta.string(6, "VisuModiRam");
+    meta.end();
+
+    file.extend(&meta.buf);
+    file.extend((meta.buf.len() as u32).to_le_bytes());
+    file.extend(b"PAR1");
+    std::fs::write(path, file)
+}
+
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::super::a2l::Conversion;
+    use super::super::session_export::{ExportChannel, RecordedSession};
+    use super::*;
+
+    /// Value decoded with the Thrift compact protocol.
+    #[derive(Debug)]
+    enum Field {
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Field>),
+        Struct(Vec<(i16, Field)>),
+    }
+
+    impl Field {
+        fn get(&self, id: i16) -> &Field {
+            match self {
+                Field::Struct(fields) => fields
+                    .iter()
+                    .find(|(i, _)| *i == id)
+                    .map(|(_, field)| field)
+                    .unwrap_or_else(|| panic!("field {id} is missing")),
+                _ => panic!("not a struct"),
+            }
+        }
+
+        fn has(&self, id: i16) -> bool {
+            matches!(self, Field::Struct(fields) if fields.iter().any(|(i, _)| *i == id))
+        }
+
+        fn int(&self) -> i64 {
+            match self {
+                Field::Int(value) => *value,
+                _ => panic!("not an integer"),
+            }
+        }
+
+        fn text(&self) -> &str {
+            match self {
+                Field::Binary(bytes) => std::str::from_utf8(bytes).unwrap(),
+                _ => panic!("not a binary"),
+            }
+        }
+
+        fn list(&self) -> &[Field] {
+            match self {
+                Field::List(list) => list,
+                _ => panic!("not a list"),
+            }
+        }
+    }
+
+    struct Reader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl Reader<'_> {
+        fn byte(&mut self) -> u8 {
+            self.pos += 1;
+            self.data[self.pos - 1]
+        }
+
+        fn varint(&mut self) -> u64 {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = self.byte();
+                value |= ((byte & 0x7F) as u64) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            value
+        }
+
+        fn zigzag(&mut self) -> i64 {
+            let value = self.varint();
+            (value >> 1) as i64 ^ -((value & 1) as i64)
+        }
+
+        fn value(&mut self, kind: u8) -> Field {
+            match kind {
+                T_I32 | T_I64 => Field::Int(self.zigzag()),
+                T_BINARY => {
+                    let len = self.varint() as usize;
+                    self.pos += len;
+                    Field::Binary(self.data[self.pos - len..self.pos].to_vec())
+                }
+                T_LIST => {
+                    let header = self.byte();
+                    let len = match header >> 4 {
+                        15 => self.varint() as usize,
+                        len => len as usize,
+                    };
+                    Field::List((0..len).map(|_| self.value(header & 0x0F)).collect())
+                }
+                T_STRUCT => {
+                    let mut fields = Vec::new();
+                    let mut last = 0;
+                    loop {
+                        let header = self.byte();
+                        if header == 0 {
+                            break;
+                        }
+                        let id = match header >> 4 {
+                            0 => self.zigzag() as i16,
+                            delta => last + delta as i16,
+                        };
+                        last = id;
+                        fields.push((id, self.value(header & 0x0F)));
+                    }
+                    Field::Struct(fields)
+                }
+                kind => panic!("unexpected type {kind}"),
+            }
+        }
+    }
+
+    fn recording(time_base: TimeBase) -> Recording {
+        let speed = ExportChannel {
+            name: "speed".to_string(),
+            conversion: Conversion {
+                factor: 0.5,
+                offset: -3.0,
+                unit: "km/h".to_string(),
+                calibration: false,
+            },
+            samples: vec![(1000, 1.0), (1100, 2.5), (1200, -3.0)],
+        };
+        let count = ExportChannel {
+            name: "count".to_string(),
+            conversion: Conversion::default(),
+            samples: vec![(1000, 7.0), (1200, 9.0)],
+        };
+        let session = RecordedSession {
+            channels: vec![],
+            clock: vec![(0, 1_700_000_000_000)],
+        };
+        Recording::new(vec![speed, count], time_base, session)
+    }
+
+    /// (footer, page header and values of every column) of a written file.
+    fn write_and_read(recording: &Recording, name: &str) -> (Field, Vec<(Field, Vec<u8>)>) {
+        let path = std::env::temp_dir().join(format!("vmr_{}_{}", std::process::id(), name));
+        write_parquet(recording, &path).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&file[..4], b"PAR1");
+        assert_eq!(&file[file.len() - 4..], b"PAR1");
+        let end = file.len() - 8;
+        let footer_len = u32::from_le_bytes(file[end..end + 4].try_into().unwrap()) as usize;
+        let mut reader = Reader {
+            data: &file[..end],
+            pos: end - footer_len,
+        };
+        let footer = reader.value(T_STRUCT);
+        assert_eq!(reader.pos, end);
+
+        let row_groups = footer.get(4).list();
+        assert_eq!(row_groups.len(), 1);
+        let mut pages = Vec::new();
+        for chunk in row_groups[0].get(1).list() {
+            let offset = chunk.get(2).int() as usize;
+            let meta = chunk.get(3);
+            assert_eq!(meta.get(9).int() as usize, offset);
+            let mut reader = Reader {
+                data: &file,
+                pos: offset,
+            };
+            let header = reader.value(T_STRUCT);
+            let size = header.get(2).int() as usize;
+            assert_eq!(header.get(3).int() as usize, size);
+            assert_eq!(reader.pos + size - offset, meta.get(6).int() as usize);
+            pages.push((header, file[reader.pos..reader.pos + size].to_vec()));
+        }
+        (footer, pages)
+    }
+
+    fn doubles(bytes: &[u8]) -> Vec<f64> {
+        bytes
+            .chunks(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn encodes_thrift_compact_fields() {
+        let mut thrift = Thrift::default();
+        thrift.begin();
+        thrift.i32(1, 1);
+        thrift.i64(3, -2);
+        // 差が15を超えるフィールド番号は長い形式
+        thrift.string(20, "ab");
+        thrift.list(21, T_I32, 20);
+        thrift.struct_field(22);
+        thrift.i32(1, -300);
+        thrift.end();
+        thrift.end();
+        assert_eq!(
+            thrift.buf,
+            [
+                0x15, 0x02, 0x26, 0x03, 0x08, 0x28, 0x02, b'a', b'b', 0x19, 0xF5, 0x14, 0x1C, 0x15,
+                0xD7, 0x04, 0x00, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_a_wide_table_with_relative_time() {
+        let (footer, pages) = write_and_read(&recording(TimeBase::Relative), "relative.parquet");
+        assert_eq!(footer.get(1).int(), 1);
+        assert_eq!(footer.get(3).int(), 3);
+        assert_eq!(footer.get(6).text(), "VisuModiRam");
+
+        let schema = footer.get(2).list();
+        let names: Vec<&str> = schema.iter().map(|s| s.get(4).text()).collect();
+        assert_eq!(names, ["schema", "time_s", "speed", "count"]);
+        assert_eq!(schema[0].get(5).int(), 3);
+        for (element, repetition) in schema[1..].iter().zip([REQUIRED, OPTIONAL, OPTIONAL]) {
+            assert_eq!(element.get(1).int(), TYPE_DOUBLE as i64);
+            assert_eq!(element.get(3).int(), repetition as i64);
+            assert!(!element.has(6));
+        }
+
+        let units = footer.get(5).list();
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].get(1).text(), "unit:speed");
+        assert_eq!(units[0].get(2).text(), "km/h");
+
+        for ((header, _), name) in pages.iter().zip(&names[1..]) {
+            assert_eq!(header.get(1).int(), DATA_PAGE as i64);
+            assert_eq!(header.get(5).get(1).int(), 3, "{name}");
+        }
+        assert_eq!(doubles(&pages[0].1), [1.0, 1.1, 1.2]);
+
+        // 定義レベル: 長さ, ビットパック1グループ, ビット列
+        let speed = &pages[1].1;
+        assert_eq!(speed[..6], [2, 0, 0, 0, 0x03, 0b111]);
+        assert_eq!(doubles(&speed[6..]), [1.0, 2.5, -3.0]);
+        let count = &pages[2].1;
+        assert_eq!(count[..6], [2, 0, 0, 0, 0x03, 0b101]);
+        assert_eq!(doubles(&count[6..]), [7.0, 9.0]);
+    }
+
+    #[test]
+    fn writes_wall_clock_time_as_timestamp_millis() {
+        let (footer, pages) = write_and_read(&recording(TimeBase::WallClock), "wall.parquet");
+        let time = &footer.get(2).list()[1];
+        assert_eq!(time.get(4).text(), "time_utc");
+        assert_eq!(time.get(1).int(), TYPE_INT64 as i64);
+        assert_eq!(time.get(6).int(), TIMESTAMP_MILLIS as i64);
+
+        let times: Vec<i64> = pages[0]
+            .1
+            .chunks(8)
+            .map(|b| i64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            times,
+            [1_700_000_001_000, 1_700_000_001_100, 1_700_000_001_200]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use probe_rs::{Permissions, Probe, Session, WireProtocol};
+
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ProbeProtocol {
+    Swd,
+    Jtag,
+}
+
+impl std::fmt::Display for ProbeProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Swd => write!(f, "SWD"),
+            Self::Jtag => write!(f, "JTAG"),
+        }
+    }
+}
+
+impl Default for ProbeProtocol {
+    fn default() -> Self {
+        Self::Swd
+    }
+}
+
+impl From<ProbeProtocol> for WireProtocol {
+    fn from(protocol: ProbeProtocol) -> Self {
+        match protocol {
+            ProbeProtocol::Swd => WireProtocol::Swd,
+            ProbeProtocol::Jtag => WireProtocol::Jtag,
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+/// How the debug probe connects to the target, shared by the watch loop and the flasher.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ProbeConfig {
+    /// Clock of the debug interface; 0 keeps the probe's default.
+    pub speed_khz: u32,
+    pub protocol: ProbeProtocol,
+    /// Hold NRST while connecting, for chips that sleep or disable the debug pins.
+    pub connect_under_reset: bool,
+    /// Never halt or reset the core on our own (hot attach to a running system).
+    pub attach_without_reset: bool,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            speed_khz: 4000,
+            protocol: ProbeProtocol::Swd,
+            connect_under_reset: false,
+            attach_without_reset: false,
+        }
+    }
+}
+
+impl ProbeConfig {
+    /// Rejects option combinations that can not work together.
+    pub fn check(&self) -> Result<(), String> {
+        if self.connect_under_reset && self.attach_without_reset {
+            return Err(
+                "\"connect under reset\" resets the target, it can not be combined with \"attach without reset\""
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Like `check`, for programming the flash.
+    pub fn check_flash(&self) -> Result<(), String> {
+        self.check()?;
+        if self.attach_without_reset {
+            return Err(
+                "flashing halts and resets the core, turn off \"attach without reset\"".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Opens the probe `probe_sn` with these settings and attaches to `target_mcu`.
+    pub fn attach(&self, probe_sn: &str, target_mcu: &str) -> Result<Session, probe_rs::Error> {
+        self.check()
+            .map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e)))?;
+
+        let info = Probe::list_all()
+            .into_iter()
+            .find(|probe| probe.serial_number.as_deref() == Some(probe_sn))
+            .ok_or_else(|| probe_rs::Error::Other(anyhow::anyhow!("No matching probe found")))?;
+        let name = info.identifier.clone();
+        let mut probe = info.open()?;
+
+        probe.select_protocol(self.protocol.into()).map_err(|e| {
+            probe_rs::Error::Other(anyhow::anyhow!(
+                "{} does not support {}: {}",
+                name,
+                self.protocol,
+                e
+            ))
+        })?;
+        if self.speed_khz != 0 {
+            let _actual = probe.set_speed(self.speed_khz).map_err(|e| {
+                probe_rs::Error::Other(anyhow::anyhow!(
+                    "{} can not run {} at {} kHz: {}",
+                    name,
+                    self.protocol,
+                    self.speed_khz,
+                    e
+                ))
+            })?;
+            #[cfg(debug_assertions)]
+            println!("{} speed {} kHz", self.protocol, _actual);
+        }
+
+        if self.connect_under_reset {
+            probe.attach_under_reset(target_mcu.to_string(), Permissions::default())
+        } else {
+            probe.attach(target_mcu.to_string(), Permissions::default())
+        }
+    }
+}
@@ -1,23 +1,148 @@
 use super::elf_parser::VariableInfo;
 use probe_rs::{
+    architecture::arm::{component::TraceSink, SwoConfig},
     flashing::{self, DownloadOptions, FlashProgress},
-    DebugProbeError, Permissions, Probe,
+    DebugProbeError,
 };
 use sensorlog::{logfile_config::LogfileConfig, measure::Measurement, quota, Sensorlog};
 use shellexpand;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use stopwatch::Stopwatch;
 
+use super::a2l::Conversion;
+use super::call_stack::{CallStack, StackFrame};
+use super::cfi_unwinder::CfiTable;
+use super::defmt_decoder::{DefmtDecoder, DefmtPlotChannel};
+use super::dwarf_locals::DwarfLocals;
+use super::elf_parser::search_symbol_address;
+use super::elf_symbols::ElfSymbols;
+use super::fault_analyzer::{FaultAnalyzer, FaultReport};
+use super::flash_options::{AfterFlash, EraseMode, FlashReport, FlashSetting};
+use super::literal_parser::ScalarType;
 use super::memory_interface::MCUMemory;
+use super::memory_view::{MemoryBlock, MemoryViews};
+use super::probe_config::ProbeConfig;
+use super::ram_snapshot::RamSnapshot;
+use super::rtt_channel::{RttLine, RttLog, RttSession, RttStatus};
+use super::run_control::{HaltCause, RunCommand, RunControl, TargetStatus};
+use super::semihosting::{Semihosting, SemihostingIo, SemihostingState};
+use super::session_event::{SessionEvent, SessionEventKind};
+use super::session_export::{
+    export_session, log_directory, ExportSetting, RecordedChannel, RecordedSession,
+};
+use super::session_health::{ConnectionState, ReconnectPolicy, SessionHealth};
+use super::stack_monitor::{StackMonitor, StackSetting, StackUsage, STACK_CHANNEL_TYPE};
+use super::swo_trace::{ItmDecoder, SwoSetting, SwoTrace, TraceChannelMapper, ITM_CHANNEL_TYPE};
+use super::symbol_map::SymbolMap;
+use super::symbol_resolver::SymbolResolver;
+use super::target_backend::BackendSetting;
+use super::watchpoint::{DataWatchpoint, WatchpointHit, WatchpointKind, WatchpointSetting};
+use super::write_history::{WriteHistory, WriteRecord, WriteStatus};
 // ----------------------------------------------------------------------------
 #[derive(Default, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct WatchSetting {
+    /// Name of the target in a workspace with several targets; empty for a single target.
+    pub target_name: String,
     pub target_mcu: String,
     pub probe_sn: String,
     pub watch_list: Vec<VariableInfo>,
+    pub elf_path: String,
+    pub probe_config: ProbeConfig,
+    pub backend: BackendSetting,
+    /// Scaling of watch list symbols by name; the log holds physical values.
+    pub conversions: BTreeMap<String, Conversion>,
+}
+
+impl WatchSetting {
+    /// Name of `symbol` in the log, `target:symbol` when the target has a name.
+    pub fn channel_name(&self, symbol: &str) -> String {
+        if self.target_name.is_empty() {
+            symbol.to_string()
+        } else {
+            format!("{}:{}", self.target_name, symbol)
+        }
+    }
+
+    /// Physical value of `raw` read from `symbol`, `raw` itself when it is not scaled.
+    pub fn physical(&self, symbol: &str, raw: &str) -> String {
+        match self.conversions.get(symbol) {
+            Some(conversion) => conversion.physical(raw),
+            None => raw.to_string(),
+        }
+    }
+
+    /// The symbol part of `channel` if it belongs to this target.
+    pub fn symbol_name<'a>(&self, channel: &'a str) -> Option<&'a str> {
+        if self.target_name.is_empty() {
+            return Some(channel);
+        }
+        channel
+            .strip_prefix(self.target_name.as_str())
+            .and_then(|symbol| symbol.strip_prefix(':'))
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SessionOptions {
+    pub rtt_enable: bool,
+    pub rtt_log_to_storage: bool,
+    pub defmt_enable: bool,
+    pub swo: SwoSetting,
+    pub semihosting_enable: bool,
+    pub stack: StackSetting,
+    pub fault_detect: bool,
+    pub reconnect: ReconnectPolicy,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            rtt_enable: false,
+            rtt_log_to_storage: true,
+            defmt_enable: false,
+            swo: Default::default(),
+            semihosting_enable: true,
+            stack: Default::default(),
+            fault_detect: true,
+            reconnect: Default::default(),
+        }
+    }
+}
+
+// 書き込み後にmainまで走らせるときの待ち時間
+const FLASH_HALT_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(1000);
+// 再接続を待つ間に停止要求を確認する間隔
+const RECONNECT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+const RTT_ATTACH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+const DEFMT_CHANNEL_TYPE: &str = "defmt";
+const TRACE_FILE_READ_BUFFER: usize = 4096;
+// printfは1文字ずつ呼ばれることがあるので、1周期で続けて処理する回数
+const SEMIHOSTING_BURST: usize = 64;
+
+// Arc<Mutex<Vec<T>>> を中身だけ保存する
+#[cfg(feature = "serde")]
+mod shared_vec {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::{Arc, Mutex};
+
+    pub fn serialize<S: Serializer, T: Serialize>(
+        value: &Arc<Mutex<Vec<T>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.lock().unwrap().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<Mutex<Vec<T>>>, D::Error> {
+        Ok(Arc::new(Mutex::new(Vec::<T>::deserialize(deserializer)?)))
+    }
 }
 // ----------------------------------------------------------------------------
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -54,6 +179,7 @@ pub struct Progress {
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ProbeInterface {
     pub setting: WatchSetting,
+    pub options: SessionOptions,
     #[cfg_attr(feature = "serde", serde(skip))]
     flash_progress: Arc<Mutex<Progress>>,
 
@@ -65,13 +191,65 @@ pub struct ProbeInterface {
     write_que: Arc<Mutex<BTreeMap<VariableInfo, String>>>,
     #[cfg_attr(feature = "serde", serde(skip))]
     log_timer: Arc<Mutex<Stopwatch>>,
+    // タイマーを共有している監視スレッドの数 (最後のスレッドだけがタイマーを止める)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchers: Arc<Mutex<usize>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    run_command_que: Arc<Mutex<Vec<RunCommand>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    target_status: Arc<Mutex<TargetStatus>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchpoints: Arc<Mutex<Vec<WatchpointSetting>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchpoint_hits: Arc<Mutex<Vec<WatchpointHit>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rtt_log: Arc<Mutex<RttLog>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rtt_down_que: Arc<Mutex<Vec<(usize, String)>>>,
+    #[cfg_attr(feature = "serde", serde(with = "shared_vec"))]
+    defmt_channels: Arc<Mutex<Vec<DefmtPlotChannel>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    semihosting: Arc<Mutex<SemihostingIo>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    session_events: Arc<Mutex<Vec<SessionEvent>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    write_history: Arc<Mutex<WriteHistory>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    memory_views: Arc<Mutex<MemoryViews>>,
+    // (読み込んだELFのパス, シンボル表)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    symbol_map: Arc<Mutex<(String, Option<Arc<SymbolMap>>)>>,
+    // RAMスナップショットの要求 (Some(halt)) と結果
+    #[cfg_attr(feature = "serde", serde(skip))]
+    snapshot_request: Arc<Mutex<Option<bool>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    snapshot_result: Arc<Mutex<Option<Result<RamSnapshot, String>>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    stack_usage: Arc<Mutex<Vec<StackUsage>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    fault_report: Arc<Mutex<Option<FaultReport>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    flash_report: Arc<Mutex<Option<FlashReport>>>,
+    // 停止中のコールスタック (動作中はNone) と再評価の要求
+    #[cfg_attr(feature = "serde", serde(skip))]
+    call_stack: Arc<Mutex<Option<Result<Vec<StackFrame>, String>>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    call_stack_request: Arc<Mutex<bool>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    session_health: Arc<Mutex<SessionHealth>>,
+    // 同時に見る他のターゲット (ログとタイマーを共有する)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    peers: Arc<Mutex<Vec<ProbeInterface>>>,
+    // 書き出し用のチャンネルと実時間の記録
+    #[cfg_attr(feature = "serde", serde(skip))]
+    session: Arc<Mutex<RecordedSession>>,
 }
 
-fn log_service_default() -> Sensorlog {
+pub(super) fn log_service_default() -> Sensorlog {
     let mut logfile_config = LogfileConfig::new();
     logfile_config.set_default_storage_quota(quota::StorageQuota::Unlimited);
 
-    let datadir = PathBuf::from(format!("{}", shellexpand::tilde("~/.EguiMonitorLog")));
+    let datadir = log_directory();
     if !datadir.exists() {
         std::fs::create_dir_all(&datadir).expect("faild create logfile dir");
     }
@@ -83,11 +261,35 @@ impl Default for ProbeInterface {
     fn default() -> Self {
         Self {
             setting: Default::default(),
+            options: Default::default(),
             watching_flag: Arc::new(Mutex::new(false)),
             log_service: Arc::new(Mutex::new(log_service_default())),
             write_que: Arc::new(Mutex::new(BTreeMap::new())),
             log_timer: Arc::new(Mutex::new(Stopwatch::new())),
+            watchers: Arc::new(Mutex::new(0)),
             flash_progress: Arc::new(Mutex::new(Default::default())),
+            run_command_que: Arc::new(Mutex::new(Vec::new())),
+            target_status: Arc::new(Mutex::new(Default::default())),
+            watchpoints: Arc::new(Mutex::new(Vec::new())),
+            watchpoint_hits: Arc::new(Mutex::new(Vec::new())),
+            rtt_log: Arc::new(Mutex::new(Default::default())),
+            rtt_down_que: Arc::new(Mutex::new(Vec::new())),
+            defmt_channels: Arc::new(Mutex::new(Vec::new())),
+            semihosting: Arc::new(Mutex::new(Default::default())),
+            session_events: Arc::new(Mutex::new(Vec::new())),
+            write_history: Arc::new(Mutex::new(Default::default())),
+            memory_views: Arc::new(Mutex::new(Default::default())),
+            symbol_map: Arc::new(Mutex::new((String::new(), None))),
+            snapshot_request: Arc::new(Mutex::new(None)),
+            snapshot_result: Arc::new(Mutex::new(None)),
+            stack_usage: Arc::new(Mutex::new(Vec::new())),
+            fault_report: Arc::new(Mutex::new(None)),
+            flash_report: Arc::new(Mutex::new(None)),
+            call_stack: Arc::new(Mutex::new(None)),
+            call_stack_request: Arc::new(Mutex::new(false)),
+            session_health: Arc::new(Mutex::new(Default::default())),
+            peers: Arc::new(Mutex::new(Vec::new())),
+            session: Arc::new(Mutex::new(RecordedSession::default())),
         }
     }
 }
@@ -98,6 +300,99 @@ impl ProbeInterface {
         Ok(())
     }
 
+    /// Sets every target of the workspace. The first one is watched by this interface,
+    /// the others share its log and time base and are addressed as `target:symbol`.
+    /// Nothing is changed while any target is being watched.
+    pub fn set_targets(&mut self, mut settings: Vec<WatchSetting>) -> Result<(), probe_rs::Error> {
+        if settings.is_empty() {
+            return Ok(());
+        }
+        // 動作中のスレッドを見失わないように、止まっている間だけ入れ替える
+        let watching = self.now_watching()
+            || self
+                .peers
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .any(|peer| peer.now_watching());
+        if watching {
+            return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                "stop watching before changing the targets"
+            )));
+        }
+        let others = settings.split_off(1);
+        self.set_probe(settings.remove(0))?;
+
+        let peers = others
+            .into_iter()
+            .map(|setting| Self {
+                setting,
+                options: self.options.clone(),
+                log_service: Arc::clone(&self.log_service),
+                log_timer: Arc::clone(&self.log_timer),
+                watchers: Arc::clone(&self.watchers),
+                session: Arc::clone(&self.session),
+                ..Default::default()
+            })
+            .collect();
+        *self.peers.lock().unwrap() = peers;
+        Ok(())
+    }
+
+    /// Name of this target and of the other targets of the workspace.
+    pub fn get_target_names(&mut self) -> Vec<String> {
+        let mut names = vec![self.setting.target_name.clone()];
+        for peer in self.peers.lock().unwrap().iter() {
+            names.push(peer.setting.target_name.clone());
+        }
+        names
+    }
+
+    // チャンネル名からターゲットを探し、そのターゲットとシンボル名を返す
+    fn route(&mut self, channel: &str) -> (Option<ProbeInterface>, String) {
+        for peer in self.peers.lock().unwrap().iter() {
+            if peer.setting.target_name.is_empty() {
+                continue;
+            }
+            if let Some(symbol) = peer.setting.symbol_name(channel) {
+                return (Some(peer.clone()), symbol.to_string());
+            }
+        }
+        let symbol = self.setting.symbol_name(channel).unwrap_or(channel);
+        (None, symbol.to_string())
+    }
+
+    // タイマーを動かすときに、その時刻と実時間・チャンネルをセッションファイルに残す
+    fn start_log_timer(&mut self) {
+        let elapsed = {
+            let mut timer = self.log_timer.lock().unwrap();
+            if timer.is_running() {
+                return;
+            }
+            timer.start();
+            timer.elapsed_ms().max(0) as u64
+        };
+        let mut channels = Vec::new();
+        for channel in self.get_channel_list() {
+            let (peer, symbol) = self.route(&channel.name);
+            let setting = match &peer {
+                Some(peer) => &peer.setting,
+                None => &self.setting,
+            };
+            channels.push(RecordedChannel {
+                conversion: setting
+                    .conversions
+                    .get(&symbol)
+                    .cloned()
+                    .unwrap_or_default(),
+                name: channel.name,
+            });
+        }
+        let mut session = self.session.lock().unwrap();
+        session.start(elapsed, channels);
+        let _res = session.save();
+    }
+
     pub fn watching_start(
         &mut self,
         duration: std::time::Duration,
@@ -114,83 +409,1294 @@ impl ProbeInterface {
         let _write_que = Arc::clone(&self.write_que);
 
         let _log_timer = Arc::clone(&self.log_timer);
-        _log_timer.lock().unwrap().start();
+        let _watchers = Arc::clone(&self.watchers);
+        // 他のターゲットとタイマーを共有しているので、動いていれば触らない
+        self.start_log_timer();
+        *_watchers.lock().unwrap() += 1;
+
+        let _run_command_que = Arc::clone(&self.run_command_que);
+        let _target_status = Arc::clone(&self.target_status);
+        _run_command_que.lock().unwrap().clear();
+        let _watchpoints = Arc::clone(&self.watchpoints);
+        let _watchpoint_hits = Arc::clone(&self.watchpoint_hits);
+        let _rtt_log = Arc::clone(&self.rtt_log);
+        let _rtt_down_que = Arc::clone(&self.rtt_down_que);
+        _rtt_down_que.lock().unwrap().clear();
+        let _defmt_channels = Arc::clone(&self.defmt_channels);
+        let _semihosting = Arc::clone(&self.semihosting);
+        _semihosting.lock().unwrap().exit_code = None;
+        let _session_events = Arc::clone(&self.session_events);
+        let _write_history = Arc::clone(&self.write_history);
+        let _memory_views = Arc::clone(&self.memory_views);
+        let _snapshot_request = Arc::clone(&self.snapshot_request);
+        let _snapshot_result = Arc::clone(&self.snapshot_result);
+        let _stack_usage = Arc::clone(&self.stack_usage);
+        let _fault_report = Arc::clone(&self.fault_report);
+        let _call_stack = Arc::clone(&self.call_stack);
+        let _call_stack_request = Arc::clone(&self.call_stack_request);
+        let _session_health = Arc::clone(&self.session_health);
+        *_session_health.lock().unwrap() = SessionHealth {
+            state: ConnectionState::Connecting,
+            ..Default::default()
+        };
+
+        for peer in self.peers.lock().unwrap().iter_mut() {
+            peer.options = self.options.clone();
+            let _handle = peer.watching_start(duration);
+        }
+        if !self.setting.backend.is_probe() {
+            return self.backend_watching_start(duration);
+        }
 
         let setting = self.setting.clone();
+        let options = self.options.clone();
 
         std::thread::spawn(move || {
-            let probes = Probe::list_all();
+            // 複数ターゲットのときはチャンネルを`target:symbol`で記録する
+            let channel_name = |symbol: &str| setting.channel_name(symbol);
+            // ELFから読むものは再接続しても変わらない
+            let resolver = SymbolResolver::load(&PathBuf::from(&setting.elf_path));
+            let cfi = CfiTable::load(&PathBuf::from(&setting.elf_path));
+            let dwarf_locals = DwarfLocals::load(&PathBuf::from(&setting.elf_path));
+            // コールスタックを取ったときのPC (停止位置が変わったら取り直す)
+            let mut call_stack_pc: Option<Option<u64>> = None;
+            // 同じフォルトを何度も解析しない (フォルトから抜けたら戻す)
+            let mut fault_reported = false;
+            // 読み出しに失敗しているシンボル (イベントを1回だけ出す)
+            let mut read_errors: BTreeSet<String> = BTreeSet::new();
 
-            let probe = probes
-                .into_iter()
-                .find(|probe| probe.serial_number == Some(setting.probe_sn.clone()))
-                .ok_or_else(|| {
-                    std::io::Error::new(std::io::ErrorKind::Other, "No matching probe found")
-                })
-                .unwrap()
-                .open()
-                .unwrap();
-
-            // Attach to a chip.
-            let mut session = probe
-                .attach(setting.target_mcu.clone(), Permissions::default())
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-            // Select a core.
-            let mut core = session.core(0).or_else(|_| {
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "No matching probe found",
-                ))
-            })?;
-
-            loop {
-                let entry_time = std::time::Instant::now();
-                if *flag.lock().unwrap() == false {
-                    break;
-                }
+            // RTTの制御ブロックはELFのシンボルから探し、見つからなければRAMをスキャンする
+            let rtt_control_block =
+                search_symbol_address(&PathBuf::from(&setting.elf_path), "_SEGGER_RTT");
+            let mut rtt_session: Option<RttSession> = None;
+            let mut rtt_attach_time: Option<std::time::Instant> = None;
+            let mut defmt_decoder = if options.defmt_enable {
+                DefmtDecoder::load(&PathBuf::from(&setting.elf_path))
+            } else {
+                None
+            };
+            let mut defmt_channel = None;
+            let mut semihosting = Semihosting::default();
+            let mut semihosting_exited = false;
+            let mut stacks = if options.stack.enable {
+                StackMonitor::regions(&options.stack, &PathBuf::from(&setting.elf_path))
+            } else {
+                Vec::new()
+            };
+            *_stack_usage.lock().unwrap() = stacks.clone();
+            let mut stack_scan_time: Option<std::time::Instant> = None;
+            // 書き込み後、次の周期で読み戻す (history seq, symbol)
+            let mut pending_verify: Vec<(usize, VariableInfo)> = Vec::new();
 
-                for symbol in &setting.watch_list {
-                    let val_str = MCUMemory::read(&mut core, symbol);
-                    let now_time = _log_timer.lock().unwrap().elapsed_ms();
+            // 接続が切れた時刻に欠測値を入れるチャンネル
+            let gap_channels: Vec<String> = setting
+                .watch_list
+                .iter()
+                .map(|symbol| symbol.name.clone())
+                .chain(
+                    stacks
+                        .iter()
+                        .map(|usage| StackSetting::channel_name(&usage.name)),
+                )
+                .collect();
+            // 再接続の試行回数 (0 = 最初の接続)
+            let mut reconnect_attempt: u32 = 0;
 
-                    match _log_service.lock().unwrap().store_measurement(
-                        Some(now_time as u64),
-                        &symbol.name,
-                        &val_str,
-                    ) {
-                        Ok(_) => {}
+            let res: Result<(), std::io::Error> = loop {
+                // Attach to a chip.
+                let mut session = match setting
+                    .probe_config
+                    .attach(&setting.probe_sn, &setting.target_mcu)
+                {
+                    Ok(session) => session,
+                    Err(e) => {
+                        let error = std::io::Error::new(std::io::ErrorKind::Other, e.to_string());
+                        // 最初の接続に失敗したときは設定の誤りとみなして再試行しない
+                        if reconnect_attempt == 0 {
+                            break Err(error);
+                        }
+                        _session_health.lock().unwrap().error = Some(e.to_string());
+                        if wait_reconnect(
+                            &options.reconnect,
+                            &mut reconnect_attempt,
+                            &flag,
+                            &_log_timer,
+                            &_session_health,
+                        ) {
+                            continue;
+                        }
+                        break if *flag.lock().unwrap() {
+                            Err(error)
+                        } else {
+                            Ok(())
+                        };
+                    }
+                };
+                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                {
+                    let mut health = _session_health.lock().unwrap();
+                    health.set_state(ConnectionState::Connected, now_time);
+                    health.attempts = 0;
+                    health.error = None;
+                }
+                if reconnect_attempt > 0 {
+                    _session_events.lock().unwrap().push(SessionEvent {
+                        time_ms: now_time,
+                        kind: SessionEventKind::Info,
+                        message: format!("reconnected ({} attempts)", reconnect_attempt),
+                    });
+                    reconnect_attempt = 0;
+                }
+                if setting.probe_config.connect_under_reset {
+                    _session_events.lock().unwrap().push(SessionEvent {
+                        time_ms: now_time,
+                        kind: SessionEventKind::Info,
+                        message: "connected under reset".to_string(),
+                    });
+                }
+                // 自分からは止めない (フォルト解析やスナップショットでのhaltもしない)
+                let hot_attach = setting.probe_config.attach_without_reset;
+                let memory_map = session.target().memory_map.clone();
+
+                // SWO : TPIUとプローブ側の設定はprobe-rsに任せ、ITM/DWTはここで設定する
+                let swo_data_trace = if options.swo.enable {
+                    TraceChannelMapper::data_trace_symbols(&options.swo, &setting.watch_list)
+                } else {
+                    Vec::new()
+                };
+                let mut swo_trace = None;
+                if options.swo.enable {
+                    let config = SwoConfig::new(options.swo.core_clock_hz)
+                        .set_baud(options.swo.baud)
+                        .set_continuous_formatting(false);
+                    let res = session
+                        .setup_tracing(0, TraceSink::Swo(config))
+                        .and_then(|_| {
+                            let mut core = session.core(0)?;
+                            SwoTrace::configure(&mut core, &options.swo, &swo_data_trace)
+                        });
+                    match res {
+                        Ok(_) => {
+                            let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                            swo_trace = Some((
+                                ItmDecoder::default(),
+                                TraceChannelMapper::new(
+                                    &options.swo,
+                                    &setting.watch_list,
+                                    now_time,
+                                ),
+                            ));
+                        }
                         Err(_e) => {
                             #[cfg(debug_assertions)]
-                            println!("測定値の保存中にエラーが発生しました: {}", _e);
+                            println!("swo setup error: {}", _e);
+                        }
+                    }
+                }
+
+                // SWOのデータトレースが先頭のコンパレータを使うので、watchpointはその後ろから使う
+                let watchpoint_slot_base = if swo_trace.is_some() {
+                    swo_data_trace.len()
+                } else {
+                    0
+                };
+
+                // watchpointの要求リストと、実際にDWTへ設定したリスト(index = comparator番号)
+                // 再接続したときは設定し直す
+                let mut requested_watchpoints: Vec<WatchpointSetting> = Vec::new();
+                let mut armed_watchpoints: Vec<WatchpointSetting> = Vec::new();
+
+                // 切断を検出したらその理由を返す (Noneは停止要求)
+                let lost: Option<String> = loop {
+                    let entry_time = std::time::Instant::now();
+                    if *flag.lock().unwrap() == false {
+                        break None;
+                    }
+
+                    // トレースデータはSessionから読むので、Coreを取得する前に読む
+                    if let Some((decoder, mapper)) = &mut swo_trace {
+                        match session.read_trace_data() {
+                            Ok(bytes) => {
+                                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                                let packets = decoder.feed(&bytes);
+                                for (time, channel, value) in mapper.map(packets, now_time) {
+                                    let _res = _log_service.lock().unwrap().store_measurement(
+                                        Some(time as u64),
+                                        &channel_name(&channel),
+                                        &setting.physical(&channel, &value),
+                                    );
+                                }
+                            }
+                            Err(_e) => {
+                                #[cfg(debug_assertions)]
+                                println!("swo read error: {}", _e);
+                            }
+                        }
+                    }
+
+                    // Select a core.
+                    let mut core = match session.core(0) {
+                        Ok(core) => core,
+                        Err(e) => break Some(e.to_string()),
+                    };
+
+                    let run_commands = _run_command_que.lock().unwrap().clone();
+                    _run_command_que.lock().unwrap().clear();
+                    for command in run_commands {
+                        let res = RunControl::execute(&mut core, command);
+                        #[cfg(debug_assertions)]
+                        println!("run control {} {:?}", command, res);
+
+                        // リセット直後はSPがスタックの先頭にあるので、その下を塗る
+                        if command == RunCommand::ResetAndHalt
+                            && res.is_ok()
+                            && options.stack.paint_on_reset
+                        {
+                            if let Some(main_stack) = stacks.first_mut() {
+                                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                                let event = match StackMonitor::paint(&mut core, main_stack) {
+                                    Ok(_) => SessionEvent {
+                                        time_ms: now_time,
+                                        kind: SessionEventKind::Info,
+                                        message: format!(
+                                            "stack painted ({} bytes)",
+                                            main_stack.size() - main_stack.high_water
+                                        ),
+                                    },
+                                    Err(e) => {
+                                        main_stack.error = Some(e.to_string());
+                                        SessionEvent {
+                                            time_ms: now_time,
+                                            kind: SessionEventKind::Error,
+                                            message: format!("stack paint failed: {}", e),
+                                        }
+                                    }
+                                };
+                                _session_events.lock().unwrap().push(event);
+                                stack_scan_time = None;
+                            }
+                        }
+                    }
+
+                    let watchpoints = _watchpoints.lock().unwrap().clone();
+                    if watchpoints != requested_watchpoints {
+                        for slot in 0..armed_watchpoints.len() {
+                            let _res =
+                                DataWatchpoint::disarm(&mut core, watchpoint_slot_base + slot);
+                        }
+                        armed_watchpoints.clear();
+                        for watchpoint in &watchpoints {
+                            match DataWatchpoint::arm(
+                                &mut core,
+                                watchpoint_slot_base + armed_watchpoints.len(),
+                                watchpoint,
+                            ) {
+                                Ok(_) => armed_watchpoints.push(watchpoint.clone()),
+                                Err(_e) => {
+                                    #[cfg(debug_assertions)]
+                                    println!("watchpoint {} : {}", watchpoint.symbol.name, _e);
+                                }
+                            }
+                        }
+                        requested_watchpoints = watchpoints;
+                    }
+
+                    let last_status = _target_status.lock().unwrap().clone();
+                    // DHCSRが読めないのはプローブかターゲットが応答していないとき
+                    let mut status =
+                        match RunControl::read_status(&mut core, resolver.as_ref(), &last_status) {
+                            Ok(status) => status,
+                            Err(e) => break Some(e.to_string()),
+                        };
+
+                    if status.halt_reason == Some(HaltCause::Watchpoint)
+                        && status.newly_halted(&last_status)
+                    {
+                        if let Ok(Some(slot)) = DataWatchpoint::matched_slot(
+                            &mut core,
+                            watchpoint_slot_base..watchpoint_slot_base + armed_watchpoints.len(),
+                        ) {
+                            let watchpoint = &armed_watchpoints[slot - watchpoint_slot_base];
+                            _watchpoint_hits.lock().unwrap().push(WatchpointHit {
+                                time_ms: _log_timer.lock().unwrap().elapsed_ms(),
+                                symbol: watchpoint.symbol.name.clone(),
+                                kind: watchpoint.kind,
+                                pc: status.pc.unwrap_or_default(),
+                                location: status.location.clone(),
+                            });
+
+                            if watchpoint.auto_resume && core.run().is_ok() {
+                                status =
+                                    RunControl::read_status(&mut core, resolver.as_ref(), &status)
+                                        .unwrap_or_default();
+                            }
+                        }
+                    }
+
+                    // BKPT 0xAB で止まっていればsemihostingの要求を処理して再開する
+                    if options.semihosting_enable {
+                        if !status.is_halted() {
+                            semihosting_exited = false;
+                        }
+                        let mut burst = 0;
+                        while status.halt_reason == Some(HaltCause::Breakpoint)
+                            && !semihosting_exited
+                            && burst < SEMIHOSTING_BURST
+                        {
+                            burst += 1;
+                            let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                            let res = semihosting.service(
+                                &mut core,
+                                now_time,
+                                &mut _semihosting.lock().unwrap(),
+                            );
+                            match res {
+                                Ok(SemihostingState::Resumed) => {
+                                    status = RunControl::read_status(
+                                        &mut core,
+                                        resolver.as_ref(),
+                                        &status,
+                                    )
+                                    .unwrap_or_default();
+                                }
+                                Ok(SemihostingState::Exited(code)) => {
+                                    semihosting_exited = true;
+                                    _session_events.lock().unwrap().push(SessionEvent {
+                                        time_ms: now_time,
+                                        kind: if code == 0 {
+                                            SessionEventKind::Info
+                                        } else {
+                                            SessionEventKind::Error
+                                        },
+                                        message: format!("semihosting exit ({})", code),
+                                    });
+                                }
+                                Ok(_) => break,
+                                Err(_e) => {
+                                    #[cfg(debug_assertions)]
+                                    println!("semihosting error: {}", _e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+
+                    if options.fault_detect {
+                        match FaultAnalyzer::detect(&mut core, &status) {
+                            // 動作中は回復できる例外のために止めない。停止したか
+                            // HardFaultに昇格した時点で解析する
+                            Ok(Some(kind)) if !kind.is_fatal() && !status.is_halted() => {}
+                            Ok(Some(kind))
+                                if !fault_reported && hot_attach && !status.is_halted() =>
+                            {
+                                fault_reported = true;
+                                _session_events.lock().unwrap().push(SessionEvent {
+                                time_ms: _log_timer.lock().unwrap().elapsed_ms(),
+                                kind: SessionEventKind::Error,
+                                message: format!(
+                                    "{} detected, not analyzed because \"attach without reset\" keeps the core running",
+                                    kind
+                                ),
+                            });
+                            }
+                            Ok(Some(kind)) if !fault_reported => {
+                                fault_reported = true;
+                                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                                let res = FaultAnalyzer::analyze(
+                                    &mut core,
+                                    kind,
+                                    resolver.as_ref(),
+                                    cfi.as_ref(),
+                                    now_time,
+                                );
+                                let event = match res {
+                                    Ok(report) => {
+                                        let place = match (&report.location, &report.frame) {
+                                            (Some(location), _) => location.to_string(),
+                                            (None, Some(frame)) => format!("0x{:08X}", frame.pc),
+                                            (None, None) => "unknown PC".to_string(),
+                                        };
+                                        let message = format!("{} at {}", report.kind, place);
+                                        *_fault_report.lock().unwrap() = Some(report);
+                                        message
+                                    }
+                                    Err(e) => format!("{} detected, analysis failed: {}", kind, e),
+                                };
+                                _session_events.lock().unwrap().push(SessionEvent {
+                                    time_ms: now_time,
+                                    kind: SessionEventKind::Error,
+                                    message: event,
+                                });
+                                // 解析のために停止させたので状態を読み直す
+                                status =
+                                    RunControl::read_status(&mut core, resolver.as_ref(), &status)
+                                        .unwrap_or_default();
+                            }
+                            Ok(Some(_)) => {}
+                            Ok(None) => fault_reported = false,
+                            Err(_e) => {
+                                #[cfg(debug_assertions)]
+                                println!("fault detect error: {}", _e);
+                            }
+                        }
+                    }
+
+                    let target_halted = status.is_halted();
+                    let call_stack_refresh =
+                        std::mem::take(&mut *_call_stack_request.lock().unwrap());
+                    if target_halted && (call_stack_refresh || call_stack_pc != Some(status.pc)) {
+                        call_stack_pc = Some(status.pc);
+                        let res = CallStack::capture(
+                            &mut core,
+                            cfi.as_ref(),
+                            resolver.as_ref(),
+                            dwarf_locals.as_ref(),
+                        );
+                        *_call_stack.lock().unwrap() = Some(res.map_err(|e| e.to_string()));
+                    } else if !target_halted && call_stack_pc.is_some() {
+                        call_stack_pc = None;
+                        *_call_stack.lock().unwrap() = None;
+                    }
+                    *_target_status.lock().unwrap() = status;
+
+                    // 停止中は値が変化しないので記録しない
+                    // SWOでトレースしている変数はポーリングしない
+                    let watch_list = if target_halted {
+                        Vec::new()
+                    } else {
+                        setting
+                            .watch_list
+                            .iter()
+                            .filter(|symbol| !swo_data_trace.iter().any(|v| v.name == symbol.name))
+                            .cloned()
+                            .collect()
+                    };
+
+                    for symbol in &watch_list {
+                        let res = MCUMemory::read(&mut core, symbol);
+                        let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                        // 読めなかった値は記録しない (古い値を出し続けないように)
+                        let val_str = match res {
+                            Ok(val_str) => {
+                                read_errors.remove(&symbol.name);
+                                val_str
+                            }
+                            Err(e) => {
+                                if read_errors.insert(symbol.name.clone()) {
+                                    _session_events.lock().unwrap().push(SessionEvent {
+                                        time_ms: now_time,
+                                        kind: SessionEventKind::Warning,
+                                        message: format!("read {} failed: {}", symbol.name, e),
+                                    });
+                                }
+                                continue;
+                            }
+                        };
+
+                        match _log_service.lock().unwrap().store_measurement(
+                            Some(now_time as u64),
+                            &channel_name(&symbol.name),
+                            &setting.physical(&symbol.name, &val_str),
+                        ) {
+                            Ok(_) => {}
+                            Err(_e) => {
+                                #[cfg(debug_assertions)]
+                                println!("測定値の保存中にエラーが発生しました: {}", _e);
+                            }
+                        }
+                    }
+                    for (seq, symbol) in pending_verify.drain(..) {
+                        let len = _write_history
+                            .lock()
+                            .unwrap()
+                            .get_mut(seq)
+                            .map_or(0, |record| record.encoded.len());
+                        let readback = MCUMemory::read_bytes(&mut core, &symbol, len);
+
+                        let mut history = _write_history.lock().unwrap();
+                        if let Some(record) = history.get_mut(seq) {
+                            match readback {
+                                Ok(bytes) => {
+                                    record.readback = Some(MCUMemory::decode(&symbol, &bytes));
+                                    record.status = if bytes == record.encoded {
+                                        WriteStatus::Verified
+                                    } else {
+                                        WriteStatus::Mismatch
+                                    };
+                                }
+                                Err(e) => {
+                                    record.status = WriteStatus::Failed;
+                                    record.error = Some(format!("read back failed: {}", e));
+                                }
+                            }
+                        }
+                    }
+
+                    let write_map = _write_que.lock().unwrap().clone();
+                    _write_que.lock().unwrap().clear();
+                    for (symbol, value) in write_map {
+                        let mut record = WriteRecord {
+                            time_ms: _log_timer.lock().unwrap().elapsed_ms(),
+                            symbol: symbol.name.clone(),
+                            requested: value.clone(),
+                            ..Default::default()
+                        };
+                        match MCUMemory::write(&mut core, &symbol, &value) {
+                            Ok(encoded) => {
+                                record.encoded = encoded;
+                                record.status = WriteStatus::Written;
+                            }
+                            Err(e) => {
+                                record.encoded =
+                                    MCUMemory::encode(&symbol, &value).unwrap_or_default();
+                                record.status = WriteStatus::Failed;
+                                record.error = Some(e.to_string());
+                            }
+                        }
+                        let written = record.status == WriteStatus::Written;
+                        let seq = _write_history.lock().unwrap().push(record);
+                        if written {
+                            pending_verify.push((seq, symbol));
+                        }
+                    }
+
+                    let memory_requests = _memory_views.lock().unwrap().take_requests();
+                    for (key, address, len) in memory_requests {
+                        let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                        let block = MemoryViews::read(&mut core, address, len, now_time);
+                        _memory_views.lock().unwrap().store(key, block);
+                    }
+
+                    if !stacks.is_empty()
+                        && stack_scan_time.map_or(true, |t| {
+                            t.elapsed().as_millis() as u64 >= options.stack.scan_interval_ms
+                        })
+                    {
+                        stack_scan_time = Some(std::time::Instant::now());
+                        let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                        for usage in &mut stacks {
+                            if usage.range.is_empty() {
+                                continue;
+                            }
+                            let last_percent = usage.percent();
+                            if let Err(e) = StackMonitor::scan(&mut core, usage) {
+                                usage.error = Some(e.to_string());
+                                continue;
+                            }
+                            if !usage.painted {
+                                continue;
+                            }
+                            let _res = _log_service.lock().unwrap().store_measurement(
+                                Some(now_time as u64),
+                                &channel_name(&StackSetting::channel_name(&usage.name)),
+                                &usage.high_water.to_string(),
+                            );
+                            if last_percent < options.stack.warning_percent
+                                && usage.percent() >= options.stack.warning_percent
+                            {
+                                _session_events.lock().unwrap().push(SessionEvent {
+                                    time_ms: now_time,
+                                    kind: SessionEventKind::Warning,
+                                    message: format!(
+                                        "stack {} used {:.0}% ({} / {} bytes)",
+                                        usage.name,
+                                        usage.percent(),
+                                        usage.high_water,
+                                        usage.size()
+                                    ),
+                                });
+                            }
+                        }
+                        *_stack_usage.lock().unwrap() = stacks.clone();
+                    }
+
+                    let snapshot_request = _snapshot_request.lock().unwrap().take();
+                    if let Some(halt) = snapshot_request {
+                        let halt = halt && !hot_attach;
+                        let res = RamSnapshot::capture(&mut core, &memory_map, halt);
+                        let unix_time = std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_or(0, |d| d.as_secs());
+                        *_snapshot_result.lock().unwrap() = Some(
+                            res.map(|regions| RamSnapshot {
+                                chip: setting.target_mcu.clone(),
+                                elf_path: setting.elf_path.clone(),
+                                unix_time,
+                                time_ms: _log_timer.lock().unwrap().elapsed_ms(),
+                                halted: halt || target_halted,
+                                regions,
+                            })
+                            .map_err(|e| e.to_string()),
+                        );
+                    }
+
+                    if options.rtt_enable {
+                        if rtt_session.is_none()
+                            && rtt_attach_time.map_or(true, |t| t.elapsed() > RTT_ATTACH_INTERVAL)
+                        {
+                            rtt_attach_time = Some(std::time::Instant::now());
+                            if let Ok(mut rtt) =
+                                RttSession::attach(&mut core, &memory_map, rtt_control_block)
+                            {
+                                let up_channels = rtt.up_channels();
+                                if defmt_decoder.is_some() {
+                                    // "defmt"という名前のチャンネル、無ければ0番をdefmtとして扱う
+                                    defmt_channel = up_channels
+                                        .iter()
+                                        .find(|ch| ch.name == "defmt")
+                                        .map(|ch| ch.number)
+                                        .or(Some(0));
+                                }
+                                _rtt_log.lock().unwrap().status = RttStatus {
+                                    attached: true,
+                                    up_channels,
+                                    down_channels: rtt.down_channels(),
+                                };
+                                rtt_session = Some(rtt);
+                            }
+                        }
+
+                        if let Some(rtt) = &mut rtt_session {
+                            let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                            let mut received = rtt.poll(&mut core, now_time, defmt_channel);
+
+                            if let (Some(decoder), Some(channel), true) =
+                                (&mut defmt_decoder, defmt_channel, received.is_ok())
+                            {
+                                match rtt.read_bytes(&mut core, channel) {
+                                    Ok(bytes) => {
+                                        let plot_channels = _defmt_channels.lock().unwrap().clone();
+                                        for (text, meta) in decoder.decode(&bytes) {
+                                            for plot in &plot_channels {
+                                                if plot.template != meta.format {
+                                                    continue;
+                                                }
+                                                if let Some(Some(value)) = meta.values.get(plot.arg)
+                                                {
+                                                    let _res = _log_service
+                                                        .lock()
+                                                        .unwrap()
+                                                        .store_measurement(
+                                                            Some(now_time as u64),
+                                                            &channel_name(&plot.name),
+                                                            &format!("{}", value),
+                                                        );
+                                                }
+                                            }
+                                            _rtt_log.lock().unwrap().push(
+                                                now_time,
+                                                channel,
+                                                text,
+                                                Some(meta),
+                                            );
+                                        }
+                                    }
+                                    Err(e) => received = Err(e),
+                                }
+                            }
+
+                            match received {
+                                Ok(lines) => {
+                                    for (time, channel, text) in lines {
+                                        if options.rtt_log_to_storage {
+                                            let _res =
+                                                _log_service.lock().unwrap().store_measurement(
+                                                    Some(time as u64),
+                                                    &channel_name(&format!("rtt:{}", channel)),
+                                                    &text,
+                                                );
+                                        }
+                                        _rtt_log.lock().unwrap().push(time, channel, text, None);
+                                    }
+                                }
+                                Err(_e) => {
+                                    #[cfg(debug_assertions)]
+                                    println!("rtt read error: {}", _e);
+                                    _rtt_log.lock().unwrap().status.attached = false;
+                                    rtt_session = None;
+                                }
+                            }
+                        }
+
+                        let down_que = _rtt_down_que.lock().unwrap().clone();
+                        _rtt_down_que.lock().unwrap().clear();
+                        if let Some(rtt) = &mut rtt_session {
+                            for (channel, text) in down_que {
+                                let _res = rtt.write(&mut core, channel, text.as_bytes());
+                            }
+                        }
+                    }
+
+                    let exit_time = std::time::Instant::now();
+
+                    let elapsed_time = exit_time.duration_since(entry_time);
+                    if let Some(sleep_duration) = duration.checked_sub(elapsed_time) {
+                        std::thread::sleep(sleep_duration);
+                    }
+                };
+
+                let Some(lost) = lost else {
+                    if let Ok(mut core) = session.core(0) {
+                        for slot in 0..armed_watchpoints.len() {
+                            let _res =
+                                DataWatchpoint::disarm(&mut core, watchpoint_slot_base + slot);
+                        }
+                        if swo_trace.is_some() {
+                            let _res = SwoTrace::release(&mut core, swo_data_trace.len());
                         }
                     }
+                    break Ok(());
+                };
+                drop(session);
+
+                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                {
+                    let mut health = _session_health.lock().unwrap();
+                    health.set_state(ConnectionState::Lost, now_time);
+                    health.gaps += 1;
+                    health.error = Some(lost.clone());
+                }
+                _session_events.lock().unwrap().push(SessionEvent {
+                    time_ms: now_time,
+                    kind: SessionEventKind::Error,
+                    message: format!("connection lost: {}", lost),
+                });
+                // 途切れた区間がグラフで分かるように欠測値を入れる
+                for channel in &gap_channels {
+                    let _res = _log_service.lock().unwrap().store_measurement(
+                        Some(now_time as u64),
+                        &channel_name(channel),
+                        "NaN",
+                    );
                 }
-                let write_map = _write_que.lock().unwrap().clone();
-                _write_que.lock().unwrap().clear();
-                for que in write_map {
-                    let _res = MCUMemory::write(&mut core, &que.0, &que.1);
+                // 古い状態を表示し続けないように、接続ごとの状態を捨てる
+                _rtt_log.lock().unwrap().status.attached = false;
+                rtt_session = None;
+                defmt_channel = None;
+                *_target_status.lock().unwrap() = Default::default();
+                *_call_stack.lock().unwrap() = None;
+                call_stack_pc = None;
+                fault_reported = false;
+
+                if !wait_reconnect(
+                    &options.reconnect,
+                    &mut reconnect_attempt,
+                    &flag,
+                    &_log_timer,
+                    &_session_health,
+                ) {
+                    break if *flag.lock().unwrap() {
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::Other,
+                            format!("connection lost: {}", lost),
+                        ))
+                    } else {
+                        Ok(())
+                    };
                 }
+            };
 
-                let exit_time = std::time::Instant::now();
+            _rtt_log.lock().unwrap().status.attached = false;
+            if let Err(e) = &res {
+                // 自分で止まったことをUIに伝える
+                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                *flag.lock().unwrap() = false;
+                *_target_status.lock().unwrap() = Default::default();
+                let mut health = _session_health.lock().unwrap();
+                health.set_state(ConnectionState::Stopped, now_time);
+                health.error = Some(e.to_string());
+            }
+            release_log_timer(&_watchers, &_log_timer);
+            res
+        })
+    }
 
-                let elapsed_time = exit_time.duration_since(entry_time);
-                if let Some(sleep_duration) = duration.checked_sub(elapsed_time) {
-                    std::thread::sleep(sleep_duration);
+    // probe-rs以外のバックエンドの監視ループ (変数の読み書き、メモリ表示、実行制御)
+    fn backend_watching_start(
+        &mut self,
+        duration: std::time::Duration,
+    ) -> std::thread::JoinHandle<Result<(), std::io::Error>> {
+        let flag = Arc::clone(&self.watching_flag);
+        let _log_service = Arc::clone(&self.log_service);
+        let _log_timer = Arc::clone(&self.log_timer);
+        let _watchers = Arc::clone(&self.watchers);
+        let _write_que = Arc::clone(&self.write_que);
+        let _run_command_que = Arc::clone(&self.run_command_que);
+        let _target_status = Arc::clone(&self.target_status);
+        let _session_events = Arc::clone(&self.session_events);
+        let _write_history = Arc::clone(&self.write_history);
+        let _memory_views = Arc::clone(&self.memory_views);
+        let _session_health = Arc::clone(&self.session_health);
+        let _fault_report = Arc::clone(&self.fault_report);
+
+        let setting = self.setting.clone();
+        let options = self.options.clone();
+
+        std::thread::spawn(move || {
+            let channel_name = |symbol: &str| setting.channel_name(symbol);
+            let resolver = SymbolResolver::load(&PathBuf::from(&setting.elf_path));
+            let cfi = CfiTable::load(&PathBuf::from(&setting.elf_path));
+            let mut read_errors: BTreeSet<String> = BTreeSet::new();
+            let mut pending_verify: Vec<(usize, VariableInfo)> = Vec::new();
+            let mut reconnect_attempt: u32 = 0;
+
+            let res: Result<(), std::io::Error> = loop {
+                let mut backend = match setting.backend.open(&setting.elf_path) {
+                    Ok(backend) => backend,
+                    Err(e) => {
+                        let error = std::io::Error::new(std::io::ErrorKind::Other, e.to_string());
+                        if reconnect_attempt == 0 {
+                            break Err(error);
+                        }
+                        _session_health.lock().unwrap().error = Some(e.to_string());
+                        if wait_reconnect(
+                            &options.reconnect,
+                            &mut reconnect_attempt,
+                            &flag,
+                            &_log_timer,
+                            &_session_health,
+                        ) {
+                            continue;
+                        }
+                        break if *flag.lock().unwrap() {
+                            Err(error)
+                        } else {
+                            Ok(())
+                        };
+                    }
+                };
+                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                {
+                    let mut health = _session_health.lock().unwrap();
+                    health.set_state(ConnectionState::Connected, now_time);
+                    health.attempts = 0;
+                    health.error = None;
                 }
+                _session_events.lock().unwrap().push(SessionEvent {
+                    time_ms: now_time,
+                    kind: SessionEventKind::Info,
+                    message: format!("connected to {}", backend.describe()),
+                });
+                reconnect_attempt = 0;
+
+                // 対応していればウォッチリストをターゲットから周期送信させる
+                let stream_list: Vec<(&VariableInfo, usize)> = setting
+                    .watch_list
+                    .iter()
+                    .filter_map(|symbol| {
+                        ScalarType::from_c_type(&symbol.types, symbol.size)
+                            .map(|scalar| (symbol, scalar.size()))
+                    })
+                    .collect();
+                let ranges: Vec<(u64, usize)> = stream_list
+                    .iter()
+                    .map(|(symbol, size)| (symbol.address, *size))
+                    .collect();
+                let streaming = match backend.subscribe(&ranges, duration.as_millis() as u32) {
+                    Ok(streaming) => streaming,
+                    Err(e) => {
+                        _session_events.lock().unwrap().push(SessionEvent {
+                            time_ms: _log_timer.lock().unwrap().elapsed_ms(),
+                            kind: SessionEventKind::Warning,
+                            message: e.to_string(),
+                        });
+                        false
+                    }
+                };
+                // (ターゲットの時刻, セッション時間) 最初のサンプルで合わせる
+                let mut stream_clock: Option<(u32, i64)> = None;
+
+                let lost: Option<String> = loop {
+                    let entry_time = std::time::Instant::now();
+                    if *flag.lock().unwrap() == false {
+                        break None;
+                    }
+
+                    let run_commands = std::mem::take(&mut *_run_command_que.lock().unwrap());
+                    for command in run_commands {
+                        let res = backend.run_command(command);
+                        if let Err(e) = &res {
+                            _session_events.lock().unwrap().push(SessionEvent {
+                                time_ms: _log_timer.lock().unwrap().elapsed_ms(),
+                                kind: SessionEventKind::Warning,
+                                message: e.to_string(),
+                            });
+                        }
+                    }
+
+                    let last_status = _target_status.lock().unwrap().clone();
+                    let mut status = match backend.poll_status() {
+                        Ok(status) => status,
+                        Err(e) => break Some(e.to_string()),
+                    };
+                    if let Some(pc) = status.pc {
+                        status.location = if last_status.pc == Some(pc) {
+                            last_status.location.clone()
+                        } else {
+                            resolver.as_ref().and_then(|resolver| resolver.resolve(pc))
+                        };
+                    }
+                    let target_halted = status.is_halted();
+                    let newly_halted = status.newly_halted(&last_status);
+                    *_target_status.lock().unwrap() = status;
+
+                    // コアダンプは保存されたレジスタとメモリから解析する
+                    if newly_halted && options.fault_detect {
+                        if let Some(saved) = backend.saved_registers() {
+                            let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                            let report = FaultAnalyzer::analyze_image(
+                                saved.registers,
+                                saved.psp,
+                                saved.xpsr,
+                                |address| {
+                                    let mut word = [0u8; 4];
+                                    backend
+                                        .read_memory(address, &mut word)
+                                        .ok()
+                                        .map(|_| u32::from_le_bytes(word))
+                                },
+                                resolver.as_ref(),
+                                cfi.as_ref(),
+                                now_time,
+                            );
+                            if let Some(report) = report {
+                                let place = match (&report.location, &report.frame) {
+                                    (Some(location), _) => location.to_string(),
+                                    (None, Some(frame)) => format!("0x{:08X}", frame.pc),
+                                    (None, None) => "unknown PC".to_string(),
+                                };
+                                _session_events.lock().unwrap().push(SessionEvent {
+                                    time_ms: now_time,
+                                    kind: SessionEventKind::Error,
+                                    message: format!("{} at {}", report.kind, place),
+                                });
+                                *_fault_report.lock().unwrap() = Some(report);
+                            }
+                        }
+                    }
+
+                    let accessible = match backend.begin_access() {
+                        Ok(accessible) => accessible,
+                        Err(e) => break Some(e.to_string()),
+                    };
+                    if accessible {
+                        if streaming {
+                            let samples = match backend.take_samples() {
+                                Ok(samples) => samples,
+                                Err(e) => break Some(e.to_string()),
+                            };
+                            for sample in samples {
+                                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                                let clock = stream_clock.get_or_insert((sample.time_ms, now_time));
+                                // ターゲットがリセットされて時刻が戻ったら合わせ直す
+                                if sample.time_ms < clock.0 {
+                                    *clock = (sample.time_ms, now_time);
+                                }
+                                let time_ms = clock.1 + (sample.time_ms - clock.0) as i64;
+
+                                let mut offset = 0;
+                                for (symbol, size) in &stream_list {
+                                    let Some(bytes) = sample.data.get(offset..offset + size) else {
+                                        break;
+                                    };
+                                    offset += size;
+                                    let _res = _log_service.lock().unwrap().store_measurement(
+                                        Some(time_ms as u64),
+                                        &channel_name(&symbol.name),
+                                        &setting.physical(
+                                            &symbol.name,
+                                            &MCUMemory::decode(symbol, bytes),
+                                        ),
+                                    );
+                                }
+                            }
+                        }
+
+                        // 停止中は値が変化しないので、止まった時の値だけ記録する
+                        // (コアダンプは最初の1回だけ)。周期送信中はポーリングしない
+                        let watch_list = if streaming || (target_halted && !newly_halted) {
+                            Vec::new()
+                        } else {
+                            setting.watch_list.clone()
+                        };
+                        for symbol in &watch_list {
+                            let Some(scalar) = ScalarType::from_c_type(&symbol.types, symbol.size)
+                            else {
+                                continue;
+                            };
+                            let mut bytes = vec![0u8; scalar.size()];
+                            let res = backend.read_memory(symbol.address, &mut bytes);
+                            let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                            if let Err(e) = res {
+                                if read_errors.insert(symbol.name.clone()) {
+                                    _session_events.lock().unwrap().push(SessionEvent {
+                                        time_ms: now_time,
+                                        kind: SessionEventKind::Warning,
+                                        message: format!("read {} failed: {}", symbol.name, e),
+                                    });
+                                }
+                                continue;
+                            }
+                            read_errors.remove(&symbol.name);
+                            let _res = _log_service.lock().unwrap().store_measurement(
+                                Some(now_time as u64),
+                                &channel_name(&symbol.name),
+                                &setting.physical(&symbol.name, &MCUMemory::decode(symbol, &bytes)),
+                            );
+                        }
+
+                        for (seq, symbol) in pending_verify.drain(..) {
+                            let mut history = _write_history.lock().unwrap();
+                            let Some(record) = history.get_mut(seq) else {
+                                continue;
+                            };
+                            let mut bytes = vec![0u8; record.encoded.len()];
+                            match backend.read_memory(symbol.address, &mut bytes) {
+                                Ok(_) => {
+                                    record.readback = Some(MCUMemory::decode(&symbol, &bytes));
+                                    record.status = if bytes == record.encoded {
+                                        WriteStatus::Verified
+                                    } else {
+                                        WriteStatus::Mismatch
+                                    };
+                                }
+                                Err(e) => {
+                                    record.status = WriteStatus::Failed;
+                                    record.error = Some(format!("read back failed: {}", e));
+                                }
+                            }
+                        }
+
+                        let write_map = std::mem::take(&mut *_write_que.lock().unwrap());
+                        for (symbol, value) in write_map {
+                            let mut record = WriteRecord {
+                                time_ms: _log_timer.lock().unwrap().elapsed_ms(),
+                                symbol: symbol.name.clone(),
+                                requested: value.clone(),
+                                ..Default::default()
+                            };
+                            let res = MCUMemory::encode(&symbol, &value).and_then(|encoded| {
+                                record.encoded = encoded.clone();
+                                backend.write_memory(symbol.address, &encoded)
+                            });
+                            match res {
+                                Ok(_) => record.status = WriteStatus::Written,
+                                Err(e) => {
+                                    record.status = WriteStatus::Failed;
+                                    record.error = Some(e.to_string());
+                                }
+                            }
+                            let written = record.status == WriteStatus::Written;
+                            let seq = _write_history.lock().unwrap().push(record);
+                            if written {
+                                pending_verify.push((seq, symbol));
+                            }
+                        }
+
+                        let memory_requests = _memory_views.lock().unwrap().take_requests();
+                        for (key, address, len) in memory_requests {
+                            let mut data = vec![0u8; len];
+                            let res = backend.read_memory(address, &mut data);
+                            let time_ms = _log_timer.lock().unwrap().elapsed_ms();
+                            let block = match res {
+                                Ok(_) => MemoryBlock {
+                                    address,
+                                    data,
+                                    time_ms,
+                                    error: None,
+                                },
+                                Err(e) => MemoryBlock {
+                                    address,
+                                    data: Vec::new(),
+                                    time_ms,
+                                    error: Some(e.to_string()),
+                                },
+                            };
+                            _memory_views.lock().unwrap().store(key, block);
+                        }
+
+                        if let Err(e) = backend.end_access() {
+                            break Some(e.to_string());
+                        }
+                    }
+
+                    let elapsed_time = entry_time.elapsed();
+                    if let Some(sleep_duration) = duration.checked_sub(elapsed_time) {
+                        std::thread::sleep(sleep_duration);
+                    }
+                };
+
+                let Some(lost) = lost else {
+                    break Ok(());
+                };
+                drop(backend);
+
+                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                {
+                    let mut health = _session_health.lock().unwrap();
+                    health.set_state(ConnectionState::Lost, now_time);
+                    health.gaps += 1;
+                    health.error = Some(lost.clone());
+                }
+                _session_events.lock().unwrap().push(SessionEvent {
+                    time_ms: now_time,
+                    kind: SessionEventKind::Error,
+                    message: format!("connection lost: {}", lost),
+                });
+                for symbol in &setting.watch_list {
+                    let _res = _log_service.lock().unwrap().store_measurement(
+                        Some(now_time as u64),
+                        &channel_name(&symbol.name),
+                        "NaN",
+                    );
+                }
+                *_target_status.lock().unwrap() = Default::default();
+
+                if !wait_reconnect(
+                    &options.reconnect,
+                    &mut reconnect_attempt,
+                    &flag,
+                    &_log_timer,
+                    &_session_health,
+                ) {
+                    break if *flag.lock().unwrap() {
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::Other,
+                            format!("connection lost: {}", lost),
+                        ))
+                    } else {
+                        Ok(())
+                    };
+                }
+            };
+
+            if let Err(e) = &res {
+                let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                *flag.lock().unwrap() = false;
+                *_target_status.lock().unwrap() = Default::default();
+                let mut health = _session_health.lock().unwrap();
+                health.set_state(ConnectionState::Stopped, now_time);
+                health.error = Some(e.to_string());
             }
-            Ok(())
+            release_log_timer(&_watchers, &_log_timer);
+            res
+        })
+    }
+
+    /// Decodes a raw SWO capture (file or named pipe) into the log without a probe.
+    pub fn trace_replay_start(
+        &mut self,
+        path: PathBuf,
+    ) -> std::thread::JoinHandle<Result<(), std::io::Error>> {
+        use std::io::Read;
+
+        let flag = self.watching_flag.clone();
+        if *flag.lock().unwrap() == true {
+            return std::thread::spawn(move || Ok(()));
+        }
+        *flag.lock().unwrap() = true;
+
+        let _log_service = Arc::clone(&self.log_service);
+        let _log_timer = Arc::clone(&self.log_timer);
+        let _watchers = Arc::clone(&self.watchers);
+        self.start_log_timer();
+        *_watchers.lock().unwrap() += 1;
+
+        let swo = self.options.swo.clone();
+        let watch_list = self.setting.watch_list.clone();
+        let setting = self.setting.clone();
+
+        std::thread::spawn(move || {
+            let res: Result<(), std::io::Error> = (|| {
+                let mut file = std::fs::File::open(&path)?;
+                let mut decoder = ItmDecoder::default();
+                let mut mapper = TraceChannelMapper::new(
+                    &swo,
+                    &watch_list,
+                    _log_timer.lock().unwrap().elapsed_ms(),
+                );
+                let mut buf = [0u8; TRACE_FILE_READ_BUFFER];
+
+                // パイプの場合は書き込み側が閉じるまで読み続ける
+                while *flag.lock().unwrap() == true {
+                    let count = file.read(&mut buf)?;
+                    if count == 0 {
+                        break;
+                    }
+                    let now_time = _log_timer.lock().unwrap().elapsed_ms();
+                    let packets = decoder.feed(&buf[..count]);
+                    for (time, channel, value) in mapper.map(packets, now_time) {
+                        let _res = _log_service.lock().unwrap().store_measurement(
+                            Some(time as u64),
+                            &setting.channel_name(&channel),
+                            &setting.physical(&channel, &value),
+                        );
+                    }
+                }
+                Ok(())
+            })();
+
+            *flag.lock().unwrap() = false;
+            release_log_timer(&_watchers, &_log_timer);
+            res
         })
     }
 
     pub fn watching_stop(&mut self) {
+        for peer in self.peers.lock().unwrap().iter_mut() {
+            peer.watching_stop();
+        }
         *self.watching_flag.lock().unwrap() = false;
         self.log_timer.lock().unwrap().stop();
+        *self.target_status.lock().unwrap() = Default::default();
+        *self.call_stack.lock().unwrap() = None;
+        let now_time = self.log_timer.lock().unwrap().elapsed_ms();
+        let mut health = self.session_health.lock().unwrap();
+        health.set_state(ConnectionState::Stopped, now_time);
+        health.error = None;
     }
 
     pub fn now_watching(&mut self) -> bool {
         *self.watching_flag.lock().unwrap()
     }
 
+    pub fn get_session_health(&mut self) -> SessionHealth {
+        self.session_health.lock().unwrap().clone()
+    }
+
+    /// Connection state of this target and of the other targets, with their names.
+    pub fn get_targets_health(&mut self) -> Vec<(String, SessionHealth)> {
+        let mut list = vec![(self.setting.target_name.clone(), self.get_session_health())];
+        for peer in self.peers.lock().unwrap().iter_mut() {
+            list.push((peer.setting.target_name.clone(), peer.get_session_health()));
+        }
+        list
+    }
+
+    /// Channels that can be exported: those recorded so far, else the current ones.
+    pub fn get_recorded_channels(&mut self) -> Vec<String> {
+        let session = self.session.lock().unwrap().clone();
+        if session.channels.is_empty() {
+            self.get_channel_list()
+                .into_iter()
+                .map(|c| c.name)
+                .collect()
+        } else {
+            session.channels.into_iter().map(|c| c.name).collect()
+        }
+    }
+
+    /// Writes the recorded channels to `path` in the format of `setting`.
+    pub fn export_recording(
+        &mut self,
+        setting: &ExportSetting,
+        path: &std::path::Path,
+    ) -> Result<String, String> {
+        let session = self.session.lock().unwrap().clone();
+        let mut log = self.log_service.lock().unwrap();
+        export_session(&mut log, &session, setting, path)
+    }
+
     pub fn get_newest_date(&mut self, index: &str) -> Option<f64> {
         let now_time = self.log_timer.lock().unwrap().elapsed_ms();
         let last_time = now_time - 500;
@@ -202,6 +1708,8 @@ impl ProbeInterface {
         if let Some(val) = res {
             let res = val.data.parse::<f32>();
             match res {
+                // 接続が切れた時刻の欠測値
+                Ok(val) if val.is_nan() => return None,
                 Ok(val) => return Some(val as f64),
                 Err(_) => return None,
             }
@@ -228,7 +1736,10 @@ impl ProbeInterface {
         let mut vec = Vec::new();
         for measurement in measurements {
             let time = (measurement.time) as f64 / 1000.0;
-            let data = measurement.data.parse::<f64>().unwrap();
+            // 欠測値 (NaN) はそのまま返し、グラフ側で線を切る
+            let Ok(data) = measurement.data.parse::<f64>() else {
+                continue;
+            };
             vec.push([time, data]);
         }
         vec
@@ -249,120 +1760,629 @@ impl ProbeInterface {
     }
 
     pub fn insert_wirte_que(&mut self, symbol: &VariableInfo, data: &str) {
-        self.write_que
+        let (peer, name) = self.route(&symbol.name);
+        let symbol = VariableInfo {
+            name,
+            ..symbol.clone()
+        };
+        if let Some(mut peer) = peer {
+            return peer.insert_wirte_que(&symbol, data);
+        }
+        // 物理値で入力された値を生値に戻す (数値でなければそのまま書いてエラーにする)
+        let data = match self.setting.conversions.get(&symbol.name) {
+            Some(conversion) => conversion
+                .raw(data, &symbol.types, symbol.size)
+                .unwrap_or_else(|_| data.to_string()),
+            None => data.to_string(),
+        };
+        self.write_que.lock().unwrap().insert(symbol, data);
+    }
+
+    /// Latest write of `symbol_name`; `Pending` while it is still queued.
+    pub fn get_last_write(&mut self, symbol_name: &str) -> Option<WriteRecord> {
+        let (peer, symbol_name) = self.route(symbol_name);
+        if let Some(mut peer) = peer {
+            return peer.get_last_write(&symbol_name);
+        }
+        let symbol_name = symbol_name.as_str();
+        if let Some((_, value)) = self
+            .write_que
             .lock()
             .unwrap()
-            .insert(symbol.clone(), data.to_string());
+            .iter()
+            .find(|(symbol, _)| symbol.name == symbol_name)
+        {
+            return Some(WriteRecord {
+                symbol: symbol_name.to_string(),
+                requested: value.clone(),
+                status: WriteStatus::Pending,
+                ..Default::default()
+            });
+        }
+        self.write_history.lock().unwrap().last_of(symbol_name)
+    }
+
+    /// Records a write that was rejected before it reached the queue (e.g. by an
+    /// editor widget), so it is reported like any other failed write.
+    pub fn reject_write(&mut self, symbol_name: &str, requested: &str, error: String) {
+        let (peer, symbol_name) = self.route(symbol_name);
+        if let Some(mut peer) = peer {
+            return peer.reject_write(&symbol_name, requested, error);
+        }
+        let time_ms = self.log_timer.lock().unwrap().elapsed_ms();
+        self.write_history.lock().unwrap().push(WriteRecord {
+            time_ms,
+            symbol: symbol_name,
+            requested: requested.to_string(),
+            status: WriteStatus::Failed,
+            error: Some(error),
+            ..Default::default()
+        });
+    }
+
+    /// Writes of all targets on the common time base, named `target:symbol`.
+    pub fn get_write_history(&mut self) -> Vec<WriteRecord> {
+        let mut records = self.write_history.lock().unwrap().records();
+        let peers = self.peers.lock().unwrap().clone();
+        if peers.is_empty() {
+            return records;
+        }
+        for record in &mut records {
+            record.symbol = self.setting.channel_name(&record.symbol);
+        }
+        for mut peer in peers {
+            for mut record in peer.get_write_history() {
+                record.symbol = peer.setting.channel_name(&record.symbol);
+                records.push(record);
+            }
+        }
+        records.sort_by_key(|record| record.time_ms);
+        records
+    }
+
+    pub fn clear_write_history(&mut self) {
+        for peer in self.peers.lock().unwrap().iter_mut() {
+            peer.clear_write_history();
+        }
+        self.write_history.lock().unwrap().clear();
+    }
+
+    pub fn export_write_history(&mut self, path: &PathBuf) -> Result<(), std::io::Error> {
+        let text = WriteHistory::to_csv(&self.get_write_history());
+        std::fs::write(path, text)
+    }
+
+    pub fn insert_run_command(&mut self, command: RunCommand) {
+        self.run_command_que.lock().unwrap().push(command);
+    }
+
+    pub fn get_target_status(&mut self) -> TargetStatus {
+        self.target_status.lock().unwrap().clone()
+    }
+
+    pub fn set_watchpoint(
+        &mut self,
+        symbol: &VariableInfo,
+        kind: WatchpointKind,
+        auto_resume: bool,
+    ) {
+        let (peer, symbol_name) = self.route(&symbol.name);
+        let symbol = VariableInfo {
+            name: symbol_name,
+            ..symbol.clone()
+        };
+        if let Some(mut peer) = peer {
+            return peer.set_watchpoint(&symbol, kind, auto_resume);
+        }
+        let mut watchpoints = self.watchpoints.lock().unwrap();
+        watchpoints.retain(|w| w.symbol.name != symbol.name);
+        watchpoints.push(WatchpointSetting {
+            symbol,
+            kind,
+            auto_resume,
+        });
+    }
+
+    pub fn remove_watchpoint(&mut self, symbol_name: &str) {
+        let (peer, symbol_name) = self.route(symbol_name);
+        if let Some(mut peer) = peer {
+            return peer.remove_watchpoint(&symbol_name);
+        }
+        self.watchpoints
+            .lock()
+            .unwrap()
+            .retain(|w| w.symbol.name != symbol_name);
+    }
+
+    /// Watchpoints of all targets, named `target:symbol` when there are several.
+    pub fn get_watchpoints(&mut self) -> Vec<WatchpointSetting> {
+        let mut watchpoints = self.watchpoints.lock().unwrap().clone();
+        let peers = self.peers.lock().unwrap().clone();
+        if peers.is_empty() {
+            return watchpoints;
+        }
+        for watchpoint in &mut watchpoints {
+            watchpoint.symbol.name = self.setting.channel_name(&watchpoint.symbol.name);
+        }
+        for mut peer in peers {
+            for mut watchpoint in peer.get_watchpoints() {
+                watchpoint.symbol.name = peer.setting.channel_name(&watchpoint.symbol.name);
+                watchpoints.push(watchpoint);
+            }
+        }
+        watchpoints
+    }
+
+    pub fn get_watchpoint_hits(&mut self) -> Vec<WatchpointHit> {
+        let mut hits = self.watchpoint_hits.lock().unwrap().clone();
+        let peers = self.peers.lock().unwrap().clone();
+        if peers.is_empty() {
+            return hits;
+        }
+        for hit in &mut hits {
+            hit.symbol = self.setting.channel_name(&hit.symbol);
+        }
+        for mut peer in peers {
+            for mut hit in peer.get_watchpoint_hits() {
+                hit.symbol = peer.setting.channel_name(&hit.symbol);
+                hits.push(hit);
+            }
+        }
+        hits.sort_by_key(|hit| hit.time_ms);
+        hits
+    }
+
+    pub fn clear_watchpoint_hits(&mut self) {
+        for peer in self.peers.lock().unwrap().iter_mut() {
+            peer.clear_watchpoint_hits();
+        }
+        self.watchpoint_hits.lock().unwrap().clear();
+    }
+
+    pub fn get_rtt_lines_since(&mut self, from_seq: usize) -> Vec<RttLine> {
+        self.rtt_log.lock().unwrap().lines_since(from_seq)
+    }
+
+    pub fn get_rtt_status(&mut self) -> RttStatus {
+        self.rtt_log.lock().unwrap().status.clone()
+    }
+
+    pub fn insert_rtt_down_que(&mut self, channel: usize, text: &str) {
+        self.rtt_down_que
+            .lock()
+            .unwrap()
+            .push((channel, text.to_string()));
+    }
+
+    pub fn clear_rtt_log(&mut self) {
+        self.rtt_log.lock().unwrap().clear();
+    }
+
+    pub fn add_defmt_channel(&mut self, name: &str, template: &str, arg: usize) {
+        let name = format!("{}:{}", DEFMT_CHANNEL_TYPE, name);
+        let mut channels = self.defmt_channels.lock().unwrap();
+        channels.retain(|ch| ch.name != name);
+        channels.push(DefmtPlotChannel {
+            name,
+            template: template.to_string(),
+            arg,
+        });
+    }
+
+    pub fn remove_defmt_channel(&mut self, name: &str) {
+        self.defmt_channels
+            .lock()
+            .unwrap()
+            .retain(|ch| ch.name != name);
+    }
+
+    pub fn get_defmt_channels(&mut self) -> Vec<DefmtPlotChannel> {
+        self.defmt_channels.lock().unwrap().clone()
+    }
+
+    /// Watch list plus the channels that are not sampled from memory (e.g. defmt values).
+    /// Channels of this target and of the other targets, named `target:symbol`.
+    pub fn get_channel_list(&mut self) -> Vec<VariableInfo> {
+        let mut list = self.local_channel_list();
+        for channel in &mut list {
+            channel.name = self.setting.channel_name(&channel.name);
+        }
+        for peer in self.peers.lock().unwrap().iter_mut() {
+            list.extend(peer.get_channel_list());
+        }
+        list
+    }
+
+    fn local_channel_list(&mut self) -> Vec<VariableInfo> {
+        let mut list = self.setting.watch_list.clone();
+        for channel in self.get_defmt_channels() {
+            list.push(VariableInfo {
+                name: channel.name,
+                types: DEFMT_CHANNEL_TYPE.to_string(),
+                address: 0,
+                size: 0,
+            });
+        }
+        if self.options.stack.enable {
+            for name in self.options.stack.stack_names() {
+                list.push(VariableInfo {
+                    name: StackSetting::channel_name(&name),
+                    types: STACK_CHANNEL_TYPE.to_string(),
+                    address: 0,
+                    size: 0,
+                });
+            }
+        }
+        if self.options.swo.enable {
+            for port in self.options.swo.enabled_ports() {
+                list.push(VariableInfo {
+                    name: SwoSetting::port_channel_name(port),
+                    types: ITM_CHANNEL_TYPE.to_string(),
+                    address: 0,
+                    size: 0,
+                });
+            }
+        }
+        list
+    }
+
+    pub fn get_semihosting_lines_since(&mut self, from_seq: usize) -> Vec<RttLine> {
+        self.semihosting
+            .lock()
+            .unwrap()
+            .output
+            .lines_since(from_seq)
+    }
+
+    pub fn insert_semihosting_input(&mut self, text: &str) {
+        self.semihosting
+            .lock()
+            .unwrap()
+            .input
+            .extend(text.as_bytes());
+    }
+
+    /// (waiting for input, exit code)
+    pub fn get_semihosting_status(&mut self) -> (bool, Option<i64>) {
+        let semihosting = self.semihosting.lock().unwrap();
+        (semihosting.waiting_input, semihosting.exit_code)
+    }
+
+    pub fn clear_semihosting_console(&mut self) {
+        self.semihosting.lock().unwrap().output.clear();
+    }
+
+    /// Events of all targets on the common time base, tagged with the target name.
+    pub fn get_session_events(&mut self) -> Vec<SessionEvent> {
+        let mut events = self.session_events.lock().unwrap().clone();
+        let peers = self.peers.lock().unwrap().clone();
+        if peers.is_empty() {
+            return events;
+        }
+        for event in &mut events {
+            event.message = format!("{}: {}", self.setting.target_name, event.message);
+        }
+        for mut peer in peers {
+            let name = peer.setting.target_name.clone();
+            for mut event in peer.get_session_events() {
+                event.message = format!("{}: {}", name, event.message);
+                events.push(event);
+            }
+        }
+        events.sort_by_key(|event| event.time_ms);
+        events
+    }
+
+    pub fn clear_session_events(&mut self) {
+        for peer in self.peers.lock().unwrap().iter_mut() {
+            peer.clear_session_events();
+        }
+        self.session_events.lock().unwrap().clear();
+    }
+
+    /// Asks the watch thread to sample `len` bytes at `address`; call it every frame.
+    pub fn request_memory(&mut self, key: &str, address: u64, len: usize) {
+        self.memory_views.lock().unwrap().request(key, address, len);
+    }
+
+    pub fn get_memory_block(&mut self, key: &str) -> Option<MemoryBlock> {
+        self.memory_views.lock().unwrap().block(key)
+    }
+
+    /// Symbol table of `setting.elf_path`. It is parsed in the background, so
+    /// this returns None until the ELF has been loaded.
+    pub fn get_symbol_map(&mut self) -> Option<Arc<SymbolMap>> {
+        let mut cache = self.symbol_map.lock().unwrap();
+        if cache.0 != self.setting.elf_path {
+            *cache = (self.setting.elf_path.clone(), None);
+
+            let elf_path = self.setting.elf_path.clone();
+            let cache_clone = Arc::clone(&self.symbol_map);
+            std::thread::spawn(move || {
+                let map = SymbolMap::load(&PathBuf::from(&elf_path));
+                let mut cache = cache_clone.lock().unwrap();
+                if cache.0 == elf_path {
+                    cache.1 = map.map(Arc::new);
+                }
+            });
+        }
+        cache.1.clone()
+    }
+
+    pub fn get_fault_report(&mut self) -> Option<FaultReport> {
+        self.fault_report.lock().unwrap().clone()
+    }
+
+    pub fn clear_fault_report(&mut self) {
+        *self.fault_report.lock().unwrap() = None;
+    }
+
+    /// Frames of the halted core; None while it is running.
+    pub fn get_call_stack(&mut self) -> Option<Result<Vec<StackFrame>, String>> {
+        self.call_stack.lock().unwrap().clone()
+    }
+
+    /// Evaluates the call stack again on the next cycle, e.g. after writing a variable.
+    pub fn refresh_call_stack(&mut self) {
+        *self.call_stack_request.lock().unwrap() = true;
+    }
+
+    pub fn get_stack_usage(&mut self) -> Vec<StackUsage> {
+        self.stack_usage.lock().unwrap().clone()
+    }
+
+    /// Captures all RAM regions on the next cycle of the watch thread.
+    pub fn request_ram_snapshot(&mut self, halt: bool) {
+        *self.snapshot_result.lock().unwrap() = None;
+        *self.snapshot_request.lock().unwrap() = Some(halt);
+    }
+
+    pub fn take_ram_snapshot(&mut self) -> Option<Result<RamSnapshot, String>> {
+        self.snapshot_result.lock().unwrap().take()
+    }
+
+    pub fn get_elapsed_ms(&mut self) -> i64 {
+        self.log_timer.lock().unwrap().elapsed_ms()
     }
 
     pub fn get_flash_progress(&mut self) -> Progress {
         self.flash_progress.lock().unwrap().clone()
     }
 
-    //pub fn flash(&mut self, elf_path: PathBuf) -> Result<(), probe_rs::Error> {
+    /// Result of the last `flash`; None while it is running.
+    pub fn get_flash_report(&mut self) -> Option<FlashReport> {
+        self.flash_report.lock().unwrap().clone()
+    }
+
     pub fn flash(
         &mut self,
-        elf_path: PathBuf,
-    ) -> std::thread::JoinHandle<Result<(), probe_rs::Error>> {
-        use flashing::ProgressEvent::*;
-
-        let probes = Probe::list_all();
+        flash_setting: FlashSetting,
+    ) -> std::thread::JoinHandle<Result<FlashReport, probe_rs::Error>> {
         let setting = self.setting.clone();
 
         if self.now_watching() {
             let err = Err(probe_rs::Error::Probe(DebugProbeError::Attached));
             return std::thread::spawn(move || err);
         }
+        if setting.backend.is_offline() {
+            let err = Err(probe_rs::Error::Other(anyhow::anyhow!(
+                "nothing is written to hardware in core dump mode"
+            )));
+            return std::thread::spawn(move || err);
+        }
 
-        self.flash_progress.lock().unwrap().state = FlashProgressState::Erasing;
+        let image = flash_setting.image(&setting.elf_path);
+        let report = Arc::new(Mutex::new(FlashReport {
+            image: image.display().to_string(),
+            format: flash_setting.image_format(),
+            erase: flash_setting.erase,
+            after: flash_setting.after,
+            ..Default::default()
+        }));
+        *self.flash_report.lock().unwrap() = None;
+        *self.flash_progress.lock().unwrap() = Progress {
+            state: FlashProgressState::Erasing,
+            progress: 0.0,
+        };
         let progress_clone = Arc::clone(&self.flash_progress);
+        let _flash_report = Arc::clone(&self.flash_report);
 
         std::thread::spawn(move || {
-            let probe = probes
-                .into_iter()
-                .find(|probe| probe.serial_number == Some(setting.probe_sn.clone()))
-                .ok_or_else(|| {
-                    std::io::Error::new(std::io::ErrorKind::Other, "No matching probe found")
-                })
-                .unwrap()
-                .open()
-                .unwrap();
+            let start = std::time::Instant::now();
+            let res = Self::flash_image(
+                &setting,
+                &flash_setting,
+                &image,
+                Arc::clone(&progress_clone),
+                Arc::clone(&report),
+            );
 
-            // Attach to a chip.
-            let mut session = probe.attach(setting.target_mcu.clone(), Permissions::default())?;
+            let mut report = report.lock().unwrap().clone();
+            report.total_ms = start.elapsed().as_millis() as u64;
+            progress_clone.lock().unwrap().state = match &res {
+                Ok(_) => FlashProgressState::Finished,
+                Err(e) => {
+                    report.error = Some(e.to_string());
+                    FlashProgressState::Failed
+                }
+            };
 
-            let total_page_size = Arc::new(Mutex::new(0u32));
-            let total_sector_size = Arc::new(Mutex::new(0u64));
+            #[cfg(debug_assertions)]
+            println!("flash\n{}", report);
 
-            // Register callback to update the progress.
-            let progress = FlashProgress::new(move |event| {
-                let mut progress = progress_clone.lock().unwrap();
-                let mut total_page_size = total_page_size.lock().unwrap();
-                let mut total_sector_size = total_sector_size.lock().unwrap();
+            *_flash_report.lock().unwrap() = Some(report.clone());
+            res.map(|_| report)
+        })
+    }
 
-                match event {
-                    Initialized { flash_layout } => {
-                        *total_page_size = flash_layout.pages().iter().map(|s| s.size()).sum();
-                        *total_sector_size = flash_layout.sectors().iter().map(|s| s.size()).sum();
-                    }
-                    StartedProgramming => {
-                        progress.state = FlashProgressState::Programing;
-                        progress.progress = 0.0;
-                    }
-                    StartedErasing => {
-                        progress.state = FlashProgressState::Erasing;
-                        progress.progress = 0.0;
-                    }
-                    StartedFilling => {}
-                    PageProgrammed { size, .. } => {
-                        progress.progress += (size as f64) / (total_page_size.clone() as f64);
-                    }
-                    SectorErased { size, .. } => {
-                        progress.progress += (size as f64) / (total_sector_size.clone() as f64);
-                    }
-                    PageFilled { .. } => {}
-                    FailedErasing => {
-                        progress.state = FlashProgressState::Failed;
-                    }
-                    FinishedErasing => {
-                        progress.state = FlashProgressState::Failed;
-                    }
-                    FailedProgramming => {
-                        progress.state = FlashProgressState::Failed;
-                    }
-                    FinishedProgramming => {
-                        progress.state = FlashProgressState::Finished;
-                    }
-                    FailedFilling => {
-                        progress.state = FlashProgressState::Failed;
-                    }
-                    FinishedFilling => {}
-                    DiagnosticMessage { .. } => todo!(),
-                }
-            });
+    fn flash_image(
+        setting: &WatchSetting,
+        flash_setting: &FlashSetting,
+        image: &PathBuf,
+        progress_clone: Arc<Mutex<Progress>>,
+        report: Arc<Mutex<FlashReport>>,
+    ) -> Result<(), probe_rs::Error> {
+        use flashing::ProgressEvent::*;
 
-            let mut options = DownloadOptions::default();
-            options.progress = Some(progress);
+        flash_setting
+            .check()
+            .and_then(|_| setting.probe_config.check_flash())
+            .map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e)))?;
 
-            let _res = flashing::download_file_with_options(
-                &mut session,
-                elf_path,
-                flashing::Format::Elf,
-                options,
-            );
+        // Attach to a chip.
+        let mut session = setting
+            .probe_config
+            .attach(&setting.probe_sn, &setting.target_mcu)?;
 
-            #[cfg(debug_assertions)]
-            println!("flash {:?}", _res);
+        let total_page_size = Arc::new(Mutex::new(0u32));
+        let total_sector_size = Arc::new(Mutex::new(0u64));
+        // 消去・書き込みそれぞれの開始時刻と、書き込み完了時刻 (以降はベリファイ)
+        let phase_start = Arc::new(Mutex::new(std::time::Instant::now()));
+        let programmed_at: Arc<Mutex<Option<std::time::Instant>>> = Arc::new(Mutex::new(None));
+        let programmed_at_clone = Arc::clone(&programmed_at);
+        let report_clone = Arc::clone(&report);
 
-            // Reset target according to CLI options
-            {
-                let mut core = session.core(0)?;
+        // Register callback to update the progress.
+        let progress = FlashProgress::new(move |event| {
+            let mut progress = progress_clone.lock().unwrap();
+            let mut report = report_clone.lock().unwrap();
+            let mut total_page_size = total_page_size.lock().unwrap();
+            let mut total_sector_size = total_sector_size.lock().unwrap();
+            let mut phase_start = phase_start.lock().unwrap();
 
-                core.reset()?;
+            match event {
+                Initialized { flash_layout } => {
+                    *total_page_size = flash_layout.pages().iter().map(|s| s.size()).sum();
+                    *total_sector_size = flash_layout.sectors().iter().map(|s| s.size()).sum();
+                }
+                StartedProgramming => {
+                    progress.state = FlashProgressState::Programing;
+                    progress.progress = 0.0;
+                    *phase_start = std::time::Instant::now();
+                }
+                StartedErasing => {
+                    progress.state = FlashProgressState::Erasing;
+                    progress.progress = 0.0;
+                    *phase_start = std::time::Instant::now();
+                }
+                StartedFilling => {}
+                PageProgrammed { size, .. } => {
+                    progress.progress += (size as f64) / (total_page_size.clone() as f64);
+                    report.written_bytes += size as u64;
+                }
+                SectorErased { size, .. } => {
+                    progress.progress += (size as f64) / (total_sector_size.clone() as f64);
+                    report.erased_bytes += size;
+                }
+                PageFilled { .. } => {}
+                FailedErasing => {
+                    progress.state = FlashProgressState::Failed;
+                }
+                FinishedErasing => {
+                    report.erase_ms += phase_start.elapsed().as_millis() as u64;
+                }
+                FailedProgramming => {
+                    progress.state = FlashProgressState::Failed;
+                }
+                FinishedProgramming => {
+                    report.program_ms += phase_start.elapsed().as_millis() as u64;
+                    *programmed_at_clone.lock().unwrap() = Some(std::time::Instant::now());
+                }
+                FailedFilling => {
+                    progress.state = FlashProgressState::Failed;
+                }
+                FinishedFilling => {}
+                DiagnosticMessage { message } => {
+                    report.diagnostics.push(message);
+                }
             }
-            Ok(())
-        })
+        });
+
+        let mut options = DownloadOptions::default();
+        options.progress = Some(progress);
+        options.verify = flash_setting.verify;
+        options.do_chip_erase = flash_setting.erase == EraseMode::Chip;
+
+        flashing::download_file_with_options(
+            &mut session,
+            image,
+            flash_setting.probe_rs_format(),
+            options,
+        )
+        .map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e)))?;
+
+        if flash_setting.verify {
+            // ベリファイは書き込み完了からダウンロード終了までの間に行われる
+            report.lock().unwrap().verify_ms = programmed_at
+                .lock()
+                .unwrap()
+                .map(|t| t.elapsed().as_millis() as u64);
+        }
+
+        let mut core = session.core(0)?;
+        match flash_setting.after {
+            AfterFlash::Run => core.reset()?,
+            AfterFlash::HaltAtMain => {
+                let main = ElfSymbols::load(image)
+                    .and_then(|symbols| symbols.find("main").map(|main| main.value & !1))
+                    .ok_or_else(|| {
+                        probe_rs::Error::Other(anyhow::anyhow!("`main` not found in the ELF"))
+                    })?;
+                core.reset_and_halt(FLASH_HALT_TIMEOUT)?;
+                core.set_hw_breakpoint(main)?;
+                core.run()?;
+                let res = core.wait_for_core_halted(FLASH_HALT_TIMEOUT);
+                core.clear_hw_breakpoint(main)?;
+                res?;
+            }
+            AfterFlash::NoReset => {}
+        }
+        Ok(())
     }
 }
 
 // ----------------------------------------------------------------------------
+
+/// Called when a watch thread ends. The timer is shared by every target of the
+/// workspace, so only the last running thread stops it.
+fn release_log_timer(watchers: &Arc<Mutex<usize>>, log_timer: &Arc<Mutex<Stopwatch>>) {
+    let mut watchers = watchers.lock().unwrap();
+    *watchers = watchers.saturating_sub(1);
+    if *watchers == 0 {
+        log_timer.lock().unwrap().stop();
+    }
+}
+
+/// Waits the backoff before the next reconnect attempt. False when the policy
+/// gives up or the session is stopped while waiting.
+fn wait_reconnect(
+    policy: &ReconnectPolicy,
+    attempt: &mut u32,
+    flag: &Arc<Mutex<bool>>,
+    log_timer: &Arc<Mutex<Stopwatch>>,
+    health: &Arc<Mutex<SessionHealth>>,
+) -> bool {
+    *attempt += 1;
+    let Some(delay) = policy.delay(*attempt) else {
+        return false;
+    };
+    let now_time = log_timer.lock().unwrap().elapsed_ms();
+    {
+        let mut health = health.lock().unwrap();
+        health.set_state(ConnectionState::Reconnecting, now_time);
+        health.attempts = *attempt;
+        health.next_retry_ms = Some(now_time + delay.as_millis() as i64);
+    }
+
+    let until = std::time::Instant::now() + delay;
+    loop {
+        if *flag.lock().unwrap() == false {
+            return false;
+        }
+        let now = std::time::Instant::now();
+        if now >= until {
+            return true;
+        }
+        std::thread::sleep(RECONNECT_POLL_INTERVAL.min(until - now));
+    }
+}
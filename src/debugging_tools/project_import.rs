@@ -0,0 +1,776 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::a2l::Conversion;
+use super::elf_parser::VariableInfo;
+use super::probe_interface::WatchSetting;
+
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PanelKind {
+    Plot,
+    Gauges,
+    EditTable,
+}
+
+impl std::fmt::Display for PanelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Plot => write!(f, "Plot"),
+            Self::Gauges => write!(f, "gauge_view"),
+            Self::EditTable => write!(f, "edit_table"),
+        }
+    }
+}
+
+/// Widget window to create, with the symbols it shows.
+#[derive(Clone, Debug)]
+pub struct ImportedPanel {
+    pub kind: PanelKind,
+    pub title: String,
+    pub symbols: Vec<String>,
+}
+
+/// Watch list and dashboard of another tool's project.
+///
+/// `setting.watch_list` may hold symbols without a type or address (FreeMASTER
+/// refers to variables by name); they are resolved when the ELF is loaded.
+#[derive(Default, Clone, Debug)]
+pub struct ImportedProject {
+    pub setting: WatchSetting,
+    /// Fastest read period of the project.
+    pub period_ms: Option<u64>,
+    pub panels: Vec<ImportedPanel>,
+    /// Things that could not be converted.
+    pub warnings: Vec<String>,
+}
+
+impl ImportedProject {
+    fn add_symbol(&mut self, symbol: VariableInfo) {
+        if !self
+            .setting
+            .watch_list
+            .iter()
+            .any(|s| s.name == symbol.name)
+        {
+            self.setting.watch_list.push(symbol);
+        }
+    }
+
+    fn add_period(&mut self, period_ms: u64) {
+        if period_ms > 0 {
+            self.period_ms = Some(self.period_ms.map_or(period_ms, |p| p.min(period_ms)));
+        }
+    }
+
+    // 変数の無いパネルは作らない
+    fn add_panel(&mut self, kind: PanelKind, title: &str, symbols: Vec<String>) {
+        if !symbols.is_empty() {
+            self.panels.push(ImportedPanel {
+                kind,
+                title: title.to_string(),
+                symbols,
+            });
+        }
+    }
+}
+
+/// Reads a STM32CubeMonitor flow (`.json`) or a FreeMASTER project (`.pmpx`).
+pub fn import_project(path: &Path) -> Result<ImportedProject, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("pmpx") => import_freemaster(&text),
+        _ => import_cube_monitor(&text),
+    }
+}
+
+fn c_type_of_size(signed: bool, float: bool, size: usize) -> Option<&'static str> {
+    match (float, signed, size) {
+        (true, _, 4) => Some("float"),
+        (true, _, 8) => Some("double"),
+        (false, true, 1) => Some("signed char"),
+        (false, true, 2) => Some("short int"),
+        (false, true, 4) => Some("int"),
+        (false, true, 8) => Some("long long int"),
+        (false, false, 1) => Some("unsigned char"),
+        (false, false, 2) => Some("short unsigned int"),
+        (false, false, 4) => Some("unsigned int"),
+        (false, false, 8) => Some("long long unsigned int"),
+        _ => None,
+    }
+}
+
+fn parse_address(text: &str) -> Option<u64> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+// ----------------------------------------------------------------------------
+// STM32CubeMonitorの変数の型番号
+fn cube_type(code: u64) -> Option<(&'static str, usize)> {
+    let c_type = match code {
+        1 => "unsigned char",
+        2 => "signed char",
+        3 => "short unsigned int",
+        4 => "short int",
+        5 => "unsigned int",
+        6 => "int",
+        7 => "long long unsigned int",
+        8 => "long long int",
+        9 => "float",
+        10 => "double",
+        _ => return None,
+    };
+    let size = match code {
+        1 | 2 => 1,
+        3 | 4 => 2,
+        5 | 6 | 9 => 4,
+        _ => 8,
+    };
+    Some((c_type, size))
+}
+
+fn text_field<'a>(node: &'a Value, keys: &[&str]) -> Option<&'a str> {
+    keys.iter()
+        .filter_map(|key| node.get(*key)?.as_str())
+        .find(|text| !text.trim().is_empty())
+}
+
+fn number_field(node: &Value, key: &str) -> Option<f64> {
+    match node.get(key)? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Reads a STM32CubeMonitor (Node-RED) flow export.
+///
+/// The `variables` nodes (and the `exe-config` node they refer to) give the watch
+/// list, ELF and read rate. Charts become Plots and gauges become Gauges, showing
+/// the variables of the `variables` nodes wired to them, or the variable named by
+/// their label. Dashboard inputs wired to the write side become an Edit Table.
+pub fn import_cube_monitor(text: &str) -> Result<ImportedProject, String> {
+    let flow: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let nodes = match &flow {
+        Value::Array(nodes) => nodes.clone(),
+        // 1つのフローを{"flows": [...]}の形で保存したもの
+        Value::Object(object) => match object.get("flows") {
+            Some(Value::Array(nodes)) => nodes.clone(),
+            _ => return Err("not a Node-RED flow".to_string()),
+        },
+        _ => return Err("not a Node-RED flow".to_string()),
+    };
+    fn kind(node: &Value) -> &str {
+        node.get("type").and_then(Value::as_str).unwrap_or("")
+    }
+    let id = |node: &Value| {
+        node.get("id")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string()
+    };
+
+    let by_id: BTreeMap<String, &Value> = nodes.iter().map(|node| (id(node), node)).collect();
+    // 配線を逆向きにたどるための表
+    let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for node in &nodes {
+        // processingノードはgroupidでvariablesノードとつながる
+        if let Some(group) = node.get("groupid").and_then(Value::as_str) {
+            sources.entry(id(node)).or_default().push(group.to_string());
+        }
+        let Some(Value::Array(outputs)) = node.get("wires") else {
+            continue;
+        };
+        for target in outputs.iter().filter_map(Value::as_array).flatten() {
+            if let Some(target) = target.as_str() {
+                sources
+                    .entry(target.to_string())
+                    .or_default()
+                    .push(id(node));
+            }
+        }
+    }
+    let upstream = |start: &str| -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        let mut stack = vec![start.to_string()];
+        while let Some(current) = stack.pop() {
+            for source in sources.get(&current).into_iter().flatten() {
+                if found.insert(source.clone()) {
+                    stack.push(source.clone());
+                }
+            }
+        }
+        found
+    };
+
+    let mut import = ImportedProject::default();
+    // variablesノードごとの変数名
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for node in nodes.iter().filter(|node| kind(node) == "variables") {
+        let config = node
+            .get("execonfig")
+            .and_then(Value::as_str)
+            .and_then(|config| by_id.get(config));
+        if let Some(elf) = config.and_then(|config| text_field(config, &["exefile", "filename"])) {
+            if import.setting.elf_path.is_empty() {
+                import.setting.elf_path = elf.to_string();
+            }
+        }
+
+        let list = match node.get("variablelist") {
+            Some(Value::Array(list)) if !list.is_empty() => list.clone(),
+            _ => config
+                .and_then(|config| config.get("exevariablelist"))
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default(),
+        };
+        let mut names = Vec::new();
+        for variable in &list {
+            if variable.get("checked").and_then(Value::as_bool) == Some(false) {
+                continue;
+            }
+            let Some(name) = text_field(variable, &["name"]) else {
+                continue;
+            };
+            let address = text_field(variable, &["address"]).and_then(parse_address);
+            let Some((types, size)) = variable
+                .get("type")
+                .and_then(|code| code.as_u64().or_else(|| code.as_str()?.parse().ok()))
+                .and_then(cube_type)
+            else {
+                import
+                    .warnings
+                    .push(format!("{name}: unknown type, resolved from the ELF"));
+                import.add_symbol(VariableInfo {
+                    name: name.to_string(),
+                    types: String::new(),
+                    address: address.unwrap_or(0),
+                    size: 0,
+                });
+                names.push(name.to_string());
+                continue;
+            };
+            import.add_symbol(VariableInfo {
+                name: name.to_string(),
+                types: types.to_string(),
+                address: address.unwrap_or(0),
+                size,
+            });
+            names.push(name.to_string());
+        }
+
+        // 読み出し周期 (Hz または ms)
+        if let Some(hz) = number_field(node, "frequency").filter(|hz| *hz > 0.0) {
+            import.add_period((1000.0 / hz).round().max(1.0) as u64);
+        } else if let Some(ms) = number_field(node, "period").filter(|ms| *ms > 0.0) {
+            import.add_period(ms as u64);
+        }
+        groups.insert(id(node), names);
+    }
+
+    let all_names: Vec<String> = import
+        .setting
+        .watch_list
+        .iter()
+        .map(|symbol| symbol.name.clone())
+        .collect();
+    let panel_symbols = |node: &Value| -> Vec<String> {
+        // ラベルが変数名ならその変数だけ
+        if let Some(label) = text_field(node, &["label", "title", "name", "topic"]) {
+            if all_names.iter().any(|name| name == label) {
+                return vec![label.to_string()];
+            }
+        }
+        let mut names = Vec::new();
+        for source in upstream(&id(node)) {
+            for name in groups.get(&source).into_iter().flatten() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    };
+
+    // ゲージはダッシュボードのグループごとに1つのウィンドウにまとめる
+    let mut gauge_groups: Vec<(String, Vec<String>)> = Vec::new();
+    let mut edit_names: Vec<String> = Vec::new();
+    for node in &nodes {
+        let title = text_field(node, &["label", "name", "title"]).unwrap_or("");
+        match kind(node) {
+            "ui_chartst" | "ui_chart" => {
+                let title = if title.is_empty() { "Plot" } else { title };
+                import.add_panel(PanelKind::Plot, title, panel_symbols(node));
+            }
+            "ui_gauge" => {
+                let group = text_field(node, &["group"]).unwrap_or("").to_string();
+                let names = panel_symbols(node);
+                match gauge_groups.iter_mut().find(|(g, _)| *g == group) {
+                    Some((_, list)) => {
+                        for name in names {
+                            if !list.contains(&name) {
+                                list.push(name);
+                            }
+                        }
+                    }
+                    None => gauge_groups.push((group, names)),
+                }
+            }
+            "ui_numeric" | "ui_slider" | "ui_text_input" | "ui_switch" | "ui_button" => {
+                if let Some(label) = text_field(node, &["topic", "label", "name"]) {
+                    if all_names.iter().any(|name| name == label)
+                        && !edit_names.iter().any(|name| name == label)
+                    {
+                        edit_names.push(label.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for (index, (_, names)) in gauge_groups.into_iter().enumerate() {
+        import.add_panel(PanelKind::Gauges, &format!("Gauges {}", index + 1), names);
+    }
+    import.add_panel(PanelKind::EditTable, "Edit Table", edit_names);
+
+    if import.setting.watch_list.is_empty() {
+        return Err("no variables node in the flow".to_string());
+    }
+    Ok(import)
+}
+// ----------------------------------------------------------------------------
+// 大文字小文字を区別せずに属性を探す
+fn attribute<'a>(node: &roxmltree::Node<'a, '_>, names: &[&str]) -> Option<&'a str> {
+    node.attributes()
+        .into_iter()
+        .find(|attr| {
+            names
+                .iter()
+                .any(|name| attr.name().eq_ignore_ascii_case(name))
+        })
+        .map(|attr| attr.value())
+        .filter(|value| !value.trim().is_empty())
+}
+
+fn is_tag(node: &roxmltree::Node, names: &[&str]) -> bool {
+    node.is_element()
+        && names
+            .iter()
+            .any(|name| node.tag_name().name().eq_ignore_ascii_case(name))
+}
+
+// FreeMASTERの型名 ("unsigned int", "UINT", "ieee float" ...) とサイズ
+fn freemaster_type(type_name: &str, size: Option<usize>) -> Option<&'static str> {
+    let type_name = type_name.to_ascii_lowercase();
+    let float = type_name.contains("float") || type_name.contains("ieee") || type_name == "double";
+    let signed = !(type_name.contains("unsigned") || type_name.starts_with('u'));
+    let size = size.or(match type_name.as_str() {
+        "double" => Some(8),
+        "float" => Some(4),
+        _ => None,
+    })?;
+    if type_name.contains("bool") {
+        return c_type_of_size(false, false, size);
+    }
+    c_type_of_size(signed, float, size)
+}
+
+/// Reads the variables, variable watch, oscilloscopes and recorders of a FreeMASTER
+/// project (`.pmpx`).
+///
+/// Variables address their symbol by name or by a number. Oscilloscopes and recorders
+/// become Plots; variables that may be modified become an Edit Table.
+pub fn import_freemaster(text: &str) -> Result<ImportedProject, String> {
+    let document = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+    let mut import = ImportedProject::default();
+    // 表示名 -> シンボル名
+    let mut names: BTreeMap<String, String> = BTreeMap::new();
+    let mut editable = Vec::new();
+
+    for node in document
+        .descendants()
+        .filter(|node| is_tag(node, &["Variable", "Var"]))
+    {
+        let Some(display) = attribute(&node, &["name"]) else {
+            continue;
+        };
+        let address = attribute(&node, &["address"]).unwrap_or(display);
+        // アドレスが数値ならその表示名、シンボルならシンボル名で監視する
+        let (name, number) = match parse_address(address) {
+            Some(number) => (display.to_string(), number),
+            None => (address.trim().to_string(), 0),
+        };
+        let size = attribute(&node, &["size"]).and_then(|size| size.trim().parse().ok());
+        let types =
+            attribute(&node, &["type"]).and_then(|type_name| freemaster_type(type_name, size));
+        if types.is_none() && number != 0 {
+            import.warnings.push(format!(
+                "{display}: unknown type at a fixed address, skipped"
+            ));
+            continue;
+        }
+        import.add_symbol(VariableInfo {
+            name: name.clone(),
+            types: types.unwrap_or("").to_string(),
+            address: number,
+            size: size.unwrap_or(0),
+        });
+        names.insert(display.to_string(), name.clone());
+
+        if let Some(period) = attribute(&node, &["period"]).and_then(|period| {
+            period
+                .trim()
+                .trim_end_matches("ms")
+                .trim()
+                .parse::<f64>()
+                .ok()
+        }) {
+            import.add_period(period as u64);
+        }
+
+        // 線形変換 y = a x + b と単位
+        let factor =
+            attribute(&node, &["factor"]).and_then(|value| value.trim().parse::<f64>().ok());
+        let offset =
+            attribute(&node, &["offset"]).and_then(|value| value.trim().parse::<f64>().ok());
+        let unit = attribute(&node, &["unit"]).unwrap_or("");
+        if factor.is_some() || offset.is_some() || !unit.is_empty() {
+            import.setting.conversions.insert(
+                name.clone(),
+                Conversion {
+                    factor: factor.unwrap_or(1.0),
+                    offset: offset.unwrap_or(0.0),
+                    unit: unit.to_string(),
+                    calibration: false,
+                },
+            );
+        }
+
+        let read_only = attribute(&node, &["readonly"])
+            .is_some_and(|value| matches!(value.trim(), "1" | "true" | "yes"));
+        let modify = attribute(&node, &["modify"])
+            .is_some_and(|value| !matches!(value.trim(), "0" | "false" | "no" | "never"));
+        if modify && !read_only {
+            editable.push(name);
+        }
+    }
+    if import.setting.watch_list.is_empty() {
+        return Err("no variables in the project".to_string());
+    }
+
+    // 子要素から参照している変数を集める
+    let referenced = |node: &roxmltree::Node| -> Vec<String> {
+        let mut list = Vec::new();
+        for child in node.descendants().filter(|child| child.is_element()) {
+            if child == *node {
+                continue;
+            }
+            let reference = attribute(&child, &["var"])
+                .or_else(|| child.text().map(str::trim).filter(|text| !text.is_empty()));
+            if let Some(name) = reference.and_then(|reference| names.get(reference)) {
+                if !list.contains(name) {
+                    list.push(name.clone());
+                }
+            }
+        }
+        list
+    };
+    // 変更できる変数のうち、どれかのウォッチに出ているもの
+    let mut watched: Option<BTreeSet<String>> = None;
+    for node in document.descendants() {
+        if is_tag(&node, &["Scope", "Oscilloscope", "Recorder"]) {
+            let title = attribute(&node, &["name"]).unwrap_or("Plot");
+            import.add_panel(PanelKind::Plot, title, referenced(&node));
+        } else if is_tag(&node, &["Watch", "VariableWatch", "WatchGrid"]) {
+            watched
+                .get_or_insert_with(BTreeSet::new)
+                .extend(referenced(&node));
+        }
+    }
+    if let Some(watched) = watched {
+        editable.retain(|name| watched.contains(name));
+    }
+    import.add_panel(PanelKind::EditTable, "Edit Table", editable);
+    Ok(import)
+}
+
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_MONITOR_FLOW: &str = r#"[
+        {"id": "cfg1", "type": "exe-config", "name": "demo", "exefile": "C:\\work\\demo.elf",
+         "exevariablelist": [{"address": "0x20000010", "name": "counter", "type": 5, "checked": true}]},
+        {"id": "v1", "type": "variables", "execonfig": "cfg1", "frequency": "20",
+         "variablelist": [
+            {"address": "0x20000010", "name": "counter", "type": 5, "checked": true},
+            {"address": "0x20000014", "name": "speed", "type": 9, "checked": true},
+            {"address": "0x20000018", "name": "mode", "type": 1, "checked": false},
+            {"address": "0x2000001C", "name": "level", "type": "42", "checked": true}],
+         "wires": [["acq1"]]},
+        {"id": "acq1", "type": "acq out", "wires": []},
+        {"id": "acq2", "type": "acq in", "wires": [["proc"]]},
+        {"id": "proc", "type": "processing", "groupid": "v1", "wires": [["chart", "sw"]]},
+        {"id": "sw", "type": "switch", "wires": [["g1"], ["g2"]]},
+        {"id": "chart", "type": "ui_chartst", "label": "Signals", "group": "grp", "wires": []},
+        {"id": "g1", "type": "ui_gauge", "label": "speed", "group": "grp", "wires": []},
+        {"id": "g2", "type": "ui_gauge", "label": "counter", "group": "grp", "wires": []},
+        {"id": "n1", "type": "ui_numeric", "topic": "speed", "wires": [["wr"]]},
+        {"id": "wr", "type": "acq out", "wires": []}
+    ]"#;
+
+    const FREEMASTER_PROJECT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Project>
+ <Variables>
+  <Variable Name="Motor speed" Address="g_speed" Type="unsigned int" Size="2" Period="50" Factor="0.1" Offset="-10" Unit="rpm" Modify="any"/>
+  <Variable Name="flag" Address="0x20000100" Type="bool" Size="1" Period="100" Modify="0"/>
+  <Variable Name="limit" Address="g_limit" Type="ieee float" Size="4" Period="20ms" Modify="any"/>
+  <Variable Name="blob" Address="0x20000200" Type="fixed"/>
+ </Variables>
+ <Scope Name="Speed scope"><ScopeVar Var="Motor speed"/><ScopeVar Var="flag"/></Scope>
+ <Watch><Item>Motor speed</Item><Item>flag</Item></Watch>
+</Project>"#;
+
+    fn symbol(name: &str, types: &str, address: u64, size: usize) -> VariableInfo {
+        VariableInfo {
+            name: name.to_string(),
+            types: types.to_string(),
+            address,
+            size,
+        }
+    }
+
+    fn panels(import: &ImportedProject) -> Vec<(PanelKind, &str, Vec<&str>)> {
+        import
+            .panels
+            .iter()
+            .map(|panel| {
+                (
+                    panel.kind,
+                    panel.title.as_str(),
+                    panel.symbols.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn imports_a_cube_monitor_flow() {
+        let import = import_cube_monitor(CUBE_MONITOR_FLOW).unwrap();
+        assert_eq!(import.setting.elf_path, "C:\\work\\demo.elf");
+        assert_eq!(
+            import.setting.watch_list,
+            vec![
+                symbol("counter", "unsigned int", 0x2000_0010, 4),
+                symbol("speed", "float", 0x2000_0014, 4),
+                symbol("level", "", 0x2000_001C, 0),
+            ]
+        );
+        // 20Hz
+        assert_eq!(import.period_ms, Some(50));
+        assert_eq!(
+            panels(&import),
+            vec![
+                (
+                    PanelKind::Plot,
+                    "Signals",
+                    vec!["counter", "speed", "level"]
+                ),
+                (PanelKind::Gauges, "Gauges 1", vec!["speed", "counter"]),
+                (PanelKind::EditTable, "Edit Table", vec!["speed"]),
+            ]
+        );
+        assert_eq!(
+            import.warnings,
+            vec!["level: unknown type, resolved from the ELF"]
+        );
+    }
+
+    #[test]
+    fn imports_a_wrapped_flow_and_rejects_others() {
+        let wrapped = format!(r#"{{"flows": {CUBE_MONITOR_FLOW}}}"#);
+        let import = import_cube_monitor(&wrapped).unwrap();
+        assert_eq!(import.setting.watch_list.len(), 3);
+
+        assert_eq!(
+            import_cube_monitor(r#"{"nodes": []}"#).err().unwrap(),
+            "not a Node-RED flow"
+        );
+        assert_eq!(
+            import_cube_monitor(r#"[{"id": "c", "type": "ui_chart"}]"#)
+                .err()
+                .unwrap(),
+            "no variables node in the flow"
+        );
+        assert!(import_cube_monitor("[{").is_err());
+    }
+
+    #[test]
+    fn imports_a_freemaster_project() {
+        let import = import_freemaster(FREEMASTER_PROJECT).unwrap();
+        assert_eq!(
+            import.setting.watch_list,
+            vec![
+                symbol("g_speed", "short unsigned int", 0, 2),
+                symbol("flag", "unsigned char", 0x2000_0100, 1),
+                symbol("g_limit", "float", 0, 4),
+            ]
+        );
+        assert_eq!(import.period_ms, Some(20));
+        assert_eq!(
+            import.setting.conversions.get("g_speed"),
+            Some(&Conversion {
+                factor: 0.1,
+                offset: -10.0,
+                unit: "rpm".to_string(),
+                calibration: false,
+            })
+        );
+        assert_eq!(import.setting.conversions.len(), 1);
+        // g_limitは変更可能だがウォッチに無い
+        assert_eq!(
+            panels(&import),
+            vec![
+                (PanelKind::Plot, "Speed scope", vec!["g_speed", "flag"]),
+                (PanelKind::EditTable, "Edit Table", vec!["g_speed"]),
+            ]
+        );
+        assert_eq!(
+            import.warnings,
+            vec!["blob: unknown type at a fixed address, skipped"]
+        );
+    }
+
+    #[test]
+    fn rejects_a_freemaster_project_without_variables() {
+        assert_eq!(
+            import_freemaster("<Project><Scope Name=\"s\"/></Project>")
+                .err()
+                .unwrap(),
+            "no variables in the project"
+        );
+        assert!(import_freemaster("<Project>").is_err());
+    }
+
+    // STM32CubeMonitor 1.x の書き出しそのままのノード構成 (座標やダッシュボードの
+    // 設定などの使わないフィールドも含む)
+    const CUBE_MONITOR_EXPORT: &str = r#"[
+        {"id":"3b6fbc1c.c3e5e4","type":"tab","label":"Basic_Flow","disabled":false,"info":""},
+        {"id":"1ebbb430.dac7dc","type":"variables","z":"3b6fbc1c.c3e5e4","groupname":"myVariables",
+         "accesspoint":0,"execonfig":"e9f1bb1e.01e048","variablelist":[],"triggerstartmode":"manual",
+         "triggername":"","triggerthreshold":"","frequency":"","frequencyType":"0",
+         "snapshotheader":"","mode":"direct","lastImportedTime":1700000000000,"openStatus":true,
+         "x":240,"y":120,"wires":[["6013fb0b.5b7504"],["4a573fb0.4bc99"]]},
+        {"id":"fc00295e.79ada8","type":"acquisition in","z":"3b6fbc1c.c3e5e4","name":"myProbe_In",
+         "probeconfig":"50a6d9e2.a7ba38","x":120,"y":280,"wires":[["a7c3e070.5a6c"],[]]},
+        {"id":"6013fb0b.5b7504","type":"acquisition out","z":"3b6fbc1c.c3e5e4","name":"myProbe_Out",
+         "probeconfig":"50a6d9e2.a7ba38","x":520,"y":120,"wires":[]},
+        {"id":"a7c3e070.5a6c","type":"processing","z":"3b6fbc1c.c3e5e4","groupname":"myVariables",
+         "groupid":"1ebbb430.dac7dc","expressions":[],"statistics":[],"logmode":"no",
+         "logformat":"stcm","x":390,"y":280,"wires":[["f9c34dde.1e2f4","7f6e9f52.b5e6a"],[]]},
+        {"id":"f9c34dde.1e2f4","type":"ui_chartst","z":"3b6fbc1c.c3e5e4","group":"f9c34dde.1e2f5",
+         "name":"myChart","order":4,"width":15,"height":9,"chartType":"line","curveType":"linear",
+         "duration":"10","ymin":"","ymax":"","x":580,"y":280,"wires":[]},
+        {"id":"7f6e9f52.b5e6a","type":"ui_gauge","z":"3b6fbc1c.c3e5e4","name":"","group":"f9c34dde.1e2f5",
+         "order":5,"width":0,"height":0,"gtype":"gage","title":"gauge","label":"units",
+         "format":"{{value}}","min":0,"max":10,"x":570,"y":340,"wires":[]},
+        {"id":"8a8bf2d3.1f6e2","type":"ui_numeric","z":"3b6fbc1c.c3e5e4","name":"","label":"setpoint",
+         "tooltip":"","group":"f9c34dde.1e2f5","order":6,"width":0,"height":0,"wrap":false,
+         "passthru":true,"topic":"setpoint","format":"{{value}}","min":0,"max":"1000","step":1,
+         "x":100,"y":180,"wires":[["1ebbb430.dac7dc"]]},
+        {"id":"e9f1bb1e.01e048","type":"exe-config","name":"myExe","exefile":"C:\\Users\\dev\\STM32CubeIDE\\workspace\\demo\\Debug\\demo.elf",
+         "exefolder":"C:\\Users\\dev\\STM32CubeIDE\\workspace\\demo\\Debug",
+         "exevariablelist":[
+            {"address":"0x20000038","name":"counter","type":5,"checked":true},
+            {"address":"0x2000003c","name":"setpoint","type":9,"checked":true},
+            {"address":"0x20000040","name":"table[0]","type":3,"checked":false}],
+         "expand":false,"filename":"demo.elf","exeModifiedTime":1700000000000,
+         "configLastUpdate":1700000000000},
+        {"id":"50a6d9e2.a7ba38","type":"probe","probeid":"066DFF485550755187121020",
+         "probename":"ST-Link v2-1B (...121020)","protocol":"SWD","frequency":"1.8 MHz - Default",
+         "probeproperty":"{\"SWD\":[\"4.6 MHz\",\"1.8 MHz - Default\",\"950 kHz\"]}",
+         "probeversion":"ST Link firmware version V2.J37","connectionType":"p2p","nickname":""},
+        {"id":"f9c34dde.1e2f5","type":"ui_group","name":"Chart","tab":"17d09b07.741a55","order":1,
+         "disp":true,"width":15,"collapse":false},
+        {"id":"17d09b07.741a55","type":"ui_tab","name":"Home","icon":"dashboard","disabled":false,
+         "hidden":false}
+    ]"#;
+
+    #[test]
+    fn imports_a_cube_monitor_export() {
+        let import = import_cube_monitor(CUBE_MONITOR_EXPORT).unwrap();
+        assert_eq!(
+            import.setting.elf_path,
+            "C:\\Users\\dev\\STM32CubeIDE\\workspace\\demo\\Debug\\demo.elf"
+        );
+        // variablelistが空ならexe-configの一覧を使う
+        assert_eq!(
+            import.setting.watch_list,
+            vec![
+                symbol("counter", "unsigned int", 0x2000_0038, 4),
+                symbol("setpoint", "float", 0x2000_003C, 4),
+            ]
+        );
+        // frequencyが空なら周期は決めない
+        assert_eq!(import.period_ms, None);
+        assert_eq!(
+            panels(&import),
+            vec![
+                (PanelKind::Plot, "myChart", vec!["counter", "setpoint"]),
+                (PanelKind::Gauges, "Gauges 1", vec!["counter", "setpoint"]),
+                (PanelKind::EditTable, "Edit Table", vec!["setpoint"]),
+            ]
+        );
+        assert!(import.warnings.is_empty());
+    }
+
+    // FreeMASTERのVariable Watchは複数のグリッドを持てる
+    const FREEMASTER_WATCH_GRIDS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Project>
+ <Variables>
+  <Variable Name="gain" Address="g_gain" Type="ieee float" Size="4" Period="10" A="3" B="1" Modify="any"/>
+  <Variable Name="limit" Address="g_limit" Type="unsigned int" Size="4" Modify="any"/>
+  <Variable Name="mode" Address="g_mode" Type="unsigned int" Size="1" Modify="any" ReadOnly="1"/>
+  <Variable Name="spare" Address="g_spare" Type="int" Size="2" Modify="any"/>
+ </Variables>
+ <Watch><Item>gain</Item><Item>mode</Item></Watch>
+ <Watch><Item>limit</Item></Watch>
+</Project>"#;
+
+    #[test]
+    fn freemaster_edit_table_holds_every_watch_grid() {
+        let import = import_freemaster(FREEMASTER_WATCH_GRIDS).unwrap();
+        assert_eq!(import.period_ms, Some(10));
+        // AやBは係数として扱わない
+        assert!(import.setting.conversions.is_empty());
+        assert_eq!(
+            panels(&import),
+            vec![(
+                PanelKind::EditTable,
+                "Edit Table",
+                vec!["g_gain", "g_limit"]
+            )]
+        );
+
+        // ウォッチが無ければ変更できる変数をすべて並べる
+        let without_watch = FREEMASTER_WATCH_GRIDS.replace("<Watch>", "<Other>");
+        let without_watch = without_watch.replace("</Watch>", "</Other>");
+        let import = import_freemaster(&without_watch).unwrap();
+        assert_eq!(
+            panels(&import),
+            vec![(
+                PanelKind::EditTable,
+                "Edit Table",
+                vec!["g_gain", "g_limit", "g_spare"]
+            )]
+        );
+    }
+}
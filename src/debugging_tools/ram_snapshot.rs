@@ -0,0 +1,457 @@
+use probe_rs::config::MemoryRegion;
+use probe_rs::{Core, MemoryInterface};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read, Seek, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::elf_parser::VariableInfo;
+use super::memory_interface::MCUMemory;
+use super::symbol_map::SymbolMap;
+
+const SNAPSHOT_MAGIC: &str = "VisuModiRam RAM snapshot v1";
+// シンボルに属さない変化は、この間隔より近ければ1つのグループにまとめる
+const UNOWNED_GAP: u64 = 16;
+// シンボルに属さない変化を16進で表示する最大バイト数
+const UNOWNED_PREVIEW_LEN: usize = 32;
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SnapshotRegion {
+    pub address: u64,
+    pub data: Vec<u8>,
+}
+
+impl SnapshotRegion {
+    fn range(&self) -> Range<u64> {
+        self.address..self.address + self.data.len() as u64
+    }
+}
+
+/// All RAM regions of the target at one moment, with where it came from.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RamSnapshot {
+    pub chip: String,
+    pub elf_path: String,
+    /// Wall clock time of the capture [s since UNIX epoch].
+    pub unix_time: u64,
+    /// Session time of the capture, same time base as the log.
+    pub time_ms: i64,
+    /// The core was halted while reading, so the regions are consistent.
+    pub halted: bool,
+    pub regions: Vec<SnapshotRegion>,
+}
+
+impl std::fmt::Display for RamSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} @ {:.3}s ({} bytes)",
+            self.chip,
+            self.time_ms as f64 / 1000.0,
+            self.size()
+        )
+    }
+}
+
+impl RamSnapshot {
+    /// RAM ranges of the probe-rs memory map, without duplicates.
+    pub fn ram_ranges(memory_map: &[MemoryRegion]) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Ram(ram) => Some(ram.range.clone()),
+                _ => None,
+            })
+            .collect();
+        ranges.sort_by_key(|range| (range.start, range.end));
+        ranges.dedup();
+        ranges
+    }
+
+    /// Reads every RAM region. With `halt` the core is stopped during the read
+    /// and resumed afterwards if it was running.
+    pub fn capture(
+        core: &mut Core,
+        memory_map: &[MemoryRegion],
+        halt: bool,
+    ) -> Result<Vec<SnapshotRegion>, probe_rs::Error> {
+        let was_running = halt && !core.core_halted()?;
+        if was_running {
+            core.halt(Duration::from_millis(100))?;
+        }
+
+        let mut regions = Vec::new();
+        let mut res = Ok(());
+        for range in Self::ram_ranges(memory_map) {
+            let mut data = vec![0u8; (range.end - range.start) as usize];
+            if let Err(e) = core.read_8(range.start, &mut data) {
+                res = Err(e);
+                break;
+            }
+            regions.push(SnapshotRegion {
+                address: range.start,
+                data,
+            });
+        }
+
+        if was_running {
+            core.run()?;
+        }
+        res.map(|_| regions)
+    }
+
+    pub fn size(&self) -> usize {
+        self.regions.iter().map(|region| region.data.len()).sum()
+    }
+
+    pub fn read(&self, address: u64, len: usize) -> Option<&[u8]> {
+        let region = self
+            .regions
+            .iter()
+            .find(|region| region.range().contains(&address))?;
+        let start = (address - region.address) as usize;
+        region.data.get(start..start + len)
+    }
+
+    // ------------------------------------------------------------------------
+    /// Text header (one `key=value` per line, ended by an empty line) followed
+    /// by the raw bytes of the regions in header order.
+    pub fn save(&self, path: &PathBuf) -> Result<(), std::io::Error> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "{}", SNAPSHOT_MAGIC)?;
+        writeln!(file, "chip={}", self.chip)?;
+        writeln!(file, "elf={}", self.elf_path)?;
+        writeln!(file, "unix_time={}", self.unix_time)?;
+        writeln!(file, "time_ms={}", self.time_ms)?;
+        writeln!(file, "halted={}", self.halted)?;
+        for region in &self.regions {
+            writeln!(
+                file,
+                "region=0x{:08X},0x{:X}",
+                region.address,
+                region.data.len()
+            )?;
+        }
+        writeln!(file)?;
+        for region in &self.regions {
+            file.write_all(&region.data)?;
+        }
+        file.flush()
+    }
+
+    pub fn load(path: &PathBuf) -> Result<Self, std::io::Error> {
+        let invalid = |message: &str| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+        };
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+
+        let mut line = String::new();
+        file.read_line(&mut line)?;
+        if line.trim_end() != SNAPSHOT_MAGIC {
+            return Err(invalid("not a RAM snapshot file"));
+        }
+
+        let mut snapshot = Self::default();
+        let mut sizes = Vec::new();
+        loop {
+            line.clear();
+            if file.read_line(&mut line)? == 0 {
+                return Err(invalid("truncated header"));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid(&format!("invalid header line \"{}\"", line)));
+            };
+            match key {
+                "chip" => snapshot.chip = value.to_string(),
+                "elf" => snapshot.elf_path = value.to_string(),
+                "unix_time" => snapshot.unix_time = value.parse().unwrap_or_default(),
+                "time_ms" => snapshot.time_ms = value.parse().unwrap_or_default(),
+                "halted" => snapshot.halted = value == "true",
+                "region" => {
+                    let parse = |text: &str| u64::from_str_radix(text.trim_start_matches("0x"), 16);
+                    let (address, len) = value
+                        .split_once(',')
+                        .and_then(|(address, len)| Some((parse(address).ok()?, parse(len).ok()?)))
+                        .ok_or_else(|| invalid(&format!("invalid region \"{}\"", value)))?;
+                    sizes.push((address, len as usize));
+                }
+                // 知らないキーは読み飛ばす
+                _ => {}
+            }
+        }
+
+        // ヘッダの大きさは信用せず、ファイルに残っているバイト数と照合してから確保する
+        let remaining = file
+            .get_ref()
+            .metadata()?
+            .len()
+            .saturating_sub(file.stream_position()?);
+        let total = sizes
+            .iter()
+            .try_fold(0u64, |total, (_, len)| total.checked_add(*len as u64));
+        if total.map_or(true, |total| total > remaining) {
+            return Err(invalid("region sizes exceed the file size"));
+        }
+
+        for (address, len) in sizes {
+            let mut data = vec![0u8; len];
+            file.read_exact(&mut data)?;
+            snapshot.regions.push(SnapshotRegion { address, data });
+        }
+        Ok(snapshot)
+    }
+
+    // ------------------------------------------------------------------------
+    /// Changed bytes between `self` (old) and `new`, grouped by the owning ELF symbol.
+    pub fn diff(&self, new: &RamSnapshot, symbol_map: Option<&SymbolMap>) -> Vec<SnapshotDiff> {
+        let mut owned: BTreeMap<u64, SnapshotDiff> = BTreeMap::new();
+        let mut unowned: Vec<SnapshotDiff> = Vec::new();
+
+        for old_region in &self.regions {
+            for new_region in &new.regions {
+                let start = old_region.address.max(new_region.address);
+                let end = old_region.range().end.min(new_region.range().end);
+                for address in start..end {
+                    let old_byte = old_region.data[(address - old_region.address) as usize];
+                    let new_byte = new_region.data[(address - new_region.address) as usize];
+                    if old_byte == new_byte {
+                        continue;
+                    }
+
+                    match symbol_map.and_then(|map| map.owner_of(address)) {
+                        Some(owner) => {
+                            let group =
+                                owned.entry(owner.address).or_insert_with(|| SnapshotDiff {
+                                    symbol: Some(owner.name.clone()),
+                                    address: owner.address,
+                                    size: owner.size,
+                                    ..Default::default()
+                                });
+                            group.changed_bytes += 1;
+
+                            let member = symbol_map.and_then(|map| map.member_of(address));
+                            if let Some(member) = member {
+                                if !group.values.iter().any(|v| v.name == member.name) {
+                                    group.values.push(self.value_change(new, member));
+                                }
+                            }
+                        }
+                        None => match unowned.last_mut() {
+                            Some(group)
+                                if address < group.address + group.size as u64 + UNOWNED_GAP =>
+                            {
+                                group.size = (address - group.address) as usize + 1;
+                                group.changed_bytes += 1;
+                            }
+                            _ => unowned.push(SnapshotDiff {
+                                symbol: None,
+                                address,
+                                size: 1,
+                                changed_bytes: 1,
+                                ..Default::default()
+                            }),
+                        },
+                    }
+                }
+            }
+        }
+
+        for group in &mut unowned {
+            let address = group.address;
+            let len = group.size.min(UNOWNED_PREVIEW_LEN);
+            let hex = |snapshot: &RamSnapshot| {
+                snapshot
+                    .read(address, len)
+                    .map(|bytes| {
+                        bytes
+                            .iter()
+                            .map(|b| format!("{:02X}", b))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .unwrap_or_default()
+            };
+            group.values.push(ValueChange {
+                name: format!("0x{:08X}", address),
+                address,
+                old: hex(self),
+                new: hex(new),
+            });
+        }
+
+        let mut diffs: Vec<SnapshotDiff> = owned.into_values().collect();
+        diffs.extend(unowned);
+        diffs.sort_by_key(|diff| diff.address);
+        diffs
+    }
+
+    fn value_change(&self, new: &RamSnapshot, member: &VariableInfo) -> ValueChange {
+        let decode = |snapshot: &RamSnapshot| {
+            snapshot
+                .read(member.address, member.size)
+                .map(|bytes| MCUMemory::decode(member, bytes))
+                .unwrap_or_default()
+        };
+        ValueChange {
+            name: member.name.clone(),
+            address: member.address,
+            old: decode(self),
+            new: decode(new),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ValueChange {
+    pub name: String,
+    pub address: u64,
+    pub old: String,
+    pub new: String,
+}
+
+/// Changes inside one symbol, or inside one run of bytes no symbol owns (`symbol: None`).
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SnapshotDiff {
+    pub symbol: Option<String>,
+    pub address: u64,
+    pub size: usize,
+    pub changed_bytes: usize,
+    pub values: Vec<ValueChange>,
+}
+
+impl SnapshotDiff {
+    pub fn name(&self) -> String {
+        self.symbol
+            .clone()
+            .unwrap_or_else(|| format!("(no symbol) 0x{:08X}", self.address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("vmr_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn save_and_load() {
+        let snapshot = RamSnapshot {
+            chip: "STM32F446RETx".to_string(),
+            elf_path: "firmware.elf".to_string(),
+            unix_time: 1_700_000_000,
+            time_ms: 1234,
+            halted: true,
+            regions: vec![
+                SnapshotRegion {
+                    address: 0x2000_0000,
+                    data: vec![1, 2, 3, 4],
+                },
+                SnapshotRegion {
+                    address: 0x1000_0000,
+                    data: vec![0xAA; 3],
+                },
+            ],
+        };
+        let path = temp_file("snapshot.bin", &[]);
+        snapshot.save(&path).unwrap();
+        let loaded = RamSnapshot::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), snapshot);
+    }
+
+    #[test]
+    fn region_sizes_are_checked_against_the_file() {
+        for (name, header) in [
+            ("truncated.bin", "region=0x20000000,0x10\n"),
+            ("huge.bin", "region=0x20000000,0xFFFFFFFFFFFFFFF0\n"),
+            (
+                "sum_overflow.bin",
+                "region=0x20000000,0x8000000000000000\nregion=0x0,0x8000000000000000\n",
+            ),
+        ] {
+            let mut contents = format!("{}\n{}\n", SNAPSHOT_MAGIC, header).into_bytes();
+            contents.extend([0u8; 8]);
+            let path = temp_file(name, &contents);
+            let loaded = RamSnapshot::load(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(
+                loaded.unwrap_err().kind(),
+                std::io::ErrorKind::InvalidData,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn diff_groups_changes_by_symbol() {
+        let variable = |name: &str, types: &str, address: u64, size: usize| VariableInfo {
+            name: name.to_string(),
+            types: types.to_string(),
+            address,
+            size,
+        };
+        // 64 KiB を超えるバッファの末尾近くも、そのシンボルに属する
+        let symbol_map = SymbolMap::new(
+            vec![
+                variable("buffer", "", 0x2000_0000, 0x2_0000),
+                variable("counter", "unsigned int", 0x2002_0000, 4),
+            ],
+            vec![variable("counter", "unsigned int", 0x2002_0000, 4)],
+        );
+        let snapshot = |data: Vec<u8>| RamSnapshot {
+            regions: vec![SnapshotRegion {
+                address: 0x2000_0000,
+                data,
+            }],
+            ..Default::default()
+        };
+        let old = snapshot(vec![0; 0x2_0100]);
+        let mut data = vec![0; 0x2_0100];
+        data[0x1_8000] = 1;
+        data[0x1_8001] = 2;
+        data[0x2_0000] = 7;
+        data[0x2_0080] = 0xAB;
+        data[0x2_0082] = 0xCD;
+        let new = snapshot(data);
+
+        let diffs = old.diff(&new, Some(&symbol_map));
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(diffs[0].name(), "buffer");
+        assert_eq!(diffs[0].size, 0x2_0000);
+        assert_eq!(diffs[0].changed_bytes, 2);
+        assert!(diffs[0].values.is_empty());
+
+        assert_eq!(diffs[1].name(), "counter");
+        assert_eq!(diffs[1].changed_bytes, 1);
+        assert_eq!(
+            diffs[1].values,
+            vec![ValueChange {
+                name: "counter".to_string(),
+                address: 0x2002_0000,
+                old: "0".to_string(),
+                new: "7".to_string(),
+            }]
+        );
+
+        assert_eq!(diffs[2].symbol, None);
+        assert_eq!(diffs[2].address, 0x2002_0080);
+        assert_eq!(diffs[2].size, 3);
+        assert_eq!(diffs[2].changed_bytes, 2);
+        assert_eq!(diffs[2].values[0].old, "00 00 00");
+        assert_eq!(diffs[2].values[0].new, "AB 00 CD");
+
+        // シンボル情報なしでは全てシンボルなしのグループになる
+        let diffs = old.diff(&new, None);
+        assert!(diffs.iter().all(|diff| diff.symbol.is_none()));
+        assert_eq!(diffs.len(), 3);
+    }
+}
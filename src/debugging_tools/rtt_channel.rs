@@ -0,0 +1,276 @@
+use probe_rs::config::MemoryRegion;
+use probe_rs::rtt::{Rtt, ScanRegion};
+use probe_rs::Core;
+use std::collections::VecDeque;
+
+use super::defmt_decoder::DefmtMeta;
+
+const RTT_LOG_CAPACITY: usize = 20000;
+const RTT_READ_BUFFER: usize = 1024;
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RttLine {
+    pub seq: usize,
+    pub time_ms: i64,
+    pub channel: usize,
+    pub text: String,
+    pub meta: Option<DefmtMeta>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RttChannelInfo {
+    pub number: usize,
+    pub name: String,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RttStatus {
+    pub attached: bool,
+    pub up_channels: Vec<RttChannelInfo>,
+    pub down_channels: Vec<RttChannelInfo>,
+}
+// ----------------------------------------------------------------------------
+/// Ring buffer of received RTT lines shared between the watch thread and the UI.
+#[derive(Default)]
+pub struct RttLog {
+    lines: VecDeque<RttLine>,
+    next_seq: usize,
+    pub status: RttStatus,
+}
+
+impl RttLog {
+    pub fn push(&mut self, time_ms: i64, channel: usize, text: String, meta: Option<DefmtMeta>) {
+        self.lines.push_back(RttLine {
+            seq: self.next_seq,
+            time_ms,
+            channel,
+            text,
+            meta,
+        });
+        self.next_seq += 1;
+
+        while self.lines.len() > RTT_LOG_CAPACITY {
+            self.lines.pop_front();
+        }
+    }
+
+    /// Lines with `seq >= from_seq`, for widgets that keep their own copy.
+    pub fn lines_since(&self, from_seq: usize) -> Vec<RttLine> {
+        self.lines
+            .iter()
+            .skip_while(|line| line.seq < from_seq)
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+// ----------------------------------------------------------------------------
+/// RTT connection living inside the watch thread.
+pub struct RttSession {
+    rtt: Rtt,
+    // 改行が来るまでの途中の行 (受信開始時刻, 文字列)
+    pending: Vec<Option<(i64, String)>>,
+    // 読み出しの境界で切れたUTF-8の途中のバイト
+    partial: Vec<Vec<u8>>,
+}
+
+impl RttSession {
+    pub fn attach(
+        core: &mut Core,
+        memory_map: &[MemoryRegion],
+        control_block: Option<u64>,
+    ) -> Result<Self, probe_rs::Error> {
+        let region = match control_block {
+            Some(address) => ScanRegion::Exact(address as u32),
+            None => ScanRegion::Ram,
+        };
+        let rtt = Rtt::attach_region(core, memory_map, &region)
+            .map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e.to_string())))?;
+
+        let pending = vec![None; rtt.up_channels().len()];
+        let partial = vec![Vec::new(); rtt.up_channels().len()];
+        Ok(Self {
+            rtt,
+            pending,
+            partial,
+        })
+    }
+
+    pub fn up_channels(&mut self) -> Vec<RttChannelInfo> {
+        self.rtt
+            .up_channels()
+            .iter()
+            .map(|ch| RttChannelInfo {
+                number: ch.number(),
+                name: ch.name().unwrap_or_default().to_string(),
+            })
+            .collect()
+    }
+
+    pub fn down_channels(&mut self) -> Vec<RttChannelInfo> {
+        self.rtt
+            .down_channels()
+            .iter()
+            .map(|ch| RttChannelInfo {
+                number: ch.number(),
+                name: ch.name().unwrap_or_default().to_string(),
+            })
+            .collect()
+    }
+
+    /// Reads all text up-channels except `skip_channel` and returns the completed lines.
+    pub fn poll(
+        &mut self,
+        core: &mut Core,
+        time_ms: i64,
+        skip_channel: Option<usize>,
+    ) -> Result<Vec<(i64, usize, String)>, probe_rs::Error> {
+        let mut lines = Vec::new();
+        let mut buf = [0u8; RTT_READ_BUFFER];
+
+        for (index, channel) in self.rtt.up_channels().iter().enumerate() {
+            if Some(channel.number()) == skip_channel {
+                continue;
+            }
+            loop {
+                let count = channel
+                    .read(core, &mut buf)
+                    .map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e.to_string())))?;
+                if count == 0 {
+                    break;
+                }
+
+                let text = decode_utf8(&mut self.partial[index], &buf[..count]);
+                for c in text.chars() {
+                    let (start_time, line) =
+                        self.pending[index].get_or_insert_with(|| (time_ms, String::new()));
+                    match c {
+                        '\n' => {
+                            lines.push((*start_time, channel.number(), line.clone()));
+                            self.pending[index] = None;
+                        }
+                        '\r' => {}
+                        _ => line.push(c),
+                    }
+                }
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Raw bytes of a binary up-channel (e.g. defmt).
+    pub fn read_bytes(
+        &mut self,
+        core: &mut Core,
+        channel: usize,
+    ) -> Result<Vec<u8>, probe_rs::Error> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; RTT_READ_BUFFER];
+
+        if let Some(channel) = self
+            .rtt
+            .up_channels()
+            .iter()
+            .find(|ch| ch.number() == channel)
+        {
+            loop {
+                let count = channel
+                    .read(core, &mut buf)
+                    .map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e.to_string())))?;
+                if count == 0 {
+                    break;
+                }
+                data.extend_from_slice(&buf[..count]);
+            }
+        }
+        Ok(data)
+    }
+
+    pub fn write(
+        &mut self,
+        core: &mut Core,
+        channel: usize,
+        data: &[u8],
+    ) -> Result<(), probe_rs::Error> {
+        let down = self
+            .rtt
+            .down_channels()
+            .iter()
+            .find(|ch| ch.number() == channel)
+            .ok_or_else(|| probe_rs::Error::Other(anyhow::anyhow!("No such down channel")))?;
+
+        let mut written = 0;
+        while written < data.len() {
+            let count = down
+                .write(core, &data[written..])
+                .map_err(|e| probe_rs::Error::Other(anyhow::anyhow!(e.to_string())))?;
+            if count == 0 {
+                // バッファが一杯のときは残りを捨てる
+                break;
+            }
+            written += count;
+        }
+        Ok(())
+    }
+}
+// ----------------------------------------------------------------------------
+/// Decodes `data` after the bytes left over from the previous read, keeping an
+/// incomplete trailing UTF-8 sequence in `partial` for the next call.
+fn decode_utf8(partial: &mut Vec<u8>, data: &[u8]) -> String {
+    let mut bytes = std::mem::take(partial);
+    bytes.extend_from_slice(data);
+
+    // 末尾の文字の先頭バイトを探し、足りない分があれば次回に回す
+    let mut tail = 0;
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        if byte & 0xC0 == 0x80 {
+            continue;
+        }
+        let needed = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        if needed > back {
+            tail = back;
+        }
+        break;
+    }
+
+    *partial = bytes.split_off(bytes.len() - tail);
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_utf8;
+
+    #[test]
+    fn multibyte_character_split_across_reads() {
+        let text = "温度=25℃\n".as_bytes();
+        for split in 0..=text.len() {
+            let mut partial = Vec::new();
+            let mut decoded = decode_utf8(&mut partial, &text[..split]);
+            decoded += &decode_utf8(&mut partial, &text[split..]);
+            assert_eq!(decoded, "温度=25℃\n", "split at {split}");
+            assert!(partial.is_empty());
+        }
+    }
+
+    #[test]
+    fn invalid_bytes_are_replaced() {
+        let mut partial = Vec::new();
+        assert_eq!(decode_utf8(&mut partial, b"a\xFFb"), "a\u{FFFD}b");
+        // 継続バイトだけが続く場合は次回に持ち越さない
+        assert_eq!(
+            decode_utf8(&mut partial, b"\x80\x80\x80"),
+            "\u{FFFD}\u{FFFD}\u{FFFD}"
+        );
+        assert!(partial.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use probe_rs::{Core, CoreStatus, HaltReason};
+use std::time::Duration;
+
+use super::symbol_resolver::{SourceInfo, SymbolResolver};
+
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RunCommand {
+    Halt,
+    Run,
+    Step,
+    Reset,
+    ResetAndHalt,
+}
+
+impl std::fmt::Display for RunCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CoreState {
+    Unknown,
+    Running,
+    Halted,
+    LockedUp,
+    Sleeping,
+}
+
+impl std::fmt::Display for CoreState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for CoreState {
+    fn default() -> Self {
+        Self::Unknown
+    }
+}
+
+impl From<CoreStatus> for CoreState {
+    fn from(status: CoreStatus) -> Self {
+        match status {
+            CoreStatus::Running => Self::Running,
+            CoreStatus::Halted(_) => Self::Halted,
+            CoreStatus::LockedUp => Self::LockedUp,
+            CoreStatus::Sleeping => Self::Sleeping,
+            CoreStatus::Unknown => Self::Unknown,
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HaltCause {
+    Breakpoint,
+    Watchpoint,
+    Step,
+    Request,
+    Exception,
+    External,
+    Multiple,
+    Unknown,
+}
+
+impl std::fmt::Display for HaltCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+impl From<HaltReason> for HaltCause {
+    fn from(reason: HaltReason) -> Self {
+        match reason {
+            HaltReason::Breakpoint(_) => Self::Breakpoint,
+            HaltReason::Watchpoint => Self::Watchpoint,
+            HaltReason::Step => Self::Step,
+            HaltReason::Request => Self::Request,
+            HaltReason::Exception => Self::Exception,
+            HaltReason::External => Self::External,
+            HaltReason::Multiple => Self::Multiple,
+            HaltReason::Unknown => Self::Unknown,
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct TargetStatus {
+    pub state: CoreState,
+    pub halt_reason: Option<HaltCause>,
+    pub pc: Option<u64>,
+    pub location: Option<SourceInfo>,
+}
+
+impl TargetStatus {
+    pub fn is_halted(&self) -> bool {
+        self.state == CoreState::Halted || self.state == CoreState::LockedUp
+    }
+
+    /// True only for the first status sample after the core stopped.
+    pub fn newly_halted(&self, last_status: &TargetStatus) -> bool {
+        self.state == CoreState::Halted && last_status.state != CoreState::Halted
+    }
+}
+// ----------------------------------------------------------------------------
+pub struct RunControl {}
+
+impl RunControl {
+    pub fn execute(core: &mut Core, command: RunCommand) -> Result<(), probe_rs::Error> {
+        match command {
+            RunCommand::Halt => core.halt(HALT_TIMEOUT).map(|_| ()),
+            RunCommand::Run => core.run(),
+            RunCommand::Step => {
+                // stepが出来るのはhalt中のみ
+                if !core.core_halted()? {
+                    core.halt(HALT_TIMEOUT)?;
+                }
+                core.step().map(|_| ())
+            }
+            RunCommand::Reset => core.reset(),
+            RunCommand::ResetAndHalt => core.reset_and_halt(HALT_TIMEOUT).map(|_| ()),
+        }
+    }
+
+    pub fn read_status(
+        core: &mut Core,
+        resolver: Option<&SymbolResolver>,
+        last_status: &TargetStatus,
+    ) -> Result<TargetStatus, probe_rs::Error> {
+        let status = core.status()?;
+
+        let halt_reason = match status {
+            CoreStatus::Halted(reason) => Some(HaltCause::from(reason)),
+            _ => None,
+        };
+
+        let state = CoreState::from(status);
+        if state != CoreState::Halted {
+            return Ok(TargetStatus {
+                state,
+                halt_reason,
+                pc: None,
+                location: None,
+            });
+        }
+
+        let pc: u64 = core.read_core_reg(core.program_counter())?;
+
+        // 同じPCで止まっている間はELFの検索を省略する
+        let location = if last_status.pc == Some(pc) && last_status.location.is_some() {
+            last_status.location.clone()
+        } else {
+            resolver.and_then(|resolver| resolver.resolve(pc))
+        };
+
+        Ok(TargetStatus {
+            state,
+            halt_reason,
+            pc: Some(pc),
+            location,
+        })
+    }
+}
@@ -0,0 +1,228 @@
+use probe_rs::{Core, MemoryInterface, RegisterId};
+use std::collections::VecDeque;
+
+use super::rtt_channel::RttLog;
+
+// Thumb encoding of `BKPT 0xAB`
+const BKPT_SEMIHOSTING: u16 = 0xBEAB;
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_READ: u32 = 0x06;
+const SYS_READC: u32 = 0x07;
+const SYS_ISTTY: u32 = 0x09;
+const SYS_CLOCK: u32 = 0x10;
+const SYS_TIME: u32 = 0x11;
+const SYS_ERRNO: u32 = 0x13;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+const WRITE0_MAX_LEN: usize = 1024;
+// SYS_WRITE 1回で読むバイト数の上限と、ターゲットから1度に読む大きさ
+const WRITE_MAX_LEN: usize = 64 * 1024;
+const WRITE_CHUNK_LEN: usize = 4096;
+
+// ":tt" を開いたときのハンドル (newlibは r, w, a の順に開く)
+const HANDLE_STDIN: u32 = 1;
+const HANDLE_STDOUT: u32 = 2;
+pub const HANDLE_STDERR: u32 = 3;
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SemihostingState {
+    /// The halt was not caused by a semihosting call.
+    NotSemihosting,
+    /// The call was serviced and the core was resumed.
+    Resumed,
+    /// SYS_READC/SYS_READ is waiting for console input; the core stays halted.
+    WaitingInput,
+    /// SYS_EXIT with the exit code; the core stays halted.
+    Exited(i64),
+}
+
+/// Console I/O shared between the watch thread and the console widget.
+#[derive(Default)]
+pub struct SemihostingIo {
+    pub output: RttLog,
+    pub input: VecDeque<u8>,
+    pub waiting_input: bool,
+    pub exit_code: Option<i64>,
+}
+// ----------------------------------------------------------------------------
+/// Services ARM semihosting calls (`BKPT 0xAB`) of the halted core.
+#[derive(Default)]
+pub struct Semihosting {
+    // 改行が来るまでの途中の行 (handle, 受信開始時刻, 文字列)
+    pending: Vec<(u32, i64, String)>,
+}
+
+impl Semihosting {
+    pub fn service(
+        &mut self,
+        core: &mut Core,
+        time_ms: i64,
+        console: &mut SemihostingIo,
+    ) -> Result<SemihostingState, probe_rs::Error> {
+        let pc: u32 = core.read_core_reg(core.program_counter())?;
+        let mut instruction = [0u8; 2];
+        core.read_8(pc as u64, &mut instruction)?;
+        if u16::from_le_bytes(instruction) != BKPT_SEMIHOSTING {
+            return Ok(SemihostingState::NotSemihosting);
+        }
+
+        let operation: u32 = core.read_core_reg(RegisterId(0))?;
+        let parameter: u32 = core.read_core_reg(RegisterId(1))?;
+
+        let result: u32 = match operation {
+            SYS_OPEN => {
+                let args = Self::read_args::<3>(core, parameter)?;
+                let name =
+                    Self::read_string(core, args[0], (args[2] as usize).min(WRITE0_MAX_LEN))?;
+                match (name.as_str(), args[1]) {
+                    (":tt", 0..=3) => HANDLE_STDIN,
+                    (":tt", 4..=7) => HANDLE_STDOUT,
+                    (":tt", 8..=11) => HANDLE_STDERR,
+                    _ => u32::MAX,
+                }
+            }
+            SYS_CLOSE => 0,
+            SYS_WRITEC => {
+                let c = core.read_word_8(parameter as u64)?;
+                self.output(console, HANDLE_STDOUT, time_ms, &[c]);
+                0
+            }
+            SYS_WRITE0 => {
+                let text = Self::read_string(core, parameter, WRITE0_MAX_LEN)?;
+                self.output(console, HANDLE_STDOUT, time_ms, text.as_bytes());
+                0
+            }
+            SYS_WRITE => {
+                let args = Self::read_args::<3>(core, parameter)?;
+                if args[0] == HANDLE_STDOUT || args[0] == HANDLE_STDERR {
+                    // 壊れた長さで巨大な確保をしないよう上限で切り、残りは書けなかった分として返す
+                    let len = (args[2] as usize).min(WRITE_MAX_LEN);
+                    let mut buff = Vec::with_capacity(len);
+                    let mut chunk = [0u8; WRITE_CHUNK_LEN];
+                    while buff.len() < len {
+                        let chunk = &mut chunk[..(len - buff.len()).min(WRITE_CHUNK_LEN)];
+                        core.read_8(args[1] as u64 + buff.len() as u64, chunk)?;
+                        buff.extend_from_slice(chunk);
+                    }
+                    self.output(console, args[0], time_ms, &buff);
+                    args[2] - len as u32
+                } else {
+                    args[2]
+                }
+            }
+            SYS_READ => {
+                let args = Self::read_args::<3>(core, parameter)?;
+                if console.input.is_empty() {
+                    console.waiting_input = true;
+                    return Ok(SemihostingState::WaitingInput);
+                }
+                let len = (args[2] as usize).min(console.input.len());
+                let data: Vec<u8> = console.input.drain(..len).collect();
+                core.write_8(args[1] as u64, &data)?;
+                args[2] - len as u32
+            }
+            SYS_READC => match console.input.pop_front() {
+                Some(c) => c as u32,
+                None => {
+                    console.waiting_input = true;
+                    return Ok(SemihostingState::WaitingInput);
+                }
+            },
+            SYS_ISTTY => 1,
+            SYS_CLOCK => (time_ms / 10) as u32,
+            SYS_TIME => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|t| t.as_secs() as u32)
+                .unwrap_or_default(),
+            SYS_ERRNO => 0,
+            SYS_EXIT | SYS_EXIT_EXTENDED => {
+                let (reason, code) = if operation == SYS_EXIT_EXTENDED {
+                    let args = Self::read_args::<2>(core, parameter)?;
+                    (args[0], args[1] as i32 as i64)
+                } else {
+                    (parameter, 0)
+                };
+                let code = if reason == ADP_STOPPED_APPLICATION_EXIT {
+                    code
+                } else {
+                    // ApplicationExit以外の理由は異常終了として理由コードを返す
+                    reason as i64
+                };
+                self.flush(console);
+                console.exit_code = Some(code);
+                return Ok(SemihostingState::Exited(code));
+            }
+            _ => {
+                #[cfg(debug_assertions)]
+                println!("semihosting: unsupported operation 0x{:02x}", operation);
+                u32::MAX
+            }
+        };
+
+        console.waiting_input = false;
+        core.write_core_reg(RegisterId(0), result)?;
+        core.write_core_reg(core.program_counter(), pc + 2)?;
+        core.run()?;
+        Ok(SemihostingState::Resumed)
+    }
+
+    /// Pushes the unterminated lines to the console (e.g. before exit).
+    pub fn flush(&mut self, console: &mut SemihostingIo) {
+        for (handle, time, line) in self.pending.drain(..) {
+            console.output.push(time, handle as usize, line, None);
+        }
+    }
+
+    fn output(&mut self, console: &mut SemihostingIo, handle: u32, time_ms: i64, data: &[u8]) {
+        let text = String::from_utf8_lossy(data).to_string();
+        for c in text.chars() {
+            let index = match self.pending.iter().position(|(h, _, _)| *h == handle) {
+                Some(index) => index,
+                None => {
+                    self.pending.push((handle, time_ms, String::new()));
+                    self.pending.len() - 1
+                }
+            };
+            match c {
+                '\n' => {
+                    let (_, time, line) = self.pending.remove(index);
+                    console.output.push(time, handle as usize, line, None);
+                }
+                '\r' => {}
+                _ => self.pending[index].2.push(c),
+            }
+        }
+    }
+
+    fn read_args<const N: usize>(
+        core: &mut Core,
+        address: u32,
+    ) -> Result<[u32; N], probe_rs::Error> {
+        let mut args = [0u32; N];
+        core.read_32(address as u64, &mut args)?;
+        Ok(args)
+    }
+
+    fn read_string(
+        core: &mut Core,
+        address: u32,
+        max_len: usize,
+    ) -> Result<String, probe_rs::Error> {
+        let mut text = Vec::new();
+        for i in 0..max_len {
+            let c = core.read_word_8(address as u64 + i as u64)?;
+            if c == 0 {
+                break;
+            }
+            text.push(c);
+        }
+        Ok(String::from_utf8_lossy(&text).to_string())
+    }
+}
@@ -0,0 +1,702 @@
+//! Framed UART protocol for targets that expose only a serial port.
+//!
+//! Every frame, in both directions:
+//!
+//! | SOF  | cmd | seq | len | payload   | crc |
+//! |------|-----|-----|-----|-----------|-----|
+//! | 0xA5 | u8  | u8  | u16 | len bytes | u16 |
+//!
+//! Multi-byte fields are little endian. `crc` is CRC-16/CCITT-FALSE (poly 0x1021,
+//! init 0xFFFF) over `cmd` .. the end of the payload. A reply carries `cmd | 0x80`
+//! and the `seq` of the request, or `ERROR` with an error code byte.
+//!
+//! | cmd  | request payload                                | reply payload                 |
+//! |------|------------------------------------------------|-------------------------------|
+//! | 0x01 | PING                                           | version (u8), max len (u16)   |
+//! | 0x02 | READ address (u32), length (u16)               | address (u32), data           |
+//! | 0x03 | WRITE address (u32), data                      | (empty)                       |
+//! | 0x04 | SUBSCRIBE period ms (u16), count (u8),         | (empty)                       |
+//! |      | count x (address (u32), length (u8))           |                               |
+//!
+//! After SUBSCRIBE with a period other than 0 the target sends `STREAM` frames by
+//! itself: target time in ms (u32) followed by the data of every entry, in order.
+//! A period of 0 stops the stream. The reference target implementation is in
+//! `firmware/uart_monitor`.
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use super::run_control::{CoreState, TargetStatus};
+use super::target_backend::{backend_error, StreamSample, TargetBackend};
+
+const SOF: u8 = 0xA5;
+const CMD_PING: u8 = 0x01;
+const CMD_READ: u8 = 0x02;
+const CMD_WRITE: u8 = 0x03;
+const CMD_SUBSCRIBE: u8 = 0x04;
+const REPLY: u8 = 0x80;
+const STREAM: u8 = 0x85;
+const ERROR: u8 = 0xFF;
+
+const PROTOCOL_VERSION: u8 = 1;
+// SOF, cmd, seq, len
+const HEADER_LEN: usize = 5;
+const CRC_LEN: usize = 2;
+// これより長いlenはノイズとみなして読み飛ばす
+const MAX_FRAME_PAYLOAD: usize = 4096;
+// READ/WRITEのアドレスと長さの分
+const ACCESS_OVERHEAD: usize = 6;
+const SUBSCRIBE_MAX_ENTRIES: usize = 255;
+
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+const REQUEST_RETRY: usize = 3;
+// ストリームが届いている間は生存確認のPINGを省く
+const ALIVE_TIMEOUT: Duration = Duration::from_millis(1000);
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SerialSetting {
+    /// e.g. "/dev/ttyUSB0", "COM3"
+    pub port: String,
+    pub baud_rate: u32,
+    /// Let the target send the watch list periodically instead of polling it.
+    pub stream: bool,
+}
+
+impl Default for SerialSetting {
+    fn default() -> Self {
+        Self {
+            port: String::new(),
+            baud_rate: 115_200,
+            stream: true,
+        }
+    }
+}
+
+impl SerialSetting {
+    pub fn check(&self) -> Result<(), String> {
+        if self.port.trim().is_empty() {
+            return Err("set the serial port".to_string());
+        }
+        if self.baud_rate == 0 {
+            return Err("set the baud rate".to_string());
+        }
+        Ok(())
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Debug, PartialEq)]
+struct Frame {
+    cmd: u8,
+    seq: u8,
+    payload: Vec<u8>,
+}
+
+/// Host side of the serial monitoring protocol.
+pub struct SerialMonitor {
+    port: Box<dyn serialport::SerialPort>,
+    name: String,
+    stream_enable: bool,
+    seq: u8,
+    max_payload: usize,
+    received: Vec<u8>,
+    samples: Vec<StreamSample>,
+    subscribed: bool,
+    last_received: Instant,
+}
+
+impl SerialMonitor {
+    pub fn open(setting: &SerialSetting) -> Result<Self, probe_rs::Error> {
+        setting.check().map_err(|e| backend_error(&e))?;
+        let port = serialport::new(setting.port.trim(), setting.baud_rate)
+            .timeout(REPLY_TIMEOUT)
+            .open()
+            .map_err(|e| backend_error(&format!("{}: {}", setting.port, e)))?;
+
+        let mut serial = Self {
+            port,
+            name: setting.port.trim().to_string(),
+            stream_enable: setting.stream,
+            seq: 0,
+            max_payload: 0,
+            received: Vec::new(),
+            samples: Vec::new(),
+            subscribed: false,
+            last_received: Instant::now(),
+        };
+        // 前回のセッションのストリームが残っていれば止める
+        serial.request(CMD_SUBSCRIBE, &[0, 0, 0])?;
+
+        let reply = serial.request(CMD_PING, &[])?;
+        let [version, len_lo, len_hi, ..] = reply[..] else {
+            return Err(backend_error(&format!("{}: short PING reply", serial.name)));
+        };
+        if version != PROTOCOL_VERSION {
+            return Err(backend_error(&format!(
+                "{}: protocol version {} is not supported",
+                serial.name, version
+            )));
+        }
+        serial.max_payload = u16::from_le_bytes([len_lo, len_hi]) as usize;
+        if serial.max_payload <= ACCESS_OVERHEAD {
+            return Err(backend_error(&format!(
+                "{}: payload of {} bytes is too short",
+                serial.name, serial.max_payload
+            )));
+        }
+
+        #[cfg(debug_assertions)]
+        println!(
+            "serial {} : max payload {}",
+            serial.name, serial.max_payload
+        );
+
+        serial.samples.clear();
+        Ok(serial)
+    }
+
+    // ------------------------------------------------------------------------
+    fn io_error(&self, e: std::io::Error) -> probe_rs::Error {
+        backend_error(&format!("{}: {}", self.name, e))
+    }
+
+    // 受信済みのバイト列に追加する。タイムアウトならfalse
+    fn fill(&mut self, deadline: Instant) -> Result<bool, probe_rs::Error> {
+        let timeout = deadline
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1));
+        self.port
+            .set_timeout(timeout)
+            .map_err(|e| backend_error(&format!("{}: {}", self.name, e)))?;
+        let mut buff = [0u8; 1024];
+        match self.port.read(&mut buff) {
+            Ok(0) => Ok(false),
+            Ok(count) => {
+                self.received.extend_from_slice(&buff[..count]);
+                Ok(true)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                Ok(false)
+            }
+            Err(e) => Err(self.io_error(e)),
+        }
+    }
+
+    fn send(&mut self, cmd: u8, payload: &[u8]) -> Result<(), probe_rs::Error> {
+        self.seq = self.seq.wrapping_add(1);
+        let frame = encode_frame(cmd, self.seq, payload);
+        self.port.write_all(&frame).map_err(|e| self.io_error(e))
+    }
+
+    fn take_frame(&mut self) -> Option<Frame> {
+        let frame = parse_frame(&mut self.received)?;
+        self.last_received = Instant::now();
+        Some(frame)
+    }
+
+    fn stream_frame(&mut self, payload: &[u8]) {
+        if let [t0, t1, t2, t3, data @ ..] = payload {
+            self.samples.push(StreamSample {
+                time_ms: u32::from_le_bytes([*t0, *t1, *t2, *t3]),
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    // 要求への応答を待つ (途中のストリームは溜めておく)
+    fn receive_reply(&mut self, cmd: u8) -> Result<Option<Vec<u8>>, probe_rs::Error> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            while let Some(frame) = self.take_frame() {
+                match frame.cmd {
+                    STREAM => self.stream_frame(&frame.payload),
+                    _ if frame.seq != self.seq => {}
+                    ERROR => {
+                        return Err(backend_error(&format!(
+                            "{}: target error {}",
+                            self.name,
+                            error_text(frame.payload.first().copied().unwrap_or(0))
+                        )))
+                    }
+                    reply if reply == cmd | REPLY => return Ok(Some(frame.payload)),
+                    _ => {}
+                }
+            }
+            if !self.fill(deadline)? && Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn request(&mut self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, probe_rs::Error> {
+        for _ in 0..REQUEST_RETRY {
+            self.send(cmd, payload)?;
+            if let Some(reply) = self.receive_reply(cmd)? {
+                return Ok(reply);
+            }
+        }
+        Err(backend_error(&format!(
+            "{}: target did not reply",
+            self.name
+        )))
+    }
+}
+
+impl TargetBackend for SerialMonitor {
+    fn describe(&self) -> String {
+        format!("serial {}", self.name)
+    }
+
+    fn poll_status(&mut self) -> Result<TargetStatus, probe_rs::Error> {
+        if !self.subscribed || self.last_received.elapsed() > ALIVE_TIMEOUT {
+            self.request(CMD_PING, &[])?;
+        }
+        Ok(TargetStatus {
+            state: CoreState::Running,
+            ..Default::default()
+        })
+    }
+
+    fn read_memory(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+        let chunk_len = self.max_payload - ACCESS_OVERHEAD;
+        for (index, chunk) in data.chunks_mut(chunk_len).enumerate() {
+            let chunk_address = address + (index * chunk_len) as u64;
+            let mut payload = (chunk_address as u32).to_le_bytes().to_vec();
+            payload.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+            let reply = self.request(CMD_READ, &payload)?;
+            match reply.get(4..) {
+                Some(bytes) if bytes.len() == chunk.len() => chunk.copy_from_slice(bytes),
+                _ => {
+                    return Err(backend_error(&format!(
+                        "{}: wrong READ reply at 0x{:08X}",
+                        self.name, chunk_address
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<(), probe_rs::Error> {
+        let chunk_len = self.max_payload - ACCESS_OVERHEAD;
+        for (index, chunk) in data.chunks(chunk_len).enumerate() {
+            let chunk_address = address + (index * chunk_len) as u64;
+            let mut payload = (chunk_address as u32).to_le_bytes().to_vec();
+            payload.extend_from_slice(chunk);
+            self.request(CMD_WRITE, &payload)?;
+        }
+        Ok(())
+    }
+
+    fn subscribe(
+        &mut self,
+        ranges: &[(u64, usize)],
+        period_ms: u32,
+    ) -> Result<bool, probe_rs::Error> {
+        if !self.stream_enable || ranges.is_empty() {
+            return Ok(false);
+        }
+        let sample_len: usize = ranges.iter().map(|(_, len)| len).sum();
+        if ranges.len() > SUBSCRIBE_MAX_ENTRIES
+            || ranges.iter().any(|(_, len)| *len > u8::MAX as usize)
+            || 3 + ranges.len() * 5 > self.max_payload
+            || 4 + sample_len > self.max_payload
+        {
+            return Err(backend_error(&format!(
+                "{}: the watch list is too long to stream, polling it",
+                self.name
+            )));
+        }
+
+        let mut payload = (period_ms.clamp(1, u16::MAX as u32) as u16)
+            .to_le_bytes()
+            .to_vec();
+        payload.push(ranges.len() as u8);
+        for (address, len) in ranges {
+            payload.extend_from_slice(&(*address as u32).to_le_bytes());
+            payload.push(*len as u8);
+        }
+        self.request(CMD_SUBSCRIBE, &payload)?;
+        self.subscribed = true;
+        Ok(true)
+    }
+
+    fn take_samples(&mut self) -> Result<Vec<StreamSample>, probe_rs::Error> {
+        // 届いている分だけ読む
+        while self.port.bytes_to_read().unwrap_or(0) > 0 {
+            if !self.fill(Instant::now())? {
+                break;
+            }
+        }
+        while let Some(frame) = self.take_frame() {
+            if frame.cmd == STREAM {
+                self.stream_frame(&frame.payload);
+            }
+        }
+        Ok(std::mem::take(&mut self.samples))
+    }
+}
+
+impl Drop for SerialMonitor {
+    fn drop(&mut self) {
+        if self.subscribed {
+            let _res = self.send(CMD_SUBSCRIBE, &[0, 0, 0]);
+        }
+    }
+}
+
+fn encode_frame(cmd: u8, seq: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len() + CRC_LEN);
+    frame.extend_from_slice(&[SOF, cmd, seq]);
+    frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    frame.extend_from_slice(payload);
+    let crc = crc16(&frame[1..]);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+// 受信済みのバイト列から1フレーム取り出す。壊れたフレームはSOFの次から探し直す
+fn parse_frame(received: &mut Vec<u8>) -> Option<Frame> {
+    loop {
+        let Some(start) = received.iter().position(|b| *b == SOF) else {
+            received.clear();
+            return None;
+        };
+        received.drain(..start);
+        if received.len() < HEADER_LEN {
+            return None;
+        }
+        let len = u16::from_le_bytes([received[3], received[4]]) as usize;
+        if len > MAX_FRAME_PAYLOAD {
+            received.remove(0);
+            continue;
+        }
+        let end = HEADER_LEN + len + CRC_LEN;
+        if received.len() < end {
+            return None;
+        }
+        let crc = u16::from_le_bytes([received[end - 2], received[end - 1]]);
+        if crc != crc16(&received[1..end - 2]) {
+            received.remove(0);
+            continue;
+        }
+        let frame: Vec<u8> = received.drain(..end).collect();
+        return Some(Frame {
+            cmd: frame[1],
+            seq: frame[2],
+            payload: frame[HEADER_LEN..end - CRC_LEN].to_vec(),
+        });
+    }
+}
+
+fn error_text(code: u8) -> String {
+    match code {
+        1 => "unknown command".to_string(),
+        2 => "bad length".to_string(),
+        3 => "address not accessible".to_string(),
+        4 => "too many entries".to_string(),
+        code => format!("{code}"),
+    }
+}
+
+/// CRC-16/CCITT-FALSE
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, byte| {
+        let mut crc = crc ^ ((*byte as u16) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(cmd: u8, seq: u8, payload: &[u8]) -> Frame {
+        Frame {
+            cmd,
+            seq,
+            payload: payload.to_vec(),
+        }
+    }
+
+    #[test]
+    fn crc_is_ccitt_false() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn encodes_the_documented_layout() {
+        let encoded = encode_frame(CMD_READ, 7, &[0x00, 0x10, 0x00, 0x20, 0x04, 0x00]);
+        assert_eq!(encoded[..HEADER_LEN], [SOF, CMD_READ, 7, 6, 0]);
+        assert_eq!(encoded.len(), HEADER_LEN + 6 + CRC_LEN);
+        let crc = crc16(&encoded[1..HEADER_LEN + 6]);
+        assert_eq!(encoded[HEADER_LEN + 6..], crc.to_le_bytes());
+    }
+
+    #[test]
+    fn parses_back_to_back_frames() {
+        let mut received = encode_frame(CMD_PING | REPLY, 1, &[1, 0, 1]);
+        received.extend(encode_frame(STREAM, 0, &[]));
+        assert_eq!(
+            parse_frame(&mut received),
+            Some(frame(CMD_PING | REPLY, 1, &[1, 0, 1]))
+        );
+        assert_eq!(parse_frame(&mut received), Some(frame(STREAM, 0, &[])));
+        assert_eq!(parse_frame(&mut received), None);
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_frame() {
+        let encoded = encode_frame(STREAM, 3, &[1, 2, 3, 4, 5, 6]);
+        for split in 0..encoded.len() {
+            let mut received = encoded[..split].to_vec();
+            assert_eq!(parse_frame(&mut received), None, "split at {split}");
+            received.extend_from_slice(&encoded[split..]);
+            assert_eq!(
+                parse_frame(&mut received),
+                Some(frame(STREAM, 3, &[1, 2, 3, 4, 5, 6])),
+                "split at {split}"
+            );
+        }
+    }
+
+    #[test]
+    fn resynchronizes_after_noise() {
+        let good = encode_frame(CMD_WRITE | REPLY, 9, &[]);
+        let mut corrupted = encode_frame(CMD_READ | REPLY, 8, &[SOF, 1, 2]);
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+
+        // ゴミ、CRCの壊れたフレーム (中にSOFを含む)、長すぎるlen の後の正しいフレーム
+        let mut received = vec![0x00, 0x13, 0x37];
+        received.extend(corrupted);
+        received.extend([SOF, CMD_READ, 1, 0xFF, 0xFF]);
+        received.extend(&good);
+        assert_eq!(
+            parse_frame(&mut received),
+            Some(frame(CMD_WRITE | REPLY, 9, &[]))
+        );
+        assert!(received.is_empty());
+
+        let mut received = vec![0x01, 0x02, 0x03];
+        assert_eq!(parse_frame(&mut received), None);
+        assert!(received.is_empty());
+    }
+
+    // ------------------------------------------------------------------------
+    // firmware/uart_monitorと同じように応答する疑似端末の向こう側のターゲット
+    #[cfg(unix)]
+    mod pty_target {
+        use super::*;
+        use serialport::SerialPort;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+        use std::thread::JoinHandle;
+
+        pub const RAM_BASE: u32 = 0x2000_0000;
+        pub const RAM_SIZE: usize = 256;
+        pub const MAX_PAYLOAD: usize = 64;
+
+        pub struct PtyTarget {
+            pub port: String,
+            pub ram: Arc<Mutex<Vec<u8>>>,
+            // 最後に受けたSUBSCRIBEの周期
+            pub period: Arc<Mutex<Option<u16>>>,
+            stop: Arc<AtomicBool>,
+            thread: Option<JoinHandle<()>>,
+            _slave: serialport::TTYPort,
+        }
+
+        impl PtyTarget {
+            pub fn start() -> Self {
+                let (mut master, slave) = serialport::TTYPort::pair().unwrap();
+                let port = slave.name().unwrap();
+                let ram: Arc<Mutex<Vec<u8>>> =
+                    Arc::new(Mutex::new((0..RAM_SIZE).map(|i| i as u8).collect()));
+                let period = Arc::new(Mutex::new(None));
+                let stop = Arc::new(AtomicBool::new(false));
+
+                let (_ram, _period, _stop) = (ram.clone(), period.clone(), stop.clone());
+                let thread = std::thread::spawn(move || {
+                    let started = Instant::now();
+                    let mut received = Vec::new();
+                    let mut entries: Vec<(usize, usize)> = Vec::new();
+                    let mut stream_last = Instant::now();
+                    master.set_timeout(Duration::from_millis(1)).unwrap();
+                    while !_stop.load(Ordering::SeqCst) {
+                        let mut buff = [0u8; 256];
+                        match master.read(&mut buff) {
+                            Ok(count) => received.extend_from_slice(&buff[..count]),
+                            Err(e) if e.kind() == ErrorKind::TimedOut => {}
+                            Err(_) => return,
+                        }
+                        while let Some(frame) = parse_frame(&mut received) {
+                            let ram = &mut _ram.lock().unwrap();
+                            let reply = handle(&frame, ram, &mut entries, &_period);
+                            let _res = master.write_all(&reply);
+                        }
+                        let period = _period.lock().unwrap().unwrap_or(0);
+                        if period != 0
+                            && stream_last.elapsed() >= Duration::from_millis(period as u64)
+                        {
+                            stream_last = Instant::now();
+                            let ram = _ram.lock().unwrap();
+                            let time_ms = started.elapsed().as_millis() as u32;
+                            let mut sample = time_ms.to_le_bytes().to_vec();
+                            for (offset, len) in &entries {
+                                sample.extend_from_slice(&ram[*offset..offset + len]);
+                            }
+                            let _res = master.write_all(&encode_frame(STREAM, 0, &sample));
+                        }
+                    }
+                });
+                Self {
+                    port,
+                    ram,
+                    period,
+                    stop,
+                    thread: Some(thread),
+                    _slave: slave,
+                }
+            }
+        }
+
+        impl Drop for PtyTarget {
+            fn drop(&mut self) {
+                self.stop.store(true, Ordering::SeqCst);
+                if let Some(thread) = self.thread.take() {
+                    let _res = thread.join();
+                }
+            }
+        }
+
+        // RAMの外はエラー3
+        fn offset(address: &[u8], len: usize) -> Option<usize> {
+            let address = u32::from_le_bytes(address.try_into().ok()?);
+            let offset = address.checked_sub(RAM_BASE)? as usize;
+            (offset + len <= RAM_SIZE).then_some(offset)
+        }
+
+        fn handle(
+            frame: &Frame,
+            ram: &mut [u8],
+            entries: &mut Vec<(usize, usize)>,
+            period: &Mutex<Option<u16>>,
+        ) -> Vec<u8> {
+            let payload = &frame.payload;
+            let error = |code: u8| encode_frame(ERROR, frame.seq, &[code]);
+            let reply = |data: &[u8]| encode_frame(frame.cmd | REPLY, frame.seq, data);
+            match frame.cmd {
+                CMD_PING => {
+                    let mut data = vec![PROTOCOL_VERSION];
+                    data.extend_from_slice(&(MAX_PAYLOAD as u16).to_le_bytes());
+                    reply(&data)
+                }
+                CMD_READ if payload.len() == 6 => {
+                    let len = u16::from_le_bytes([payload[4], payload[5]]) as usize;
+                    if len + 4 > MAX_PAYLOAD {
+                        return error(2);
+                    }
+                    let Some(offset) = offset(&payload[..4], len) else {
+                        return error(3);
+                    };
+                    let mut data = payload[..4].to_vec();
+                    data.extend_from_slice(&ram[offset..offset + len]);
+                    reply(&data)
+                }
+                CMD_WRITE if payload.len() >= 4 => {
+                    let data = &payload[4..];
+                    let Some(offset) = offset(&payload[..4], data.len()) else {
+                        return error(3);
+                    };
+                    ram[offset..offset + data.len()].copy_from_slice(data);
+                    reply(&[])
+                }
+                CMD_SUBSCRIBE if payload.len() >= 3 => {
+                    let mut list = Vec::new();
+                    for entry in payload[3..].chunks(5) {
+                        let len = entry[4] as usize;
+                        let Some(offset) = offset(&entry[..4], len) else {
+                            return error(3);
+                        };
+                        list.push((offset, len));
+                    }
+                    *entries = list;
+                    *period.lock().unwrap() = Some(u16::from_le_bytes([payload[0], payload[1]]));
+                    reply(&[])
+                }
+                CMD_READ | CMD_WRITE | CMD_SUBSCRIBE => error(2),
+                _ => error(1),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn session_with_a_target_on_a_pseudo_terminal() {
+        use pty_target::{PtyTarget, MAX_PAYLOAD, RAM_BASE};
+
+        let target = PtyTarget::start();
+        let setting = SerialSetting {
+            port: target.port.clone(),
+            ..Default::default()
+        };
+        let mut serial = SerialMonitor::open(&setting).unwrap();
+        assert_eq!(serial.max_payload, MAX_PAYLOAD);
+        // 開くときに前のストリームを止めている
+        assert_eq!(*target.period.lock().unwrap(), Some(0));
+        assert_eq!(serial.poll_status().unwrap().state, CoreState::Running);
+
+        // 1フレームに収まらない長さは分けて読み書きする
+        let mut data = vec![0u8; 100];
+        serial.read_memory(RAM_BASE as u64 + 10, &mut data).unwrap();
+        assert!(data.iter().enumerate().all(|(i, b)| *b == (i + 10) as u8));
+
+        let written: Vec<u8> = (0..70).map(|i| 0xFF - i as u8).collect();
+        serial
+            .write_memory(RAM_BASE as u64 + 100, &written)
+            .unwrap();
+        assert_eq!(target.ram.lock().unwrap()[100..170], written[..]);
+
+        let error = serial
+            .read_memory(RAM_BASE as u64 + 250, &mut [0u8; 8])
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("address not accessible"),
+            "{error}"
+        );
+
+        let ranges = [(RAM_BASE as u64, 4), (RAM_BASE as u64 + 100, 2)];
+        assert!(serial.subscribe(&ranges, 5).unwrap());
+        assert_eq!(*target.period.lock().unwrap(), Some(5));
+        target.ram.lock().unwrap()[..4].copy_from_slice(&[1, 2, 3, 4]);
+        std::thread::sleep(Duration::from_millis(100));
+
+        // ストリーム中も要求には応答する
+        let mut data = [0u8; 4];
+        serial.read_memory(RAM_BASE as u64, &mut data).unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+
+        let samples = serial.take_samples().unwrap();
+        assert!(samples.len() > 3, "{} samples", samples.len());
+        assert!(samples.windows(2).all(|w| w[0].time_ms <= w[1].time_ms));
+        let last = samples.last().unwrap();
+        assert_eq!(last.data, [1, 2, 3, 4, 0xFF, 0xFE]);
+        assert!(serial.take_samples().unwrap().len() < samples.len());
+
+        // 閉じるとストリームを止める
+        drop(serial);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(*target.period.lock().unwrap(), Some(0));
+    }
+}
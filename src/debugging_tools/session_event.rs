@@ -0,0 +1,34 @@
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SessionEventKind {
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for SessionEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for SessionEventKind {
+    fn default() -> Self {
+        Self::Info
+    }
+}
+
+/// Something that happened on the target during the session (e.g. semihosting exit),
+/// shown as a marker on the time axis.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SessionEvent {
+    pub time_ms: i64,
+    pub kind: SessionEventKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for SessionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.kind, self.message)
+    }
+}
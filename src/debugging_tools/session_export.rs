@@ -0,0 +1,670 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sensorlog::Sensorlog;
+
+use super::a2l::Conversion;
+use super::mdf_writer::write_mdf4;
+use super::parquet_writer::write_parquet;
+
+// ----------------------------------------------------------------------------
+const SESSION_FILE: &str = "session.txt";
+
+/// Directory of the sensorlog files.
+pub(super) fn log_directory() -> PathBuf {
+    PathBuf::from(format!("{}", shellexpand::tilde("~/.EguiMonitorLog")))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RecordedChannel {
+    pub name: String,
+    /// Scaling already applied to the logged values.
+    pub conversion: Conversion,
+}
+
+/// Channels and clock of the recording, kept next to the log as `session.txt` so that
+/// the headless export knows them.
+#[derive(Clone, Debug, Default)]
+pub struct RecordedSession {
+    pub channels: Vec<RecordedChannel>,
+    /// (log time [ms], wall clock [ms since the UNIX epoch]) of every start of the log timer.
+    pub clock: Vec<(u64, u64)>,
+}
+
+impl RecordedSession {
+    pub fn path() -> PathBuf {
+        log_directory().join(SESSION_FILE)
+    }
+
+    /// Remembers that the log timer (re)started at `time_ms` with `channels`.
+    pub fn start(&mut self, time_ms: u64, channels: Vec<RecordedChannel>) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        self.clock.push((time_ms, now));
+        for channel in channels {
+            match self.channels.iter_mut().find(|c| c.name == channel.name) {
+                Some(known) => *known = channel,
+                None => self.channels.push(channel),
+            }
+        }
+    }
+
+    /// Wall clock of the log time `time_ms`, counted from the timer start before it.
+    pub fn wall_clock_ms(&self, time_ms: u64) -> Option<u64> {
+        let (base, wall) = self
+            .clock
+            .iter()
+            .rev()
+            .find(|(base, _)| *base <= time_ms)
+            .or(self.clock.first())?;
+        Some((*wall as i64 + time_ms as i64 - *base as i64).max(0) as u64)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut text = String::from("# VisuModiRam recorded session\n");
+        for (time_ms, wall_ms) in &self.clock {
+            text += &format!("clock\t{}\t{}\n", time_ms, wall_ms);
+        }
+        for channel in &self.channels {
+            let conversion = &channel.conversion;
+            text += &format!(
+                "channel\t{}\t{:?}\t{:?}\t{}\n",
+                channel.name, conversion.factor, conversion.offset, conversion.unit
+            );
+        }
+        std::fs::write(Self::path(), text)
+    }
+
+    pub fn load() -> Result<Self, String> {
+        let text = std::fs::read_to_string(Self::path())
+            .map_err(|e| format!("{}: {}", Self::path().display(), e))?;
+        let mut session = Self::default();
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["clock", time_ms, wall_ms] => {
+                    if let (Ok(time_ms), Ok(wall_ms)) = (time_ms.parse(), wall_ms.parse()) {
+                        session.clock.push((time_ms, wall_ms));
+                    }
+                }
+                ["channel", name, factor, offset, unit] => {
+                    session.channels.push(RecordedChannel {
+                        name: name.to_string(),
+                        conversion: Conversion {
+                            factor: factor.parse().unwrap_or(1.0),
+                            offset: offset.parse().unwrap_or(0.0),
+                            unit: unit.to_string(),
+                            calibration: false,
+                        },
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(session)
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ExportFormat {
+    /// One row per timestamp, one column per channel.
+    CsvWide,
+    /// One row per sample: time, channel, value, unit.
+    CsvLong,
+    Parquet,
+    Mdf4,
+}
+
+impl Default for ExportFormat {
+    fn default() -> Self {
+        Self::CsvWide
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CsvWide => write!(f, "CSV (wide)"),
+            Self::CsvLong => write!(f, "CSV (long)"),
+            Self::Parquet => write!(f, "Parquet"),
+            Self::Mdf4 => write!(f, "MDF4"),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::CsvWide | Self::CsvLong => "csv",
+            Self::Parquet => "parquet",
+            Self::Mdf4 => "mf4",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum TimeBase {
+    /// Seconds of the log timer.
+    Relative,
+    /// UTC date and time.
+    WallClock,
+}
+
+impl Default for TimeBase {
+    fn default() -> Self {
+        Self::Relative
+    }
+}
+
+impl std::fmt::Display for TimeBase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Relative => write!(f, "relative"),
+            Self::WallClock => write!(f, "wall clock"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ExportSetting {
+    pub format: ExportFormat,
+    pub time_base: TimeBase,
+    /// Range of log time [s]; `None` exports up to the last sample.
+    pub start_s: f64,
+    pub end_s: Option<f64>,
+    /// Channels to write, all recorded channels when empty.
+    pub channels: Vec<String>,
+}
+
+impl Default for ExportSetting {
+    fn default() -> Self {
+        Self {
+            format: Default::default(),
+            time_base: Default::default(),
+            start_s: 0.0,
+            end_s: None,
+            channels: Vec::new(),
+        }
+    }
+}
+
+impl ExportSetting {
+    pub fn check(&self) -> Result<(), String> {
+        if !self.start_s.is_finite() || self.start_s < 0.0 {
+            return Err("the start must be 0 s or later".to_string());
+        }
+        match self.end_s {
+            Some(end_s) if end_s.is_nan() || end_s <= self.start_s => {
+                Err("the end must be after the start".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+/// Samples (log time [ms], value) of one exported channel.
+pub(super) struct ExportChannel {
+    pub name: String,
+    pub conversion: Conversion,
+    pub samples: Vec<(u64, f64)>,
+}
+
+/// Channels read from the log, with the time base to write them in.
+pub(super) struct Recording {
+    pub channels: Vec<ExportChannel>,
+    pub time_base: TimeBase,
+    session: RecordedSession,
+}
+
+impl Recording {
+    pub fn new(
+        channels: Vec<ExportChannel>,
+        time_base: TimeBase,
+        session: RecordedSession,
+    ) -> Self {
+        Self {
+            channels,
+            time_base,
+            session,
+        }
+    }
+
+    pub fn wall_clock_ms(&self, time_ms: u64) -> u64 {
+        self.session.wall_clock_ms(time_ms).unwrap_or(0)
+    }
+
+    pub fn first_time_ms(&self) -> Option<u64> {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.samples.first())
+            .map(|(time_ms, _)| *time_ms)
+            .min()
+    }
+
+    /// Timestamps of all channels with the value of each channel at that time.
+    pub fn rows(&self) -> Vec<(u64, Vec<Option<f64>>)> {
+        let mut rows: BTreeMap<u64, Vec<Option<f64>>> = BTreeMap::new();
+        for (index, channel) in self.channels.iter().enumerate() {
+            for (time_ms, value) in &channel.samples {
+                rows.entry(*time_ms)
+                    .or_insert_with(|| vec![None; self.channels.len()])[index] = Some(*value);
+            }
+        }
+        rows.into_iter().collect()
+    }
+
+    fn time_text(&self, time_ms: u64) -> String {
+        match self.time_base {
+            TimeBase::Relative => format!("{:.3}", time_ms as f64 / 1000.0),
+            TimeBase::WallClock => format_utc(self.wall_clock_ms(time_ms)),
+        }
+    }
+}
+
+// ISO 8601 (UTC) のミリ秒付き日時
+pub(super) fn format_utc(unix_ms: u64) -> String {
+    let days = (unix_ms / 86_400_000) as i64;
+    let ms_of_day = unix_ms % 86_400_000;
+    // 1970-01-01からの日数を年月日にする (Howard Hinnantのcivil_from_days)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000
+    )
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn column_name(channel: &ExportChannel) -> String {
+    if channel.conversion.unit.is_empty() {
+        channel.name.clone()
+    } else {
+        format!("{} [{}]", channel.name, channel.conversion.unit)
+    }
+}
+
+fn write_csv(recording: &Recording, long: bool, path: &Path) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let time_header = match recording.time_base {
+        TimeBase::Relative => "time_s",
+        TimeBase::WallClock => "time_utc",
+    };
+    if long {
+        writeln!(file, "{},channel,value,unit", time_header)?;
+        let mut samples: Vec<(u64, &ExportChannel, f64)> = recording
+            .channels
+            .iter()
+            .flat_map(|channel| {
+                channel
+                    .samples
+                    .iter()
+                    .map(move |(time_ms, value)| (*time_ms, channel, *value))
+            })
+            .collect();
+        samples.sort_by_key(|(time_ms, _, _)| *time_ms);
+        for (time_ms, channel, value) in samples {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                recording.time_text(time_ms),
+                csv_field(&channel.name),
+                value,
+                csv_field(&channel.conversion.unit)
+            )?;
+        }
+    } else {
+        let header: Vec<String> = recording
+            .channels
+            .iter()
+            .map(|channel| csv_field(&column_name(channel)))
+            .collect();
+        writeln!(file, "{},{}", time_header, header.join(","))?;
+        for (time_ms, values) in recording.rows() {
+            let values: Vec<String> = values
+                .iter()
+                .map(|value| value.map_or(String::new(), |value| value.to_string()))
+                .collect();
+            writeln!(
+                file,
+                "{},{}",
+                recording.time_text(time_ms),
+                values.join(",")
+            )?;
+        }
+    }
+    file.flush()
+}
+
+/// Writes the logged channels of `setting` to `path` and returns a summary.
+pub fn export_session(
+    log: &mut Sensorlog,
+    session: &RecordedSession,
+    setting: &ExportSetting,
+    path: &Path,
+) -> Result<String, String> {
+    setting.check()?;
+    if setting.time_base == TimeBase::WallClock && session.clock.is_empty() {
+        return Err("the wall clock of this recording is unknown".to_string());
+    }
+    let names: Vec<String> = if setting.channels.is_empty() {
+        session.channels.iter().map(|c| c.name.clone()).collect()
+    } else {
+        setting.channels.clone()
+    };
+    if names.is_empty() {
+        return Err("no channels to export".to_string());
+    }
+
+    let start_ms = (setting.start_s * 1000.0) as u64;
+    let end_ms = setting.end_s.map(|end_s| (end_s * 1000.0) as u64);
+    let mut channels = Vec::new();
+    for name in names {
+        let measurements = log
+            .fetch_measurements(&name, end_ms, Some(start_ms), None)
+            .map_err(|e| format!("{}: {:?}", name, e))?;
+        // 数値でない記録 (RTTの文字列など) は書き出さない
+        let mut samples: Vec<(u64, f64)> = measurements
+            .iter()
+            .filter(|m| m.time >= start_ms && m.time <= end_ms.unwrap_or(u64::MAX))
+            .filter_map(|m| Some((m.time, m.data.trim().parse::<f64>().ok()?)))
+            .collect();
+        samples.sort_by_key(|(time_ms, _)| *time_ms);
+        let conversion = session
+            .channels
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.conversion.clone())
+            .unwrap_or_default();
+        channels.push(ExportChannel {
+            name,
+            conversion,
+            samples,
+        });
+    }
+    let recording = Recording::new(channels, setting.time_base, session.clone());
+    let sample_count: usize = recording.channels.iter().map(|c| c.samples.len()).sum();
+    if sample_count == 0 {
+        return Err("no samples in the range".to_string());
+    }
+
+    let res = match setting.format {
+        ExportFormat::CsvWide => write_csv(&recording, false, path),
+        ExportFormat::CsvLong => write_csv(&recording, true, path),
+        ExportFormat::Parquet => write_parquet(&recording, path),
+        ExportFormat::Mdf4 => write_mdf4(&recording, path),
+    };
+    res.map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(format!(
+        "{} samples of {} channels written",
+        sample_count,
+        recording.channels.len()
+    ))
+}
+// ----------------------------------------------------------------------------
+const USAGE: &str = "usage: VisuModiRam export [--format csv|csv-long|parquet|mdf4] [--wall-clock]
+                          [--from <s>] [--to <s>] [--channels <a,b,...>] <output>
+       VisuModiRam export --list";
+
+/// `VisuModiRam export ...` : writes the last recorded session without opening the GUI.
+/// Returns the exit code.
+pub fn export_command(args: impl Iterator<Item = String>) -> i32 {
+    match run_export_command(args.collect()) {
+        Ok(message) => {
+            println!("{}", message);
+            0
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    }
+}
+
+/// Options of `VisuModiRam export`.
+#[derive(Debug, Default, PartialEq)]
+struct ExportArgs {
+    setting: ExportSetting,
+    output: Option<PathBuf>,
+    list: bool,
+    help: bool,
+}
+
+fn parse_export_args(args: Vec<String>) -> Result<ExportArgs, String> {
+    let mut parsed = ExportArgs::default();
+    let mut format = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or(format!("{} needs a value\n{}", name, USAGE))
+        };
+        let setting = &mut parsed.setting;
+        match arg.as_str() {
+            "--format" => {
+                format = Some(match value("--format")?.as_str() {
+                    "csv" | "csv-wide" => ExportFormat::CsvWide,
+                    "csv-long" => ExportFormat::CsvLong,
+                    "parquet" => ExportFormat::Parquet,
+                    "mdf4" | "mf4" => ExportFormat::Mdf4,
+                    other => return Err(format!("unknown format {}\n{}", other, USAGE)),
+                })
+            }
+            "--wall-clock" => setting.time_base = TimeBase::WallClock,
+            "--from" => {
+                setting.start_s = value("--from")?
+                    .parse()
+                    .map_err(|_| format!("--from takes seconds\n{}", USAGE))?
+            }
+            "--to" => {
+                setting.end_s = Some(
+                    value("--to")?
+                        .parse()
+                        .map_err(|_| format!("--to takes seconds\n{}", USAGE))?,
+                )
+            }
+            "--channels" => {
+                setting.channels = value("--channels")?
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect()
+            }
+            "--list" => parsed.list = true,
+            "-h" | "--help" => {
+                return Ok(ExportArgs {
+                    help: true,
+                    ..Default::default()
+                })
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => parsed.output = Some(PathBuf::from(arg)),
+        }
+    }
+    // 形式の指定が無ければ拡張子から決める
+    let extension = parsed
+        .output
+        .as_ref()
+        .and_then(|output| output.extension())
+        .and_then(|ext| ext.to_str());
+    parsed.setting.format = format.unwrap_or(match extension {
+        Some("parquet") => ExportFormat::Parquet,
+        Some("mf4") | Some("mdf") => ExportFormat::Mdf4,
+        _ => ExportFormat::CsvWide,
+    });
+    Ok(parsed)
+}
+
+fn run_export_command(args: Vec<String>) -> Result<String, String> {
+    let args = parse_export_args(args)?;
+    if args.help {
+        return Ok(USAGE.to_string());
+    }
+
+    let session = RecordedSession::load()?;
+    if args.list {
+        let lines: Vec<String> = session
+            .channels
+            .iter()
+            .map(|channel| format!("{}\t{}", channel.name, channel.conversion.unit))
+            .collect();
+        return Ok(lines.join("\n"));
+    }
+    let output = args.output.ok_or(USAGE.to_string())?;
+    let mut log = super::probe_interface::log_service_default();
+    export_session(&mut log, &session, &args.setting, &output)
+}
+
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(time_base: TimeBase) -> Recording {
+        let speed = ExportChannel {
+            name: "speed".to_string(),
+            conversion: Conversion {
+                factor: 0.5,
+                offset: -3.0,
+                unit: "km/h".to_string(),
+                calibration: false,
+            },
+            samples: vec![(1000, 1.5), (1100, f64::NAN), (1200, 2.0)],
+        };
+        // カンマを含む名前は引用符で囲む
+        let count = ExportChannel {
+            name: "tgt:a,b".to_string(),
+            conversion: Conversion::default(),
+            samples: vec![(1000, 1.0), (1200, 3.0)],
+        };
+        let session = RecordedSession {
+            channels: vec![],
+            clock: vec![(0, 1_760_000_000_000)],
+        };
+        Recording::new(vec![speed, count], time_base, session)
+    }
+
+    fn write_and_read(recording: &Recording, long: bool, name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("vmr_{}_{}", std::process::id(), name));
+        write_csv(recording, long, &path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        text
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn format_utc_dates() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_utc(1_709_210_096_789), "2024-02-29T12:34:56.789Z");
+        assert_eq!(format_utc(946_684_799_999), "1999-12-31T23:59:59.999Z");
+        // 400年周期の境目
+        assert_eq!(format_utc(951_868_799_999), "2000-02-29T23:59:59.999Z");
+        assert_eq!(format_utc(951_868_800_000), "2000-03-01T00:00:00.000Z");
+        // 2100年はうるう年でない
+        assert_eq!(format_utc(4_107_542_400_000), "2100-03-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn wide_csv_leaves_gaps_empty_and_keeps_nan() {
+        let text = write_and_read(&recording(TimeBase::Relative), false, "wide.csv");
+        assert_eq!(
+            text,
+            "time_s,speed [km/h],\"tgt:a,b\"\n\
+             1.000,1.5,1\n\
+             1.100,NaN,\n\
+             1.200,2,3\n"
+        );
+    }
+
+    #[test]
+    fn long_csv_has_one_row_per_sample() {
+        let text = write_and_read(&recording(TimeBase::WallClock), true, "long.csv");
+        assert_eq!(
+            text,
+            "time_utc,channel,value,unit\n\
+             2025-10-09T08:53:21.000Z,speed,1.5,km/h\n\
+             2025-10-09T08:53:21.000Z,\"tgt:a,b\",1,\n\
+             2025-10-09T08:53:21.100Z,speed,NaN,km/h\n\
+             2025-10-09T08:53:21.200Z,speed,2,km/h\n\
+             2025-10-09T08:53:21.200Z,\"tgt:a,b\",3,\n"
+        );
+    }
+
+    #[test]
+    fn export_command_arguments() {
+        let parsed = parse_export_args(args(&[
+            "--wall-clock",
+            "--from",
+            "1.5",
+            "--to",
+            "20",
+            "--channels",
+            "speed, tgt:count,,",
+            "out.parquet",
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed,
+            ExportArgs {
+                setting: ExportSetting {
+                    format: ExportFormat::Parquet,
+                    time_base: TimeBase::WallClock,
+                    start_s: 1.5,
+                    end_s: Some(20.0),
+                    channels: vec!["speed".to_string(), "tgt:count".to_string()],
+                },
+                output: Some(PathBuf::from("out.parquet")),
+                list: false,
+                help: false,
+            }
+        );
+
+        // --formatは拡張子より優先する
+        let parsed = parse_export_args(args(&["out.parquet", "--format", "csv-long"])).unwrap();
+        assert_eq!(parsed.setting.format, ExportFormat::CsvLong);
+        let format = |output: &str| parse_export_args(args(&[output])).unwrap().setting.format;
+        assert_eq!(format("out.mf4"), ExportFormat::Mdf4);
+        assert_eq!(format("out.mdf"), ExportFormat::Mdf4);
+        assert_eq!(format("out.txt"), ExportFormat::CsvWide);
+
+        assert!(parse_export_args(args(&["--list"])).unwrap().list);
+        assert!(parse_export_args(args(&["-h", "--bogus"])).unwrap().help);
+        assert_eq!(run_export_command(args(&["--help"])).unwrap(), USAGE);
+
+        let error = |list: &[&str]| parse_export_args(args(list)).unwrap_err();
+        assert!(error(&["--format", "xlsx"]).starts_with("unknown format xlsx\n"));
+        assert!(error(&["--from"]).starts_with("--from needs a value\n"));
+        assert!(error(&["--to", "end"]).starts_with("--to takes seconds\n"));
+        assert!(error(&["--bogus"]).starts_with("unknown option --bogus\n"));
+    }
+}
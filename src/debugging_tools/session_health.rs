@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// The probe or the target stopped answering.
+    Lost,
+    Reconnecting,
+    Stopped,
+}
+
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        Self::Stopped
+    }
+}
+// ----------------------------------------------------------------------------
+/// What to do when the connection drops (target power cycle, USB glitch).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ReconnectPolicy {
+    pub enable: bool,
+    pub initial_delay_ms: u64,
+    /// The delay doubles after every failed attempt up to this value.
+    pub max_delay_ms: u64,
+    /// 0 retries until the session is stopped.
+    pub max_attempts: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            initial_delay_ms: 500,
+            max_delay_ms: 8000,
+            max_attempts: 0,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Wait before the `attempt`-th reconnect (counted from 1), None to give up.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if !self.enable || (self.max_attempts != 0 && attempt > self.max_attempts) {
+            return None;
+        }
+        let shift = attempt.saturating_sub(1).min(16);
+        let delay_ms = self
+            .initial_delay_ms
+            .saturating_mul(1 << shift)
+            .min(self.max_delay_ms.max(self.initial_delay_ms));
+        Some(Duration::from_millis(delay_ms))
+    }
+}
+// ----------------------------------------------------------------------------
+/// Connection state of the acquisition thread, published to the UI.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SessionHealth {
+    pub state: ConnectionState,
+    /// Session time the state was entered.
+    pub since_ms: i64,
+    /// Reconnect attempts since the connection was lost.
+    pub attempts: u32,
+    /// Session time of the next reconnect attempt.
+    pub next_retry_ms: Option<i64>,
+    /// Times the connection was lost during this session.
+    pub gaps: u32,
+    pub error: Option<String>,
+}
+
+impl SessionHealth {
+    pub fn set_state(&mut self, state: ConnectionState, now_ms: i64) {
+        if self.state != state {
+            self.state = state;
+            self.since_ms = now_ms;
+        }
+        if state != ConnectionState::Reconnecting {
+            self.next_retry_ms = None;
+        }
+    }
+
+    /// Values shown by the UI are live only in this state.
+    pub fn is_connected(&self) -> bool {
+        self.state == ConnectionState::Connected
+    }
+}
@@ -0,0 +1,171 @@
+use probe_rs::{Core, MemoryInterface};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use super::elf_symbols::ElfSymbols;
+use super::symbol_map::SymbolMap;
+
+/// Fill pattern of unused stack; the same value FreeRTOS uses for new task stacks.
+pub const STACK_PAINT_PATTERN: u32 = 0xA5A5_A5A5;
+pub const STACK_CHANNEL_TYPE: &str = "stack";
+pub const MAIN_STACK_NAME: &str = "main";
+// 塗るときに現在のSPから空けておく量 [byte]
+const PAINT_SP_MARGIN: u64 = 64;
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct StackSetting {
+    pub enable: bool,
+    /// Take the main stack from `_estack` and `_Min_Stack_Size` of the ELF.
+    pub use_linker_symbols: bool,
+    /// Main stack used when `use_linker_symbols` is off, or the symbols are missing.
+    pub top: u64,
+    pub size: u64,
+    /// Paint the main stack after every reset-and-halt.
+    pub paint_on_reset: bool,
+    pub scan_interval_ms: u64,
+    pub warning_percent: f64,
+    /// Static task stack arrays (e.g. `xIdleStack`) scanned for the FreeRTOS fill pattern.
+    pub task_stacks: Vec<String>,
+}
+
+impl Default for StackSetting {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            use_linker_symbols: true,
+            top: 0x2000_5000,
+            size: 0x400,
+            paint_on_reset: true,
+            scan_interval_ms: 500,
+            warning_percent: 80.0,
+            task_stacks: Vec::new(),
+        }
+    }
+}
+
+impl StackSetting {
+    pub fn channel_name(stack_name: &str) -> String {
+        format!("{}:{}", STACK_CHANNEL_TYPE, stack_name)
+    }
+
+    pub fn stack_names(&self) -> Vec<String> {
+        std::iter::once(MAIN_STACK_NAME.to_string())
+            .chain(self.task_stacks.iter().cloned())
+            .collect()
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct StackUsage {
+    pub name: String,
+    pub range: Range<u64>,
+    /// Deepest usage seen since the last paint [byte].
+    pub high_water: u64,
+    /// The region was painted (or filled by the RTOS); otherwise `high_water` is meaningless.
+    pub painted: bool,
+    pub error: Option<String>,
+}
+
+impl StackUsage {
+    pub fn size(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.size() == 0 {
+            return 0.0;
+        }
+        self.high_water as f64 / self.size() as f64 * 100.0
+    }
+}
+// ----------------------------------------------------------------------------
+pub struct StackMonitor {}
+
+impl StackMonitor {
+    /// Stack regions to watch; the main stack first.
+    pub fn regions(setting: &StackSetting, elf_path: &PathBuf) -> Vec<StackUsage> {
+        let mut main = Range {
+            start: setting.top.saturating_sub(setting.size),
+            end: setting.top,
+        };
+        if setting.use_linker_symbols {
+            if let Some(symbols) = ElfSymbols::load(elf_path) {
+                if let (Some(top), Some(size)) =
+                    (symbols.find("_estack"), symbols.find("_Min_Stack_Size"))
+                {
+                    main = top.value.saturating_sub(size.value)..top.value;
+                }
+            }
+        }
+
+        let mut regions = vec![StackUsage {
+            name: MAIN_STACK_NAME.to_string(),
+            range: main,
+            ..Default::default()
+        }];
+
+        if !setting.task_stacks.is_empty() {
+            let symbol_map = SymbolMap::load(elf_path);
+            for name in &setting.task_stacks {
+                let symbol = symbol_map.as_ref().and_then(|map| map.find(name));
+                regions.push(StackUsage {
+                    name: name.clone(),
+                    range: symbol.map_or(0..0, |s| s.address..s.address + s.size as u64),
+                    // タスクのスタックはRTOSが生成時に塗る
+                    painted: true,
+                    error: symbol.is_none().then(|| "symbol not found".to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+        regions
+    }
+
+    /// Fills the stack below the current SP with the pattern. Meant to be called
+    /// right after reset-and-halt, when SP is still at the top of the stack.
+    pub fn paint(core: &mut Core, usage: &mut StackUsage) -> Result<(), probe_rs::Error> {
+        let sp: u64 = core.read_core_reg(core.stack_pointer())?;
+        let start = (usage.range.start + 3) & !3;
+        let end = sp
+            .saturating_sub(PAINT_SP_MARGIN)
+            .min(usage.range.end)
+            .max(start)
+            & !3;
+
+        let words = vec![STACK_PAINT_PATTERN; ((end - start) / 4) as usize];
+        core.write_32(start, &words)?;
+        usage.high_water = usage.range.end.saturating_sub(end);
+        usage.painted = true;
+        usage.error = None;
+        Ok(())
+    }
+
+    /// Updates the high water mark from the first overwritten pattern word above the bottom.
+    pub fn scan(core: &mut Core, usage: &mut StackUsage) -> Result<(), probe_rs::Error> {
+        let start = (usage.range.start + 3) & !3;
+        let end = usage.range.end & !3;
+        if end <= start {
+            return Ok(());
+        }
+        let mut words = vec![0u32; ((end - start) / 4) as usize];
+        core.read_32(start, &mut words)?;
+
+        if !usage.painted {
+            // 前回のセッションやスタートアップコードで塗られていればそのまま使う
+            usage.painted = words.first() == Some(&STACK_PAINT_PATTERN);
+            if !usage.painted {
+                return Ok(());
+            }
+        }
+
+        let untouched = words
+            .iter()
+            .take_while(|word| **word == STACK_PAINT_PATTERN)
+            .count() as u64;
+        let used = usage.range.end - (start + untouched * 4);
+        usage.high_water = usage.high_water.max(used);
+        Ok(())
+    }
+}
@@ -0,0 +1,610 @@
+use probe_rs::{Core, MemoryInterface};
+
+use super::elf_parser::VariableInfo;
+use super::memory_interface::MCUMemory;
+
+// ITM / DWT registers of ARMv7-M
+const DEMCR: u64 = 0xE000_EDFC;
+const DEMCR_TRCENA: u32 = 1 << 24;
+const ITM_TER: u64 = 0xE000_0E00;
+const ITM_TCR: u64 = 0xE000_0E80;
+const ITM_LAR: u64 = 0xE000_0FB0;
+const CORESIGHT_UNLOCK: u32 = 0xC5AC_CE55;
+const DWT_COMP_BASE: u64 = 0xE000_1020;
+const DWT_COMP_STRIDE: u64 = 0x10;
+// DWT_FUNCTIONn.FUNCTION : PC + data value packet on write access
+const DWT_FUNCTION_PC_VALUE_WRITE: u32 = 0b1111;
+
+pub const ITM_CHANNEL_TYPE: &str = "itm";
+pub const DWT_DATA_TRACE_MAX: usize = 4;
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ItmValueFormat {
+    Unsigned,
+    Signed,
+    Float,
+}
+
+impl std::fmt::Display for ItmValueFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for ItmValueFormat {
+    fn default() -> Self {
+        Self::Unsigned
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SwoSetting {
+    pub enable: bool,
+    pub core_clock_hz: u32,
+    pub baud: u32,
+    /// Bit mask of the enabled ITM stimulus ports.
+    pub stimulus_ports: u32,
+    pub port_format: ItmValueFormat,
+    pub timestamps: bool,
+    /// Watch list symbols traced by the DWT comparators (max 4).
+    pub data_trace: Vec<String>,
+}
+
+impl Default for SwoSetting {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            core_clock_hz: 72_000_000,
+            baud: 2_000_000,
+            stimulus_ports: 0x1,
+            port_format: Default::default(),
+            timestamps: true,
+            data_trace: Vec::new(),
+        }
+    }
+}
+
+impl SwoSetting {
+    pub fn port_channel_name(port: u8) -> String {
+        format!("{}:{}", ITM_CHANNEL_TYPE, port)
+    }
+
+    pub fn enabled_ports(&self) -> Vec<u8> {
+        (0..32u8)
+            .filter(|port| self.stimulus_ports & (1 << port) != 0)
+            .collect()
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum TracePacket {
+    Sync,
+    Overflow,
+    LocalTimestamp {
+        delta: u32,
+    },
+    Instrumentation {
+        port: u8,
+        payload: u32,
+        size: usize,
+    },
+    DataTracePc {
+        comparator: u8,
+        pc: u32,
+    },
+    DataTraceAddress {
+        comparator: u8,
+        offset: u16,
+    },
+    DataTraceValue {
+        comparator: u8,
+        value: u32,
+        size: usize,
+        write: bool,
+    },
+    Hardware {
+        discriminator: u8,
+        payload: u32,
+    },
+    Unknown {
+        header: u8,
+    },
+}
+
+/// Software ITM/DWT packet decoder for a raw SWO byte stream (TPIU formatter bypassed).
+///
+/// Bytes can come from the probe, a file or a pipe; partial packets are kept
+/// until the rest arrives.
+#[derive(Default)]
+pub struct ItmDecoder {
+    buffer: Vec<u8>,
+}
+
+impl ItmDecoder {
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<TracePacket> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut packets = Vec::new();
+        let mut pos = 0;
+        while pos < self.buffer.len() {
+            match Self::parse_packet(&self.buffer[pos..]) {
+                Some((packet, used)) => {
+                    if let Some(packet) = packet {
+                        packets.push(packet);
+                    }
+                    pos += used;
+                }
+                None => break, // 続きのバイトを待つ
+            }
+        }
+        self.buffer.drain(..pos);
+        packets
+    }
+
+    // (packet, 消費したバイト数)。バイトが足りない場合はNone
+    fn parse_packet(bytes: &[u8]) -> Option<(Option<TracePacket>, usize)> {
+        let header = bytes[0];
+
+        // Synchronization : 0x00 ... 0x00 0x80
+        if header == 0x00 {
+            let zeros = bytes.iter().take_while(|b| **b == 0x00).count();
+            if zeros == bytes.len() {
+                return None;
+            }
+            if bytes[zeros] == 0x80 {
+                return Some((Some(TracePacket::Sync), zeros + 1));
+            }
+            return Some((None, zeros));
+        }
+
+        if header == 0x70 {
+            return Some((Some(TracePacket::Overflow), 1));
+        }
+
+        let size = match header & 0x03 {
+            1 => 1,
+            2 => 2,
+            3 => 4,
+            _ => 0,
+        };
+
+        // Protocol packets
+        if size == 0 {
+            // Local timestamp
+            if header & 0x0F == 0x00 {
+                if header & 0x80 == 0 {
+                    let delta = ((header >> 4) & 0x07) as u32;
+                    return Some((Some(TracePacket::LocalTimestamp { delta }), 1));
+                }
+                let (value, used) = Self::continuation(&bytes[1..])?;
+                return Some((Some(TracePacket::LocalTimestamp { delta: value }), 1 + used));
+            }
+            // Global timestamp / Extension : 内容は使わないので読み飛ばす
+            if header & 0x80 != 0 {
+                let (_, used) = Self::continuation(&bytes[1..])?;
+                return Some((None, 1 + used));
+            }
+            return Some((Some(TracePacket::Unknown { header }), 1));
+        }
+
+        // Source packets
+        if bytes.len() < 1 + size {
+            return None;
+        }
+        let mut payload = [0u8; 4];
+        payload[..size].copy_from_slice(&bytes[1..1 + size]);
+        let payload = u32::from_le_bytes(payload);
+        let discriminator = header >> 3;
+
+        let packet = if header & 0x04 == 0 {
+            TracePacket::Instrumentation {
+                port: discriminator,
+                payload,
+                size,
+            }
+        } else {
+            let comparator = (discriminator >> 1) & 0x03;
+            match discriminator & 0b11000 {
+                0b01000 if discriminator & 1 == 0 => TracePacket::DataTracePc {
+                    comparator,
+                    pc: payload,
+                },
+                0b01000 => TracePacket::DataTraceAddress {
+                    comparator,
+                    offset: payload as u16,
+                },
+                0b10000 => TracePacket::DataTraceValue {
+                    comparator,
+                    value: payload,
+                    size,
+                    write: discriminator & 1 == 1,
+                },
+                _ => TracePacket::Hardware {
+                    discriminator,
+                    payload,
+                },
+            }
+        };
+        Some((Some(packet), 1 + size))
+    }
+
+    // 7bitずつのcontinuation形式 (最大4byte)
+    fn continuation(bytes: &[u8]) -> Option<(u32, usize)> {
+        let mut value = 0u32;
+        for (i, b) in bytes.iter().take(4).enumerate() {
+            value |= ((b & 0x7F) as u32) << (7 * i);
+            if b & 0x80 == 0 || i == 3 {
+                return Some((value, i + 1));
+            }
+        }
+        None
+    }
+}
+// ----------------------------------------------------------------------------
+/// Turns decoded packets into `(time_ms, channel, value)` measurements.
+pub struct TraceChannelMapper {
+    setting: SwoSetting,
+    data_trace: Vec<VariableInfo>,
+    base_time_ms: f64,
+    timestamp_ticks: u64,
+    // 次のローカルタイムスタンプを待っている (channel, value)
+    pending: Vec<(String, String)>,
+    // ローカルタイムスタンプを受信したか
+    timestamped: bool,
+}
+
+impl TraceChannelMapper {
+    pub fn new(setting: &SwoSetting, watch_list: &Vec<VariableInfo>, base_time_ms: i64) -> Self {
+        Self {
+            setting: setting.clone(),
+            data_trace: Self::data_trace_symbols(setting, watch_list),
+            base_time_ms: base_time_ms as f64,
+            timestamp_ticks: 0,
+            pending: Vec::new(),
+            timestamped: false,
+        }
+    }
+
+    pub fn data_trace_symbols(
+        setting: &SwoSetting,
+        watch_list: &Vec<VariableInfo>,
+    ) -> Vec<VariableInfo> {
+        setting
+            .data_trace
+            .iter()
+            .filter_map(|name| watch_list.iter().find(|v| &v.name == name).cloned())
+            .take(DWT_DATA_TRACE_MAX)
+            .collect()
+    }
+
+    /// A local timestamp packet follows the packets it dates, so values are held back
+    /// until the next one arrives. `now_ms` is used when the stream carries no local
+    /// timestamps.
+    pub fn map(&mut self, packets: Vec<TracePacket>, now_ms: i64) -> Vec<(i64, String, String)> {
+        let mut values = Vec::new();
+
+        for packet in packets {
+            if let TracePacket::LocalTimestamp { delta } = packet {
+                self.timestamp_ticks += delta as u64;
+                self.timestamped = true;
+                let elapsed =
+                    self.timestamp_ticks as f64 * 1000.0 / self.setting.core_clock_hz as f64;
+                let time_ms = (self.base_time_ms + elapsed) as i64;
+                values.extend(
+                    self.pending
+                        .drain(..)
+                        .map(|(channel, value)| (time_ms, channel, value)),
+                );
+                continue;
+            }
+            let Some((channel, value)) = self.value(packet) else {
+                continue;
+            };
+            if self.setting.timestamps {
+                self.pending.push((channel, value));
+            } else {
+                values.push((now_ms, channel, value));
+            }
+        }
+
+        if !self.timestamped {
+            values.extend(
+                self.pending
+                    .drain(..)
+                    .map(|(channel, value)| (now_ms, channel, value)),
+            );
+        }
+        values
+    }
+
+    fn value(&self, packet: TracePacket) -> Option<(String, String)> {
+        match packet {
+            TracePacket::Instrumentation {
+                port,
+                payload,
+                size,
+            } => {
+                if self.setting.stimulus_ports & (1 << port) == 0 {
+                    return None;
+                }
+                let value = match self.setting.port_format {
+                    ItmValueFormat::Unsigned => format!("{}", payload),
+                    ItmValueFormat::Signed => match size {
+                        1 => format!("{}", payload as u8 as i8),
+                        2 => format!("{}", payload as u16 as i16),
+                        _ => format!("{}", payload as i32),
+                    },
+                    ItmValueFormat::Float => format!("{:?}", f32::from_bits(payload)),
+                };
+                Some((SwoSetting::port_channel_name(port), value))
+            }
+            TracePacket::DataTraceValue {
+                comparator,
+                value,
+                size,
+                write: true,
+            } => {
+                let symbol = self.data_trace.get(comparator as usize)?;
+                let bytes = value.to_le_bytes();
+                let value = MCUMemory::decode(symbol, &bytes[..size.min(symbol.size)]);
+                (!value.is_empty()).then(|| (symbol.name.clone(), value))
+            }
+            _ => None,
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+pub struct SwoTrace {}
+
+impl SwoTrace {
+    /// Configures ITM stimulus ports and the DWT data trace comparators.
+    /// TPIU and the probe side are set up by `Session::setup_tracing` beforehand.
+    pub fn configure(
+        core: &mut Core,
+        setting: &SwoSetting,
+        data_trace: &Vec<VariableInfo>,
+    ) -> Result<(), probe_rs::Error> {
+        let demcr = core.read_word_32(DEMCR)?;
+        core.write_word_32(DEMCR, demcr | DEMCR_TRCENA)?;
+
+        core.write_word_32(ITM_LAR, CORESIGHT_UNLOCK)?;
+        // TraceBusID=1, DWTENA, SYNCENA, (TSENA), ITMENA
+        let mut tcr = (1 << 16) | (1 << 3) | (1 << 2) | 1;
+        if setting.timestamps {
+            tcr |= 1 << 1;
+        }
+        core.write_word_32(ITM_TCR, tcr)?;
+        core.write_word_32(ITM_TER, setting.stimulus_ports)?;
+
+        for (slot, symbol) in data_trace.iter().enumerate() {
+            let size = symbol.size.max(1) as u64;
+            if !size.is_power_of_two() || symbol.address % size != 0 {
+                return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                    "{} is not naturally aligned",
+                    symbol.name
+                )));
+            }
+            let base = DWT_COMP_BASE + (slot as u64) * DWT_COMP_STRIDE;
+            core.write_word_32(base + 8, 0)?;
+            core.write_word_32(base, symbol.address as u32)?;
+            core.write_word_32(base + 4, size.trailing_zeros())?;
+            // DATAVSIZE : 0=byte, 1=halfword, 2=word
+            let datavsize = (size.trailing_zeros().min(2)) << 10;
+            core.write_word_32(base + 8, DWT_FUNCTION_PC_VALUE_WRITE | datavsize)?;
+        }
+        Ok(())
+    }
+
+    pub fn release(core: &mut Core, data_trace_cnt: usize) -> Result<(), probe_rs::Error> {
+        for slot in 0..data_trace_cnt {
+            let base = DWT_COMP_BASE + (slot as u64) * DWT_COMP_STRIDE;
+            core.write_word_32(base + 8, 0)?;
+        }
+        core.write_word_32(ITM_TER, 0)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_packets() {
+        let mut decoder = ItmDecoder::default();
+        let packets = decoder.feed(&[
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x80, // sync
+            0x70, // overflow
+            0x30, // local timestamp format 2
+            0xC0, 0x85, 0x01, // local timestamp format 1
+            0x94, 0x81, 0x01, // global timestamp (読み飛ばす)
+        ]);
+        assert_eq!(
+            packets,
+            vec![
+                TracePacket::Sync,
+                TracePacket::Overflow,
+                TracePacket::LocalTimestamp { delta: 3 },
+                TracePacket::LocalTimestamp { delta: 133 },
+            ]
+        );
+    }
+
+    #[test]
+    fn instrumentation_packets() {
+        let mut decoder = ItmDecoder::default();
+        let packets = decoder.feed(&[0x09, 0x41, 0x1A, 0xCD, 0xAB, 0x03, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(
+            packets,
+            vec![
+                TracePacket::Instrumentation {
+                    port: 1,
+                    payload: 0x41,
+                    size: 1
+                },
+                TracePacket::Instrumentation {
+                    port: 3,
+                    payload: 0xABCD,
+                    size: 2
+                },
+                TracePacket::Instrumentation {
+                    port: 0,
+                    payload: 0x1234_5678,
+                    size: 4
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn packet_split_across_feeds() {
+        let mut decoder = ItmDecoder::default();
+        assert!(decoder.feed(&[0x03, 0x78, 0x56]).is_empty());
+        assert!(decoder.feed(&[0x34]).is_empty());
+        assert_eq!(
+            decoder.feed(&[0x12, 0xC0, 0x81]),
+            vec![TracePacket::Instrumentation {
+                port: 0,
+                payload: 0x1234_5678,
+                size: 4
+            }]
+        );
+        assert_eq!(
+            decoder.feed(&[0x01]),
+            vec![TracePacket::LocalTimestamp { delta: 129 }]
+        );
+    }
+
+    #[test]
+    fn dwt_data_trace_packets() {
+        let mut decoder = ItmDecoder::default();
+        let packets = decoder.feed(&[
+            0x47, 0x10, 0x02, 0x00, 0x08, // PC, comparator 0
+            0x6E, 0x34, 0x00, // address offset, comparator 2
+            0x9E, 0x34, 0x12, // data value write, comparator 1
+            0x85, 0x7F, // data value read, comparator 0
+            0x0D, 0x01, // hardware (exception trace)
+        ]);
+        assert_eq!(
+            packets,
+            vec![
+                TracePacket::DataTracePc {
+                    comparator: 0,
+                    pc: 0x0800_0210
+                },
+                TracePacket::DataTraceAddress {
+                    comparator: 2,
+                    offset: 0x34
+                },
+                TracePacket::DataTraceValue {
+                    comparator: 1,
+                    value: 0x1234,
+                    size: 2,
+                    write: true
+                },
+                TracePacket::DataTraceValue {
+                    comparator: 0,
+                    value: 0x7F,
+                    size: 1,
+                    write: false
+                },
+                TracePacket::Hardware {
+                    discriminator: 1,
+                    payload: 0x01
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn mapper_uses_local_timestamps_and_port_mask() {
+        let setting = SwoSetting {
+            core_clock_hz: 1000,
+            stimulus_ports: 0b01,
+            port_format: ItmValueFormat::Signed,
+            ..Default::default()
+        };
+        let symbol = VariableInfo {
+            name: "counter".to_string(),
+            types: "short int".to_string(),
+            address: 0x2000_0000,
+            size: 2,
+        };
+        let watch_list = vec![symbol];
+        let mut mapper = TraceChannelMapper::new(
+            &SwoSetting {
+                data_trace: vec!["counter".to_string()],
+                ..setting
+            },
+            &watch_list,
+            100,
+        );
+
+        // タイムスタンプはその前のパケットの時刻
+        let values = mapper.map(
+            vec![
+                TracePacket::LocalTimestamp { delta: 100 },
+                TracePacket::Instrumentation {
+                    port: 0,
+                    payload: 0xFF,
+                    size: 1,
+                },
+                TracePacket::Instrumentation {
+                    port: 1,
+                    payload: 1,
+                    size: 1,
+                },
+                TracePacket::DataTraceValue {
+                    comparator: 0,
+                    value: 0xFFFE,
+                    size: 2,
+                    write: true,
+                },
+                TracePacket::LocalTimestamp { delta: 400 },
+                TracePacket::Instrumentation {
+                    port: 0,
+                    payload: 0x01,
+                    size: 1,
+                },
+            ],
+            0,
+        );
+        assert_eq!(
+            values,
+            vec![
+                (600, "itm:0".to_string(), "-1".to_string()),
+                (600, "counter".to_string(), "-2".to_string()),
+            ]
+        );
+        // 残りは次の呼び出しのタイムスタンプで出る
+        let values = mapper.map(vec![TracePacket::LocalTimestamp { delta: 250 }], 0);
+        assert_eq!(values, vec![(850, "itm:0".to_string(), "1".to_string())]);
+    }
+
+    #[test]
+    fn mapper_without_timestamps_uses_the_receive_time() {
+        let packet = TracePacket::Instrumentation {
+            port: 0,
+            payload: 7,
+            size: 1,
+        };
+        let mut mapper = TraceChannelMapper::new(&SwoSetting::default(), &vec![], 100);
+        assert_eq!(
+            mapper.map(vec![packet.clone()], 42),
+            vec![(42, "itm:0".to_string(), "7".to_string())]
+        );
+
+        let setting = SwoSetting {
+            timestamps: false,
+            ..Default::default()
+        };
+        let mut mapper = TraceChannelMapper::new(&setting, &vec![], 100);
+        assert_eq!(
+            mapper.map(vec![TracePacket::LocalTimestamp { delta: 10 }, packet], 43),
+            vec![(43, "itm:0".to_string(), "7".to_string())]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use ddbug_parser::{File, FileHash};
+use std::path::PathBuf;
+
+use super::elf_parser::{get_variable_info, VariableInfo};
+
+// ----------------------------------------------------------------------------
+/// Address-sorted global variables of the ELF, for "which symbol owns this byte".
+#[derive(Default, Clone)]
+pub struct SymbolMap {
+    /// Top level variables (`size` covers the whole struct / array).
+    symbols: Vec<VariableInfo>,
+    /// Scalar members as listed in the watch list (`a.b[1]` ...).
+    members: Vec<VariableInfo>,
+    /// Largest end address among `symbols[..=i]`.
+    symbol_reach: Vec<u64>,
+    /// Largest end address among `members[..=i]`.
+    member_reach: Vec<u64>,
+}
+
+impl SymbolMap {
+    pub fn load(elf_path: &PathBuf) -> Option<Self> {
+        let ctx = File::parse(elf_path.to_str()?.to_string()).ok()?;
+        let hash = FileHash::new(ctx.file());
+
+        let mut symbols = Vec::new();
+        let mut members = Vec::new();
+        for unit in ctx.file().units().iter() {
+            for var in unit.variables() {
+                let (Some(name), Some(address)) = (var.name(), var.address()) else {
+                    continue;
+                };
+                if address == 0 || var.ty(&hash).is_none() {
+                    continue;
+                }
+                let scalars = get_variable_info(var, &hash);
+                let types = match scalars.as_slice() {
+                    [scalar] if scalar.name == name => scalar.types.clone(),
+                    _ => String::new(),
+                };
+                let size = var
+                    .byte_size(&hash)
+                    .map(|size| size as usize)
+                    .unwrap_or_else(|| scalars.iter().map(|s| s.size).sum());
+
+                symbols.push(VariableInfo {
+                    name: name.to_string(),
+                    types,
+                    address,
+                    size,
+                });
+                members.extend(scalars);
+            }
+        }
+
+        Some(Self::new(symbols, members))
+    }
+
+    pub fn new(mut symbols: Vec<VariableInfo>, mut members: Vec<VariableInfo>) -> Self {
+        symbols.sort_by_key(|symbol| symbol.address);
+        symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+        members.sort_by_key(|member| member.address);
+        Self {
+            symbol_reach: Self::reach(&symbols),
+            member_reach: Self::reach(&members),
+            symbols,
+            members,
+        }
+    }
+
+    pub fn symbols(&self) -> &Vec<VariableInfo> {
+        &self.symbols
+    }
+
+    pub fn find(&self, name: &str) -> Option<&VariableInfo> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Top level variable that contains `address`.
+    pub fn owner_of(&self, address: u64) -> Option<&VariableInfo> {
+        Self::containing(&self.symbols, &self.symbol_reach, address)
+    }
+
+    /// Scalar member that contains `address`, decodable with `MCUMemory::decode`.
+    pub fn member_of(&self, address: u64) -> Option<&VariableInfo> {
+        Self::containing(&self.members, &self.member_reach, address)
+    }
+
+    fn end(v: &VariableInfo) -> u64 {
+        v.address + v.size.max(1) as u64
+    }
+
+    fn reach(list: &[VariableInfo]) -> Vec<u64> {
+        list.iter()
+            .scan(0, |reach, v| {
+                *reach = Self::end(v).max(*reach);
+                Some(*reach)
+            })
+            .collect()
+    }
+
+    fn containing<'a>(
+        list: &'a [VariableInfo],
+        reach: &[u64],
+        address: u64,
+    ) -> Option<&'a VariableInfo> {
+        let index = list.partition_point(|v| v.address <= address);
+        // reach[i] <= address なら、それより前のシンボルはどれも address まで届かない
+        list[..index]
+            .iter()
+            .zip(&reach[..index])
+            .rev()
+            .take_while(|(_, reach)| address < **reach)
+            .map(|(v, _)| v)
+            .find(|v| address < Self::end(v))
+    }
+}
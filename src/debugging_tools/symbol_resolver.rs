@@ -0,0 +1,59 @@
+use probe_rs::debug::DebugInfo;
+use std::path::PathBuf;
+
+// ----------------------------------------------------------------------------
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SourceInfo {
+    pub function: String,
+    pub file: String,
+    pub line: Option<u64>,
+}
+
+impl std::fmt::Display for SourceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} ({}:{})", self.function, self.file, line),
+            None => write!(f, "{} ({})", self.function, self.file),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+/// Resolves target addresses to function names and source lines of the loaded ELF.
+pub struct SymbolResolver {
+    debug_info: DebugInfo,
+}
+
+impl SymbolResolver {
+    pub fn load(elf_path: &PathBuf) -> Option<Self> {
+        if !elf_path.is_file() {
+            return None;
+        }
+        DebugInfo::from_file(elf_path)
+            .ok()
+            .map(|debug_info| Self { debug_info })
+    }
+
+    pub fn resolve(&self, address: u64) -> Option<SourceInfo> {
+        let function = self.debug_info.function_name(address, false).ok().flatten();
+        let location = self.debug_info.get_source_location(address);
+
+        if function.is_none() && location.is_none() {
+            return None;
+        }
+
+        let (file, line) = match location {
+            Some(location) => (location.file.unwrap_or_default(), location.line),
+            None => (String::new(), None),
+        };
+
+        Some(SourceInfo {
+            function: function.unwrap_or_else(|| format!("0x{:08x}", address)),
+            file,
+            line,
+        })
+    }
+
+    pub fn debug_info(&self) -> &DebugInfo {
+        &self.debug_info
+    }
+}
@@ -0,0 +1,153 @@
+use super::core_dump::{CoreDump, CoreDumpSetting, SavedRegisters};
+use super::gdb_remote::{GdbRemote, GdbSetting};
+#[cfg(target_os = "linux")]
+use super::linux_process::LinuxProcess;
+use super::linux_process::LinuxProcessSetting;
+use super::run_control::{RunCommand, TargetStatus};
+use super::serial_monitor::{SerialMonitor, SerialSetting};
+use super::xcp_master::{XcpMaster, XcpSetting};
+
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum BackendKind {
+    /// Debug probe driven by probe-rs.
+    Probe,
+    /// GDB remote serial protocol server (OpenOCD, pyOCD, QEMU gdbstub).
+    Gdb,
+    /// Host process of a software-in-the-loop build (Linux only).
+    LinuxProcess,
+    /// RAM dump loaded from files, read-only.
+    CoreDump,
+    /// Framed protocol over a UART (see `serial_monitor`).
+    Serial,
+    /// XCP slave on Ethernet (TCP or UDP).
+    Xcp,
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Probe => write!(f, "probe-rs"),
+            Self::Gdb => write!(f, "GDB server"),
+            Self::LinuxProcess => write!(f, "Linux process"),
+            Self::CoreDump => write!(f, "core dump"),
+            Self::Serial => write!(f, "UART"),
+            Self::Xcp => write!(f, "XCP"),
+        }
+    }
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        Self::Probe
+    }
+}
+// ----------------------------------------------------------------------------
+/// How to reach the target. The settings of every kind are kept while switching.
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct BackendSetting {
+    pub kind: BackendKind,
+    pub gdb: GdbSetting,
+    pub linux: LinuxProcessSetting,
+    pub dump: CoreDumpSetting,
+    pub serial: SerialSetting,
+    pub xcp: XcpSetting,
+}
+
+impl BackendSetting {
+    pub fn is_probe(&self) -> bool {
+        self.kind == BackendKind::Probe
+    }
+
+    /// Nothing is written to hardware and values never change.
+    pub fn is_offline(&self) -> bool {
+        self.kind == BackendKind::CoreDump
+    }
+
+    /// Connects a backend other than probe-rs. `elf_path` is the image the watch list
+    /// addresses come from.
+    pub fn open(&self, elf_path: &str) -> Result<Box<dyn TargetBackend>, probe_rs::Error> {
+        match self.kind {
+            BackendKind::Probe => Err(backend_error("probe-rs is not opened as a backend")),
+            BackendKind::Gdb => Ok(Box::new(GdbRemote::connect(&self.gdb)?)),
+            #[cfg(target_os = "linux")]
+            BackendKind::LinuxProcess => Ok(Box::new(LinuxProcess::attach(&self.linux, elf_path)?)),
+            #[cfg(not(target_os = "linux"))]
+            BackendKind::LinuxProcess => {
+                let _ = elf_path;
+                Err(backend_error("processes can be attached only on Linux"))
+            }
+            BackendKind::CoreDump => Ok(Box::new(CoreDump::load(&self.dump)?)),
+            BackendKind::Serial => Ok(Box::new(SerialMonitor::open(&self.serial)?)),
+            BackendKind::Xcp => Ok(Box::new(XcpMaster::connect(&self.xcp)?)),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+/// Values sent by the target on its own after `TargetBackend::subscribe`.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct StreamSample {
+    /// Target clock.
+    pub time_ms: u32,
+    /// Bytes of every subscribed range, concatenated in order.
+    pub data: Vec<u8>,
+}
+// ----------------------------------------------------------------------------
+/// Memory access and run control of a target that is not reached through probe-rs.
+pub trait TargetBackend: Send {
+    /// Short name for session events, e.g. "GDB server localhost:3333".
+    fn describe(&self) -> String;
+
+    /// Run state of the target; `location` is filled in by the caller.
+    fn poll_status(&mut self) -> Result<TargetStatus, probe_rs::Error>;
+
+    /// Prepares one polling cycle of memory accesses. False when the memory can not
+    /// be accessed now (e.g. an all-stop GDB server while the target runs).
+    fn begin_access(&mut self) -> Result<bool, probe_rs::Error> {
+        Ok(true)
+    }
+
+    /// Ends the cycle started by `begin_access`.
+    fn end_access(&mut self) -> Result<(), probe_rs::Error> {
+        Ok(())
+    }
+
+    fn read_memory(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error>;
+
+    fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<(), probe_rs::Error>;
+
+    fn run_command(&mut self, command: RunCommand) -> Result<(), probe_rs::Error> {
+        Err(backend_error(&format!(
+            "{} can not {}",
+            self.describe(),
+            command.to_string().to_lowercase()
+        )))
+    }
+
+    /// Registers saved with a post-mortem image, for the fault analyzer.
+    fn saved_registers(&self) -> Option<SavedRegisters> {
+        None
+    }
+
+    /// Asks the target to send `ranges` (address, length) every `period_ms`.
+    /// False when the backend can only be polled.
+    fn subscribe(
+        &mut self,
+        _ranges: &[(u64, usize)],
+        _period_ms: u32,
+    ) -> Result<bool, probe_rs::Error> {
+        Ok(false)
+    }
+
+    /// Samples received since the last call.
+    fn take_samples(&mut self) -> Result<Vec<StreamSample>, probe_rs::Error> {
+        Ok(Vec::new())
+    }
+}
+
+pub(super) fn backend_error(message: &str) -> probe_rs::Error {
+    probe_rs::Error::Other(anyhow::anyhow!(message.to_string()))
+}
@@ -0,0 +1,184 @@
+use probe_rs::{Core, CoreType, MemoryInterface};
+
+use super::elf_parser::VariableInfo;
+use super::symbol_resolver::SourceInfo;
+
+// DWT (Data Watchpoint and Trace) registers of ARMv6-M / ARMv7-M / ARMv8-M
+const DEMCR: u64 = 0xE000_EDFC;
+const DEMCR_TRCENA: u32 = 1 << 24;
+const DWT_CTRL: u64 = 0xE000_1000;
+const DWT_COMP_BASE: u64 = 0xE000_1020;
+const DWT_COMP_STRIDE: u64 = 0x10;
+const DWT_FUNCTION_MATCHED: u32 = 1 << 24;
+// ARMv8-M DWT_FUNCTIONn : ACTION = debug event, DATAVSIZE
+const DWT_V8_ACTION_DEBUG_EVENT: u32 = 0b01 << 4;
+const DWT_V8_DATAVSIZE_SHIFT: u32 = 10;
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum WatchpointKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl std::fmt::Display for WatchpointKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for WatchpointKind {
+    fn default() -> Self {
+        Self::Write
+    }
+}
+
+impl WatchpointKind {
+    // ARMv6-M / ARMv7-M DWT_FUNCTIONn.FUNCTION : data address comparison, debug event
+    fn dwt_function_v7(&self) -> u32 {
+        match self {
+            Self::Read => 0b0101,
+            Self::Write => 0b0110,
+            Self::Access => 0b0111,
+        }
+    }
+
+    // ARMv8-M DWT_FUNCTIONn.MATCH : data address comparison
+    fn dwt_match_v8(&self) -> u32 {
+        match self {
+            Self::Read => 0b0110,
+            Self::Write => 0b0101,
+            Self::Access => 0b0100,
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct WatchpointSetting {
+    pub symbol: VariableInfo,
+    pub kind: WatchpointKind,
+    /// Resume the core right after the hit is recorded ("write tracer").
+    pub auto_resume: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchpointHit {
+    pub time_ms: i64,
+    pub symbol: String,
+    pub kind: WatchpointKind,
+    pub pc: u64,
+    pub location: Option<SourceInfo>,
+}
+
+impl std::fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(
+                f,
+                "{} {} @ 0x{:08x} {}",
+                self.symbol, self.kind, self.pc, location
+            ),
+            None => write!(f, "{} {} @ 0x{:08x}", self.symbol, self.kind, self.pc),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+/// Hardware data watchpoints driven through the DWT comparators.
+///
+/// probe-rs only exposes instruction breakpoints, so the comparators are
+/// programmed directly via the core's memory interface. ARMv6-M and ARMv7-M use
+/// FUNCTION with an address MASK; ARMv8-M uses MATCH/ACTION and has no MASK, so
+/// one comparator covers at most a word there.
+pub struct DataWatchpoint {}
+
+impl DataWatchpoint {
+    pub fn num_comparators(core: &mut Core) -> Result<usize, probe_rs::Error> {
+        Self::enable_trace(core)?;
+        let ctrl = core.read_word_32(DWT_CTRL)?;
+        Ok((ctrl >> 28) as usize)
+    }
+
+    pub fn arm(
+        core: &mut Core,
+        slot: usize,
+        setting: &WatchpointSetting,
+    ) -> Result<(), probe_rs::Error> {
+        let size = setting.symbol.size.max(1) as u64;
+        if !size.is_power_of_two() || setting.symbol.address % size != 0 {
+            return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                "{} is not naturally aligned",
+                setting.symbol.name
+            )));
+        }
+        if slot >= Self::num_comparators(core)? {
+            return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                "No free DWT comparator"
+            )));
+        }
+
+        let base = DWT_COMP_BASE + (slot as u64) * DWT_COMP_STRIDE;
+        let size_log2 = size.trailing_zeros();
+
+        match core.core_type() {
+            CoreType::Armv6m | CoreType::Armv7m | CoreType::Armv7em => {
+                core.write_word_32(base + 8, 0)?;
+                core.write_word_32(base, setting.symbol.address as u32)?;
+                core.write_word_32(base + 4, size_log2)?;
+                core.write_word_32(base + 8, setting.kind.dwt_function_v7())?;
+            }
+            CoreType::Armv8m => {
+                if size > 4 {
+                    return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                        "{} is larger than a word (ARMv8-M comparators have no mask)",
+                        setting.symbol.name
+                    )));
+                }
+                core.write_word_32(base + 8, 0)?;
+                core.write_word_32(base, setting.symbol.address as u32)?;
+                core.write_word_32(
+                    base + 8,
+                    setting.kind.dwt_match_v8()
+                        | DWT_V8_ACTION_DEBUG_EVENT
+                        | size_log2 << DWT_V8_DATAVSIZE_SHIFT,
+                )?;
+            }
+            other => {
+                return Err(probe_rs::Error::Other(anyhow::anyhow!(
+                    "Data watchpoints are not supported on {other:?}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn disarm(core: &mut Core, slot: usize) -> Result<(), probe_rs::Error> {
+        let base = DWT_COMP_BASE + (slot as u64) * DWT_COMP_STRIDE;
+        core.write_word_32(base + 8, 0)?;
+        Ok(())
+    }
+
+    /// Returns the slot in `slots` whose MATCHED flag is set. Reading FUNCTIONn clears the flag.
+    pub fn matched_slot(
+        core: &mut Core,
+        slots: std::ops::Range<usize>,
+    ) -> Result<Option<usize>, probe_rs::Error> {
+        for slot in slots {
+            let base = DWT_COMP_BASE + (slot as u64) * DWT_COMP_STRIDE;
+            let function = core.read_word_32(base + 8)?;
+            if function & DWT_FUNCTION_MATCHED != 0 {
+                return Ok(Some(slot));
+            }
+        }
+        Ok(None)
+    }
+
+    fn enable_trace(core: &mut Core) -> Result<(), probe_rs::Error> {
+        let demcr = core.read_word_32(DEMCR)?;
+        if demcr & DEMCR_TRCENA == 0 {
+            core.write_word_32(DEMCR, demcr | DEMCR_TRCENA)?;
+        }
+        Ok(())
+    }
+}
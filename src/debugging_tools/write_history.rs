@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+
+const WRITE_HISTORY_CAPACITY: usize = 10000;
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WriteStatus {
+    /// Still in the write queue.
+    Pending,
+    /// Written, waiting for the read-back on the next cycle.
+    Written,
+    Verified,
+    /// The read-back differs from the encoded bytes (e.g. the firmware overwrote it).
+    Mismatch,
+    Failed,
+}
+
+impl std::fmt::Display for WriteStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Default for WriteStatus {
+    fn default() -> Self {
+        Self::Pending
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct WriteRecord {
+    pub seq: usize,
+    pub time_ms: i64,
+    pub symbol: String,
+    pub requested: String,
+    pub encoded: Vec<u8>,
+    pub status: WriteStatus,
+    pub error: Option<String>,
+    pub readback: Option<String>,
+}
+
+impl WriteRecord {
+    pub fn encoded_hex(&self) -> String {
+        self.encoded
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl std::fmt::Display for WriteRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} <- \"{}\" : {}",
+            self.symbol, self.requested, self.status
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, " ({})", error)?;
+        }
+        if let Some(readback) = &self.readback {
+            write!(f, ", read back {}", readback)?;
+        }
+        Ok(())
+    }
+}
+// ----------------------------------------------------------------------------
+/// Results of every write request, shared between the watch thread and the UI.
+#[derive(Default)]
+pub struct WriteHistory {
+    records: VecDeque<WriteRecord>,
+    next_seq: usize,
+}
+
+impl WriteHistory {
+    pub fn push(&mut self, mut record: WriteRecord) -> usize {
+        record.seq = self.next_seq;
+        self.next_seq += 1;
+        self.records.push_back(record);
+
+        while self.records.len() > WRITE_HISTORY_CAPACITY {
+            self.records.pop_front();
+        }
+        self.next_seq - 1
+    }
+
+    pub fn get_mut(&mut self, seq: usize) -> Option<&mut WriteRecord> {
+        self.records.iter_mut().find(|record| record.seq == seq)
+    }
+
+    pub fn last_of(&self, symbol: &str) -> Option<WriteRecord> {
+        self.records
+            .iter()
+            .rev()
+            .find(|record| record.symbol == symbol)
+            .cloned()
+    }
+
+    pub fn records(&self) -> Vec<WriteRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// CSV of `records`, e.g. the histories of several targets merged.
+    pub fn to_csv(records: &[WriteRecord]) -> String {
+        let mut text = "time_s,symbol,requested,encoded,status,error,readback\n".to_string();
+        for record in records {
+            text += &format!(
+                "{:.3},{},\"{}\",{},{},\"{}\",{}\n",
+                record.time_ms as f64 / 1000.0,
+                record.symbol,
+                record.requested.replace('"', "\"\""),
+                record.encoded_hex(),
+                record.status,
+                record
+                    .error
+                    .clone()
+                    .unwrap_or_default()
+                    .replace('"', "\"\""),
+                record.readback.clone().unwrap_or_default()
+            );
+        }
+        text
+    }
+}
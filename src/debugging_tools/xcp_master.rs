@@ -0,0 +1,758 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::run_control::{CoreState, TargetStatus};
+use super::target_backend::{backend_error, StreamSample, TargetBackend};
+
+// コマンド (XCP 1.x)
+const CMD_CONNECT: u8 = 0xFF;
+const CMD_DISCONNECT: u8 = 0xFE;
+const CMD_GET_STATUS: u8 = 0xFD;
+const CMD_SET_MTA: u8 = 0xF6;
+const CMD_SHORT_UPLOAD: u8 = 0xF4;
+const CMD_DOWNLOAD: u8 = 0xF0;
+const CMD_SET_DAQ_PTR: u8 = 0xE2;
+const CMD_WRITE_DAQ: u8 = 0xE1;
+const CMD_SET_DAQ_LIST_MODE: u8 = 0xE0;
+const CMD_START_STOP_DAQ_LIST: u8 = 0xDE;
+const CMD_START_STOP_SYNCH: u8 = 0xDD;
+const CMD_GET_DAQ_PROCESSOR_INFO: u8 = 0xDA;
+const CMD_GET_DAQ_RESOLUTION_INFO: u8 = 0xD9;
+const CMD_GET_DAQ_EVENT_INFO: u8 = 0xD7;
+const CMD_FREE_DAQ: u8 = 0xD6;
+const CMD_ALLOC_DAQ: u8 = 0xD5;
+const CMD_ALLOC_ODT: u8 = 0xD4;
+const CMD_ALLOC_ODT_ENTRY: u8 = 0xD3;
+
+// スレーブからのパケットID (0xFC未満はDTO)
+const PID_RESPONSE: u8 = 0xFF;
+const PID_ERROR: u8 = 0xFE;
+const PID_DTO_MAX: u8 = 0xFB;
+
+const RESOURCE_DAQ: u8 = 1 << 2;
+const COMM_MODE_MOTOROLA: u8 = 1 << 0;
+const COMM_MODE_GRANULARITY: u8 = 0b110;
+const DAQ_PROPERTY_DYNAMIC: u8 = 1 << 0;
+const DAQ_PROPERTY_TIMESTAMP: u8 = 1 << 4;
+const DAQ_KEY_IDENTIFICATION: u8 = 0b1100_0000;
+const DAQ_MODE_TIMESTAMP: u8 = 1 << 4;
+const TIMESTAMP_SIZE_MASK: u8 = 0b111;
+
+// XCP on Ethernetのヘッダ (LEN, CTR)
+const HEADER_LEN: usize = 4;
+// XCP on Ethernetで許されるMAX_CTO, MAX_DTOの最小値
+const MIN_PACKET_LEN: usize = 8;
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(2000);
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+// DAQが届いている間は生存確認のGET_STATUSを省く
+const ALIVE_TIMEOUT: Duration = Duration::from_millis(1000);
+// ----------------------------------------------------------------------------
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum XcpTransport {
+    Udp,
+    Tcp,
+}
+
+impl std::fmt::Display for XcpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_uppercase())
+    }
+}
+
+impl Default for XcpTransport {
+    fn default() -> Self {
+        Self::Udp
+    }
+}
+// ----------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct XcpSetting {
+    pub transport: XcpTransport,
+    /// host:port of the slave, 5555 by default.
+    pub address: String,
+    /// Address extension sent with every address.
+    pub address_extension: u8,
+    /// Measure the watch list with a DAQ list instead of polling it.
+    pub daq: bool,
+    /// Event channel the DAQ list is sampled on.
+    pub event_channel: u16,
+}
+
+impl Default for XcpSetting {
+    fn default() -> Self {
+        Self {
+            transport: Default::default(),
+            address: "localhost:5555".to_string(),
+            address_extension: 0,
+            daq: true,
+            event_channel: 0,
+        }
+    }
+}
+
+impl XcpSetting {
+    pub fn check(&self) -> Result<(), String> {
+        match self.address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+            _ => Err("set the slave address as host:port".to_string()),
+        }
+    }
+}
+// ----------------------------------------------------------------------------
+enum Link {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+/// One DAQ list holding the subscribed ranges, packed into ODTs in order.
+struct DaqLayout {
+    first_pid: u8,
+    /// Data bytes of every ODT.
+    odt_lengths: Vec<usize>,
+    timestamp_size: usize,
+    tick_ns: f64,
+}
+
+/// XCP on Ethernet master.
+pub struct XcpMaster {
+    link: Link,
+    address: String,
+    address_extension: u8,
+    daq_enable: bool,
+    event_channel: u16,
+    ctr: u16,
+    max_cto: usize,
+    max_dto: usize,
+    received: Vec<u8>,
+    daq: Option<DaqLayout>,
+    // 組み立て中のサンプル (次のODT番号, タイムスタンプ, データ)
+    pending: Option<(usize, u32, Vec<u8>)>,
+    samples: Vec<StreamSample>,
+    // タイムスタンプの前回値と積算時間[ns]
+    clock: Option<(u32, f64)>,
+    started: Instant,
+    last_received: Instant,
+}
+
+impl XcpMaster {
+    pub fn connect(setting: &XcpSetting) -> Result<Self, probe_rs::Error> {
+        setting.check().map_err(|e| backend_error(&e))?;
+        let socket = setting
+            .address
+            .to_socket_addrs()
+            .map_err(|e| backend_error(&format!("{}: {}", setting.address, e)))?
+            .next()
+            .ok_or_else(|| backend_error(&format!("{}: no address", setting.address)))?;
+        let link = match setting.transport {
+            XcpTransport::Tcp => {
+                let stream = TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT)
+                    .map_err(|e| backend_error(&format!("connect {}: {}", setting.address, e)))?;
+                let _res = stream.set_nodelay(true);
+                Link::Tcp(stream)
+            }
+            XcpTransport::Udp => {
+                let local = if socket.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let udp = UdpSocket::bind(local)
+                    .and_then(|udp| udp.connect(socket).map(|_| udp))
+                    .map_err(|e| backend_error(&format!("{}: {}", setting.address, e)))?;
+                Link::Udp(udp)
+            }
+        };
+
+        let mut xcp = Self {
+            link,
+            address: setting.address.clone(),
+            address_extension: setting.address_extension,
+            daq_enable: setting.daq,
+            event_channel: setting.event_channel,
+            ctr: 0,
+            // CONNECTの応答までは最小値
+            max_cto: MIN_PACKET_LEN,
+            max_dto: MIN_PACKET_LEN,
+            received: Vec::new(),
+            daq: None,
+            pending: None,
+            samples: Vec::new(),
+            clock: None,
+            started: Instant::now(),
+            last_received: Instant::now(),
+        };
+
+        let reply = xcp.command(&[CMD_CONNECT, 0x00])?;
+        let [resource, comm_mode, max_cto, dto_lo, dto_hi, ..] = reply[..] else {
+            return Err(xcp.error("short CONNECT response"));
+        };
+        if comm_mode & COMM_MODE_MOTOROLA != 0 {
+            return Err(xcp.error("Motorola byte order is not supported"));
+        }
+        if comm_mode & COMM_MODE_GRANULARITY != 0 {
+            return Err(xcp.error("only byte address granularity is supported"));
+        }
+        xcp.max_cto = max_cto as usize;
+        xcp.max_dto = u16::from_le_bytes([dto_lo, dto_hi]) as usize;
+        if xcp.max_cto < MIN_PACKET_LEN {
+            return Err(xcp.error("MAX_CTO is too short"));
+        }
+        if xcp.max_dto < MIN_PACKET_LEN {
+            return Err(xcp.error("MAX_DTO is too short"));
+        }
+        xcp.daq_enable &= resource & RESOURCE_DAQ != 0;
+
+        #[cfg(debug_assertions)]
+        println!(
+            "xcp {} : MAX_CTO {} MAX_DTO {} resource {:#04x}",
+            xcp.address, xcp.max_cto, xcp.max_dto, resource
+        );
+
+        Ok(xcp)
+    }
+
+    // ------------------------------------------------------------------------
+    fn error(&self, message: &str) -> probe_rs::Error {
+        backend_error(&format!("XCP {}: {}", self.address, message))
+    }
+
+    // 受信済みのバイト列に追加する。タイムアウトならfalse
+    fn fill(&mut self, deadline: Instant) -> Result<bool, probe_rs::Error> {
+        let timeout = deadline
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1));
+        let mut buff = [0u8; 65536];
+        let res = match &mut self.link {
+            Link::Tcp(stream) => stream
+                .set_read_timeout(Some(timeout))
+                .and_then(|_| stream.read(&mut buff)),
+            Link::Udp(udp) => udp
+                .set_read_timeout(Some(timeout))
+                .and_then(|_| udp.recv(&mut buff)),
+        };
+        match res {
+            Ok(0) if matches!(self.link, Link::Tcp(_)) => {
+                Err(self.error("the slave closed the connection"))
+            }
+            Ok(count) => {
+                self.received.extend_from_slice(&buff[..count]);
+                Ok(true)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                Ok(false)
+            }
+            Err(e) => Err(self.error(&e.to_string())),
+        }
+    }
+
+    fn send(&mut self, packet: &[u8]) -> Result<(), probe_rs::Error> {
+        self.ctr = self.ctr.wrapping_add(1);
+        let mut frame = Vec::with_capacity(HEADER_LEN + packet.len());
+        frame.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+        frame.extend_from_slice(&self.ctr.to_le_bytes());
+        frame.extend_from_slice(packet);
+        let res = match &mut self.link {
+            Link::Tcp(stream) => stream.write_all(&frame),
+            Link::Udp(udp) => udp.send(&frame).map(|_| ()),
+        };
+        res.map_err(|e| self.error(&e.to_string()))
+    }
+
+    fn take_packet(&mut self) -> Option<Vec<u8>> {
+        if self.received.len() < HEADER_LEN {
+            return None;
+        }
+        let len = u16::from_le_bytes([self.received[0], self.received[1]]) as usize;
+        if self.received.len() < HEADER_LEN + len {
+            return None;
+        }
+        let packet: Vec<u8> = self
+            .received
+            .drain(..HEADER_LEN + len)
+            .skip(HEADER_LEN)
+            .collect();
+        self.last_received = Instant::now();
+        Some(packet)
+    }
+
+    /// Sends a command and returns its positive response without the PID.
+    fn command(&mut self, packet: &[u8]) -> Result<Vec<u8>, probe_rs::Error> {
+        let code = packet[0];
+        self.send(packet)?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            while let Some(packet) = self.take_packet() {
+                match packet.first() {
+                    Some(&PID_RESPONSE) => return Ok(packet[1..].to_vec()),
+                    Some(&PID_ERROR) => {
+                        let error = packet.get(1).copied().unwrap_or(0);
+                        return Err(self.error(&format!(
+                            "command 0x{:02X} failed: {}",
+                            code,
+                            error_text(error)
+                        )));
+                    }
+                    Some(&pid) if pid <= PID_DTO_MAX => self.dto(&packet),
+                    // イベント、サービス要求は使わない
+                    _ => {}
+                }
+            }
+            if !self.fill(deadline)? && Instant::now() >= deadline {
+                return Err(self.error(&format!("no response to command 0x{code:02X}")));
+            }
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    fn dto(&mut self, packet: &[u8]) {
+        let Some(daq) = &self.daq else {
+            return;
+        };
+        let odt = packet[0].wrapping_sub(daq.first_pid) as usize;
+        let Some(&odt_len) = daq.odt_lengths.get(odt) else {
+            return;
+        };
+        let mut payload = &packet[1..];
+
+        if odt == 0 {
+            let mut timestamp = [0u8; 4];
+            if daq.timestamp_size > 0 {
+                let Some(raw) = payload.get(..daq.timestamp_size) else {
+                    return;
+                };
+                timestamp[..raw.len()].copy_from_slice(raw);
+                payload = &payload[daq.timestamp_size..];
+            }
+            self.pending = Some((0, u32::from_le_bytes(timestamp), Vec::new()));
+        }
+        // 途中のODTが抜けたサンプルは捨てる
+        let complete = match &mut self.pending {
+            Some((next, _, data)) if *next == odt && payload.len() >= odt_len => {
+                data.extend_from_slice(&payload[..odt_len]);
+                *next += 1;
+                *next == daq.odt_lengths.len()
+            }
+            _ => {
+                self.pending = None;
+                false
+            }
+        };
+        if complete {
+            let (_, timestamp, data) = self.pending.take().unwrap_or_default();
+            let time_ms = self.sample_time(timestamp);
+            self.samples.push(StreamSample { time_ms, data });
+        }
+    }
+
+    // スレーブのタイムスタンプを周回を考慮して積算する。無ければ受信時刻
+    fn sample_time(&mut self, timestamp: u32) -> u32 {
+        let Some(daq) = self.daq.as_ref().filter(|daq| daq.timestamp_size > 0) else {
+            return self.started.elapsed().as_millis() as u32;
+        };
+        let mask = if daq.timestamp_size >= 4 {
+            u32::MAX
+        } else {
+            (1u32 << (daq.timestamp_size * 8)) - 1
+        };
+        let tick_ns = daq.tick_ns;
+        let (last, total_ns) = self.clock.get_or_insert((timestamp, 0.0));
+        *total_ns += (timestamp.wrapping_sub(*last) & mask) as f64 * tick_ns;
+        *last = timestamp;
+        (*total_ns / 1e6) as u32
+    }
+
+    fn stop_daq(&mut self) -> Result<(), probe_rs::Error> {
+        if self.daq.take().is_some() {
+            self.command(&[CMD_START_STOP_SYNCH, 0x00])?;
+        }
+        self.pending = None;
+        self.clock = None;
+        Ok(())
+    }
+
+    fn address_bytes(&self, address: u64) -> [u8; 5] {
+        let [a0, a1, a2, a3] = (address as u32).to_le_bytes();
+        [self.address_extension, a0, a1, a2, a3]
+    }
+}
+
+impl TargetBackend for XcpMaster {
+    fn describe(&self) -> String {
+        format!("XCP slave {}", self.address)
+    }
+
+    fn poll_status(&mut self) -> Result<TargetStatus, probe_rs::Error> {
+        if self.daq.is_none() || self.last_received.elapsed() > ALIVE_TIMEOUT {
+            self.command(&[CMD_GET_STATUS])?;
+        }
+        Ok(TargetStatus {
+            state: CoreState::Running,
+            ..Default::default()
+        })
+    }
+
+    fn read_memory(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+        let chunk_len = (self.max_cto - 1).min(u8::MAX as usize);
+        for (index, chunk) in data.chunks_mut(chunk_len).enumerate() {
+            let chunk_address = address + (index * chunk_len) as u64;
+            let mut packet = vec![CMD_SHORT_UPLOAD, chunk.len() as u8, 0];
+            packet.extend_from_slice(&self.address_bytes(chunk_address));
+            let reply = self.command(&packet)?;
+            let bytes = reply
+                .get(..chunk.len())
+                .ok_or_else(|| self.error("short SHORT_UPLOAD response"))?;
+            chunk.copy_from_slice(bytes);
+        }
+        Ok(())
+    }
+
+    fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<(), probe_rs::Error> {
+        let mut packet = vec![CMD_SET_MTA, 0, 0];
+        packet.extend_from_slice(&self.address_bytes(address));
+        self.command(&packet)?;
+
+        // MTAは書き込んだ分だけ進む
+        for chunk in data.chunks((self.max_cto - 2).min(u8::MAX as usize)) {
+            let mut packet = vec![CMD_DOWNLOAD, chunk.len() as u8];
+            packet.extend_from_slice(chunk);
+            self.command(&packet)?;
+        }
+        Ok(())
+    }
+
+    fn subscribe(
+        &mut self,
+        ranges: &[(u64, usize)],
+        period_ms: u32,
+    ) -> Result<bool, probe_rs::Error> {
+        self.stop_daq()?;
+        if !self.daq_enable || ranges.is_empty() {
+            return Ok(false);
+        }
+
+        let info = self.command(&[CMD_GET_DAQ_PROCESSOR_INFO])?;
+        let [properties, _, _, _, _, _, key_byte, ..] = info[..] else {
+            return Err(self.error("short GET_DAQ_PROCESSOR_INFO response"));
+        };
+        if properties & DAQ_PROPERTY_DYNAMIC == 0 {
+            return Err(self.error("only dynamic DAQ configuration is supported, polling"));
+        }
+        if key_byte & DAQ_KEY_IDENTIFICATION != 0 {
+            return Err(self.error("only absolute ODT numbers are supported, polling"));
+        }
+
+        let resolution = self.command(&[CMD_GET_DAQ_RESOLUTION_INFO])?;
+        let [_, max_entry_size, _, _, timestamp_mode, ticks_lo, ticks_hi, ..] = resolution[..]
+        else {
+            return Err(self.error("short GET_DAQ_RESOLUTION_INFO response"));
+        };
+        let timestamp_size = if properties & DAQ_PROPERTY_TIMESTAMP != 0 {
+            (timestamp_mode & TIMESTAMP_SIZE_MASK) as usize
+        } else {
+            0
+        };
+        // 最初のODTにもPIDとタイムスタンプの後に1バイトは入らないと詰められない
+        if self.max_dto < 2 + timestamp_size {
+            return Err(self.error("MAX_DTO is too short for a DAQ list, polling"));
+        }
+        let tick_ns =
+            u16::from_le_bytes([ticks_lo, ticks_hi]) as f64 * time_unit_ns(timestamp_mode >> 4);
+
+        // イベントの周期から分周比を決める
+        let event = self.event_channel.to_le_bytes();
+        let prescaler = match self.command(&[CMD_GET_DAQ_EVENT_INFO, 0, event[0], event[1]]) {
+            Ok(reply) => match reply[..] {
+                [_, _, _, cycle, unit, ..] if cycle != 0 => {
+                    let cycle_ns = cycle as f64 * time_unit_ns(unit);
+                    (period_ms as f64 * 1e6 / cycle_ns)
+                        .round()
+                        .clamp(1.0, 255.0) as u8
+                }
+                _ => 1,
+            },
+            Err(_) => 1,
+        };
+
+        // 変数をODTに詰める (ODTエントリの最大長を超えるものは分ける)
+        let max_entry_size = (max_entry_size as usize).max(1);
+        let mut odts: Vec<Vec<(u64, usize)>> = vec![Vec::new()];
+        let mut used = timestamp_size;
+        for (address, len) in ranges {
+            let mut offset = 0;
+            while offset < *len {
+                let capacity = self.max_dto.saturating_sub(1 + used);
+                if capacity == 0 {
+                    odts.push(Vec::new());
+                    used = 0;
+                    continue;
+                }
+                let size = (len - offset).min(max_entry_size).min(capacity);
+                odts.last_mut()
+                    .unwrap()
+                    .push((address + offset as u64, size));
+                used += size;
+                offset += size;
+            }
+        }
+        if odts.len() > PID_DTO_MAX as usize + 1 || odts.iter().any(|odt| odt.len() > 255) {
+            return Err(self.error("the watch list is too long for a DAQ list, polling"));
+        }
+
+        self.command(&[CMD_FREE_DAQ])?;
+        self.command(&[CMD_ALLOC_DAQ, 0, 1, 0])?;
+        self.command(&[CMD_ALLOC_ODT, 0, 0, 0, odts.len() as u8])?;
+        for (odt, entries) in odts.iter().enumerate() {
+            self.command(&[CMD_ALLOC_ODT_ENTRY, 0, 0, 0, odt as u8, entries.len() as u8])?;
+        }
+        for (odt, entries) in odts.iter().enumerate() {
+            self.command(&[CMD_SET_DAQ_PTR, 0, 0, 0, odt as u8, 0])?;
+            for (address, size) in entries {
+                let mut packet = vec![CMD_WRITE_DAQ, 0xFF, *size as u8];
+                packet.extend_from_slice(&self.address_bytes(*address));
+                self.command(&packet)?;
+            }
+        }
+        let mode = if timestamp_size > 0 {
+            DAQ_MODE_TIMESTAMP
+        } else {
+            0
+        };
+        self.command(&[
+            CMD_SET_DAQ_LIST_MODE,
+            mode,
+            0,
+            0,
+            event[0],
+            event[1],
+            prescaler,
+            0,
+        ])?;
+        let reply = self.command(&[CMD_START_STOP_DAQ_LIST, 0x02, 0, 0])?;
+        let first_pid = reply.first().copied().unwrap_or(0);
+
+        self.daq = Some(DaqLayout {
+            first_pid,
+            odt_lengths: odts
+                .iter()
+                .map(|odt| odt.iter().map(|(_, size)| size).sum())
+                .collect(),
+            timestamp_size,
+            tick_ns,
+        });
+        self.command(&[CMD_START_STOP_SYNCH, 0x01])?;
+        Ok(true)
+    }
+
+    fn take_samples(&mut self) -> Result<Vec<StreamSample>, probe_rs::Error> {
+        // 届いている分だけ読む
+        while self.fill(Instant::now())? {}
+        while let Some(packet) = self.take_packet() {
+            if packet.first().is_some_and(|pid| *pid <= PID_DTO_MAX) {
+                self.dto(&packet);
+            }
+        }
+        Ok(std::mem::take(&mut self.samples))
+    }
+}
+
+impl Drop for XcpMaster {
+    fn drop(&mut self) {
+        let _res = self.stop_daq();
+        let _res = self.send(&[CMD_DISCONNECT]);
+    }
+}
+
+/// Unit of timestamps and event cycles (`DAQ_TIMESTAMP_UNIT`) in ns.
+fn time_unit_ns(unit: u8) -> f64 {
+    match unit {
+        0..=9 => 10f64.powi(unit as i32),
+        // 1ps, 10ps, 100ps
+        0xA..=0xC => 10f64.powi(unit as i32 - 0xA - 3),
+        _ => 1.0,
+    }
+}
+
+fn error_text(code: u8) -> String {
+    match code {
+        0x00 => "command processor synchronization".to_string(),
+        0x10 => "busy".to_string(),
+        0x11 => "DAQ is running".to_string(),
+        0x12 => "program is running".to_string(),
+        0x20 => "unknown command".to_string(),
+        0x21 => "syntax error".to_string(),
+        0x22 => "out of range".to_string(),
+        0x23 => "write protected".to_string(),
+        0x24 => "access denied".to_string(),
+        0x25 => "access locked (seed & key)".to_string(),
+        0x29 => "sequence error".to_string(),
+        0x30 => "memory overflow".to_string(),
+        code => format!("0x{code:02X}"),
+    }
+}
+// ----------------------------------------------------------------------------
+#[cfg(test)]
+#[path = "../bin/xcp_slave/slave.rs"]
+#[allow(dead_code)]
+mod xcp_slave;
+
+#[cfg(test)]
+mod tests {
+    use super::xcp_slave::{self, Slave, Transport};
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    const RAM_BASE: u64 = 0x2000_0000;
+    const RAM_SIZE: usize = 0x1000;
+
+    /// src/bin/xcp_slave on a loopback port, stopped on drop.
+    struct TestSlave {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+        setting: XcpSetting,
+    }
+
+    impl TestSlave {
+        fn start(transport: XcpTransport) -> Self {
+            let mut server = Transport::bind(
+                transport == XcpTransport::Tcp,
+                SocketAddr::from(([127, 0, 0, 1], 0)),
+            )
+            .unwrap();
+            let address = server.local_addr().unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+            let flag = Arc::clone(&stop);
+            let thread = std::thread::spawn(move || {
+                let mut slave = Slave::new(RAM_BASE as u32, RAM_SIZE);
+                xcp_slave::serve(
+                    &mut slave,
+                    &mut server,
+                    |_| {},
+                    || flag.load(Ordering::Relaxed),
+                )
+                .unwrap();
+            });
+            Self {
+                stop,
+                thread: Some(thread),
+                setting: XcpSetting {
+                    transport,
+                    address: address.to_string(),
+                    ..Default::default()
+                },
+            }
+        }
+    }
+
+    impl Drop for TestSlave {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _res = thread.join();
+            }
+        }
+    }
+
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(7) ^ seed).collect()
+    }
+
+    #[test]
+    fn connects_over_udp_and_tcp() {
+        for transport in [XcpTransport::Udp, XcpTransport::Tcp] {
+            let slave = TestSlave::start(transport);
+            let mut xcp = XcpMaster::connect(&slave.setting).unwrap();
+            assert_eq!(xcp.max_cto, 64, "{transport}");
+            assert_eq!(xcp.max_dto, 256, "{transport}");
+            assert!(xcp.poll_status().is_ok(), "{transport}");
+        }
+    }
+
+    #[test]
+    fn downloads_and_uploads_across_chunks() {
+        let slave = TestSlave::start(XcpTransport::Udp);
+        let mut xcp = XcpMaster::connect(&slave.setting).unwrap();
+
+        // DOWNLOADは62バイト、SHORT_UPLOADは63バイトずつに分かれる
+        let data = pattern(200, 0x5A);
+        xcp.write_memory(RAM_BASE + 0x10, &data).unwrap();
+        let mut read = vec![0u8; data.len()];
+        xcp.read_memory(RAM_BASE + 0x10, &mut read).unwrap();
+        assert_eq!(read, data);
+
+        let mut word = [0u8; 4];
+        xcp.read_memory(RAM_BASE + 0x11, &mut word).unwrap();
+        assert_eq!(word[..], data[1..5]);
+
+        let error = xcp.read_memory(RAM_BASE + RAM_SIZE as u64, &mut word);
+        assert!(error.unwrap_err().to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn measures_with_a_daq_list() {
+        let slave = TestSlave::start(XcpTransport::Udp);
+        let mut xcp = XcpMaster::connect(&slave.setting).unwrap();
+
+        // 2つ目は1つのODTにもODTエントリにも収まらない長さ
+        let first = pattern(4, 0x11);
+        let second = pattern(600, 0x22);
+        xcp.write_memory(RAM_BASE, &first).unwrap();
+        xcp.write_memory(RAM_BASE + 0x100, &second).unwrap();
+        assert!(xcp
+            .subscribe(&[(RAM_BASE, 4), (RAM_BASE + 0x100, 600)], 1)
+            .unwrap());
+        let daq = xcp.daq.as_ref().unwrap();
+        assert_eq!(daq.timestamp_size, 4);
+        assert_eq!(daq.odt_lengths.iter().sum::<usize>(), 604);
+        assert!(daq.odt_lengths.len() >= 3);
+
+        let mut samples = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while samples.len() < 5 && Instant::now() < deadline {
+            samples.extend(xcp.take_samples().unwrap());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert!(samples.len() >= 5, "{} samples", samples.len());
+        let expected = [first, second].concat();
+        for sample in &samples {
+            assert_eq!(sample.data, expected);
+        }
+        assert!(samples
+            .windows(2)
+            .all(|pair| pair[0].time_ms <= pair[1].time_ms));
+
+        // 空の監視リストで止める
+        assert!(!xcp.subscribe(&[], 1).unwrap());
+        assert!(xcp.daq.is_none());
+    }
+
+    #[test]
+    fn rejects_a_short_max_dto() {
+        // CONNECTにMAX_DTO = 1で応答するだけのスレーブ
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let thread = std::thread::spawn(move || {
+            let mut buff = [0u8; 64];
+            let (_, master) = socket.recv_from(&mut buff).unwrap();
+            let response = [0xFF, RESOURCE_DAQ, 0x00, 8, 1, 0, 1, 1];
+            let mut frame = (response.len() as u16).to_le_bytes().to_vec();
+            frame.extend_from_slice(&[0, 0]);
+            frame.extend_from_slice(&response);
+            socket.send_to(&frame, master).unwrap();
+        });
+
+        let setting = XcpSetting {
+            address: address.to_string(),
+            ..Default::default()
+        };
+        let error = XcpMaster::connect(&setting).err().unwrap();
+        assert!(error.to_string().contains("MAX_DTO is too short"));
+        thread.join().unwrap();
+    }
+}
@@ -10,6 +10,12 @@ use visumodiram::VisuModiRam;
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+
+    // `VisuModiRam export ...` は画面を出さずに記録を書き出す
+    if std::env::args().nth(1).as_deref() == Some("export") {
+        std::process::exit(debugging_tools::export_command(std::env::args().skip(2)));
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1280.0, 720.0])
@@ -6,7 +6,13 @@ use super::{
     widgets::{self, WidgetApp},
     WidgetWindow,
 };
-use crate::debugging_tools::ProbeInterface;
+use rfd::FileDialog;
+
+use crate::debugging_tools::{
+    ConnectionState, CoreState, ExportFormat, ExportSetting, ImportedPanel, ImportedProject,
+    ItmValueFormat, PanelKind, ProbeInterface, RunCommand, SessionEventKind, SessionHealth,
+    TimeBase, WatchpointKind, DWT_DATA_TRACE_MAX,
+};
 
 #[derive(Default)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
@@ -19,6 +25,13 @@ pub struct MainMonitorTab {
 
     hide_title_bar: bool,
     move_and_resize_lock: bool,
+    // 取り込んだプロジェクトのウィンドウ (監視リストが決まってから開く)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    import_panels: Vec<ImportedPanel>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    export: ExportSetting,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    export_message: Option<Result<String, String>>,
 
     pub probe_if: ProbeInterface,
 }
@@ -29,6 +42,8 @@ impl eframe::App for MainMonitorTab {
             ctx.request_repaint();
         }
 
+        self.session_health_ui(ctx);
+
         egui::SidePanel::left("control")
             .resizable(true)
             .default_width(150.0)
@@ -58,6 +73,32 @@ impl eframe::App for MainMonitorTab {
                                     );
                                 });
                             });
+                            ui.add_enabled_ui(!self.probe_if.now_watching(), |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.checkbox(&mut self.probe_if.options.rtt_enable, "RTT")
+                                        .on_hover_text("read SEGGER RTT channels while watching");
+                                    ui.add_enabled(
+                                        self.probe_if.options.rtt_enable,
+                                        egui::Checkbox::new(
+                                            &mut self.probe_if.options.defmt_enable,
+                                            "defmt",
+                                        ),
+                                    )
+                                    .on_hover_text("decode the defmt channel with the ELF");
+                                });
+                                ui.checkbox(
+                                    &mut self.probe_if.options.semihosting_enable,
+                                    "semihosting",
+                                )
+                                .on_hover_text("service BKPT 0xAB calls and resume the target");
+                                ui.checkbox(&mut self.probe_if.options.fault_detect, "fault detect")
+                                    .on_hover_text(
+                                        "halt and analyze when a fault handler runs or the core locks up",
+                                    );
+                                self.swo_setting_ui(ui);
+                                self.stack_setting_ui(ui);
+                                self.reconnect_setting_ui(ui);
+                            });
                             if ui.button("watch start").clicked() {
                                 for wid in &mut self.widgets {
                                     wid.set_probe_to_app(self.probe_if.clone());
@@ -70,6 +111,9 @@ impl eframe::App for MainMonitorTab {
                             if ui.button("stop").clicked() {
                                 self.probe_if.watching_stop();
                             }
+
+                            ui.separator();
+                            self.run_control_ui(ui);
                         });
                         strip.cell(|ui| {
                             ui.separator();
@@ -117,6 +161,55 @@ impl eframe::App for MainMonitorTab {
                         Box::new(widgets::Gauges::default()),
                     );
                     // ----------------------------------------------------------------------------
+                    self.add_widget_botton(
+                        ui,
+                        "Hex dump",
+                        "hex_dump",
+                        Box::new(widgets::HexDump::default()),
+                    );
+                    // ----------------------------------------------------------------------------
+                    self.add_widget_botton(
+                        ui,
+                        "RAM snapshot",
+                        "ram_snapshot",
+                        Box::new(widgets::RamSnapshotView::default()),
+                    );
+                    // ----------------------------------------------------------------------------
+                    self.add_widget_botton(
+                        ui,
+                        "RTT Terminal",
+                        "rtt_terminal",
+                        Box::new(widgets::RttTerminal::default()),
+                    );
+                    // ----------------------------------------------------------------------------
+                    self.add_widget_botton(
+                        ui,
+                        "Semihosting",
+                        "semihosting",
+                        Box::new(widgets::SemihostingConsole::default()),
+                    );
+                    // ----------------------------------------------------------------------------
+                    self.add_widget_botton(
+                        ui,
+                        "Write History",
+                        "write_history",
+                        Box::new(widgets::WriteHistoryView::default()),
+                    );
+                    // ----------------------------------------------------------------------------
+                    self.add_widget_botton(
+                        ui,
+                        "Fault Report",
+                        "fault_report",
+                        Box::new(widgets::FaultReportView::default()),
+                    );
+                    // ----------------------------------------------------------------------------
+                    self.add_widget_botton(
+                        ui,
+                        "Call Stack",
+                        "call_stack",
+                        Box::new(widgets::CallStackView::default()),
+                    );
+                    // ----------------------------------------------------------------------------
 
                     ui.separator();
                     ui.strong("Editor");
@@ -149,8 +242,9 @@ impl eframe::App for MainMonitorTab {
                     );
                     // ----------------------------------------------------------------------------
 
+                    self.open_imported_panels();
                     for wid in &mut self.widgets {
-                        wid.fetch_watch_list(&self.probe_if.setting.watch_list);
+                        wid.fetch_watch_list(&self.probe_if.get_channel_list());
                     }
 
                     ui.separator();
@@ -248,7 +342,10 @@ impl eframe::App for MainMonitorTab {
             ui.heading("main panel");
         });
 
+        let target_halted = self.probe_if.get_target_status().is_halted();
         for app in &mut self.widgets {
+            app.set_target_halted(target_halted);
+
             let mut open = true;
             app.update(ctx, frame, &mut open);
 
@@ -262,6 +359,385 @@ impl eframe::App for MainMonitorTab {
 }
 
 impl MainMonitorTab {
+    fn run_control_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("run control");
+
+        let status = self.probe_if.get_target_status();
+        let now_watching = self.probe_if.now_watching();
+
+        ui.add_enabled_ui(now_watching, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let halted = status.state == CoreState::Halted;
+                if ui
+                    .add_enabled(!halted, egui::Button::new("⏸ Halt"))
+                    .clicked()
+                {
+                    self.probe_if.insert_run_command(RunCommand::Halt);
+                }
+                if ui.add_enabled(halted, egui::Button::new("▶ Run")).clicked() {
+                    self.probe_if.insert_run_command(RunCommand::Run);
+                }
+                if ui
+                    .add_enabled(halted, egui::Button::new("⏭ Step"))
+                    .clicked()
+                {
+                    self.probe_if.insert_run_command(RunCommand::Step);
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                if ui.button("Reset").clicked() {
+                    self.probe_if.insert_run_command(RunCommand::Reset);
+                }
+                if ui.button("Reset & Halt").clicked() {
+                    self.probe_if.insert_run_command(RunCommand::ResetAndHalt);
+                }
+            });
+        });
+
+        let color = match status.state {
+            CoreState::Running => Color32::GREEN,
+            CoreState::Halted | CoreState::Sleeping => Color32::YELLOW,
+            CoreState::LockedUp => Color32::RED,
+            CoreState::Unknown => Color32::GRAY,
+        };
+        ui.horizontal(|ui| {
+            ui.label("status :");
+            if let Some(reason) = status.halt_reason {
+                ui.colored_label(color, format!("{} ({})", status.state, reason));
+            } else {
+                ui.colored_label(color, status.state.to_string());
+            }
+        });
+
+        if let Some(pc) = status.pc {
+            ui.label(format!("PC : 0x{:08x}", pc));
+            if let Some(location) = &status.location {
+                ui.label(format!("in {}", location.function))
+                    .on_hover_text(location.to_string());
+                if let Some(line) = location.line {
+                    let file_name = location.file.rsplit(['/', '\\']).next().unwrap_or("");
+                    ui.label(format!("at {}:{}", file_name, line));
+                }
+            }
+        }
+
+        if let Some(report) = self.probe_if.get_fault_report() {
+            let place = match &report.location {
+                Some(location) => location.to_string(),
+                None => report.frame.map_or("unknown PC".to_string(), |frame| {
+                    format!("0x{:08X}", frame.pc)
+                }),
+            };
+            ui.colored_label(Color32::RED, format!("⚠ {}", report.kind))
+                .on_hover_text(report.to_string());
+            ui.label(place);
+        }
+
+        self.stack_usage_ui(ui);
+    }
+
+    fn stack_usage_ui(&mut self, ui: &mut egui::Ui) {
+        let stacks = self.probe_if.get_stack_usage();
+        if stacks.is_empty() {
+            return;
+        }
+        let warning = self.probe_if.options.stack.warning_percent;
+
+        ui.separator();
+        ui.label("stack high water :");
+        for usage in stacks {
+            let res = if let Some(error) = &usage.error {
+                ui.colored_label(Color32::RED, format!("{} : {}", usage.name, error))
+            } else if !usage.painted {
+                ui.colored_label(Color32::GRAY, format!("{} : not painted", usage.name))
+                    .on_hover_text("Reset & Halt to paint the stack")
+            } else {
+                let color = if usage.percent() >= warning {
+                    Color32::RED
+                } else {
+                    Color32::DARK_GREEN
+                };
+                ui.add(
+                    egui::ProgressBar::new((usage.percent() / 100.0) as f32)
+                        .fill(color)
+                        .text(format!(
+                            "{} {} / {} B",
+                            usage.name,
+                            usage.high_water,
+                            usage.size()
+                        )),
+                )
+            };
+            res.on_hover_text(format!(
+                "0x{:08X} .. 0x{:08X}",
+                usage.range.start, usage.range.end
+            ));
+        }
+    }
+
+    fn stack_setting_ui(&mut self, ui: &mut egui::Ui) {
+        let stack = &mut self.probe_if.options.stack;
+        ui.checkbox(&mut stack.enable, "stack usage")
+            .on_hover_text("paint the stack and track its high water mark");
+        if !stack.enable {
+            return;
+        }
+
+        ui.checkbox(&mut stack.use_linker_symbols, "_estack / _Min_Stack_Size")
+            .on_hover_text("take the main stack from the linker symbols of the ELF");
+        egui::Grid::new("stack_setting")
+            .num_columns(2)
+            .spacing([5.0, 4.0])
+            .show(ui, |ui| {
+                if !stack.use_linker_symbols {
+                    ui.label("top :");
+                    let mut top = format!("0x{:08X}", stack.top);
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut top).desired_width(90.))
+                        .on_hover_text("initial SP (end of the stack)")
+                        .changed()
+                    {
+                        if let Ok(value) = u64::from_str_radix(top.trim_start_matches("0x"), 16) {
+                            stack.top = value;
+                        }
+                    }
+                    ui.end_row();
+
+                    ui.label("size :");
+                    ui.add(
+                        egui::DragValue::new(&mut stack.size)
+                            .suffix("[byte]")
+                            .clamp_range(4..=0x10_0000)
+                            .speed(4),
+                    );
+                    ui.end_row();
+                }
+
+                ui.label("scan :");
+                ui.add(
+                    egui::DragValue::new(&mut stack.scan_interval_ms)
+                        .suffix("[ms]")
+                        .clamp_range(10..=10_000)
+                        .speed(10),
+                );
+                ui.end_row();
+
+                ui.label("warning :");
+                ui.add(
+                    egui::DragValue::new(&mut stack.warning_percent)
+                        .suffix("[%]")
+                        .clamp_range(1.0..=100.0),
+                );
+                ui.end_row();
+            });
+        ui.checkbox(&mut stack.paint_on_reset, "paint on Reset & Halt");
+
+        ui.label("task stacks :")
+            .on_hover_text("static stack arrays filled with 0xA5 by the RTOS");
+        let mut remove = None;
+        for (i, name) in stack.task_stacks.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                if ui.button("x").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            stack.task_stacks.remove(i);
+        }
+        let id = ui.id().with("new_task_stack");
+        let mut new_name = ui.data_mut(|d| d.get_temp::<String>(id).unwrap_or_default());
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut new_name).desired_width(90.));
+            if ui.button("add").clicked() && !new_name.is_empty() {
+                stack.task_stacks.push(std::mem::take(&mut new_name));
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(id, new_name));
+    }
+
+    fn reconnect_setting_ui(&mut self, ui: &mut egui::Ui) {
+        let reconnect = &mut self.probe_if.options.reconnect;
+        ui.checkbox(&mut reconnect.enable, "auto reconnect")
+            .on_hover_text("attach again when the probe or the target stops answering");
+        if !reconnect.enable {
+            return;
+        }
+
+        egui::Grid::new("reconnect_setting")
+            .num_columns(2)
+            .spacing([5.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("first retry :");
+                ui.add(
+                    egui::DragValue::new(&mut reconnect.initial_delay_ms)
+                        .suffix("[ms]")
+                        .clamp_range(50..=60_000)
+                        .speed(10),
+                );
+                ui.end_row();
+
+                ui.label("max delay :");
+                ui.add(
+                    egui::DragValue::new(&mut reconnect.max_delay_ms)
+                        .suffix("[ms]")
+                        .clamp_range(50..=600_000)
+                        .speed(100),
+                )
+                .on_hover_text("the delay doubles after every failed attempt");
+                ui.end_row();
+
+                ui.label("attempts :");
+                ui.add(egui::DragValue::new(&mut reconnect.max_attempts).clamp_range(0..=1000))
+                    .on_hover_text("0 retries until stopped");
+                ui.end_row();
+            });
+    }
+
+    // 接続状態のバナー (接続中で問題が無いターゲットは出さない)
+    fn session_health_ui(&mut self, ctx: &egui::Context) {
+        let now_time = self.probe_if.get_elapsed_ms();
+        let banners: Vec<_> = self
+            .probe_if
+            .get_targets_health()
+            .into_iter()
+            .filter_map(|(name, health)| {
+                Self::health_banner(&health, now_time).map(|banner| (name, health, banner))
+            })
+            .collect();
+        if banners.is_empty() {
+            return;
+        }
+
+        egui::TopBottomPanel::top("session_health").show(ctx, |ui| {
+            for (name, health, (color, text)) in banners {
+                ui.horizontal(|ui| {
+                    if !name.is_empty() {
+                        ui.strong(format!("{} :", name));
+                    }
+                    ui.colored_label(color, egui::RichText::new(text).strong());
+                    if let Some(error) = &health.error {
+                        ui.separator();
+                        ui.label(error);
+                    }
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(format!(
+                            "{} since {:.1}s",
+                            health.state,
+                            health.since_ms as f64 / 1000.0
+                        ));
+                    });
+                });
+            }
+        });
+    }
+
+    fn health_banner(health: &SessionHealth, now_time: i64) -> Option<(Color32, String)> {
+        let banner = match health.state {
+            ConnectionState::Connected if health.gaps == 0 => return None,
+            ConnectionState::Stopped if health.error.is_none() => return None,
+            ConnectionState::Connected => (
+                Color32::LIGHT_GREEN,
+                format!("connected, lost {} times during this session", health.gaps),
+            ),
+            ConnectionState::Connecting => (Color32::LIGHT_BLUE, "connecting...".to_string()),
+            ConnectionState::Lost => (Color32::RED, "connection lost".to_string()),
+            ConnectionState::Reconnecting => {
+                let retry = health.next_retry_ms.map_or(String::new(), |t| {
+                    format!(", next in {:.1}s", (t - now_time).max(0) as f64 / 1000.0)
+                });
+                (
+                    Color32::YELLOW,
+                    format!(
+                        "connection lost, reconnecting (attempt {}{})",
+                        health.attempts, retry
+                    ),
+                )
+            }
+            ConnectionState::Stopped => (Color32::RED, "stopped".to_string()),
+        };
+        Some(banner)
+    }
+
+    fn swo_setting_ui(&mut self, ui: &mut egui::Ui) {
+        let swo = &mut self.probe_if.options.swo;
+        ui.checkbox(&mut swo.enable, "SWO trace")
+            .on_hover_text("stream ITM ports and DWT data trace over SWO");
+        if !swo.enable {
+            return;
+        }
+
+        egui::Grid::new("swo_setting")
+            .num_columns(2)
+            .spacing([5.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("core clock :");
+                let mut clock_mhz = swo.core_clock_hz as f64 / 1_000_000.0;
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut clock_mhz)
+                            .suffix("[MHz]")
+                            .clamp_range(1.0..=1000.0),
+                    )
+                    .changed()
+                {
+                    swo.core_clock_hz = (clock_mhz * 1_000_000.0) as u32;
+                }
+                ui.end_row();
+
+                ui.label("baud :");
+                ui.add(
+                    egui::DragValue::new(&mut swo.baud)
+                        .clamp_range(9600..=100_000_000)
+                        .speed(1000),
+                );
+                ui.end_row();
+
+                ui.label("ITM ports :");
+                let mut mask = format!("0x{:08X}", swo.stimulus_ports);
+                if ui
+                    .add(egui::TextEdit::singleline(&mut mask).desired_width(90.))
+                    .on_hover_text("bit mask of the stimulus ports")
+                    .changed()
+                {
+                    if let Ok(value) = u32::from_str_radix(mask.trim_start_matches("0x"), 16) {
+                        swo.stimulus_ports = value;
+                    }
+                }
+                ui.end_row();
+
+                ui.label("port value :");
+                egui::ComboBox::from_id_source("itm_port_format")
+                    .selected_text(swo.port_format.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in [
+                            ItmValueFormat::Unsigned,
+                            ItmValueFormat::Signed,
+                            ItmValueFormat::Float,
+                        ] {
+                            ui.selectable_value(&mut swo.port_format, format, format.to_string());
+                        }
+                    });
+                ui.end_row();
+            });
+        ui.checkbox(&mut swo.timestamps, "local timestamps");
+
+        if ui
+            .button("Replay trace file...")
+            .on_hover_text("decode a raw SWO capture (file or named pipe) without a probe")
+            .clicked()
+        {
+            if let Some(path) = FileDialog::new().pick_file() {
+                for wid in &mut self.widgets {
+                    wid.set_probe_to_app(self.probe_if.clone());
+                    wid.switch_tab_to(super::widgets::Anchor::MonitorTab);
+                }
+                self.probe_if.trace_replay_start(path);
+            }
+        }
+    }
+
     fn watch_setting_ui(&mut self, ui: &mut egui::Ui) {
         ui.heading("watch settings...");
 
@@ -279,11 +755,257 @@ impl MainMonitorTab {
             .striped(true)
             .show(ui, |ui| {
                 let watch_list = self.probe_if.setting.watch_list.clone();
+                let watchpoints = self.probe_if.get_watchpoints();
                 for val in watch_list {
-                    ui.label(&val.name);
+                    let armed = watchpoints.iter().any(|w| w.symbol.name == val.name);
+                    let traced = self.probe_if.options.swo.data_trace.contains(&val.name);
+                    let mut text = val.name.clone();
+                    if armed {
+                        text = format!("👁 {}", text);
+                    }
+                    if traced {
+                        text = format!("⚡ {}", text);
+                    }
+                    ui.label(text).context_menu(|ui| {
+                        ui.label("data watchpoint");
+                        for kind in [
+                            WatchpointKind::Read,
+                            WatchpointKind::Write,
+                            WatchpointKind::Access,
+                        ] {
+                            if ui.button(kind.to_string()).clicked() {
+                                self.probe_if.set_watchpoint(&val, kind, false);
+                                ui.close_menu();
+                            }
+                        }
+                        if armed && ui.button("Remove").clicked() {
+                            self.probe_if.remove_watchpoint(&val.name);
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        let data_trace = &mut self.probe_if.options.swo.data_trace;
+                        let mut checked = traced;
+                        if ui
+                            .add_enabled(
+                                traced || data_trace.len() < DWT_DATA_TRACE_MAX,
+                                egui::Checkbox::new(&mut checked, "SWO data trace"),
+                            )
+                            .on_hover_text("stream every write with DWT instead of polling")
+                            .changed()
+                        {
+                            if checked {
+                                data_trace.push(val.name.clone());
+                            } else {
+                                data_trace.retain(|name| name != &val.name);
+                            }
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+
+        let defmt_channels = self.probe_if.get_defmt_channels();
+        if !defmt_channels.is_empty() {
+            ui.separator();
+            ui.heading("defmt channels");
+            egui::Grid::new("defmt_channel_list")
+                .num_columns(2)
+                .spacing([5.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for channel in defmt_channels {
+                        ui.label(&channel.name).on_hover_text(format!(
+                            "arg {} of \"{}\"",
+                            channel.arg, channel.template
+                        ));
+                        if ui.button("x").clicked() {
+                            self.probe_if.remove_defmt_channel(&channel.name);
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+
+        ui.separator();
+        self.watchpoint_ui(ui);
+
+        ui.separator();
+        self.session_export_ui(ui);
+
+        let events = self.probe_if.get_session_events();
+        if !events.is_empty() {
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.heading("session events");
+                if ui.button("clear").clicked() {
+                    self.probe_if.clear_session_events();
+                }
+            });
+            for event in events.iter().rev().take(10) {
+                let color = match event.kind {
+                    SessionEventKind::Info => Color32::LIGHT_BLUE,
+                    SessionEventKind::Warning => Color32::YELLOW,
+                    SessionEventKind::Error => Color32::RED,
+                };
+                ui.colored_label(
+                    color,
+                    format!("{:>8.3}s {}", event.time_ms as f64 / 1000.0, event.message),
+                );
+            }
+        }
+    }
+
+    fn session_export_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("export recording");
+
+        let export = &mut self.export;
+        egui::Grid::new("session_export")
+            .num_columns(2)
+            .spacing([5.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("format :");
+                egui::ComboBox::from_id_source("export_format")
+                    .selected_text(export.format.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in [
+                            ExportFormat::CsvWide,
+                            ExportFormat::CsvLong,
+                            ExportFormat::Parquet,
+                            ExportFormat::Mdf4,
+                        ] {
+                            ui.selectable_value(&mut export.format, format, format.to_string());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("time :");
+                ui.horizontal(|ui| {
+                    for time_base in [TimeBase::Relative, TimeBase::WallClock] {
+                        ui.radio_value(&mut export.time_base, time_base, time_base.to_string());
+                    }
+                });
+                ui.end_row();
+
+                ui.label("from :");
+                ui.add(
+                    egui::DragValue::new(&mut export.start_s)
+                        .suffix("[s]")
+                        .clamp_range(0.0..=f64::MAX)
+                        .speed(0.1),
+                );
+                ui.end_row();
+
+                ui.label("to :");
+                ui.horizontal(|ui| {
+                    let mut until_end = export.end_s.is_none();
+                    if ui.checkbox(&mut until_end, "end").changed() {
+                        export.end_s = (!until_end).then_some(export.start_s + 10.0);
+                    }
+                    if let Some(end_s) = &mut export.end_s {
+                        ui.add(
+                            egui::DragValue::new(end_s)
+                                .suffix("[s]")
+                                .clamp_range(0.0..=f64::MAX)
+                                .speed(0.1),
+                        );
+                    }
+                });
+                ui.end_row();
+            });
+
+        // 空のリストは全チャンネル
+        let channels = self.probe_if.get_recorded_channels();
+        ui.horizontal_wrapped(|ui| {
+            ui.label("channels :");
+            for name in &channels {
+                let mut checked = export.channels.is_empty() || export.channels.contains(name);
+                if ui.checkbox(&mut checked, name).changed() {
+                    if export.channels.is_empty() {
+                        export.channels = channels.clone();
+                    }
+                    export.channels.retain(|channel| channel != name);
+                    if checked {
+                        export.channels.push(name.clone());
+                    }
+                    if channels.iter().all(|c| export.channels.contains(c)) {
+                        export.channels.clear();
+                    }
+                }
+            }
+        });
+
+        if let Err(e) = export.check() {
+            ui.label(egui::RichText::new(e).color(Color32::RED));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if ui
+            .add_enabled(export.check().is_ok(), egui::Button::new("export…"))
+            .on_hover_text("also `VisuModiRam export --help` from a shell")
+            .clicked()
+        {
+            let extension = export.format.extension();
+            if let Some(path) = FileDialog::new()
+                .add_filter(&export.format.to_string(), &[extension])
+                .set_file_name(format!("recording.{}", extension))
+                .save_file()
+            {
+                let setting = export.clone();
+                self.export_message = Some(self.probe_if.export_recording(&setting, &path));
+            }
+        }
+        match &self.export_message {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(message)) => {
+                ui.label(egui::RichText::new(message).color(Color32::RED));
+            }
+            None => {}
+        }
+    }
+
+    fn watchpoint_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("watchpoints");
+        ui.label("right click a symbol in the watch list to arm");
+
+        egui::Grid::new("watchpoint_list")
+            .num_columns(4)
+            .spacing([5.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for watchpoint in self.probe_if.get_watchpoints() {
+                    ui.label(&watchpoint.symbol.name);
+                    ui.label(watchpoint.kind.to_string());
+                    let mut auto_resume = watchpoint.auto_resume;
+                    if ui
+                        .checkbox(&mut auto_resume, "auto resume")
+                        .on_hover_text("log the access and keep the target running")
+                        .changed()
+                    {
+                        self.probe_if.set_watchpoint(
+                            &watchpoint.symbol,
+                            watchpoint.kind,
+                            auto_resume,
+                        );
+                    }
+                    if ui.button("x").clicked() {
+                        self.probe_if.remove_watchpoint(&watchpoint.symbol.name);
+                    }
                     ui.end_row();
                 }
             });
+
+        let hits = self.probe_if.get_watchpoint_hits();
+        ui.horizontal(|ui| {
+            ui.label(format!("hits : {}", hits.len()));
+            if ui.button("clear").clicked() {
+                self.probe_if.clear_watchpoint_hits();
+            }
+        });
+        for hit in hits.iter().rev().take(10) {
+            ui.label(format!("{:>8.3}s {}", hit.time_ms as f64 / 1000.0, hit))
+                .on_hover_text(hit.to_string());
+        }
     }
 
     fn add_widget_botton(
@@ -294,16 +1016,49 @@ impl MainMonitorTab {
         widget: Box<dyn WidgetApp>,
     ) {
         if ui.button(text).clicked() {
-            self.window_cnt += 1;
-            let widget_window = WidgetWindow::new(
-                self.window_cnt,
-                format!("{}_{}", self.window_cnt, title),
-                !self.hide_title_bar,
-                self.move_and_resize_lock,
-                widget,
-            );
-
-            self.widgets.push(Box::new(widget_window));
+            self.push_widget(title, widget);
+        }
+    }
+
+    fn push_widget(&mut self, title: &str, widget: Box<dyn WidgetApp>) -> &mut WidgetWindow {
+        self.window_cnt += 1;
+        let widget_window = WidgetWindow::new(
+            self.window_cnt,
+            format!("{}_{}", self.window_cnt, title),
+            !self.hide_title_bar,
+            self.move_and_resize_lock,
+            widget,
+        );
+
+        self.widgets.push(Box::new(widget_window));
+        self.widgets.last_mut().unwrap()
+    }
+
+    /// Queues the windows of an imported project and takes over its read period.
+    pub fn apply_import(&mut self, import: &ImportedProject) {
+        self.import_panels = import.panels.clone();
+        if let Some(period_ms) = import.period_ms {
+            self.watch_duration_ms = period_ms.clamp(1, 1000);
+        }
+    }
+
+    fn open_imported_panels(&mut self) {
+        if self.import_panels.is_empty() {
+            return;
+        }
+        let channels = self.probe_if.get_channel_list();
+        if channels.is_empty() {
+            return;
+        }
+        for panel in std::mem::take(&mut self.import_panels) {
+            let widget: Box<dyn WidgetApp> = match panel.kind {
+                PanelKind::Plot => Box::new(widgets::GraphMonitor::default()),
+                PanelKind::Gauges => Box::new(widgets::Gauges::default()),
+                PanelKind::EditTable => Box::new(widgets::EditTable::default()),
+            };
+            let window = self.push_widget(&panel.title, widget);
+            window.fetch_watch_list(&channels);
+            window.select_symbols(&panel.symbols);
         }
     }
 
@@ -5,6 +5,7 @@ use eframe::{
 use egui_extras::{Column, Size, StripBuilder, TableBuilder};
 
 use rfd::FileDialog;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use crate::debugging_tools::*;
@@ -130,6 +131,11 @@ struct SymbolSearch {
     project_name: String,
 
     target_mcu: TargetMCUInfo,
+    // 監視リストの変数の換算 (A2LのCOMPU_METHOD)
+    #[cfg_attr(feature = "serde", serde(default))]
+    conversions: BTreeMap<String, Conversion>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    a2l_message: Option<Result<String, String>>,
 }
 
 #[derive(Default)]
@@ -138,11 +144,26 @@ struct ProbeSetting {
     #[cfg_attr(feature = "serde", serde(skip))]
     probes: Vec<probe_rs::DebugProbeInfo>,
     select_sn: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    probe_config: ProbeConfig,
+    #[cfg_attr(feature = "serde", serde(default))]
+    flash_setting: FlashSetting,
+    #[cfg_attr(feature = "serde", serde(default))]
+    backend: BackendSetting,
 
     #[cfg_attr(feature = "serde", serde(skip))]
     flash_probe_if: ProbeInterface,
 }
 
+// 編集中でないターゲットの設定
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+struct TargetEntry {
+    name: String,
+    symbol_search: SymbolSearch,
+    probe_setting: ProbeSetting,
+}
+
 // ----------------------------------------------------------------------------
 #[derive(PartialEq, Clone, Copy)]
 enum ProgresState {
@@ -219,15 +240,28 @@ impl PrgressBarContext {
 #[derive(Default)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct SettingTab {
+    // 編集中のターゲット
+    #[cfg_attr(feature = "serde", serde(default))]
+    target_name: String,
     symbol_search: SymbolSearch,
     probe_setting: ProbeSetting,
 
+    // 複数ターゲットのときの全ターゲット (active_targetの位置は編集中の中身と入れ替わっている)
+    #[cfg_attr(feature = "serde", serde(default))]
+    targets: Vec<TargetEntry>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    active_target: usize,
+
     #[cfg_attr(feature = "serde", serde(skip))]
     progress_bar: PrgressBarContext,
 }
 
 impl eframe::App for SettingTab {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::TopBottomPanel::top("targets").show(ctx, |ui| {
+            self.target_select_ui(ui);
+        });
+
         let window_width = ctx.available_rect().width();
 
         egui::SidePanel::left("elf parser")
@@ -245,6 +279,119 @@ impl eframe::App for SettingTab {
 
 // ----------------------------------------------------------------------------
 impl SettingTab {
+    fn target_count(&self) -> usize {
+        self.targets.len().max(1)
+    }
+
+    // 名前が空のターゲットは番号で呼ぶ
+    fn target_label(&self, index: usize) -> String {
+        let name = if index == self.active_target {
+            &self.target_name
+        } else {
+            &self.targets[index].name
+        };
+        if name.is_empty() {
+            format!("target{}", index + 1)
+        } else {
+            name.clone()
+        }
+    }
+
+    // チャンネル名 `name:symbol` から一意に戻せない名前を弾く
+    fn target_names_error(&self) -> Option<String> {
+        let labels: Vec<String> = (0..self.target_count())
+            .map(|index| self.target_label(index))
+            .collect();
+        let duplicated = labels
+            .iter()
+            .enumerate()
+            .any(|(i, label)| labels[..i].contains(label));
+        if duplicated {
+            Some("target names must be unique".to_string())
+        } else if labels.iter().any(|label| label.contains(':')) {
+            Some("target names can not contain ':'".to_string())
+        } else {
+            None
+        }
+    }
+
+    fn swap_target(&mut self, index: usize) {
+        let entry = &mut self.targets[index];
+        std::mem::swap(&mut self.target_name, &mut entry.name);
+        std::mem::swap(&mut self.symbol_search, &mut entry.symbol_search);
+        std::mem::swap(&mut self.probe_setting, &mut entry.probe_setting);
+    }
+
+    fn select_target(&mut self, index: usize) {
+        if index == self.active_target || index >= self.targets.len() {
+            return;
+        }
+        // 編集中の中身を元の位置に戻してから、選んだターゲットを取り出す
+        self.swap_target(self.active_target);
+        self.swap_target(index);
+        self.active_target = index;
+        self.progress_bar = Default::default();
+    }
+
+    fn add_target(&mut self) {
+        if self.targets.is_empty() {
+            self.targets.push(Default::default());
+            self.active_target = 0;
+        }
+        self.targets.push(Default::default());
+        self.select_target(self.targets.len() - 1);
+    }
+
+    fn remove_target(&mut self) {
+        if self.targets.len() < 2 {
+            return;
+        }
+        self.targets.remove(self.active_target);
+        self.active_target = self.active_target.min(self.targets.len() - 1);
+        self.swap_target(self.active_target);
+        self.progress_bar = Default::default();
+        if self.targets.len() == 1 {
+            self.targets.clear();
+            self.active_target = 0;
+        }
+    }
+
+    fn target_select_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("targets :");
+            for index in 0..self.target_count() {
+                if ui
+                    .selectable_label(index == self.active_target, self.target_label(index))
+                    .clicked()
+                {
+                    self.select_target(index);
+                }
+            }
+            if ui
+                .button("+")
+                .on_hover_text("watch another probe and target at the same time")
+                .clicked()
+            {
+                self.add_target();
+            }
+
+            if self.targets.is_empty() {
+                return;
+            }
+            ui.separator();
+            ui.label("name :");
+            ui.add(egui::TextEdit::singleline(&mut self.target_name).desired_width(100.))
+                .on_hover_text("channels of this target are named `name:symbol`");
+            if ui.button("remove").clicked() {
+                self.remove_target();
+            }
+
+            if let Some(error) = self.target_names_error() {
+                ui.label(RichText::new(error).color(Color32::RED));
+            }
+        });
+    }
+
     fn symbol_search_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         let mut download_enable = false;
         ui.heading("ELF file loader");
@@ -324,21 +471,34 @@ impl SettingTab {
             }
         });
 
-        if ui
-            .add_enabled(download_enable, egui::Button::new("Download"))
-            .clicked()
-        {
-            if self.probe_setting.probes.len() > 0 {
-                let elf_path =
-                    format!("{}", shellexpand::tilde(&self.symbol_search.input_elf_path));
-
-                let setting = self.get_watch_setting();
-                let _ = self.probe_setting.flash_probe_if.set_probe(setting);
-                self.probe_setting
-                    .flash_probe_if
-                    .flash(PathBuf::from(&elf_path));
-            }
+        let flash_setting = self.probe_setting.flash_setting.clone();
+        if !flash_setting.image_path.is_empty() {
+            // HEX/BINはELFを読み込まなくても書き込める
+            download_enable =
+                self.probe_setting.probes.len() > 0 && flash_setting.image("").is_file();
         }
+        let flash_check = flash_setting
+            .check()
+            .and_then(|_| self.probe_setting.probe_config.check_flash());
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    download_enable && flash_check.is_ok(),
+                    egui::Button::new("Download"),
+                )
+                .clicked()
+            {
+                if self.probe_setting.probes.len() > 0 {
+                    let setting = self.get_watch_setting();
+                    let _ = self.probe_setting.flash_probe_if.set_probe(setting);
+                    self.probe_setting.flash_probe_if.flash(flash_setting);
+                }
+            }
+            if let Err(e) = &flash_check {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+        });
+        self.flash_setting_ui(ui);
 
         if let Some(elf_parser) = &mut self.symbol_search.elf_parser {
             let now_progress = elf_parser.get_scan_progress();
@@ -414,6 +574,18 @@ impl SettingTab {
         }
 
         self.progress_bar.show(ui);
+        if let Some(report) = self.probe_setting.flash_probe_if.get_flash_report() {
+            let title = if report.succeeded() {
+                RichText::new("Flash report")
+            } else {
+                RichText::new("Flash report (failed)").color(Color32::RED)
+            };
+            egui::CollapsingHeader::new(title)
+                .id_source("flash_report")
+                .show(ui, |ui| {
+                    ui.monospace(report.to_string());
+                });
+        }
 
         ui.separator();
         ui.heading("Infomation");
@@ -596,6 +768,354 @@ impl SettingTab {
     }
     // ----------------------------------------------------------------------------
 
+    fn flash_setting_ui(&mut self, ui: &mut egui::Ui) {
+        let flash = &mut self.probe_setting.flash_setting;
+        egui::CollapsingHeader::new("Flash options")
+            .id_source("flash_options")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("image :");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut flash.image_path)
+                            .hint_text("loaded ELF")
+                            .desired_width(240.),
+                    );
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("browse…").clicked() {
+                        if let Some(path) = FileDialog::new()
+                            .add_filter("Image file", &["elf", "hex", "ihex", "bin"])
+                            .pick_file()
+                        {
+                            flash.format = FlashFormat::from_path(&path);
+                            flash.image_path = path.to_string_lossy().to_string();
+                        }
+                    }
+                });
+
+                ui.add_enabled_ui(!flash.image_path.is_empty(), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("format :");
+                        for format in [FlashFormat::Elf, FlashFormat::Hex, FlashFormat::Bin] {
+                            ui.radio_value(&mut flash.format, format, format.to_string());
+                        }
+                        if flash.format == FlashFormat::Bin {
+                            ui.label("base :");
+                            let mut base = format!("0x{:08X}", flash.base_address);
+                            if ui
+                                .add(egui::TextEdit::singleline(&mut base).desired_width(90.))
+                                .on_hover_text("load address of the BIN image")
+                                .changed()
+                            {
+                                if let Ok(value) =
+                                    u64::from_str_radix(base.trim_start_matches("0x"), 16)
+                                {
+                                    flash.base_address = value;
+                                }
+                            }
+                        }
+                    });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("erase :");
+                    ui.radio_value(&mut flash.erase, EraseMode::Sector, "sector");
+                    ui.radio_value(&mut flash.erase, EraseMode::Chip, "full chip");
+                    ui.separator();
+                    ui.checkbox(&mut flash.verify, "verify after programming");
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("after :");
+                    ui.radio_value(&mut flash.after, AfterFlash::Run, "run");
+                    ui.radio_value(&mut flash.after, AfterFlash::HaltAtMain, "halt at main");
+                    ui.radio_value(&mut flash.after, AfterFlash::NoReset, "don't reset");
+                });
+            });
+    }
+
+    fn backend_ui(&mut self, ui: &mut egui::Ui) {
+        let backend = &mut self.probe_setting.backend;
+        ui.horizontal(|ui| {
+            ui.label("connect via :");
+            ui.radio_value(
+                &mut backend.kind,
+                BackendKind::Probe,
+                BackendKind::Probe.to_string(),
+            );
+            ui.radio_value(
+                &mut backend.kind,
+                BackendKind::Gdb,
+                BackendKind::Gdb.to_string(),
+            )
+            .on_hover_text("share the target with OpenOCD, pyOCD or QEMU (-s)");
+            ui.radio_value(
+                &mut backend.kind,
+                BackendKind::LinuxProcess,
+                BackendKind::LinuxProcess.to_string(),
+            )
+            .on_hover_text("software-in-the-loop build running on this PC");
+            ui.radio_value(
+                &mut backend.kind,
+                BackendKind::CoreDump,
+                BackendKind::CoreDump.to_string(),
+            )
+            .on_hover_text("browse a RAM dump with the ELF, nothing is read from hardware");
+            ui.radio_value(
+                &mut backend.kind,
+                BackendKind::Serial,
+                BackendKind::Serial.to_string(),
+            )
+            .on_hover_text("target running the monitor of firmware/uart_monitor");
+            ui.radio_value(
+                &mut backend.kind,
+                BackendKind::Xcp,
+                BackendKind::Xcp.to_string(),
+            )
+            .on_hover_text("XCP on Ethernet slave, e.g. the xcp_slave stand-in");
+        });
+
+        if backend.kind == BackendKind::Gdb {
+            let gdb = &mut backend.gdb;
+            egui::Grid::new("gdb_setting")
+                .num_columns(2)
+                .spacing([5.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("server :");
+                    ui.add(egui::TextEdit::singleline(&mut gdb.address).desired_width(160.))
+                        .on_hover_text("host:port, OpenOCD 3333 / pyOCD 3333 / QEMU 1234");
+                    ui.end_row();
+
+                    ui.label("access :");
+                    ui.vertical(|ui| {
+                        ui.checkbox(&mut gdb.non_stop, "non-stop mode")
+                            .on_hover_text("read memory while the target runs, if the server can");
+                        ui.checkbox(&mut gdb.halt_to_read, "halt to read")
+                            .on_hover_text(
+                                "without non-stop, interrupt the target for every sample",
+                            );
+                        ui.checkbox(&mut gdb.resume_on_connect, "continue on connect");
+                    });
+                    ui.end_row();
+
+                    ui.label("reset :");
+                    ui.add(egui::TextEdit::singleline(&mut gdb.reset_command).desired_width(160.))
+                        .on_hover_text(
+                            "monitor command to reset and halt, OpenOCD / pyOCD \"reset halt\", QEMU \"system_reset\"",
+                        );
+                    ui.end_row();
+                });
+        }
+
+        if backend.kind == BackendKind::LinuxProcess {
+            let linux = &mut backend.linux;
+            egui::Grid::new("linux_process_setting")
+                .num_columns(2)
+                .spacing([5.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("PID :");
+                    ui.add(egui::DragValue::new(&mut linux.pid).speed(0))
+                        .on_hover_text("0 : find the process by name");
+                    ui.end_row();
+
+                    ui.label("process name :");
+                    ui.add_enabled(
+                        linux.pid == 0,
+                        egui::TextEdit::singleline(&mut linux.process_name).desired_width(160.),
+                    )
+                    .on_hover_text("found again after the simulation is restarted");
+                    ui.end_row();
+                });
+            if let Err(e) = linux.check() {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+            if cfg!(not(target_os = "linux")) {
+                ui.label(RichText::new("available only on Linux").color(Color32::RED));
+            }
+        }
+
+        if backend.kind == BackendKind::CoreDump {
+            let dump = &mut backend.dump;
+            let mut remove = None;
+            egui::Grid::new("core_dump_files")
+                .num_columns(4)
+                .spacing([5.0, 4.0])
+                .show(ui, |ui| {
+                    for (index, file) in dump.files.iter_mut().enumerate() {
+                        ui.label(file.file_name()).on_hover_text(&file.path);
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut file.format, FlashFormat::Elf, "ELF core");
+                            ui.radio_value(&mut file.format, FlashFormat::Hex, "Intel HEX");
+                            ui.radio_value(&mut file.format, FlashFormat::Bin, "raw");
+                        });
+                        if file.format == FlashFormat::Bin {
+                            let mut base = format!("0x{:08X}", file.address);
+                            if ui
+                                .add(egui::TextEdit::singleline(&mut base).desired_width(90.))
+                                .on_hover_text("start address of the raw binary")
+                                .changed()
+                            {
+                                if let Ok(value) =
+                                    u64::from_str_radix(base.trim_start_matches("0x"), 16)
+                                {
+                                    file.address = value;
+                                }
+                            }
+                        } else {
+                            ui.label("");
+                        }
+                        if ui.button("remove").clicked() {
+                            remove = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+            if let Some(index) = remove {
+                dump.files.remove(index);
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("add dump file…").clicked() {
+                if let Some(paths) = FileDialog::new()
+                    .add_filter("Dump file", &["bin", "hex", "ihex", "elf", "core"])
+                    .pick_files()
+                {
+                    dump.files
+                        .extend(paths.iter().map(|path| DumpFile::new(path)));
+                }
+            }
+            if let Err(e) = dump.check() {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+        }
+
+        if backend.kind == BackendKind::Serial {
+            let serial = &mut backend.serial;
+            egui::Grid::new("serial_setting")
+                .num_columns(2)
+                .spacing([5.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("port :");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut serial.port).desired_width(160.))
+                            .on_hover_text("/dev/ttyUSB0, COM3, or the pty of the host stand-in");
+                        egui::ComboBox::from_id_source("serial_ports")
+                            .selected_text("…")
+                            .width(20.)
+                            .show_ui(ui, |ui| {
+                                for port in serialport::available_ports().unwrap_or_default() {
+                                    ui.selectable_value(
+                                        &mut serial.port,
+                                        port.port_name.clone(),
+                                        &port.port_name,
+                                    );
+                                }
+                            });
+                    });
+                    ui.end_row();
+
+                    ui.label("baud rate :");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut serial.baud_rate).speed(0));
+                        for baud_rate in [115_200, 460_800, 921_600] {
+                            ui.selectable_value(
+                                &mut serial.baud_rate,
+                                baud_rate,
+                                baud_rate.to_string(),
+                            );
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("access :");
+                    ui.checkbox(&mut serial.stream, "stream the watch list")
+                        .on_hover_text(
+                            "the target sends the values every period, otherwise polled",
+                        );
+                    ui.end_row();
+                });
+            if let Err(e) = serial.check() {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+        }
+
+        if backend.kind == BackendKind::Xcp {
+            let xcp = &mut backend.xcp;
+            egui::Grid::new("xcp_setting")
+                .num_columns(2)
+                .spacing([5.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("slave :");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut xcp.address).desired_width(160.))
+                            .on_hover_text("host:port, 5555 by default");
+                        ui.radio_value(&mut xcp.transport, XcpTransport::Udp, "UDP");
+                        ui.radio_value(&mut xcp.transport, XcpTransport::Tcp, "TCP");
+                    });
+                    ui.end_row();
+
+                    ui.label("address extension :");
+                    ui.add(egui::DragValue::new(&mut xcp.address_extension).speed(0));
+                    ui.end_row();
+
+                    ui.label("access :");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut xcp.daq, "DAQ list on event")
+                            .on_hover_text(
+                                "the slave sends the watch list on the event channel, otherwise polled with SHORT_UPLOAD",
+                            );
+                        ui.add_enabled(
+                            xcp.daq,
+                            egui::DragValue::new(&mut xcp.event_channel).speed(0),
+                        );
+                    });
+                    ui.end_row();
+                });
+            if let Err(e) = xcp.check() {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+        }
+    }
+
+    fn probe_config_ui(&mut self, ui: &mut egui::Ui) {
+        let config = &mut self.probe_setting.probe_config;
+        egui::Grid::new("probe_config")
+            .num_columns(2)
+            .spacing([5.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("protocol :");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut config.protocol, ProbeProtocol::Swd, "SWD");
+                    ui.radio_value(&mut config.protocol, ProbeProtocol::Jtag, "JTAG");
+                });
+                ui.end_row();
+
+                ui.label("speed :");
+                ui.add(
+                    egui::DragValue::new(&mut config.speed_khz)
+                        .suffix("[kHz]")
+                        .clamp_range(0..=100_000)
+                        .speed(100),
+                )
+                .on_hover_text("0 : probe default");
+                ui.end_row();
+
+                ui.label("connect :");
+                ui.vertical(|ui| {
+                    ui.checkbox(&mut config.connect_under_reset, "connect under reset")
+                        .on_hover_text("hold NRST while connecting, for chips in low-power modes");
+                    ui.checkbox(
+                        &mut config.attach_without_reset,
+                        "attach without halt / reset",
+                    )
+                    .on_hover_text("never halt or reset the running target on our own");
+                });
+                ui.end_row();
+            });
+        if let Err(e) = config.check() {
+            ui.label(RichText::new(e).color(Color32::RED));
+        }
+    }
+
     fn check_probe(&mut self) {
         let probes = Probe::list_all();
 
@@ -608,6 +1128,7 @@ impl SettingTab {
 
     fn probe_setting_ui(&mut self, ui: &mut egui::Ui) {
         ui.heading("Debug probe select");
+        self.backend_ui(ui);
 
         if ui.add(egui::Button::new("probe check")).clicked() {
             self.check_probe();
@@ -691,6 +1212,9 @@ impl SettingTab {
                     ui.separator();
                     ui.heading("watch settings...");
 
+                    self.probe_config_ui(ui);
+                    ui.separator();
+
                     ui.label(format!(
                         "select prove --> {:?}",
                         self.probe_setting.select_sn
@@ -701,10 +1225,12 @@ impl SettingTab {
                     ));
 
                     ui.separator();
+                    self.a2l_ui(ui);
 
                     ui.push_id(2, |ui| {
                         let watch_list = self.get_watch_list();
                         let mut to_remove = None;
+                        let conversions = &mut self.symbol_search.conversions;
 
                         TableBuilder::new(ui)
                             .striped(true)
@@ -714,6 +1240,8 @@ impl SettingTab {
                             .column(Column::exact(20.0))
                             .column(Column::initial(120.).resizable(true))
                             .column(Column::initial(160.).resizable(true))
+                            .column(Column::initial(160.).resizable(true))
+                            .column(Column::initial(180.).resizable(true))
                             .column(Column::remainder().resizable(true))
                             .header(9.0, |mut header| {
                                 header.col(|_ui| {});
@@ -726,6 +1254,13 @@ impl SettingTab {
                                 header.col(|ui| {
                                     ui.heading("Symbol Name");
                                 });
+                                header.col(|ui| {
+                                    ui.heading("Scaling")
+                                        .on_hover_text("physical = raw × factor + offset");
+                                });
+                                header.col(|ui| {
+                                    ui.heading("A2L");
+                                });
                             })
                             .body(|mut body| {
                                 for selected in watch_list {
@@ -744,6 +1279,42 @@ impl SettingTab {
                                         row.col(|ui| {
                                             ui.label(&selected.name).on_hover_text(&selected.name);
                                         });
+                                        let conversion =
+                                            conversions.entry(selected.name.clone()).or_default();
+                                        row.col(|ui| {
+                                            ui.add(
+                                                egui::DragValue::new(&mut conversion.factor)
+                                                    .speed(0)
+                                                    .max_decimals(9),
+                                            );
+                                            ui.label("×");
+                                            ui.add(
+                                                egui::DragValue::new(&mut conversion.offset)
+                                                    .speed(0)
+                                                    .max_decimals(9),
+                                            );
+                                            ui.add(
+                                                egui::TextEdit::singleline(&mut conversion.unit)
+                                                    .hint_text("unit")
+                                                    .desired_width(40.),
+                                            );
+                                            if let Err(e) = conversion.check() {
+                                                ui.label(RichText::new("!").color(Color32::RED))
+                                                    .on_hover_text(e);
+                                            }
+                                        });
+                                        row.col(|ui| {
+                                            ui.radio_value(
+                                                &mut conversion.calibration,
+                                                false,
+                                                "measurement",
+                                            );
+                                            ui.radio_value(
+                                                &mut conversion.calibration,
+                                                true,
+                                                "characteristic",
+                                            );
+                                        });
                                     });
                                 }
                                 if let Some(name) = to_remove {
@@ -759,15 +1330,183 @@ impl SettingTab {
             });
     }
 
+    fn a2l_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("ASAP2 :");
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui
+                .button("import A2L…")
+                .on_hover_text("add the measurements and characteristics to the watch list")
+                .clicked()
+            {
+                if let Some(path) = FileDialog::new().add_filter("A2L", &["a2l"]).pick_file() {
+                    self.symbol_search.a2l_message = Some(self.import_a2l_file(&path));
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui
+                .button("export A2L…")
+                .on_hover_text("write the watch list with its scaling")
+                .clicked()
+            {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("A2L", &["a2l"])
+                    .set_file_name(format!("{}.a2l", self.symbol_search.project_name))
+                    .save_file()
+                {
+                    self.symbol_search.a2l_message = Some(self.export_a2l_file(&path));
+                }
+            }
+            match &self.symbol_search.a2l_message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(message)) => {
+                    ui.label(RichText::new(message).color(Color32::RED));
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn import_a2l_file(&mut self, path: &PathBuf) -> Result<String, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let import = import_a2l(&text)?;
+        let search = &mut self.symbol_search;
+        for (info, conversion) in &import.entries {
+            Self::select_symbol(search, info);
+            search
+                .conversions
+                .insert(info.name.clone(), conversion.clone());
+        }
+        let message = format!("{} entries imported", import.entries.len());
+        match import.warnings.first() {
+            None => Ok(message),
+            Some(first) => Err(format!(
+                "{}, {} skipped or unscaled ({}…)",
+                message,
+                import.warnings.len(),
+                first
+            )),
+        }
+    }
+
+    // ELFにある変数はそのアドレスのまま選択する
+    fn select_symbol(search: &mut SymbolSearch, info: &VariableInfo) {
+        match search
+            .selected_list
+            .iter_mut()
+            .find(|item| item.name == info.name)
+        {
+            Some(item) => item.is_selected = true,
+            None => search.selected_list.push(SelectableVariableInfo {
+                name: info.name.clone(),
+                types: info.types.clone(),
+                address: info.address,
+                size: info.size,
+                is_selected: true,
+            }),
+        }
+    }
+
+    /// Adds the watch list and scaling of an imported project to the target being edited.
+    pub fn apply_import(&mut self, import: &ImportedProject) -> String {
+        let search = &mut self.symbol_search;
+        if search.input_elf_path.is_empty() {
+            search.input_elf_path = import.setting.elf_path.clone();
+        }
+        for info in &import.setting.watch_list {
+            Self::select_symbol(search, info);
+        }
+        search
+            .conversions
+            .extend(import.setting.conversions.clone());
+
+        // 型の分からない変数はELFを読み込むまで監視できない
+        let unresolved = search
+            .selected_list
+            .iter()
+            .filter(|item| item.is_selected && item.types.is_empty())
+            .count();
+        let mut message = format!(
+            "{} variables and {} windows imported",
+            import.setting.watch_list.len(),
+            import.panels.len()
+        );
+        if unresolved > 0 {
+            message += &format!(", load the ELF to resolve {} symbols", unresolved);
+        }
+        message
+    }
+
+    fn export_a2l_file(&mut self, path: &PathBuf) -> Result<String, String> {
+        let watch_list = self.get_watch_list();
+        let project = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (text, skipped) = export_a2l(&project, &watch_list, &self.symbol_search.conversions);
+        std::fs::write(path, text).map_err(|e| e.to_string())?;
+        let message = format!("{} objects exported", watch_list.len() - skipped.len());
+        if skipped.is_empty() {
+            Ok(message)
+        } else {
+            Err(format!("{}, not scalar : {}", message, skipped.join(", ")))
+        }
+    }
+
     pub fn get_watch_list(&mut self) -> Vec<VariableInfo> {
         SelectableVariableInfo::pick_selected(&self.symbol_search.selected_list)
     }
 
+    /// Setting of the target being edited.
     pub fn get_watch_setting(&mut self) -> WatchSetting {
+        let mut setting = Self::watch_setting(&self.symbol_search, &self.probe_setting);
+        if !self.targets.is_empty() {
+            setting.target_name = self.target_label(self.active_target);
+        }
+        setting
+    }
+
+    /// Settings of every target; the first one is the primary target of the monitor.
+    /// Err when the target names can not be told apart in the channel names.
+    pub fn get_watch_settings(&mut self) -> Result<Vec<WatchSetting>, String> {
+        if self.targets.is_empty() {
+            return Ok(vec![self.get_watch_setting()]);
+        }
+        if let Some(error) = self.target_names_error() {
+            return Err(error);
+        }
+        Ok((0..self.targets.len())
+            .map(|index| {
+                let mut setting = if index == self.active_target {
+                    Self::watch_setting(&self.symbol_search, &self.probe_setting)
+                } else {
+                    let entry = &self.targets[index];
+                    Self::watch_setting(&entry.symbol_search, &entry.probe_setting)
+                };
+                setting.target_name = self.target_label(index);
+                setting
+            })
+            .collect())
+    }
+
+    fn watch_setting(symbol_search: &SymbolSearch, probe_setting: &ProbeSetting) -> WatchSetting {
+        let watch_list = SelectableVariableInfo::pick_selected(&symbol_search.selected_list);
         WatchSetting {
-            target_mcu: self.symbol_search.target_mcu.id.clone(),
-            probe_sn: self.probe_setting.select_sn.clone().unwrap_or_default(),
-            watch_list: self.get_watch_list(),
+            target_name: String::new(),
+            target_mcu: symbol_search.target_mcu.id.clone(),
+            probe_sn: probe_setting.select_sn.clone().unwrap_or_default(),
+            conversions: symbol_search
+                .conversions
+                .iter()
+                .filter(|(name, _)| watch_list.iter().any(|symbol| &symbol.name == *name))
+                .map(|(name, conversion)| (name.clone(), conversion.clone()))
+                .collect(),
+            watch_list,
+            elf_path: format!("{}", shellexpand::tilde(&symbol_search.input_elf_path)),
+            probe_config: probe_setting.probe_config.clone(),
+            backend: probe_setting.backend.clone(),
         }
     }
 
@@ -78,6 +78,7 @@ impl super::WidgetApp for PushButton {
                                     ui.text_edit_singleline(&mut btn.name);
                                 });
                                 row.col(|ui| {
+                                    self.mcu.write_status_ui(ui, &btn.symbol_name);
                                     if ui.button("Send Value").clicked() {
                                         if let Some(probe) = &mut self.mcu.probe {
                                             if let Some(symbol) = self
@@ -95,7 +96,11 @@ impl super::WidgetApp for PushButton {
                                     }
                                 });
                                 row.col(|ui| {
-                                    ui.add(egui::DragValue::new(&mut btn.send_value).speed(1.0));
+                                    ui.add(
+                                        egui::DragValue::new(&mut btn.send_value)
+                                            .speed(1.0)
+                                            .custom_parser(super::parse_drag_value),
+                                    );
                                 });
                                 row.col(|ui| {
                                     egui::ComboBox::from_id_source(btn.id)
@@ -0,0 +1,149 @@
+use eframe::egui::{self, Color32};
+use egui_extras::{Column, TableBuilder};
+
+use super::MCUinterface;
+use crate::debugging_tools::*;
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CallStackView {
+    mcu: MCUinterface,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    selected: usize,
+}
+
+// ----------------------------------------------------------------------------
+impl super::WidgetApp for CallStackView {
+    fn update(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        let (watching, call_stack) = match &mut self.mcu.probe {
+            Some(probe) => (probe.now_watching(), probe.get_call_stack()),
+            None => (false, None),
+        };
+
+        ui.horizontal(|ui| {
+            let Some(probe) = &mut self.mcu.probe else {
+                return;
+            };
+            ui.add_enabled_ui(watching, |ui| {
+                if call_stack.is_none() {
+                    if ui.button("Halt").clicked() {
+                        probe.insert_run_command(RunCommand::Halt);
+                    }
+                } else if ui
+                    .button("Refresh")
+                    .on_hover_text("evaluate the variables again")
+                    .clicked()
+                {
+                    probe.refresh_call_stack();
+                }
+            });
+        });
+        ui.separator();
+
+        let frames = match call_stack {
+            Some(Ok(frames)) => frames,
+            Some(Err(e)) => {
+                ui.colored_label(Color32::RED, e);
+                return;
+            }
+            None => {
+                ui.label(if watching {
+                    "core is running"
+                } else {
+                    "start watching to read the target"
+                });
+                return;
+            }
+        };
+        if self.selected >= frames.len() {
+            self.selected = 0;
+        }
+
+        egui::ScrollArea::vertical()
+            .id_source("call_stack_frames")
+            .max_height(120.)
+            .show(ui, |ui| {
+                for (i, frame) in frames.iter().enumerate() {
+                    let mut text = format!("#{} {}", i, frame.function());
+                    if let Some(line) = frame.location.as_ref().and_then(|l| l.line) {
+                        let file = frame.location.as_ref().map_or("", |l| l.file.as_str());
+                        text.push_str(&format!("  {}:{}", file, line));
+                    }
+                    let text = if frame.exception {
+                        egui::RichText::new(text).color(Color32::YELLOW)
+                    } else {
+                        egui::RichText::new(text)
+                    };
+                    ui.selectable_value(&mut self.selected, i, text)
+                        .on_hover_text(format!("PC 0x{:08X}  SP 0x{:08X}", frame.pc, frame.sp));
+                }
+            });
+        ui.separator();
+
+        let Some(frame) = frames.get(self.selected) else {
+            return;
+        };
+        if frame.variables.is_empty() {
+            ui.label("no debug info for the locals of this frame");
+            return;
+        }
+
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            TableBuilder::new(ui)
+                .striped(true)
+                .resizable(true)
+                .vscroll(true)
+                .column(Column::initial(120.))
+                .column(Column::initial(120.))
+                .column(Column::initial(100.))
+                .column(Column::remainder())
+                .header(20.0, |mut header| {
+                    for title in ["name", "type", "location", "value"] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(18.0, frame.variables.len(), |index, mut row| {
+                        let variable = &frame.variables[index];
+                        row.col(|ui| {
+                            if variable.parameter {
+                                ui.strong(&variable.name).on_hover_text("parameter");
+                            } else {
+                                ui.label(&variable.name);
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(&variable.types);
+                        });
+                        row.col(|ui| {
+                            ui.monospace(&variable.location);
+                        });
+                        row.col(|ui| match &variable.error {
+                            Some(e) => {
+                                ui.colored_label(Color32::RED, e);
+                            }
+                            None => {
+                                ui.monospace(&variable.value);
+                            }
+                        });
+                    });
+                });
+        });
+    }
+
+    fn fetch_watch_list(&mut self, watch_list: &Vec<crate::debugging_tools::VariableInfo>) {
+        self.mcu.fetch_watch_list(watch_list);
+    }
+
+    fn set_probe(&mut self, probe: ProbeInterface) {
+        self.mcu.set_probe(probe);
+    }
+
+    fn disalbe_scroll_area(&self) -> bool {
+        true
+    }
+}
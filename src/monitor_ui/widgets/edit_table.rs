@@ -51,21 +51,40 @@ impl super::WidgetApp for EditTable {
                                     if let Some(_text) = res {
                                         text = _text.clone();
                                     }
-                                    let res = ui.text_edit_singleline(&mut text);
+                                    // 送信前に型に合うか確認し、エラーは赤枠とツールチップで示す
+                                    let check =
+                                        match ScalarType::from_c_type(&symbol.types, symbol.size) {
+                                            Some(scalar) => check_literal(&text, scalar)
+                                                .map_err(|e| e.to_string()),
+                                            None => {
+                                                Err(format!("unsupported type {}", symbol.types))
+                                            }
+                                        };
+                                    let mut edit = egui::TextEdit::singleline(&mut text);
+                                    if check.is_err() && text != "" {
+                                        edit = edit.text_color(egui::Color32::RED);
+                                    }
+                                    let mut res = ui.add(edit);
+                                    if let (Err(e), false) = (&check, text.is_empty()) {
+                                        res = res.on_hover_text(e);
+                                    }
                                     self.edit_texts.insert(symbol.name.clone(), text.clone());
 
-                                    if res.changed() && text != "" {
+                                    if res.changed() && text != "" && check.is_ok() {
                                         if let Some(probe) = &mut self.mcu.probe {
                                             probe.insert_wirte_que(&symbol, &text);
                                         }
                                     }
                                 });
                                 row.col(|ui| {
-                                    if let Some(probe) = &mut self.mcu.probe {
-                                        if let Some(val) = probe.get_newest_date(&symbol.name) {
-                                            ui.label(format!("{}", val));
+                                    ui.horizontal(|ui| {
+                                        self.mcu.write_status_ui(ui, &symbol.name);
+                                        if let Some(probe) = &mut self.mcu.probe {
+                                            if let Some(val) = probe.get_newest_date(&symbol.name) {
+                                                ui.label(format!("{}", val));
+                                            }
                                         }
-                                    }
+                                    });
                                 });
                             });
                         }
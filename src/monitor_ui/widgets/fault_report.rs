@@ -0,0 +1,156 @@
+use eframe::egui::{self, Color32, RichText};
+use rfd::FileDialog;
+
+use super::MCUinterface;
+use crate::debugging_tools::*;
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FaultReportView {
+    mcu: MCUinterface,
+}
+
+// ----------------------------------------------------------------------------
+impl super::WidgetApp for FaultReportView {
+    fn update(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        let report = match &mut self.mcu.probe {
+            Some(probe) => probe.get_fault_report(),
+            None => None,
+        };
+
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(report.is_some(), |ui| {
+                if ui.button("Copy").clicked() {
+                    if let Some(report) = &report {
+                        ui.output_mut(|o| o.copied_text = report.to_string());
+                    }
+                }
+                if ui.button("Save").clicked() {
+                    if let Some(report) = &report {
+                        Self::save(report);
+                    }
+                }
+                if ui.button("Clear").clicked() {
+                    if let Some(probe) = &mut self.mcu.probe {
+                        probe.clear_fault_report();
+                    }
+                }
+            });
+        });
+        ui.separator();
+
+        let Some(report) = report else {
+            ui.label("no fault detected");
+            return;
+        };
+
+        ui.label(
+            RichText::new(format!(
+                "{} at {:.3}s",
+                report.kind,
+                report.time_ms as f64 / 1000.0
+            ))
+            .color(Color32::RED)
+            .strong(),
+        );
+        match (&report.frame, &report.location) {
+            (Some(frame), Some(location)) => {
+                ui.label(format!("PC 0x{:08X} in {}", frame.pc, location.function));
+                if let Some(line) = location.line {
+                    ui.label(format!("at {}:{}", location.file, line));
+                }
+            }
+            (Some(frame), None) => {
+                ui.label(format!("PC 0x{:08X}", frame.pc));
+            }
+            _ => {
+                ui.colored_label(Color32::YELLOW, "no exception frame found");
+            }
+        }
+
+        ui.separator();
+        egui::Grid::new(ui.id().with("fault_registers"))
+            .num_columns(4)
+            .spacing([10.0, 2.0])
+            .show(ui, |ui| {
+                ui.monospace("CFSR");
+                ui.monospace(format!("0x{:08X}", report.cfsr));
+                ui.monospace("HFSR");
+                ui.monospace(format!("0x{:08X}", report.hfsr));
+                ui.end_row();
+                if let Some(address) = report.mmfar {
+                    ui.monospace("MMFAR");
+                    ui.monospace(format!("0x{:08X}", address));
+                }
+                if let Some(address) = report.bfar {
+                    ui.monospace("BFAR");
+                    ui.monospace(format!("0x{:08X}", address));
+                }
+                ui.end_row();
+
+                if let Some(frame) = &report.frame {
+                    let registers = [
+                        ("R0", frame.r0),
+                        ("R1", frame.r1),
+                        ("R2", frame.r2),
+                        ("R3", frame.r3),
+                        ("R12", frame.r12),
+                        ("LR", frame.lr),
+                        ("PC", frame.pc),
+                        ("SP", frame.sp),
+                    ];
+                    for pair in registers.chunks(2) {
+                        for (name, value) in pair {
+                            ui.monospace(*name);
+                            ui.monospace(format!("0x{:08X}", value));
+                        }
+                        ui.end_row();
+                    }
+                }
+            });
+
+        ui.separator();
+        ui.strong("causes");
+        if report.causes.is_empty() {
+            ui.label("no fault status bits set");
+        }
+        for cause in &report.causes {
+            ui.label(format!("• {}", cause));
+        }
+
+        ui.separator();
+        ui.strong("call stack");
+        for (i, frame) in report.call_stack.iter().enumerate() {
+            let text = format!("#{} {}", i, frame);
+            if frame.exception {
+                ui.colored_label(Color32::YELLOW, text);
+            } else {
+                ui.label(text);
+            }
+        }
+    }
+
+    fn fetch_watch_list(&mut self, watch_list: &Vec<crate::debugging_tools::VariableInfo>) {
+        self.mcu.fetch_watch_list(watch_list);
+    }
+
+    fn set_probe(&mut self, probe: ProbeInterface) {
+        self.mcu.set_probe(probe);
+    }
+}
+
+impl FaultReportView {
+    fn save(report: &FaultReport) {
+        if let Some(path) = FileDialog::new()
+            .set_file_name("fault_report.txt")
+            .add_filter("Text", &["txt"])
+            .save_file()
+        {
+            if let Err(_e) = std::fs::write(&path, report.to_string()) {
+                #[cfg(debug_assertions)]
+                println!("failed to save fault report: {}", _e);
+            }
+        }
+    }
+}
@@ -1,16 +1,18 @@
 use eframe::egui;
-use egui_plot::{Corner, Legend, Line, LineStyle, Plot};
+use egui_plot::{Corner, Legend, Line, LineStyle, Plot, VLine};
 
 use super::MCUinterface;
 use crate::debugging_tools::*;
 
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct GraphMonitor {
     mcu: MCUinterface,
 
     time_window: u64,
     entire_duration_flag: bool,
+    show_events: bool,
 }
 
 impl Default for GraphMonitor {
@@ -19,6 +21,7 @@ impl Default for GraphMonitor {
             mcu: Default::default(),
             time_window: 10000,
             entire_duration_flag: false,
+            show_events: true,
         }
     }
 }
@@ -53,6 +56,8 @@ impl super::WidgetApp for GraphMonitor {
                         self.entire_duration_flag = !self.entire_duration_flag;
                     }
                     ui.separator();
+                    ui.checkbox(&mut self.show_events, "Events");
+                    ui.separator();
                 });
             });
 
@@ -61,26 +66,64 @@ impl super::WidgetApp for GraphMonitor {
             }
 
             plot.show(ui, |plot_ui| {
-                for val in &mut self.mcu.watch_list.clone() {
+                for (index, val) in self.mcu.watch_list.clone().iter().enumerate() {
                     if let Some(probe) = &mut self.mcu.probe {
                         let time_window = if self.entire_duration_flag {
                             None
                         } else {
                             Some(self.time_window)
                         };
-                        plot_ui.line({
-                            let data: Vec<[f64; 2]> = probe
-                                .get_log_vec(&val.name, time_window)
-                                .iter()
-                                .enumerate()
-                                .map(|(_i, y)| [y[0], y[1]])
-                                .collect();
+                        let data = probe.get_log_vec(&val.name, time_window);
 
-                            Line::new(data)
-                                //.color(Color32::from_rgb(200, 100, 100))
-                                .style(LineStyle::Solid)
-                                .name(val.name.clone())
-                        });
+                        // 接続が切れた区間 (NaN) で線を分ける。凡例が1つになるよう色と名前は揃える
+                        let color: egui::Color32 =
+                            egui::ecolor::Hsva::new(index as f32 * 0.618_034, 0.85, 0.5, 1.0)
+                                .into();
+                        for segment in data.split(|point| point[1].is_nan()) {
+                            plot_ui.line(
+                                Line::new(segment.to_vec())
+                                    .color(color)
+                                    .style(LineStyle::Solid)
+                                    .name(val.name.clone()),
+                            );
+                        }
+                    }
+                }
+
+                if self.show_events {
+                    if let Some(probe) = &mut self.mcu.probe {
+                        let now_time = probe.get_elapsed_ms();
+                        for hit in probe.get_watchpoint_hits() {
+                            if !self.entire_duration_flag
+                                && hit.time_ms < now_time - self.time_window as i64
+                            {
+                                continue;
+                            }
+                            plot_ui.vline(
+                                VLine::new(hit.time_ms as f64 / 1000.0)
+                                    .color(egui::Color32::YELLOW)
+                                    .style(LineStyle::dashed_dense())
+                                    .name(format!("{} {}", hit.symbol, hit.kind)),
+                            );
+                        }
+                        for event in probe.get_session_events() {
+                            if !self.entire_duration_flag
+                                && event.time_ms < now_time - self.time_window as i64
+                            {
+                                continue;
+                            }
+                            let color = match event.kind {
+                                SessionEventKind::Info => egui::Color32::LIGHT_BLUE,
+                                SessionEventKind::Warning => egui::Color32::YELLOW,
+                                SessionEventKind::Error => egui::Color32::RED,
+                            };
+                            plot_ui.vline(
+                                VLine::new(event.time_ms as f64 / 1000.0)
+                                    .color(color)
+                                    .style(LineStyle::dashed_loose())
+                                    .name(event.message),
+                            );
+                        }
                     }
                 }
             });
@@ -0,0 +1,455 @@
+use eframe::egui::{self, Color32, RichText};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::MCUinterface;
+use crate::debugging_tools::*;
+
+const BYTES_PER_ROW: usize = 16;
+// 変化したバイトをハイライトする時間 [s]
+const CHANGE_HIGHLIGHT_TIME: f64 = 1.0;
+
+static HEX_DUMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+enum DumpSource {
+    Address,
+    Symbol,
+}
+
+impl std::fmt::Display for DumpSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+enum WordSize {
+    Byte,
+    HalfWord,
+    Word,
+}
+
+impl std::fmt::Display for WordSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}bit", self.bytes() * 8)
+    }
+}
+
+impl WordSize {
+    fn bytes(&self) -> usize {
+        match self {
+            Self::Byte => 1,
+            Self::HalfWord => 2,
+            Self::Word => 4,
+        }
+    }
+
+    // 書き込みキューに入れるときの型
+    fn c_type(&self) -> &'static str {
+        match self {
+            Self::Byte => "unsigned char",
+            Self::HalfWord => "short unsigned int",
+            Self::Word => "unsigned int",
+        }
+    }
+
+    fn scalar(&self) -> ScalarType {
+        match self {
+            Self::Byte => ScalarType::U8,
+            Self::HalfWord => ScalarType::U16,
+            Self::Word => ScalarType::U32,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct HexDump {
+    mcu: MCUinterface,
+
+    source: DumpSource,
+    address_text: String,
+    length: usize,
+    symbol_name: String,
+    word_size: WordSize,
+    big_endian: bool,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    region_key: String,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last_block: MemoryBlock,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    changed_at: Vec<f64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    selected: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    // (address, 入力中のテキスト, フォーカス済み)
+    editing: Option<(u64, String, bool)>,
+}
+
+impl Default for HexDump {
+    fn default() -> Self {
+        Self {
+            mcu: Default::default(),
+            source: DumpSource::Address,
+            address_text: "0x20000000".to_string(),
+            length: 256,
+            symbol_name: String::new(),
+            word_size: WordSize::Byte,
+            big_endian: false,
+            region_key: format!(
+                "hex_dump_{}",
+                HEX_DUMP_COUNT.fetch_add(1, Ordering::Relaxed)
+            ),
+            last_block: Default::default(),
+            changed_at: Vec::new(),
+            selected: None,
+            editing: None,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+impl super::WidgetApp for HexDump {
+    fn update(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        let symbol_map = match &mut self.mcu.probe {
+            Some(probe) => probe.get_symbol_map(),
+            None => None,
+        };
+
+        self.setting_ui(ui, symbol_map.as_deref());
+
+        let range = self.range(symbol_map.as_deref());
+        if let (Some(probe), Some((address, len))) = (&mut self.mcu.probe, range) {
+            probe.request_memory(&self.region_key, address, len);
+            if let Some(block) = probe.get_memory_block(&self.region_key) {
+                self.update_block(block, ui.input(|i| i.time));
+            }
+        }
+
+        egui::TopBottomPanel::bottom(ui.id().with("hex_dump_info")).show_inside(ui, |ui| {
+            self.selection_ui(ui, symbol_map.as_deref());
+        });
+
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            match range {
+                None => {
+                    ui.label("invalid address or symbol");
+                    return;
+                }
+                Some((address, _)) if address != self.last_block.address => {
+                    ui.label("waiting for data...");
+                    return;
+                }
+                _ => {}
+            }
+            if let Some(error) = &self.last_block.error {
+                ui.colored_label(Color32::RED, error);
+                return;
+            }
+            self.dump_ui(ui);
+        });
+    }
+
+    fn fetch_watch_list(&mut self, watch_list: &Vec<crate::debugging_tools::VariableInfo>) {
+        self.mcu.fetch_watch_list(watch_list);
+    }
+
+    fn set_probe(&mut self, probe: ProbeInterface) {
+        self.mcu.set_probe(probe);
+    }
+
+    fn disalbe_scroll_area(&self) -> bool {
+        true
+    }
+}
+
+impl HexDump {
+    fn setting_ui(&mut self, ui: &mut egui::Ui, symbol_map: Option<&SymbolMap>) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source(ui.id().with("source"))
+                .selected_text(self.source.to_string())
+                .width(70.)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.source, DumpSource::Address, "Address");
+                    ui.selectable_value(&mut self.source, DumpSource::Symbol, "Symbol");
+                });
+
+            match self.source {
+                DumpSource::Address => {
+                    ui.add(egui::TextEdit::singleline(&mut self.address_text).desired_width(90.));
+                    ui.add(
+                        egui::DragValue::new(&mut self.length)
+                            .clamp_range(1..=MEMORY_VIEW_MAX_LEN)
+                            .suffix(" bytes"),
+                    );
+                }
+                DumpSource::Symbol => {
+                    egui::ComboBox::from_id_source(ui.id().with("symbol"))
+                        .selected_text(self.symbol_name.clone())
+                        .width(140.)
+                        .show_ui(ui, |ui| {
+                            let mut names: Vec<String> =
+                                self.mcu.watch_list.iter().map(|v| v.name.clone()).collect();
+                            if let Some(map) = symbol_map {
+                                names.extend(map.symbols().iter().map(|v| v.name.clone()));
+                            }
+                            names.sort();
+                            names.dedup();
+                            for name in names {
+                                ui.selectable_value(&mut self.symbol_name, name.clone(), name);
+                            }
+                        });
+                }
+            }
+
+            egui::ComboBox::from_id_source(ui.id().with("word_size"))
+                .selected_text(self.word_size.to_string())
+                .width(50.)
+                .show_ui(ui, |ui| {
+                    for size in [WordSize::Byte, WordSize::HalfWord, WordSize::Word] {
+                        ui.selectable_value(&mut self.word_size, size, size.to_string());
+                    }
+                });
+            ui.add_enabled(
+                self.word_size != WordSize::Byte,
+                egui::Checkbox::new(&mut self.big_endian, "big endian"),
+            );
+        });
+    }
+
+    fn range(&self, symbol_map: Option<&SymbolMap>) -> Option<(u64, usize)> {
+        match self.source {
+            DumpSource::Address => {
+                let bytes = parse_literal(&self.address_text, ScalarType::U64).ok()?;
+                let address = u64::from_le_bytes(bytes.try_into().ok()?);
+                Some((address, self.length.clamp(1, MEMORY_VIEW_MAX_LEN)))
+            }
+            DumpSource::Symbol => {
+                let symbol = symbol_map
+                    .and_then(|map| map.find(&self.symbol_name))
+                    .or_else(|| {
+                        self.mcu
+                            .watch_list
+                            .iter()
+                            .find(|v| v.name == self.symbol_name)
+                    })?;
+                Some((symbol.address, symbol.size.clamp(1, MEMORY_VIEW_MAX_LEN)))
+            }
+        }
+    }
+
+    fn update_block(&mut self, block: MemoryBlock, now: f64) {
+        if block.time_ms == self.last_block.time_ms && block.address == self.last_block.address {
+            return;
+        }
+        if block.address != self.last_block.address || block.data.len() != self.changed_at.len() {
+            // 表示範囲が変わったときはハイライトしない
+            self.changed_at = vec![f64::NEG_INFINITY; block.data.len()];
+        } else {
+            for (i, (new, old)) in block.data.iter().zip(&self.last_block.data).enumerate() {
+                if new != old {
+                    self.changed_at[i] = now;
+                }
+            }
+        }
+        self.last_block = block;
+    }
+
+    fn dump_ui(&mut self, ui: &mut egui::Ui) {
+        let now = ui.input(|i| i.time);
+        let data = self.last_block.data.clone();
+        let base = self.last_block.address;
+        let word = self.word_size.bytes();
+        let rows = (data.len() + BYTES_PER_ROW - 1) / BYTES_PER_ROW;
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + 2.;
+
+        let mut write_request = None;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show_rows(ui, row_height, rows, |ui, row_range| {
+                for row in row_range {
+                    let start = row * BYTES_PER_ROW;
+                    let end = (start + BYTES_PER_ROW).min(data.len());
+                    ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing.x = 4.;
+                        ui.monospace(format!("{:08X}:", base + start as u64));
+
+                        for offset in (start..end).step_by(word) {
+                            let bytes = &data[offset..(offset + word).min(end)];
+                            let address = base + offset as u64;
+
+                            if let Some((edit_address, text, focused)) = &mut self.editing {
+                                if *edit_address == address {
+                                    let res = ui.add(
+                                        egui::TextEdit::singleline(text)
+                                            .font(egui::TextStyle::Monospace)
+                                            .desired_width(word as f32 * 16.),
+                                    );
+                                    if !*focused {
+                                        res.request_focus();
+                                        *focused = true;
+                                    }
+                                    if res.lost_focus() {
+                                        if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                            write_request = Some((address, text.clone()));
+                                        }
+                                        self.editing = None;
+                                    }
+                                    continue;
+                                }
+                            }
+
+                            let mut text = RichText::new(self.format_word(bytes)).monospace();
+                            let changed = (offset..offset + bytes.len()).any(|i| {
+                                self.changed_at
+                                    .get(i)
+                                    .map_or(false, |t| now - t < CHANGE_HIGHLIGHT_TIME)
+                            });
+                            if changed {
+                                text = text.color(Color32::BLACK).background_color(Color32::YELLOW);
+                            }
+                            if self.selected == Some(address) {
+                                text = text.underline();
+                            }
+                            let res = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                            if res.clicked() {
+                                self.selected = Some(address);
+                            }
+                            if res.double_clicked() {
+                                self.editing = Some((
+                                    address,
+                                    format!("0x{}", self.format_word(bytes)),
+                                    false,
+                                ));
+                            }
+                        }
+
+                        ui.separator();
+                        let ascii: String = data[start..end]
+                            .iter()
+                            .map(|b| {
+                                if b.is_ascii_graphic() || *b == b' ' {
+                                    *b as char
+                                } else {
+                                    '.'
+                                }
+                            })
+                            .collect();
+                        ui.monospace(ascii);
+                    });
+                }
+            });
+
+        if let Some((address, text)) = write_request {
+            self.write_word(address, &text);
+        }
+    }
+
+    fn selection_ui(&mut self, ui: &mut egui::Ui, symbol_map: Option<&SymbolMap>) {
+        let Some(address) = self.selected else {
+            ui.label("click: select / double click: edit");
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.monospace(format!("0x{:08X}", address));
+            self.mcu.write_status_ui(ui, &format!("0x{:08X}", address));
+            match symbol_map.and_then(|map| map.owner_of(address)) {
+                Some(owner) => {
+                    let member = symbol_map.and_then(|map| map.member_of(address));
+                    let name = member.map_or(owner.name.clone(), |m| m.name.clone());
+                    ui.label(format!("{} (+{})", name, address - owner.address));
+                    if let (Some(member), true) = (member, self.last_block.error.is_none()) {
+                        let start = member.address.checked_sub(self.last_block.address);
+                        if let Some(bytes) = start.and_then(|start| {
+                            self.last_block
+                                .data
+                                .get(start as usize..start as usize + member.size)
+                        }) {
+                            ui.label(format!("= {}", MCUMemory::decode(member, bytes)));
+                        }
+                    }
+                    if ui
+                        .button("Jump to symbol")
+                        .on_hover_text("show the whole range of the owning symbol")
+                        .clicked()
+                    {
+                        self.source = DumpSource::Symbol;
+                        self.symbol_name = owner.name.clone();
+                    }
+                }
+                None if symbol_map.is_none() => {
+                    ui.label("loading symbols...");
+                }
+                None => {
+                    ui.label("no symbol");
+                }
+            }
+        });
+    }
+
+    fn format_word(&self, bytes: &[u8]) -> String {
+        let mut bytes = bytes.to_vec();
+        if !self.big_endian {
+            // リトルエンディアンの値として表示する
+            bytes.reverse();
+        }
+        bytes.iter().map(|b| format!("{:02X}", b)).collect()
+    }
+
+    fn write_word(&mut self, address: u64, text: &str) {
+        let Some(probe) = &mut self.mcu.probe else {
+            return;
+        };
+        // 領域の終わりを越えないよう、最後の半端なワードは残りのバイト数だけ書く
+        let end = self.last_block.address + self.last_block.data.len() as u64;
+        let len = (end.saturating_sub(address) as usize).min(self.word_size.bytes());
+
+        let bytes = match parse_literal(text, self.word_size.scalar()) {
+            Ok(bytes) if bytes[len..].iter().any(|b| *b != 0) => Err(format!(
+                "\"{}\" does not fit in the last {} byte(s) of the region",
+                text.trim(),
+                len
+            )),
+            Ok(bytes) => Ok(bytes[..len].to_vec()),
+            Err(e) => Err(e.to_string()),
+        };
+        let mut bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                // 入力エラーも他のウィジェットと同じ書き込み結果として残す
+                probe.reject_write(&format!("0x{:08X}", address), text, error);
+                return;
+            }
+        };
+        if self.big_endian {
+            // 表示した順番のままメモリに並べる
+            bytes.reverse();
+        }
+
+        // 1/2/4バイトにならない半端なワードは1バイトずつ書く
+        let chunk = if matches!(len, 1 | 2 | 4) { len } else { 1 };
+        for (index, chunk_bytes) in bytes.chunks(chunk).enumerate() {
+            let size = match chunk_bytes.len() {
+                1 => WordSize::Byte,
+                2 => WordSize::HalfWord,
+                _ => WordSize::Word,
+            };
+            let mut value = [0u8; 4];
+            value[..chunk_bytes.len()].copy_from_slice(chunk_bytes);
+
+            let address = address + (index * chunk) as u64;
+            let symbol = VariableInfo {
+                name: format!("0x{:08X}", address),
+                types: size.c_type().to_string(),
+                address,
+                size: size.bytes(),
+            };
+            probe.insert_wirte_que(&symbol, &format!("0x{:X}", u32::from_le_bytes(value)));
+        }
+    }
+}
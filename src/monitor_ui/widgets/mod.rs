@@ -1,19 +1,33 @@
 mod button;
+mod call_stack;
 mod edit_table;
+mod fault_report;
 mod gauge;
 mod graph_monitor;
+mod hex_dump;
+mod ram_snapshot;
+mod rtt_terminal;
+mod semihosting_console;
 mod slider;
 mod table_view;
 mod toggle_switch;
+mod write_history;
 //mod widget_test;
 
 pub use button::PushButton;
+pub use call_stack::CallStackView;
 pub use edit_table::EditTable;
+pub use fault_report::FaultReportView;
 pub use gauge::Gauges;
 pub use graph_monitor::GraphMonitor;
+pub use hex_dump::HexDump;
+pub use ram_snapshot::RamSnapshotView;
+pub use rtt_terminal::RttTerminal;
+pub use semihosting_console::SemihostingConsole;
 pub use slider::Sliders;
 pub use table_view::TableView;
 pub use toggle_switch::{toggle, ToggleSwitch};
+pub use write_history::WriteHistoryView;
 //pub use widget_test::WidgetTest;
 // ----------------------------------------------------------------------------
 use eframe::egui::{self, LayerId, Pos2, Rect, Vec2};
@@ -38,6 +52,29 @@ impl MCUinterface {
     fn set_probe(&mut self, probe: ProbeInterface) {
         self.probe = Some(Box::new(probe.clone()));
     }
+
+    // 最後の書き込み結果を色付きの点で表示する
+    fn write_status_ui(&mut self, ui: &mut egui::Ui, symbol_name: &str) {
+        let record = match &mut self.probe {
+            Some(probe) => probe.get_last_write(symbol_name),
+            None => None,
+        };
+        if let Some(record) = record {
+            let color = match record.status {
+                WriteStatus::Pending | WriteStatus::Written => egui::Color32::GRAY,
+                WriteStatus::Verified => egui::Color32::GREEN,
+                WriteStatus::Mismatch => egui::Color32::YELLOW,
+                WriteStatus::Failed => egui::Color32::RED,
+            };
+            ui.colored_label(color, "●")
+                .on_hover_text(record.to_string());
+        }
+    }
+}
+// DragValueの入力でも 0x80 や 'A' などのリテラルを受け付ける
+fn parse_drag_value(text: &str) -> Option<f64> {
+    let bytes = parse_literal(text, ScalarType::F64).ok()?;
+    Some(f64::from_le_bytes(bytes.try_into().ok()?))
 }
 // ----------------------------------------------------------------------------
 pub trait WidgetApp: serde_traitobject::Serialize + serde_traitobject::Deserialize {
@@ -142,6 +179,8 @@ pub struct WidgetWindow {
     pre_name: String,
     #[cfg_attr(feature = "serde", serde(skip))]
     first_update_flag_inv: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    target_halted: bool,
 }
 
 // basic ui functions
@@ -163,6 +202,7 @@ impl WidgetWindow {
             first_update_flag_inv: true,
             title_bar,
             lock,
+            target_halted: false,
         }
     }
 
@@ -201,6 +241,10 @@ impl WidgetWindow {
         }
 
         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if self.target_halted {
+                ui.colored_label(egui::Color32::YELLOW, "⏸ target halted");
+                ui.separator();
+            }
             if self.state.monitor_tab.sync_button_enable() {
                 if ui.button("Sync from MCU").clicked() {
                     self.state.monitor_tab.sync();
@@ -297,6 +341,16 @@ impl WidgetWindow {
         SelectableVariableInfo::fetch(&list, &mut self.state.select_tab.watch_list);
     }
 
+    /// Checks `symbols` in the watch list (with or without the target prefix) and shows them.
+    pub fn select_symbols(&mut self, symbols: &[String]) {
+        for item in &mut self.state.select_tab.watch_list {
+            item.is_selected = symbols
+                .iter()
+                .any(|symbol| item.name == *symbol || item.name.ends_with(&format!(":{}", symbol)));
+        }
+        self.switch_tab_to(Anchor::MonitorTab);
+    }
+
     pub fn set_probe_to_app(&mut self, probe: ProbeInterface) {
         self.state.monitor_tab.set_probe(probe);
     }
@@ -317,6 +371,10 @@ impl WidgetWindow {
     pub fn lock(&mut self, enable: bool) {
         self.lock = enable;
     }
+
+    pub fn set_target_halted(&mut self, halted: bool) {
+        self.target_halted = halted;
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -0,0 +1,311 @@
+use eframe::egui::{self, Color32};
+use egui_extras::{Column, TableBuilder};
+use rfd::FileDialog;
+
+use super::MCUinterface;
+use crate::debugging_tools::*;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum PendingCapture {
+    Store,
+    // キャプチャしたらそのままold側と比較する
+    DiffLive,
+}
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RamSnapshotView {
+    mcu: MCUinterface,
+
+    halt_while_capture: bool,
+    filter_text: String,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    snapshots: Vec<(String, RamSnapshot)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    old_index: Option<usize>,
+    // None : live
+    #[cfg_attr(feature = "serde", serde(skip))]
+    new_index: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pending: Option<PendingCapture>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    diffs: Vec<SnapshotDiff>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    message: Option<(Color32, String)>,
+}
+
+// ----------------------------------------------------------------------------
+impl super::WidgetApp for RamSnapshotView {
+    fn update(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.poll_capture();
+
+        let watching = match &mut self.mcu.probe {
+            Some(probe) => probe.now_watching(),
+            None => false,
+        };
+
+        ui.horizontal(|ui| {
+            let capture = ui
+                .add_enabled(
+                    watching && self.pending.is_none(),
+                    egui::Button::new("Snapshot RAM"),
+                )
+                .on_disabled_hover_text("start watching to read the target");
+            if capture.clicked() {
+                self.request_capture(PendingCapture::Store);
+            }
+            ui.checkbox(&mut self.halt_while_capture, "halt while reading");
+            ui.separator();
+            if ui.button("Load").clicked() {
+                self.load();
+            }
+            if ui
+                .add_enabled(self.old_index.is_some(), egui::Button::new("Save"))
+                .on_hover_text("save the \"old\" snapshot")
+                .clicked()
+            {
+                self.save();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("old :");
+            let old_text = self
+                .old_index
+                .and_then(|i| self.snapshots.get(i))
+                .map_or(String::new(), |(label, _)| label.clone());
+            egui::ComboBox::from_id_source(ui.id().with("old"))
+                .selected_text(old_text)
+                .width(160.)
+                .show_ui(ui, |ui| {
+                    for (i, (label, _)) in self.snapshots.iter().enumerate() {
+                        ui.selectable_value(&mut self.old_index, Some(i), label);
+                    }
+                });
+
+            ui.label("new :");
+            let new_text = match self.new_index.and_then(|i| self.snapshots.get(i)) {
+                Some((label, _)) => label.clone(),
+                None => "live".to_string(),
+            };
+            egui::ComboBox::from_id_source(ui.id().with("new"))
+                .selected_text(new_text)
+                .width(160.)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.new_index, None, "live");
+                    for (i, (label, _)) in self.snapshots.iter().enumerate() {
+                        ui.selectable_value(&mut self.new_index, Some(i), label);
+                    }
+                });
+
+            let can_diff = self.old_index.is_some()
+                && self.pending.is_none()
+                && (self.new_index.is_some() || watching);
+            if ui
+                .add_enabled(can_diff, egui::Button::new("Diff"))
+                .clicked()
+            {
+                match self.new_index {
+                    Some(_) => self.run_diff(),
+                    None => self.request_capture(PendingCapture::DiffLive),
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("symbol :");
+            ui.add(egui::TextEdit::singleline(&mut self.filter_text).desired_width(100.));
+            if self.pending.is_some() {
+                ui.spinner();
+            } else if let Some((color, message)) = &self.message {
+                ui.colored_label(*color, message);
+            }
+        });
+
+        let filter = self.filter_text.to_lowercase();
+        let rows: Vec<(&SnapshotDiff, Option<&ValueChange>)> = self
+            .diffs
+            .iter()
+            .filter(|diff| filter.is_empty() || diff.name().to_lowercase().contains(&filter))
+            .flat_map(|diff| {
+                std::iter::once((diff, None))
+                    .chain(diff.values.iter().map(move |v| (diff, Some(v))))
+            })
+            .collect();
+
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            TableBuilder::new(ui)
+                .striped(true)
+                .resizable(true)
+                .vscroll(true)
+                .column(Column::initial(160.))
+                .column(Column::initial(90.))
+                .column(Column::initial(80.))
+                .column(Column::initial(100.))
+                .column(Column::remainder())
+                .header(20.0, |mut header| {
+                    for title in ["symbol", "address", "changed", "old", "new"] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(18.0, rows.len(), |index, mut row| match rows[index] {
+                        (diff, None) => {
+                            row.col(|ui| {
+                                ui.strong(diff.name());
+                            });
+                            row.col(|ui| {
+                                ui.monospace(format!("0x{:08X}", diff.address));
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{} / {} B", diff.changed_bytes, diff.size));
+                            });
+                            row.col(|_| {});
+                            row.col(|_| {});
+                        }
+                        (_, Some(value)) => {
+                            row.col(|ui| {
+                                ui.label(format!("  {}", value.name));
+                            });
+                            row.col(|ui| {
+                                ui.monospace(format!("0x{:08X}", value.address));
+                            });
+                            row.col(|_| {});
+                            row.col(|ui| {
+                                ui.label(&value.old);
+                            });
+                            row.col(|ui| {
+                                ui.colored_label(Color32::YELLOW, &value.new);
+                            });
+                        }
+                    });
+                });
+        });
+    }
+
+    fn fetch_watch_list(&mut self, watch_list: &Vec<crate::debugging_tools::VariableInfo>) {
+        self.mcu.fetch_watch_list(watch_list);
+    }
+
+    fn set_probe(&mut self, probe: ProbeInterface) {
+        self.mcu.set_probe(probe);
+    }
+
+    fn disalbe_scroll_area(&self) -> bool {
+        true
+    }
+}
+
+impl RamSnapshotView {
+    fn request_capture(&mut self, pending: PendingCapture) {
+        if let Some(probe) = &mut self.mcu.probe {
+            probe.request_ram_snapshot(self.halt_while_capture);
+            self.pending = Some(pending);
+            self.message = None;
+        }
+    }
+
+    fn poll_capture(&mut self) {
+        let (Some(pending), Some(probe)) = (self.pending, &mut self.mcu.probe) else {
+            return;
+        };
+        if !probe.now_watching() {
+            self.pending = None;
+            self.message = Some((Color32::RED, "watching stopped".to_string()));
+            return;
+        }
+        let Some(res) = probe.take_ram_snapshot() else {
+            return;
+        };
+        self.pending = None;
+
+        match (res, pending) {
+            (Ok(snapshot), PendingCapture::Store) => {
+                let label = format!("#{} {}", self.snapshots.len() + 1, snapshot);
+                self.snapshots.push((label, snapshot));
+                if self.old_index.is_none() {
+                    self.old_index = Some(self.snapshots.len() - 1);
+                }
+                self.message = Some((Color32::GREEN, "captured".to_string()));
+            }
+            (Ok(snapshot), PendingCapture::DiffLive) => {
+                let symbol_map = self.symbol_map();
+                if let Some((_, old)) = self.old_index.and_then(|i| self.snapshots.get(i)) {
+                    self.diffs = old.diff(&snapshot, symbol_map.as_deref());
+                    self.diff_message();
+                }
+            }
+            (Err(e), _) => {
+                self.message = Some((Color32::RED, format!("capture failed: {}", e)));
+            }
+        }
+    }
+
+    fn run_diff(&mut self) {
+        let symbol_map = self.symbol_map();
+        let (Some(old), Some(new)) = (
+            self.old_index.and_then(|i| self.snapshots.get(i)),
+            self.new_index.and_then(|i| self.snapshots.get(i)),
+        ) else {
+            return;
+        };
+        self.diffs = old.1.diff(&new.1, symbol_map.as_deref());
+        self.diff_message();
+    }
+
+    fn diff_message(&mut self) {
+        let bytes: usize = self.diffs.iter().map(|diff| diff.changed_bytes).sum();
+        self.message = Some((
+            Color32::GRAY,
+            format!("{} bytes changed in {} groups", bytes, self.diffs.len()),
+        ));
+    }
+
+    fn symbol_map(&mut self) -> Option<std::sync::Arc<SymbolMap>> {
+        match &mut self.mcu.probe {
+            Some(probe) => probe.get_symbol_map(),
+            None => None,
+        }
+    }
+
+    fn load(&mut self) {
+        if let Some(path) = FileDialog::new()
+            .add_filter("RAM snapshot", &["ramsnap"])
+            .pick_file()
+        {
+            match RamSnapshot::load(&path) {
+                Ok(snapshot) => {
+                    let label = path.file_name().map_or(snapshot.to_string(), |name| {
+                        name.to_string_lossy().to_string()
+                    });
+                    self.snapshots.push((label, snapshot));
+                    if self.old_index.is_none() {
+                        self.old_index = Some(self.snapshots.len() - 1);
+                    }
+                }
+                Err(e) => {
+                    self.message = Some((Color32::RED, format!("load failed: {}", e)));
+                }
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        let Some((_, snapshot)) = self.old_index.and_then(|i| self.snapshots.get(i)) else {
+            return;
+        };
+        if let Some(path) = FileDialog::new()
+            .set_file_name("snapshot.ramsnap")
+            .add_filter("RAM snapshot", &["ramsnap"])
+            .save_file()
+        {
+            if let Err(e) = snapshot.save(&path) {
+                self.message = Some((Color32::RED, format!("save failed: {}", e)));
+            }
+        }
+    }
+}
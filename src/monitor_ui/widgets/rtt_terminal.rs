@@ -0,0 +1,330 @@
+use eframe::egui::{self, Color32, RichText};
+use rfd::FileDialog;
+
+use super::MCUinterface;
+use crate::debugging_tools::*;
+
+const TERMINAL_CAPACITY: usize = 20000;
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RttTerminal {
+    mcu: MCUinterface,
+
+    channel_filter: Option<usize>,
+    filter_text: String,
+    search_text: String,
+    show_timestamp: bool,
+    auto_scroll: bool,
+    min_level: Option<LogLevel>,
+    show_location: bool,
+
+    down_channel: usize,
+    input_text: String,
+    append_newline: bool,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lines: Vec<RttLine>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    next_seq: usize,
+}
+
+impl Default for RttTerminal {
+    fn default() -> Self {
+        Self {
+            mcu: Default::default(),
+            channel_filter: None,
+            filter_text: String::new(),
+            search_text: String::new(),
+            show_timestamp: true,
+            auto_scroll: true,
+            min_level: None,
+            show_location: false,
+            down_channel: 0,
+            input_text: String::new(),
+            append_newline: true,
+            lines: Vec::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+impl super::WidgetApp for RttTerminal {
+    fn update(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        let mut status = RttStatus::default();
+        if let Some(probe) = &mut self.mcu.probe {
+            let new_lines = probe.get_rtt_lines_since(self.next_seq);
+            if let Some(last) = new_lines.last() {
+                self.next_seq = last.seq + 1;
+            }
+            self.lines.extend(new_lines);
+            if self.lines.len() > TERMINAL_CAPACITY {
+                let overflow = self.lines.len() - TERMINAL_CAPACITY;
+                self.lines.drain(..overflow);
+            }
+            status = probe.get_rtt_status();
+        }
+
+        ui.horizontal(|ui| {
+            if status.attached {
+                ui.colored_label(Color32::GREEN, "●")
+                    .on_hover_text("RTT attached");
+            } else {
+                ui.colored_label(Color32::GRAY, "●")
+                    .on_hover_text("RTT not attached (enable RTT in watch control)");
+            }
+
+            let selected_text = match self.channel_filter {
+                Some(ch) => Self::channel_name(&status.up_channels, ch),
+                None => "All channels".to_string(),
+            };
+            egui::ComboBox::from_id_source(ui.id().with("up_channel"))
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.channel_filter, None, "All channels");
+                    for ch in &status.up_channels {
+                        ui.selectable_value(
+                            &mut self.channel_filter,
+                            Some(ch.number),
+                            Self::channel_name(&status.up_channels, ch.number),
+                        );
+                    }
+                });
+
+            egui::ComboBox::from_id_source(ui.id().with("min_level"))
+                .selected_text(match self.min_level {
+                    Some(level) => format!("≥ {}", level),
+                    None => "All levels".to_string(),
+                })
+                .width(70.)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.min_level, None, "All levels");
+                    for level in [
+                        LogLevel::Trace,
+                        LogLevel::Debug,
+                        LogLevel::Info,
+                        LogLevel::Warn,
+                        LogLevel::Error,
+                    ] {
+                        ui.selectable_value(&mut self.min_level, Some(level), level.to_string());
+                    }
+                });
+
+            ui.label("filter :");
+            ui.add(egui::TextEdit::singleline(&mut self.filter_text).desired_width(80.));
+            ui.label("search :");
+            ui.add(egui::TextEdit::singleline(&mut self.search_text).desired_width(80.));
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Save").clicked() {
+                    self.save_log();
+                }
+                if ui.button("Clear").clicked() {
+                    self.lines.clear();
+                }
+                ui.checkbox(&mut self.auto_scroll, "scroll");
+                ui.checkbox(&mut self.show_location, "location");
+                ui.checkbox(&mut self.show_timestamp, "time");
+            });
+        });
+
+        egui::TopBottomPanel::bottom(ui.id().with("rtt_input")).show_inside(ui, |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source(ui.id().with("down_channel"))
+                    .selected_text(Self::channel_name(&status.down_channels, self.down_channel))
+                    .width(80.)
+                    .show_ui(ui, |ui| {
+                        for ch in &status.down_channels {
+                            ui.selectable_value(
+                                &mut self.down_channel,
+                                ch.number,
+                                Self::channel_name(&status.down_channels, ch.number),
+                            );
+                        }
+                    });
+                ui.checkbox(&mut self.append_newline, "\\n");
+
+                let res = ui.add_enabled(
+                    status.attached,
+                    egui::TextEdit::singleline(&mut self.input_text).desired_width(200.),
+                );
+                let enter = res.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui
+                    .add_enabled(status.attached, egui::Button::new("Send"))
+                    .clicked()
+                    || enter
+                {
+                    self.send_input();
+                    res.request_focus();
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            let filter = self.filter_text.to_lowercase();
+            let search = self.search_text.to_lowercase();
+            let visible: Vec<&RttLine> = self
+                .lines
+                .iter()
+                .filter(|line| self.channel_filter.map_or(true, |ch| line.channel == ch))
+                .filter(|line| {
+                    let level = line.meta.as_ref().and_then(|meta| meta.level);
+                    match (self.min_level, level) {
+                        (Some(min_level), Some(level)) => level >= min_level,
+                        _ => true,
+                    }
+                })
+                .filter(|line| filter.is_empty() || line.text.to_lowercase().contains(&filter))
+                .collect();
+
+            if !search.is_empty() {
+                let hits = visible
+                    .iter()
+                    .filter(|line| line.text.to_lowercase().contains(&search))
+                    .count();
+                ui.label(format!("{} matches", hits));
+            }
+
+            let mut plot_request = None;
+            let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+            egui::ScrollArea::vertical()
+                .auto_shrink([false; 2])
+                .stick_to_bottom(self.auto_scroll)
+                .show_rows(ui, row_height, visible.len(), |ui, row_range| {
+                    for line in &visible[row_range] {
+                        let mut text = String::new();
+                        if self.show_timestamp {
+                            text += &format!(
+                                "{:>9.3} [{}] ",
+                                line.time_ms as f64 / 1000.0,
+                                line.channel
+                            );
+                        }
+                        if let Some(level) = line.meta.as_ref().and_then(|meta| meta.level) {
+                            text += &format!("{:<5} ", level.to_string());
+                        }
+                        text += &line.text;
+                        if let (true, Some(meta)) = (self.show_location, &line.meta) {
+                            text += &format!("  ({}", meta.module);
+                            if let Some(src_line) = meta.line {
+                                text += &format!(" {}:{}", meta.file, src_line);
+                            }
+                            text += ")";
+                        }
+
+                        let mut text = RichText::new(text).monospace();
+                        if let Some(color) = Self::level_color(line.meta.as_ref()) {
+                            text = text.color(color);
+                        }
+                        if !search.is_empty() && line.text.to_lowercase().contains(&search) {
+                            text = text.background_color(Color32::from_rgb(90, 90, 0));
+                        }
+
+                        let res = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                        if let Some(meta) = &line.meta {
+                            if meta.values.iter().any(Option::is_some) {
+                                res.context_menu(|ui| {
+                                    ui.label("plot value");
+                                    for (arg, value) in meta.values.iter().enumerate() {
+                                        let Some(value) = value else {
+                                            continue;
+                                        };
+                                        if ui.button(format!("arg {} ({})", arg, value)).clicked() {
+                                            plot_request = Some((meta.format.clone(), arg));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                            }
+                        }
+                    }
+                });
+
+            if let Some((template, arg)) = plot_request {
+                if let Some(probe) = &mut self.mcu.probe {
+                    probe.add_defmt_channel(
+                        &Self::plot_channel_name(&template, arg),
+                        &template,
+                        arg,
+                    );
+                }
+            }
+        });
+    }
+
+    fn fetch_watch_list(&mut self, watch_list: &Vec<crate::debugging_tools::VariableInfo>) {
+        self.mcu.fetch_watch_list(watch_list);
+    }
+
+    fn set_probe(&mut self, probe: ProbeInterface) {
+        self.mcu.set_probe(probe);
+    }
+
+    fn disalbe_scroll_area(&self) -> bool {
+        true
+    }
+}
+
+impl RttTerminal {
+    fn level_color(meta: Option<&DefmtMeta>) -> Option<Color32> {
+        match meta.and_then(|meta| meta.level) {
+            Some(LogLevel::Error) => Some(Color32::RED),
+            Some(LogLevel::Warn) => Some(Color32::YELLOW),
+            Some(LogLevel::Trace) | Some(LogLevel::Debug) => Some(Color32::GRAY),
+            _ => None,
+        }
+    }
+
+    // "speed = {=u16} rpm" の0番目 -> "speed"
+    fn plot_channel_name(template: &str, arg: usize) -> String {
+        parameter_label(template, arg).unwrap_or_else(|| format!("arg{}", arg))
+    }
+
+    fn channel_name(channels: &Vec<RttChannelInfo>, number: usize) -> String {
+        match channels.iter().find(|ch| ch.number == number) {
+            Some(ch) if !ch.name.is_empty() => format!("{}: {}", ch.number, ch.name),
+            _ => format!("{}", number),
+        }
+    }
+
+    fn send_input(&mut self) {
+        if self.input_text.is_empty() {
+            return;
+        }
+        let mut text = self.input_text.clone();
+        if self.append_newline {
+            text.push('\n');
+        }
+        if let Some(probe) = &mut self.mcu.probe {
+            probe.insert_rtt_down_que(self.down_channel, &text);
+        }
+        self.input_text.clear();
+    }
+
+    fn save_log(&mut self) {
+        if let Some(path) = FileDialog::new()
+            .set_file_name("rtt_log")
+            .add_filter("Text file", &["txt", "log"])
+            .save_file()
+        {
+            let text: String = self
+                .lines
+                .iter()
+                .map(|line| {
+                    format!(
+                        "{:.3}\t{}\t{}\n",
+                        line.time_ms as f64 / 1000.0,
+                        line.channel,
+                        line.text
+                    )
+                })
+                .collect();
+            if let Err(_e) = std::fs::write(path, text) {
+                #[cfg(debug_assertions)]
+                println!("failed to save rtt log: {}", _e);
+            }
+        }
+    }
+}
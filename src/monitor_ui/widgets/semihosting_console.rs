@@ -0,0 +1,142 @@
+use eframe::egui::{self, Color32, RichText};
+
+use super::MCUinterface;
+use crate::debugging_tools::*;
+
+const CONSOLE_CAPACITY: usize = 20000;
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SemihostingConsole {
+    mcu: MCUinterface,
+
+    show_timestamp: bool,
+    auto_scroll: bool,
+    input_text: String,
+    append_newline: bool,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lines: Vec<RttLine>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    next_seq: usize,
+}
+
+impl Default for SemihostingConsole {
+    fn default() -> Self {
+        Self {
+            mcu: Default::default(),
+            show_timestamp: true,
+            auto_scroll: true,
+            input_text: String::new(),
+            append_newline: true,
+            lines: Vec::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+impl super::WidgetApp for SemihostingConsole {
+    fn update(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        let mut waiting_input = false;
+        let mut exit_code = None;
+        if let Some(probe) = &mut self.mcu.probe {
+            let new_lines = probe.get_semihosting_lines_since(self.next_seq);
+            if let Some(last) = new_lines.last() {
+                self.next_seq = last.seq + 1;
+            }
+            self.lines.extend(new_lines);
+            if self.lines.len() > CONSOLE_CAPACITY {
+                let overflow = self.lines.len() - CONSOLE_CAPACITY;
+                self.lines.drain(..overflow);
+            }
+            (waiting_input, exit_code) = probe.get_semihosting_status();
+        }
+
+        ui.horizontal(|ui| {
+            match exit_code {
+                Some(0) => {
+                    ui.colored_label(Color32::GREEN, "exit (0)");
+                }
+                Some(code) => {
+                    ui.colored_label(Color32::RED, format!("exit ({})", code));
+                }
+                None if waiting_input => {
+                    ui.colored_label(Color32::YELLOW, "waiting for input");
+                }
+                None => {}
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Clear").clicked() {
+                    self.lines.clear();
+                }
+                ui.checkbox(&mut self.auto_scroll, "scroll");
+                ui.checkbox(&mut self.show_timestamp, "time");
+            });
+        });
+
+        egui::TopBottomPanel::bottom(ui.id().with("semihosting_input")).show_inside(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.append_newline, "\\n");
+                let res =
+                    ui.add(egui::TextEdit::singleline(&mut self.input_text).desired_width(200.));
+                let enter = res.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Send").clicked() || enter {
+                    self.send_input();
+                    res.request_focus();
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+            egui::ScrollArea::vertical()
+                .auto_shrink([false; 2])
+                .stick_to_bottom(self.auto_scroll)
+                .show_rows(ui, row_height, self.lines.len(), |ui, row_range| {
+                    for line in &self.lines[row_range] {
+                        let text = if self.show_timestamp {
+                            format!("{:>9.3} {}", line.time_ms as f64 / 1000.0, line.text)
+                        } else {
+                            line.text.clone()
+                        };
+                        let mut text = RichText::new(text).monospace();
+                        if line.channel == HANDLE_STDERR as usize {
+                            text = text.color(Color32::RED);
+                        }
+                        ui.label(text);
+                    }
+                });
+        });
+    }
+
+    fn fetch_watch_list(&mut self, watch_list: &Vec<crate::debugging_tools::VariableInfo>) {
+        self.mcu.fetch_watch_list(watch_list);
+    }
+
+    fn set_probe(&mut self, probe: ProbeInterface) {
+        self.mcu.set_probe(probe);
+    }
+
+    fn disalbe_scroll_area(&self) -> bool {
+        true
+    }
+}
+
+impl SemihostingConsole {
+    fn send_input(&mut self) {
+        let mut text = self.input_text.clone();
+        if self.append_newline {
+            text.push('\n');
+        }
+        if text.is_empty() {
+            return;
+        }
+        if let Some(probe) = &mut self.mcu.probe {
+            probe.insert_semihosting_input(&text);
+        }
+        self.input_text.clear();
+    }
+}
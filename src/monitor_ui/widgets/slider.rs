@@ -113,14 +113,21 @@ impl super::WidgetApp for Sliders {
                                     });
                                 });
                                 row.col(|ui| {
-                                    ui.add(egui::DragValue::new(&mut sldr.value).speed(sldr.step));
+                                    ui.add(
+                                        egui::DragValue::new(&mut sldr.value)
+                                            .speed(sldr.step)
+                                            .custom_parser(super::parse_drag_value),
+                                    );
                                 });
                                 row.col(|ui| {
-                                    if let Some(probe) = &mut self.mcu.probe {
-                                        if let Some(val) = probe.get_newest_date(&symbol.name) {
-                                            ui.label(format!("{}", val));
+                                    ui.horizontal(|ui| {
+                                        self.mcu.write_status_ui(ui, &symbol.name);
+                                        if let Some(probe) = &mut self.mcu.probe {
+                                            if let Some(val) = probe.get_newest_date(&symbol.name) {
+                                                ui.label(format!("{}", val));
+                                            }
                                         }
-                                    }
+                                    });
                                 });
                                 row.col(|ui| {
                                     //  Range
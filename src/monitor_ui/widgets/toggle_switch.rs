@@ -98,8 +98,11 @@ impl super::WidgetApp for ToggleSwitch {
                                     ui.label(&symbol.name);
                                 });
                                 row.col(|ui| {
-                                    let res =
-                                        ui.add(egui::DragValue::new(&mut tgl.off_value).speed(1.0));
+                                    let res = ui.add(
+                                        egui::DragValue::new(&mut tgl.off_value)
+                                            .speed(1.0)
+                                            .custom_parser(super::parse_drag_value),
+                                    );
                                     if !tgl.state {
                                         res.highlight();
                                     }
@@ -108,18 +111,24 @@ impl super::WidgetApp for ToggleSwitch {
                                     ui.add(toggle(&mut tgl.state));
                                 });
                                 row.col(|ui| {
-                                    let res =
-                                        ui.add(egui::DragValue::new(&mut tgl.on_value).speed(1.0));
+                                    let res = ui.add(
+                                        egui::DragValue::new(&mut tgl.on_value)
+                                            .speed(1.0)
+                                            .custom_parser(super::parse_drag_value),
+                                    );
                                     if tgl.state {
                                         res.highlight();
                                     }
                                 });
                                 row.col(|ui| {
-                                    if let Some(probe) = &mut self.mcu.probe {
-                                        if let Some(val) = probe.get_newest_date(&symbol.name) {
-                                            ui.label(format!("{}", val));
+                                    ui.horizontal(|ui| {
+                                        self.mcu.write_status_ui(ui, &symbol.name);
+                                        if let Some(probe) = &mut self.mcu.probe {
+                                            if let Some(val) = probe.get_newest_date(&symbol.name) {
+                                                ui.label(format!("{}", val));
+                                            }
                                         }
-                                    }
+                                    });
                                 });
 
                                 if pre_value != tgl.state {
@@ -0,0 +1,135 @@
+use eframe::egui::{self, Color32};
+use egui_extras::{Column, TableBuilder};
+use rfd::FileDialog;
+
+use super::MCUinterface;
+use crate::debugging_tools::*;
+
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WriteHistoryView {
+    mcu: MCUinterface,
+
+    filter_text: String,
+    errors_only: bool,
+}
+
+// ----------------------------------------------------------------------------
+impl super::WidgetApp for WriteHistoryView {
+    fn update(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        let records = match &mut self.mcu.probe {
+            Some(probe) => probe.get_write_history(),
+            None => Vec::new(),
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("symbol :");
+            ui.add(egui::TextEdit::singleline(&mut self.filter_text).desired_width(100.));
+            ui.checkbox(&mut self.errors_only, "errors only");
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Export").clicked() {
+                    self.export();
+                }
+                if ui.button("Clear").clicked() {
+                    if let Some(probe) = &mut self.mcu.probe {
+                        probe.clear_write_history();
+                    }
+                }
+            });
+        });
+
+        let filter = self.filter_text.to_lowercase();
+        let visible: Vec<&WriteRecord> = records
+            .iter()
+            .rev()
+            .filter(|record| filter.is_empty() || record.symbol.to_lowercase().contains(&filter))
+            .filter(|record| {
+                !self.errors_only
+                    || matches!(record.status, WriteStatus::Failed | WriteStatus::Mismatch)
+            })
+            .collect();
+
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            TableBuilder::new(ui)
+                .striped(true)
+                .resizable(true)
+                .vscroll(true)
+                .column(Column::initial(60.))
+                .column(Column::initial(100.))
+                .column(Column::initial(70.))
+                .column(Column::initial(90.))
+                .column(Column::initial(70.))
+                .column(Column::remainder())
+                .header(20.0, |mut header| {
+                    for title in ["time", "symbol", "value", "bytes", "status", "read back"] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(18.0, visible.len(), |index, mut row| {
+                        let record = visible[index];
+                        row.col(|ui| {
+                            ui.label(format!("{:.3}", record.time_ms as f64 / 1000.0));
+                        });
+                        row.col(|ui| {
+                            ui.label(&record.symbol);
+                        });
+                        row.col(|ui| {
+                            ui.label(&record.requested);
+                        });
+                        row.col(|ui| {
+                            ui.monospace(record.encoded_hex());
+                        });
+                        row.col(|ui| {
+                            let color = match record.status {
+                                WriteStatus::Verified => Color32::GREEN,
+                                WriteStatus::Mismatch => Color32::YELLOW,
+                                WriteStatus::Failed => Color32::RED,
+                                _ => Color32::GRAY,
+                            };
+                            let res = ui.colored_label(color, record.status.to_string());
+                            if let Some(error) = &record.error {
+                                res.on_hover_text(error);
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(record.readback.clone().unwrap_or_default());
+                        });
+                    });
+                });
+        });
+    }
+
+    fn fetch_watch_list(&mut self, watch_list: &Vec<crate::debugging_tools::VariableInfo>) {
+        self.mcu.fetch_watch_list(watch_list);
+    }
+
+    fn set_probe(&mut self, probe: ProbeInterface) {
+        self.mcu.set_probe(probe);
+    }
+
+    fn disalbe_scroll_area(&self) -> bool {
+        true
+    }
+}
+
+impl WriteHistoryView {
+    fn export(&mut self) {
+        if let Some(path) = FileDialog::new()
+            .set_file_name("write_history.csv")
+            .add_filter("CSV", &["csv"])
+            .save_file()
+        {
+            if let Some(probe) = &mut self.mcu.probe {
+                if let Err(_e) = probe.export_write_history(&path) {
+                    #[cfg(debug_assertions)]
+                    println!("failed to export write history: {}", _e);
+                }
+            }
+        }
+    }
+}
@@ -41,6 +41,8 @@ enum Dialog {
     None,
     Reset,
     FaildLoadSaveData,
+    ImportResult,
+    TargetsNotChanged,
 }
 // ----------------------------------------------------------------------------
 
@@ -58,6 +60,8 @@ pub struct VisuModiRam {
     state: State,
 
     open_dialog: Dialog,
+    import_message: String,
+    targets_error: String,
 }
 
 use egui_modal::Modal;
@@ -87,6 +91,8 @@ impl VisuModiRam {
         let mut slf = Self {
             state: State::default(),
             open_dialog: Dialog::None,
+            import_message: String::new(),
+            targets_error: String::new(),
         };
 
         #[cfg(feature = "persistence")]
@@ -153,8 +159,19 @@ impl VisuModiRam {
         self.state.selected_anchor = selected_anchor;
 
         if switch_to_main_flag {
-            let setting = self.state.setting_tab.get_watch_setting().clone();
-            self.state.main_tab.probe_if.set_probe(setting).unwrap();
+            let res = match self.state.setting_tab.get_watch_settings() {
+                Ok(settings) => self
+                    .state
+                    .main_tab
+                    .probe_if
+                    .set_targets(settings)
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                self.targets_error = e;
+                self.open_dialog = Dialog::TargetsNotChanged;
+            }
         }
 
         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -176,6 +193,20 @@ impl VisuModiRam {
                 }
             }
             ui.separator();
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui
+                .button("Import project…")
+                .on_hover_text("STM32CubeMonitor flow (.json) or FreeMASTER project (.pmpx)")
+                .clicked()
+            {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("CubeMonitor / FreeMASTER", &["json", "pmpx"])
+                    .pick_file()
+                {
+                    self.import_project(path);
+                }
+            }
+            ui.separator();
             if ui.button("Save layout").clicked() {
                 if let Some(path) = FileDialog::new()
                     .set_file_name("monitor_layout")
@@ -196,6 +227,8 @@ impl VisuModiRam {
         match self.open_dialog {
             Dialog::Reset => self.reset_dialog_ui(ui.ctx(), cmd),
             Dialog::FaildLoadSaveData => self.faild_load_save_data_dialog_ui(ui.ctx(), cmd),
+            Dialog::ImportResult => self.import_result_dialog_ui(ui.ctx()),
+            Dialog::TargetsNotChanged => self.targets_not_changed_dialog_ui(ui.ctx()),
             _ => {}
         }
     }
@@ -248,6 +281,67 @@ impl VisuModiRam {
         modal.open();
     }
 
+    fn import_project(&mut self, path: PathBuf) {
+        match crate::debugging_tools::import_project(&path) {
+            Ok(import) => {
+                let mut message = self.state.setting_tab.apply_import(&import);
+                self.state.main_tab.apply_import(&import);
+                for warning in &import.warnings {
+                    message += &format!("\n{}", warning);
+                }
+                // 監視リストを確認してからMain Monitorでウィンドウを開く
+                self.state.selected_anchor = Anchor::SettingTab;
+                self.import_message = message;
+            }
+            Err(e) => {
+                self.import_message = format!("Failed to import {}: {}", path.display(), e);
+            }
+        }
+        self.open_dialog = Dialog::ImportResult;
+    }
+
+    fn import_result_dialog_ui(&mut self, ctx: &egui::Context) {
+        let modal = Modal::new(ctx, "import_dialog");
+
+        modal.show(|ui| {
+            modal.title(ui, "Import project");
+            modal.frame(ui, |ui| {
+                modal.body(ui, self.import_message.as_str());
+            });
+            modal.buttons(ui, |ui| {
+                if modal.button(ui, "Accept").clicked() {
+                    self.open_dialog = Dialog::None;
+                };
+            });
+        });
+
+        modal.open();
+    }
+
+    fn targets_not_changed_dialog_ui(&mut self, ctx: &egui::Context) {
+        let modal = Modal::new(ctx, "targets_dialog");
+
+        modal.show(|ui| {
+            modal.title(ui, "Error!");
+            modal.frame(ui, |ui| {
+                modal.body(
+                    ui,
+                    format!(
+                        "The target settings were not applied: {}",
+                        self.targets_error
+                    ),
+                );
+            });
+            modal.buttons(ui, |ui| {
+                if modal.button(ui, "Accept").clicked() {
+                    self.open_dialog = Dialog::None;
+                };
+            });
+        });
+
+        modal.open();
+    }
+
     fn run_cmd(&mut self, ctx: &egui::Context, cmd: Command) {
         match cmd {
             Command::Nothing => {}